@@ -0,0 +1,126 @@
+//! counts the heap allocations of REST calls against a local stub server, e.g. to compare the
+//! ways of passing credentials in hot loops
+//!
+//! ```text
+//! cargo run --release --example allocations
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use homeassistant_rs::client::Client;
+use homeassistant_rs::hass;
+
+/// counts the allocations of the thread the calls are made on, not those of the stub server
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static COUNTED: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTED.with(Cell::get) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTED.with(Cell::get) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const CALLS: u64 = 1000;
+
+/// answers every request on a keep-alive connection with the same small JSON body
+fn stub_server() -> anyhow::Result<String> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut stream = stream;
+                let body = r#"{"state": "RUNNING", "recorder_state": {"migration_in_progress": false, "migration_is_live": false}}"#;
+                loop {
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+                        line.clear();
+                    }
+                    if line.is_empty() {
+                        return;
+                    }
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                        body.len()
+                    );
+                    if stream.write_all(response.as_bytes()).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    Ok(url)
+}
+
+/// runs `call` [`CALLS`] times and prints the allocations per call
+async fn measure<F: Future<Output = anyhow::Result<()>>>(name: &str, mut call: impl FnMut() -> F) {
+    // the first call opens the connection
+    call().await.unwrap();
+    ALLOCATIONS.store(0, Ordering::Relaxed);
+    COUNTED.with(|counted| counted.set(true));
+    for _ in 0..CALLS {
+        call().await.unwrap();
+    }
+    COUNTED.with(|counted| counted.set(false));
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    println!(
+        "{name:<24} {:>6.1} allocations per call",
+        allocations as f64 / CALLS as f64
+    );
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let url = stub_server()?;
+    let token = "token".to_owned();
+
+    measure("hass() with parameters", || async {
+        hass()
+            .core_state(Some(url.clone()), Some(token.clone()))
+            .await
+            .map(drop)
+    })
+    .await;
+
+    let api = format!("{url}/api/core/state");
+    measure("reqwest", || async {
+        let response = homeassistant_rs::CLIENT
+            .get(&api)
+            .bearer_auth(&token)
+            .send()
+            .await?;
+        response.bytes().await.map(drop).map_err(Into::into)
+    })
+    .await;
+
+    let client = Client::new(url.as_str(), token.as_str())?;
+    measure("Client", || async { client.core_state().await.map(drop) }).await;
+    Ok(())
+}
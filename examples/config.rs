@@ -0,0 +1,20 @@
+//! prints the version and location of the instance in `HA_URL`
+//!
+//! ```text
+//! cargo run --example config
+//! ```
+
+use homeassistant_rs::hass;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = hass().config(None, None).await?;
+    println!("{} running {}", config.location_name, config.version);
+    println!("time zone: {}", config.time_zone);
+
+    let version = config.ha_version()?;
+    if !hass().capabilities().is_supported(&config.version) {
+        println!("warning: {version} is not supported by this crate");
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! measures parsing a large `/api/states` and history payload, with `serde_json` and with the
+//! parser enabled in this build
+//!
+//! ```text
+//! cargo run --release --example decode --features simd-json
+//! ```
+
+use std::time::{Duration, Instant};
+
+use homeassistant_rs::decode;
+use homeassistant_rs::structs::{HistoryResponse, StatesResponse};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+const RUNS: u32 = 100;
+
+/// `entities` entities with the attributes of typical lights and sensors
+fn states(entities: usize) -> String {
+    let states: Vec<_> = (0..entities)
+        .map(|i| {
+            json!({
+                "entity_id": format!("sensor.room_{i}_temperature"),
+                "state": format!("{}.{}", 18 + i % 7, i % 10),
+                "attributes": {
+                    "state_class": "measurement",
+                    "unit_of_measurement": "°C",
+                    "device_class": "temperature",
+                    "friendly_name": format!("Room {i} Temperature"),
+                    "supported_color_modes": ["color_temp", "xy"],
+                    "min_color_temp_kelvin": 2202,
+                    "max_color_temp_kelvin": 6535,
+                },
+                "last_changed": "2025-07-01T12:00:00.123456+00:00",
+                "last_reported": "2025-07-01T12:04:59.654321+00:00",
+                "last_updated": "2025-07-01T12:00:00.123456+00:00",
+                "context": {"id": "01J1Z3X7Q9V5K2M8N4P6R0T2W4", "parent_id": null, "user_id": null},
+            })
+        })
+        .collect();
+    serde_json::to_string(&states).unwrap()
+}
+
+/// a day of 5 minute samples of `entities` entities
+fn history(entities: usize) -> String {
+    let history: Vec<Vec<_>> = (0..entities)
+        .map(|i| {
+            (0..288)
+                .map(|sample| {
+                    json!({
+                        "entity_id": format!("sensor.room_{i}_temperature"),
+                        "state": format!("{}.{}", 18 + sample % 7, sample % 10),
+                        "attributes": {"unit_of_measurement": "°C", "friendly_name": format!("Room {i}")},
+                        "last_changed": format!("2025-07-01T{:02}:{:02}:00+00:00", sample / 12, sample % 12 * 5),
+                        "last_updated": format!("2025-07-01T{:02}:{:02}:00+00:00", sample / 12, sample % 12 * 5),
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    serde_json::to_string(&history).unwrap()
+}
+
+/// the mean time of parsing `payload` [`RUNS`] times with `parse`, the input is copied in every
+/// run since simd-json parses in place
+fn measure<T>(payload: &str, parse: impl Fn(&mut [u8]) -> anyhow::Result<T>) -> Duration {
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..RUNS {
+        let mut bytes = payload.as_bytes().to_vec();
+        let start = Instant::now();
+        std::hint::black_box(parse(&mut bytes).unwrap());
+        elapsed += start.elapsed();
+    }
+    elapsed / RUNS
+}
+
+fn compare<T: DeserializeOwned>(name: &str, payload: &str) {
+    let serde_json = measure(payload, |bytes| Ok(serde_json::from_slice::<T>(bytes)?));
+    let backend = measure(payload, decode::from_slice::<T>);
+    println!(
+        "{name:<18} {:>6.1} MiB  serde_json {:>7.2} ms  {} {:>7.2} ms  ({:.2}x)",
+        payload.len() as f64 / (1 << 20) as f64,
+        serde_json.as_secs_f64() * 1000.0,
+        decode::BACKEND,
+        backend.as_secs_f64() * 1000.0,
+        serde_json.as_secs_f64() / backend.as_secs_f64()
+    );
+}
+
+fn main() {
+    let (states, history) = (states(5000), history(50));
+    compare::<Vec<StatesResponse>>("/api/states", &states);
+    compare::<Value>("/api/states Value", &states);
+    compare::<Vec<Vec<HistoryResponse>>>("history", &history);
+    compare::<Value>("history Value", &history);
+}
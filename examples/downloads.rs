@@ -0,0 +1,144 @@
+//! measures the peak heap usage of downloading a large camera snapshot from a local stub server,
+//! buffered with `camera_snapshot` and chunk by chunk with `Download::into_stream`
+//!
+//! ```text
+//! cargo run --release --example downloads
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use futures_util::StreamExt;
+use homeassistant_rs::camera::SnapshotOptions;
+use homeassistant_rs::client::Client;
+
+/// tracks the bytes currently allocated and their maximum
+struct Peak;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Peak {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(current, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() {
+            let current = CURRENT.fetch_add(new_size - layout.size(), Ordering::Relaxed) + new_size
+                - layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        } else {
+            CURRENT.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+        }
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Peak = Peak;
+
+/// the size of a 4K JPEG at high quality
+const SIZE: usize = 16 << 20;
+const RUNS: u32 = 10;
+
+/// answers every request with [`SIZE`] bytes of a single static image
+fn stub_server() -> anyhow::Result<String> {
+    let image: &'static [u8] = vec![0xff; SIZE].leak();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut stream = stream;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: {SIZE}\r\n\r\n"
+                );
+                loop {
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+                        line.clear();
+                    }
+                    if line.is_empty() {
+                        return;
+                    }
+                    if stream.write_all(head.as_bytes()).is_err()
+                        || stream.write_all(image).is_err()
+                    {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    Ok(url)
+}
+
+/// runs `download` [`RUNS`] times and prints the heap usage above the baseline and the throughput
+async fn measure<F: Future<Output = anyhow::Result<usize>>>(
+    name: &str,
+    mut download: impl FnMut() -> F,
+) {
+    // the first download opens the connection
+    download().await.unwrap();
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        assert_eq!(download().await.unwrap(), SIZE);
+    }
+    let elapsed = start.elapsed();
+    let peak = PEAK.load(Ordering::Relaxed) - baseline;
+    println!(
+        "{name:<24} {:>8.1} KiB peak {:>8.0} MiB/s",
+        peak as f64 / 1024.0,
+        (SIZE as u32 * RUNS) as f64 / (1 << 20) as f64 / elapsed.as_secs_f64()
+    );
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let client = Client::new(stub_server()?, "token")?;
+
+    measure("camera_snapshot", || async {
+        let snapshot = client
+            .camera_snapshot("camera.door", SnapshotOptions::new())
+            .await?;
+        Ok(snapshot.data.len())
+    })
+    .await;
+
+    measure("into_stream", || async {
+        let mut chunks = client
+            .camera_download("camera.door", SnapshotOptions::new())
+            .await?
+            .into_stream();
+        let mut size = 0;
+        while let Some(chunk) = chunks.next().await {
+            size += chunk?.len();
+        }
+        Ok(size)
+    })
+    .await;
+
+    measure("write_to", || async {
+        let written = client
+            .camera_download("camera.door", SnapshotOptions::new())
+            .await?
+            .write_to(tokio::io::sink())
+            .await?;
+        Ok(written as usize)
+    })
+    .await;
+    Ok(())
+}
@@ -0,0 +1,26 @@
+//! prints every `state_changed` event until interrupted
+//!
+//! ```text
+//! cargo run --example events
+//! ```
+
+use homeassistant_rs::futures_util::StreamExt;
+use homeassistant_rs::hass;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let ws = hass().websocket(None, None).await?;
+    let mut events = ws.subscribe_events(Some("state_changed")).await?;
+    while let Some(event) = events.next().await {
+        let event = event?;
+        println!(
+            "{} {}: {}",
+            event.time_fired.unwrap_or_default(),
+            event.data["entity_id"].as_str().unwrap_or_default(),
+            event.data["new_state"]["state"]
+                .as_str()
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! lists the available services, or calls `<domain>.<service>` on the entity passed as arguments
+//!
+//! ```text
+//! cargo run --example services -- light.toggle light.kitchen
+//! ```
+
+use homeassistant_rs::prelude::*;
+use homeassistant_rs::services::ServiceCallError;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (Some(service), Some(entity_id)) = (args.next(), args.next()) else {
+        for domain in hass().services(None, None).await? {
+            for (name, service) in &domain.services {
+                println!(
+                    "{}.{name}: {} {:?}",
+                    domain.domain,
+                    service.name,
+                    service.required_fields()
+                );
+            }
+        }
+        return Ok(());
+    };
+    let Some((domain, service)) = service.split_once('.') else {
+        anyhow::bail!("expected <domain>.<service>, got {service}");
+    };
+
+    let ws = hass().websocket(None, None).await?;
+    let result = ws
+        .call_service(
+            domain,
+            service,
+            json!({}),
+            Some(json!({"entity_id": entity_id})),
+            false,
+        )
+        .await;
+    match result {
+        Ok(_) => println!("called {domain}.{service}"),
+        Err(e) => match e.downcast_ref::<ServiceCallError>() {
+            Some(ServiceCallError::NotFound { .. }) => {
+                println!("{domain}.{service} does not exist")
+            }
+            _ => return Err(e),
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! prints the state of all entities or of the entity passed as first argument
+//!
+//! ```text
+//! cargo run --example states -- light.kitchen
+//! ```
+
+use homeassistant_rs::hass;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let entity_id = std::env::args().nth(1);
+    let states = hass().states(None, None, entity_id.as_deref()).await?;
+    for state in &states {
+        println!(
+            "{}: {}",
+            state.entity_id.as_deref().unwrap_or_default(),
+            state.state
+        );
+    }
+
+    if let Some(entity_id) = entity_id {
+        let history = hass()
+            .history(None, None, Some(&entity_id), true, true, true)
+            .await?;
+        println!("{} changes in the last day", history.len());
+        let logbook = hass()
+            .logbook(None, None, Some(&entity_id), None, None)
+            .await?;
+        println!("{} logbook entries", logbook.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,242 @@
+//! Helpers for turning fetched states and history into data that is ready for analysis
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::de::DeserializeOwned;
+
+use crate::structs::{self, EntityId};
+
+/// deserializes the attributes of each state into `T`
+///
+/// returns `(entity_id, state, attributes)` rows, states without an entity id or whose attributes
+/// do not match `T` are skipped
+/// ```
+/// use homeassistant_rs::analysis::project;
+/// # let states: Vec<homeassistant_rs::structs::StatesResponse> = vec![];
+///
+/// #[derive(serde::Deserialize)]
+/// struct Light {
+///     brightness: Option<u8>,
+/// }
+///
+/// for (entity_id, state, light) in project::<Light>(&states) {
+///     println!("{entity_id} is {state} ({:?})", light.brightness);
+/// }
+/// ```
+pub fn project<T: DeserializeOwned>(
+    states: &[structs::StatesResponse],
+) -> Vec<(EntityId, String, T)> {
+    states
+        .iter()
+        .filter_map(|state| {
+            let entity_id = EntityId::from(state.entity_id.clone()?);
+            let attributes = attributes_value(state.attributes.as_ref());
+            let projected = serde_json::from_value::<T>(attributes).ok()?;
+            Some((entity_id, state.state.clone(), projected))
+        })
+        .collect()
+}
+
+/// serializes attributes into a flat JSON object, leaving out unset fields
+fn attributes_value(attributes: Option<&structs::Attributes>) -> serde_json::Value {
+    let mut map = serde_json::Map::new();
+    if let Some(attributes) = attributes
+        && let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(attributes)
+    {
+        map.extend(fields.into_iter().filter(|(_, v)| !v.is_null()));
+    }
+    serde_json::Value::Object(map)
+}
+
+/// a numeric value at a point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub time: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// unit of time a rate is expressed in, e.g. [`TimeUnit::Hours`] for kWh → kW
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeUnit {
+    Seconds,
+    Minutes,
+    #[default]
+    Hours,
+    Days,
+}
+
+impl TimeUnit {
+    pub fn seconds(self) -> f64 {
+        match self {
+            TimeUnit::Seconds => 1.0,
+            TimeUnit::Minutes => 60.0,
+            TimeUnit::Hours => 3_600.0,
+            TimeUnit::Days => 86_400.0,
+        }
+    }
+
+    fn between(self, from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+        (to - from).as_seconds_f64() / self.seconds()
+    }
+}
+
+/// parses the numeric states of a history, states like `unavailable` or `unknown` are skipped
+pub fn numeric_series(history: &[structs::HistoryResponse]) -> Vec<Sample> {
+    let mut samples: Vec<Sample> = history
+        .iter()
+        .filter_map(|entry| {
+            Some(Sample {
+                time: DateTime::parse_from_rfc3339(&entry.last_changed)
+                    .ok()?
+                    .with_timezone(&Utc),
+                value: entry.state.parse::<f64>().ok().filter(|v| v.is_finite())?,
+            })
+        })
+        .collect();
+    samples.sort_by_key(|sample| sample.time);
+    samples
+}
+
+/// difference between consecutive samples, stamped with the time of the later sample
+pub fn delta(samples: &[Sample]) -> Vec<Sample> {
+    samples
+        .windows(2)
+        .map(|pair| Sample {
+            time: pair[1].time,
+            value: pair[1].value - pair[0].value,
+        })
+        .collect()
+}
+
+/// rate of change between consecutive samples per `unit`, multiplied by `scale`
+///
+/// e.g. a cumulative energy counter in kWh with [`TimeUnit::Hours`] and a scale of `1000.0`
+/// results in power in W. Samples with identical timestamps are skipped, counter resets show up as
+/// negative values.
+pub fn derivative(samples: &[Sample], unit: TimeUnit, scale: f64) -> Vec<Sample> {
+    samples
+        .windows(2)
+        .filter_map(|pair| {
+            let elapsed = unit.between(pair[0].time, pair[1].time);
+            (elapsed > 0.0).then(|| Sample {
+                time: pair[1].time,
+                value: (pair[1].value - pair[0].value) / elapsed * scale,
+            })
+        })
+        .collect()
+}
+
+/// Riemann sum method, matching the `method` option of Homeassistant's integration sensor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegrationMethod {
+    #[default]
+    Trapezoidal,
+    /// uses the value at the start of each interval
+    Left,
+    /// uses the value at the end of each interval
+    Right,
+}
+
+/// integrates samples over time per `unit`, multiplied by `scale`
+///
+/// returns the cumulative sum at each sample, starting at `0.0`. e.g. power in W with
+/// [`TimeUnit::Hours`] and a scale of `0.001` results in energy in kWh.
+pub fn integrate(
+    samples: &[Sample],
+    unit: TimeUnit,
+    method: IntegrationMethod,
+    scale: f64,
+) -> Vec<Sample> {
+    let mut total = 0.0;
+    let mut integrated = Vec::with_capacity(samples.len());
+
+    if let Some(first) = samples.first() {
+        integrated.push(Sample {
+            time: first.time,
+            value: total,
+        });
+    }
+
+    for pair in samples.windows(2) {
+        let elapsed = unit.between(pair[0].time, pair[1].time);
+        let value = match method {
+            IntegrationMethod::Trapezoidal => (pair[0].value + pair[1].value) / 2.0,
+            IntegrationMethod::Left => pair[0].value,
+            IntegrationMethod::Right => pair[1].value,
+        };
+        total += value * elapsed * scale;
+        integrated.push(Sample {
+            time: pair[1].time,
+            value: total,
+        });
+    }
+
+    integrated
+}
+
+/// the value at every point of the grid from `start` to `end` (inclusive) every `step`, points
+/// before the first sample are [`None`]
+///
+/// each point takes the last sample at or before it, since states hold until they change
+pub(crate) fn grid(
+    samples: &[Sample],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    step: TimeDelta,
+) -> Vec<Option<f64>> {
+    let mut values = Vec::new();
+    if step <= TimeDelta::zero() {
+        return values;
+    }
+    let (mut time, mut next, mut current) = (start, 0, None);
+    while time <= end {
+        while let Some(sample) = samples.get(next).filter(|sample| sample.time <= time) {
+            current = Some(sample.value);
+            next += 1;
+        }
+        values.push(current);
+        time += step;
+    }
+    values
+}
+
+/// resamples `samples` (sorted by time) onto a grid from `start` to `end` every `step`
+///
+/// each point takes the last sample at or before it, since states hold until they change. Points
+/// before the first sample are left out
+pub fn resample(
+    samples: &[Sample],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    step: TimeDelta,
+) -> Vec<Sample> {
+    grid(samples, start, end, step)
+        .into_iter()
+        .zip(0..)
+        .filter_map(|(value, i)| {
+            Some(Sample {
+                time: start + step * i,
+                value: value?,
+            })
+        })
+        .collect()
+}
+
+/// Pearson correlation coefficient of `pairs`, from `-1.0` to `1.0`
+///
+/// [`None`] for fewer than two pairs or if either side is constant
+pub fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_a = pairs.iter().map(|(a, _)| a).sum::<f64>() / n;
+    let mean_b = pairs.iter().map(|(_, b)| b).sum::<f64>() / n;
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (a, b) in pairs {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+    let denominator = (variance_a * variance_b).sqrt();
+    (denominator > 0.0).then(|| (covariance / denominator).clamp(-1.0, 1.0))
+}
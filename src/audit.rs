@@ -0,0 +1,206 @@
+//! Audit trail of outgoing mutations, requires the `audit` feature
+//!
+//! Once enabled, every state post, service call and fired event is recorded with its time, target
+//! and a SHA-256 digest of its payload, over REST and WebSocket alike. Payloads themselves are not
+//! kept, so the trail can be exported without leaking their content.
+//! ```
+//! use homeassistant_rs::audit;
+//!
+//! audit::enable(10_000);
+//! // ... run the automation
+//! for entry in audit::entries() {
+//!     println!("{} {:?} {} {}", entry.at, entry.kind, entry.target, entry.success);
+//! }
+//! audit::export_jsonl(std::io::stdout()).unwrap();
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{RwLock, RwLockWriteGuard};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+lazy_static::lazy_static! {
+    static ref LOG: RwLock<Option<Log>> = RwLock::new(None);
+}
+
+struct Log {
+    capacity: usize,
+    entries: VecDeque<AuditEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MutationKind {
+    State,
+    ServiceCall,
+    Event,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    Rest,
+    WebSocket,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub kind: MutationKind,
+    /// the entity id, `<domain>.<service>` or event type
+    pub target: String,
+    pub transport: Transport,
+    /// hex encoded SHA-256 of the payload, see [`digest`]
+    pub digest: String,
+    /// payload size in bytes
+    pub size: usize,
+    /// whether Homeassistant accepted the call
+    pub success: bool,
+}
+
+fn write() -> RwLockWriteGuard<'static, Option<Log>> {
+    LOG.write().unwrap_or_else(|p| p.into_inner())
+}
+
+/// starts recording, keeping the last `capacity` entries. Entries recorded so far are kept
+pub fn enable(capacity: usize) {
+    let mut log = write();
+    let entries = log.take().map(|log| log.entries).unwrap_or_default();
+    let log = log.insert(Log { capacity, entries });
+    while log.entries.len() > capacity {
+        log.entries.pop_front();
+    }
+}
+
+/// stops recording and drops all entries
+pub fn disable() {
+    write().take();
+}
+
+pub fn is_enabled() -> bool {
+    LOG.read().unwrap_or_else(|p| p.into_inner()).is_some()
+}
+
+/// all recorded entries, oldest first
+pub fn entries() -> Vec<AuditEntry> {
+    entries_since(DateTime::<Utc>::MIN_UTC)
+}
+
+/// entries recorded at or after `since`, oldest first
+pub fn entries_since(since: DateTime<Utc>) -> Vec<AuditEntry> {
+    LOG.read()
+        .unwrap_or_else(|p| p.into_inner())
+        .as_ref()
+        .map(|log| {
+            log.entries
+                .iter()
+                .filter(|entry| entry.at >= since)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// drops all entries, recording continues
+pub fn clear() {
+    if let Some(log) = write().as_mut() {
+        log.entries.clear();
+    }
+}
+
+/// writes all entries as JSON lines, returns the number of entries written
+pub fn export_jsonl(mut writer: impl std::io::Write) -> anyhow::Result<usize> {
+    let entries = entries();
+    for entry in &entries {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(entries.len())
+}
+
+/// hex encoded SHA-256 of `payload`, to compare recorded entries with known payloads
+pub fn digest(payload: &[u8]) -> String {
+    Sha256::digest(payload)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+pub(crate) fn record(
+    kind: MutationKind,
+    target: String,
+    transport: Transport,
+    payload: &[u8],
+    success: bool,
+) {
+    let mut log = write();
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+    if log.capacity == 0 {
+        return;
+    }
+    if log.entries.len() == log.capacity {
+        log.entries.pop_front();
+    }
+    log.entries.push_back(AuditEntry {
+        at: Utc::now(),
+        kind,
+        target,
+        transport,
+        digest: digest(payload),
+        size: payload.len(),
+        success,
+    });
+}
+
+/// the mutation a REST request performs, [`None`] for reading requests
+pub(crate) fn classify_rest(
+    method: &reqwest::Method,
+    path: &str,
+) -> Option<(MutationKind, String)> {
+    if method != reqwest::Method::POST {
+        return None;
+    }
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    let decode = |segment: &str| {
+        percent_encoding::percent_decode_str(segment)
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    let segments: Vec<_> = path.trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["api", "states", entity_id] => Some((MutationKind::State, decode(entity_id))),
+        ["api", "events", event_type] => Some((MutationKind::Event, decode(event_type))),
+        ["api", "services", domain, service] => Some((
+            MutationKind::ServiceCall,
+            format!("{}.{}", decode(domain), decode(service)),
+        )),
+        _ => None,
+    }
+}
+
+/// the mutation a WebSocket command performs, [`None`] for all other commands
+pub(crate) fn classify_command(payload: &serde_json::Value) -> Option<(MutationKind, String)> {
+    match payload["type"].as_str()? {
+        "call_service" => Some((
+            MutationKind::ServiceCall,
+            format!(
+                "{}.{}",
+                payload["domain"].as_str().unwrap_or_default(),
+                payload["service"].as_str().unwrap_or_default()
+            ),
+        )),
+        "fire_event" => Some((
+            MutationKind::Event,
+            payload["event_type"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned(),
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,159 @@
+//! Backfilling state changes missed while reconnecting
+//!
+//! A subscription with reconnecting enabled (see
+//! [`HomeAssistantWs::set_reconnect`](crate::ws::HomeAssistantWs::set_reconnect)) loses the events
+//! of the [`MissedWindow`]. [`WithReconnects::backfill`] queries the history of the watched entities
+//! for that window and yields the missed state changes, ordered by time, right after the
+//! [`Reconnected`](SubscriptionEvent::Reconnected) marker and before any event of the new
+//! connection:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::prelude::*;
+//! use homeassistant_rs::ws::ReconnectOptions;
+//!
+//! let ws = hass().websocket(None, None).await.unwrap();
+//! ws.set_reconnect(Some(ReconnectOptions::default()));
+//! let mut events = ws
+//!     .subscribe_events(Some("state_changed"))
+//!     .await
+//!     .unwrap()
+//!     .with_reconnects()
+//!     .backfill(["light.kitchen", "sensor.power"]);
+//!
+//! while let Some(event) = events.next().await {
+//!     println!("{:?}", event.unwrap());
+//! }
+//! # });
+//! ```
+//!
+//! Delivery is at-least-once: a change close to the edges of the window may be yielded twice.
+//! Backfilled events are `state_changed` events without a context, their `old_state` is the
+//! previous known state (only the state and attributes, the history has no context either).
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+use serde_json::{Value, json};
+
+use crate::structs::Event;
+use crate::ws::{HomeAssistantWs, MissedWindow, SubscriptionEvent, WithReconnects};
+
+impl WithReconnects<Event> {
+    /// yields the state changes of `entity_ids` missed while reconnecting, see [`backfill`](self)
+    pub fn backfill<I, S>(
+        self,
+        entity_ids: I,
+    ) -> BoxStream<'static, anyhow::Result<SubscriptionEvent<Event>>>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let ws = self.connection();
+        let entity_ids: Vec<String> = entity_ids.into_iter().map(Into::into).collect();
+        let state = (self, VecDeque::new());
+
+        stream::unfold(state, move |(mut events, mut queue)| {
+            let ws = ws.clone();
+            let entity_ids = entity_ids.clone();
+            async move {
+                if let Some(next) = queue.pop_front() {
+                    return Some((next, (events, queue)));
+                }
+                let next = events.next().await?;
+                if let Ok(SubscriptionEvent::Reconnected { missed_window }) = &next {
+                    match missed_changes(&ws, &entity_ids, *missed_window).await {
+                        Ok(changes) => queue.extend(
+                            changes
+                                .into_iter()
+                                .map(|event| Ok(SubscriptionEvent::Event(event))),
+                        ),
+                        Err(e) => queue.push_back(Err(e)),
+                    }
+                }
+                Some((next, (events, queue)))
+            }
+        })
+        .boxed()
+    }
+}
+
+/// queries `history/history_during_period` and turns the changes within `window` into
+/// `state_changed` events, ordered by time
+async fn missed_changes(
+    ws: &HomeAssistantWs,
+    entity_ids: &[String],
+    window: MissedWindow,
+) -> anyhow::Result<Vec<Event>> {
+    if entity_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let history = ws
+        .command(json!({
+            "type": "history/history_during_period",
+            "start_time": window.from.to_rfc3339(),
+            "end_time": window.to.to_rfc3339(),
+            "entity_ids": entity_ids,
+            "include_start_time_state": true,
+            "significant_changes_only": false,
+            "minimal_response": false,
+            "no_attributes": false,
+        }))
+        .await?;
+    Ok(history_events(&history, window))
+}
+
+fn timestamp(seconds: &Value) -> Option<DateTime<Utc>> {
+    let seconds = seconds.as_f64()?;
+    DateTime::from_timestamp_micros((seconds * 1_000_000.0).round() as i64)
+}
+
+/// converts a compressed history (`{"<entity_id>": [{"s", "a", "lu", "lc"}, ...]}`) into events
+pub(crate) fn history_events(history: &Value, window: MissedWindow) -> Vec<Event> {
+    let Some(entities) = history.as_object() else {
+        return vec![];
+    };
+
+    let mut events = vec![];
+
+    for (entity_id, states) in entities {
+        let mut previous: Option<Value> = None;
+        for state in states.as_array().into_iter().flatten() {
+            let Some(last_updated) = timestamp(&state["lu"]) else {
+                continue;
+            };
+            let last_changed = timestamp(&state["lc"]).unwrap_or(last_updated);
+            let current = json!({
+                "entity_id": entity_id,
+                "state": state["s"],
+                "attributes": state["a"],
+                "last_changed": last_changed.to_rfc3339(),
+                "last_updated": last_updated.to_rfc3339(),
+            });
+
+            // the state at the start of the window only serves as `old_state`
+            if last_updated > window.from {
+                events.push((
+                    last_updated,
+                    Event {
+                        event_type: "state_changed".to_owned(),
+                        data: json!({
+                            "entity_id": entity_id,
+                            "old_state": previous,
+                            "new_state": current,
+                        }),
+                        time_fired: Some(last_updated.to_rfc3339()),
+                        ..Default::default()
+                    },
+                ));
+            }
+            previous = Some(current);
+        }
+    }
+
+    events.sort_by_key(|(time, _)| *time);
+    events.into_iter().map(|(_, event)| event).collect()
+}
@@ -0,0 +1,129 @@
+//! Battery levels across domains
+//!
+//! Integrations report batteries differently: as `battery_level`/`battery` attribute of the entity
+//! itself, or as a separate sensor with the `battery` device class on the same device. The helpers
+//! here check both, using the entity registry to find the sensors belonging to a device, and fall
+//! back to the `sensor.<object_id>_battery` naming convention for entities without a device.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::registry::EntityRegistryEntry;
+use crate::structs::{EntityId, StatesResponse};
+use crate::ws::HomeAssistantWs;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryLevel {
+    pub entity_id: EntityId,
+    /// in percent
+    pub level: f64,
+    /// entity the level was read from, either `entity_id` or a battery sensor
+    pub source: EntityId,
+}
+
+fn is_battery_sensor(state: &StatesResponse) -> bool {
+    state
+        .attributes
+        .as_ref()
+        .and_then(|attributes| attributes.device_class())
+        == Some("battery")
+}
+
+/// level of a battery sensor's state, or of the battery attribute of any other entity
+fn own_level(state: &StatesResponse) -> Option<f64> {
+    if is_battery_sensor(state) {
+        state.state.parse().ok()
+    } else {
+        state.attributes.as_ref()?.battery_level()
+    }
+}
+
+struct Index<'a> {
+    states: HashMap<&'a str, &'a StatesResponse>,
+    devices: HashMap<&'a str, &'a str>,
+    device_batteries: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Index<'a> {
+    fn new(states: &'a [StatesResponse], registry: &'a [EntityRegistryEntry]) -> Self {
+        let states: HashMap<&str, &StatesResponse> = states
+            .iter()
+            .filter_map(|state| Some((state.entity_id.as_deref()?, state)))
+            .collect();
+        let devices: HashMap<&str, &str> = registry
+            .iter()
+            .filter_map(|entry| Some((entry.entity_id.as_str(), entry.device_id.as_deref()?)))
+            .collect();
+        let device_batteries = devices
+            .iter()
+            .filter(|(entity_id, _)| states.get(*entity_id).is_some_and(|s| is_battery_sensor(s)))
+            .map(|(entity_id, device_id)| (*device_id, *entity_id))
+            .collect();
+
+        Self {
+            states,
+            devices,
+            device_batteries,
+        }
+    }
+
+    fn level(&self, entity_id: &str) -> Option<BatteryLevel> {
+        let state = self.states.get(entity_id)?;
+        let found = |source: &str, level: f64| BatteryLevel {
+            entity_id: EntityId::from(entity_id),
+            level,
+            source: EntityId::from(source),
+        };
+
+        if let Some(level) = own_level(state) {
+            return Some(found(entity_id, level));
+        }
+        let source = match self.devices.get(entity_id) {
+            Some(device_id) => self.device_batteries.get(device_id)?.to_string(),
+            None => format!("sensor.{}_battery", EntityId::from(entity_id).object_id()),
+        };
+        let level = own_level(self.states.get(source.as_str())?)?;
+        Some(found(&source, level))
+    }
+}
+
+/// returns the battery level of `entity_id`
+pub fn battery_level(
+    entity_id: &str,
+    states: &[StatesResponse],
+    registry: &[EntityRegistryEntry],
+) -> Option<BatteryLevel> {
+    Index::new(states, registry).level(entity_id)
+}
+
+/// lists all batteries at or below `threshold` percent, lowest first
+///
+/// every battery is reported once, entities sharing a battery sensor are not repeated
+pub fn low_batteries(
+    states: &[StatesResponse],
+    registry: &[EntityRegistryEntry],
+    threshold: f64,
+) -> Vec<BatteryLevel> {
+    let index = Index::new(states, registry);
+    let mut seen = HashSet::new();
+    let mut low: Vec<BatteryLevel> = states
+        .iter()
+        .filter_map(|state| index.level(state.entity_id.as_deref()?))
+        .filter(|battery| battery.level <= threshold && seen.insert(battery.source.clone()))
+        .collect();
+    low.sort_by(|a, b| a.level.total_cmp(&b.level));
+    low
+}
+
+impl HomeAssistantWs {
+    /// returns the battery level of `entity_id`, see [`battery_level`]
+    pub async fn battery_level(&self, entity_id: &str) -> anyhow::Result<Option<BatteryLevel>> {
+        let (states, registry) = tokio::try_join!(self.states(), self.entity_registry())?;
+        Ok(battery_level(entity_id, &states, &registry))
+    }
+
+    /// lists all batteries at or below `threshold` percent, see [`low_batteries`]
+    pub async fn low_batteries(&self, threshold: f64) -> anyhow::Result<Vec<BatteryLevel>> {
+        let (states, registry) = tokio::try_join!(self.states(), self.entity_registry())?;
+        Ok(low_batteries(&states, &registry, threshold))
+    }
+}
@@ -0,0 +1,296 @@
+//! Camera snapshots and streaming
+//!
+//! Live video is negotiated over the WebSocket connection, for snapshots see
+//! [`HomeAssistant::camera_snapshot`] and for MJPEG
+//! [`HomeAssistant::camera_stream`](crate::HomeAssistant::camera_stream).
+//!
+//! Snapshots can be scaled by Homeassistant, e.g. for thumbnails:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::camera::SnapshotOptions;
+//! use homeassistant_rs::hass;
+//!
+//! let snapshot = hass()
+//!     .camera_snapshot(None, None, "camera.door", SnapshotOptions::new().width(320))
+//!     .await
+//!     .unwrap();
+//! println!("{:?}, {} bytes", snapshot.content_type, snapshot.data.len());
+//! # });
+//! ```
+//!
+//! A WebRTC session is set up by sending the SDP offer with
+//! [`HomeAssistantWs::camera_webrtc_offer`] and reading the [`WebRtcMessage`]s of the returned
+//! subscription: first the session id, then the answer and any number of remote ICE candidates.
+//! Local candidates are sent with [`HomeAssistantWs::camera_webrtc_candidate`]. Dropping the
+//! subscription closes the session.
+//!
+//! For external players [`HomeAssistantWs::camera_hls_url`] returns an HLS playlist URL, which
+//! already contains an access token.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+
+use crate::download::Download;
+use crate::structs::Snapshot;
+use crate::timestamp::Timestamp;
+use crate::ws::{HomeAssistantWs, Subscription};
+use crate::{Api, HomeAssistant, credentials, request, urls};
+
+/// query parameters of `/api/camera_proxy/<camera_entity_id>`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotOptions {
+    width: Option<u32>,
+    height: Option<u32>,
+    time: Option<Timestamp>,
+}
+
+impl SnapshotOptions {
+    /// a full-resolution snapshot
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// scales the image to `width`, keeping the aspect ratio unless a height is set as well
+    ///
+    /// scaling is done by the camera integration, integrations without support return the
+    /// full-resolution image
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// scales the image to `height`, see [`width`](Self::width)
+    pub fn height(mut self, height: u32) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// the `time` parameter, defaults to now. It only busts caches, Homeassistant always returns
+    /// the current image
+    pub fn time(mut self, time: impl Into<Timestamp>) -> Self {
+        self.time = Some(time.into());
+        self
+    }
+
+    /// the query string, including the leading `?`
+    pub fn query(&self) -> anyhow::Result<String> {
+        let time = self.time.clone().unwrap_or_else(Timestamp::now);
+        Ok(urls::Query::new()
+            .param("time", time.unix_seconds()?)
+            .opt("width", self.width)
+            .opt("height", self.height)
+            .to_string())
+    }
+}
+
+impl HomeAssistant {
+    /// queries `/api/camera_proxy/<camera_entity_id>` with [`SnapshotOptions`], returns the image
+    /// and its content type
+    ///
+    /// the image is buffered as a whole, see [`camera_download`](Self::camera_download) for
+    /// reading it chunk by chunk
+    pub async fn camera_snapshot(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: &str,
+        options: SnapshotOptions,
+    ) -> anyhow::Result<Snapshot> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .camera_snapshot(ha_entity_id, options)
+            .await
+    }
+
+    /// queries `/api/camera_proxy/<camera_entity_id>` with [`SnapshotOptions`] and returns the
+    /// image as [`Download`]
+    pub async fn camera_download(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: &str,
+        options: SnapshotOptions,
+    ) -> anyhow::Result<Download> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .camera_download(ha_entity_id, options)
+            .await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn camera_snapshot(
+        &self,
+        ha_entity_id: &str,
+        options: SnapshotOptions,
+    ) -> anyhow::Result<Snapshot> {
+        let download = self.camera_download(ha_entity_id, options).await?;
+
+        Ok(Snapshot {
+            content_type: download.content_type().map(str::to_owned),
+            data: download.bytes().await?,
+        })
+    }
+
+    pub(crate) async fn camera_download(
+        &self,
+        ha_entity_id: &str,
+        options: SnapshotOptions,
+    ) -> anyhow::Result<Download> {
+        let client = request(
+            self.url,
+            self.token,
+            &format!(
+                "/api/camera_proxy/{}{}",
+                urls::encode_segment(ha_entity_id),
+                options.query()?
+            ),
+        )
+        .await?;
+        Download::new(client)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct IceCandidate {
+    pub candidate: String,
+    #[serde(rename = "sdpMid", skip_serializing_if = "Option::is_none")]
+    pub sdp_mid: Option<String>,
+    #[serde(rename = "sdpMLineIndex", skip_serializing_if = "Option::is_none")]
+    pub sdp_m_line_index: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebRtcMessage {
+    /// id of the session, required for [`HomeAssistantWs::camera_webrtc_candidate`]
+    Session {
+        session_id: String,
+    },
+    /// SDP answer
+    Answer {
+        answer: String,
+    },
+    /// remote ICE candidate
+    Candidate {
+        candidate: IceCandidate,
+    },
+    Error {
+        code: String,
+        message: String,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct IceServer {
+    #[serde(deserialize_with = "one_or_many")]
+    pub urls: Vec<String>,
+    pub username: Option<String>,
+    pub credential: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtcConfiguration {
+    #[serde(rename = "iceServers", default)]
+    pub ice_servers: Vec<IceServer>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WebRtcClientConfig {
+    pub configuration: RtcConfiguration,
+    /// label of a data channel the offer has to contain, if any
+    #[serde(rename = "dataChannel")]
+    pub data_channel: Option<String>,
+    /// whether all local candidates should be gathered before sending the offer
+    #[serde(rename = "getCandidatesUpfront", default)]
+    pub get_candidates_upfront: bool,
+}
+
+/// accepts `"url"` as well as `["url", ...]`
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(url) => vec![url],
+        OneOrMany::Many(urls) => urls,
+    })
+}
+
+impl HomeAssistantWs {
+    /// `camera/webrtc/get_client_config`, returns the ICE servers to use for `entity_id`
+    pub async fn camera_webrtc_client_config(
+        &self,
+        entity_id: &str,
+    ) -> anyhow::Result<WebRtcClientConfig> {
+        self.command_as(json!({"type": "camera/webrtc/get_client_config", "entity_id": entity_id}))
+            .await
+    }
+
+    /// `camera/webrtc/offer`, sends an SDP offer and subscribes to the [`WebRtcMessage`]s of the
+    /// resulting session
+    pub async fn camera_webrtc_offer(
+        &self,
+        entity_id: &str,
+        offer: &str,
+    ) -> anyhow::Result<Subscription<WebRtcMessage>> {
+        self.subscribe(
+            json!({"type": "camera/webrtc/offer", "entity_id": entity_id, "offer": offer}),
+        )
+        .await
+    }
+
+    /// `camera/webrtc/candidate`, sends a local ICE candidate to an ongoing session
+    pub async fn camera_webrtc_candidate(
+        &self,
+        entity_id: &str,
+        session_id: &str,
+        candidate: &IceCandidate,
+    ) -> anyhow::Result<()> {
+        self.command(json!({
+            "type": "camera/webrtc/candidate",
+            "entity_id": entity_id,
+            "session_id": session_id,
+            "candidate": candidate,
+        }))
+        .await?;
+        Ok(())
+    }
+
+    /// `camera/web_rtc_offer`, the single request/response signaling used before Homeassistant
+    /// 2024.11, returns the SDP answer
+    pub async fn camera_web_rtc_offer(
+        &self,
+        entity_id: &str,
+        offer: &str,
+    ) -> anyhow::Result<String> {
+        let result = self
+            .command(
+                json!({"type": "camera/web_rtc_offer", "entity_id": entity_id, "offer": offer}),
+            )
+            .await?;
+        result["answer"]
+            .as_str()
+            .map(str::to_owned)
+            .ok_or(anyhow::Error::msg("web_rtc_offer returned no answer"))
+    }
+
+    /// `camera/stream`, returns the absolute URL of the HLS playlist of `entity_id`
+    ///
+    /// the URL carries its own access token and stays valid while the stream is being watched
+    pub async fn camera_hls_url(&self, entity_id: &str) -> anyhow::Result<String> {
+        let result = self
+            .command(json!({"type": "camera/stream", "entity_id": entity_id, "format": "hls"}))
+            .await?;
+        let path = result["url"]
+            .as_str()
+            .ok_or(anyhow::Error::msg("camera/stream returned no url"))?;
+        Ok(urls::join(self.url(), path)?.to_string())
+    }
+}
@@ -0,0 +1,303 @@
+//! Record/replay of HTTP interactions ("cassettes")
+//!
+//! A cassette is a JSON file containing request/response pairs. In [`Mode::Record`] every request
+//! is sent to the real Homeassistant instance and the response is appended to the cassette, in
+//! [`Mode::Replay`] requests are answered from the cassette and never leave the process.
+//!
+//! The token is never written to disk: the `Authorization` header is not recorded and every
+//! occurrence of the token (plus any extra secrets registered via [`Cassette::scrub`]) in paths and
+//! bodies is replaced with `<REDACTED>`.
+//!
+//! Cassettes can be installed in code:
+//! ```no_run
+//! use homeassistant_rs::cassette::{self, Cassette, Mode};
+//!
+//! cassette::install(Cassette::open("tests/cassettes/config.json", Mode::Replay).unwrap());
+//! ```
+//! [`Cassette::load`] reads cassettes from any [`Storage`] instead of the filesystem.
+//!
+//! Cassettes can also be installed through the environment, which is picked up on the first
+//! request:
+//! ```text
+//! HA_CASSETTE="tests/cassettes/config.json"
+//! HA_CASSETTE_MODE="record" # or "replay" (default)
+//! ```
+//! If that cassette can't be opened (e.g. it is missing in replay mode or isn't valid JSON), every
+//! request fails with the reason instead of reaching the real instance.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{FileStorage, Storage, StorageExt};
+
+const REDACTED: &str = "<REDACTED>";
+
+lazy_static::lazy_static! {
+    /// the installed cassette, or why the one set in `HA_CASSETTE` can't be used
+    static ref INSTALLED: RwLock<Result<Option<Arc<Cassette>>, String>> = RwLock::new(from_env());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// send requests to Homeassistant and append them to the cassette
+    Record,
+    /// answer requests from the cassette, fail if no matching interaction exists
+    Replay,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Interaction {
+    pub method: String,
+    /// path and query, without the base url
+    pub path: String,
+    pub request_body: Option<String>,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: String,
+    /// `body` is base64 encoded (used for non UTF-8 responses, e.g. camera images)
+    #[serde(default)]
+    pub base64: bool,
+}
+
+pub struct Cassette {
+    /// where the cassette is saved, and under which key
+    store: Option<(Arc<dyn Storage>, String)>,
+    mode: Mode,
+    secrets: Vec<String>,
+    interactions: Mutex<Vec<(Interaction, bool)>>,
+}
+
+impl Cassette {
+    /// opens (or, when recording, creates) the cassette at `path`
+    pub fn open(path: impl AsRef<Path>, mode: Mode) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let key = path
+            .file_name()
+            .ok_or(anyhow::Error::msg("cassette path has no file name"))?
+            .to_string_lossy();
+        let root = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        Self::load(Arc::new(FileStorage::new(root)), &key, mode)
+    }
+
+    /// loads (or, when recording, creates) the cassette stored under `key`
+    pub fn load(storage: Arc<dyn Storage>, key: &str, mode: Mode) -> anyhow::Result<Self> {
+        let interactions = match storage.get_json::<Vec<Interaction>>(key)? {
+            Some(interactions) => interactions,
+            None if mode == Mode::Record => vec![],
+            None => return Err(anyhow::Error::msg(format!("cassette {key:?} not found"))),
+        };
+
+        let mut cassette = Self::from_interactions(interactions, mode);
+        cassette.store = Some((storage, key.to_owned()));
+        Ok(cassette)
+    }
+
+    /// creates an in-memory cassette, nothing is written to disk
+    pub fn from_interactions(interactions: Vec<Interaction>, mode: Mode) -> Self {
+        Self {
+            store: None,
+            mode,
+            secrets: vec![],
+            interactions: Mutex::new(interactions.into_iter().map(|i| (i, false)).collect()),
+        }
+    }
+
+    /// registers an additional secret, which will be redacted from recorded interactions
+    pub fn scrub(mut self, secret: impl Into<String>) -> Self {
+        let secret = secret.into();
+        if !secret.is_empty() {
+            self.secrets.push(secret);
+        }
+        self
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// returns a copy of all interactions currently held by the cassette
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.lock().iter().map(|(i, _)| i.clone()).collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(Interaction, bool)>> {
+        self.interactions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn redact(&self, token: &str, str: &str) -> String {
+        let mut str = str.to_owned();
+        for secret in self.secrets.iter().map(String::as_str).chain([token]) {
+            if !secret.is_empty() {
+                str = str.replace(secret, REDACTED);
+            }
+        }
+        str
+    }
+
+    /// answers or records a request, depending on the [`Mode`]
+    pub(crate) async fn handle(
+        &self,
+        token: &str,
+        method: &str,
+        path: &str,
+        body: Option<String>,
+        request: reqwest::RequestBuilder,
+    ) -> anyhow::Result<reqwest::Response> {
+        let path = self.redact(token, path);
+        let body = body.map(|body| self.redact(token, &body));
+
+        match self.mode {
+            Mode::Replay => self.replay(method, &path, body.as_deref()),
+            Mode::Record => {
+                let response = request.send().await?;
+                let status = response.status().as_u16();
+                let content_type = response
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_owned);
+                let bytes = response.bytes().await?;
+
+                let (recorded, base64) = match std::str::from_utf8(&bytes) {
+                    Ok(text) => (self.redact(token, text), false),
+                    Err(_) => (
+                        base64::engine::general_purpose::STANDARD.encode(&bytes),
+                        true,
+                    ),
+                };
+                self.lock().push((
+                    Interaction {
+                        method: method.to_owned(),
+                        path,
+                        request_body: body,
+                        status,
+                        content_type: content_type.clone(),
+                        body: recorded,
+                        base64,
+                    },
+                    true,
+                ));
+                self.save()?;
+
+                build_response(status, content_type.as_deref(), bytes)
+            }
+        }
+    }
+
+    pub(crate) fn replay(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let mut interactions = self.lock();
+        let matches = |i: &Interaction| {
+            i.method.eq_ignore_ascii_case(method)
+                && i.path == path
+                && i.request_body.as_deref() == body
+        };
+
+        // prefer interactions which were not replayed yet, so a sequence of identical requests
+        // gets the recorded sequence of responses; once exhausted the last match is repeated
+        let index = interactions
+            .iter()
+            .position(|(i, used)| !used && matches(i))
+            .or_else(|| interactions.iter().rposition(|(i, _)| matches(i)))
+            .ok_or_else(|| {
+                anyhow::Error::msg(format!("no recorded interaction for {method} {path}"))
+            })?;
+
+        let (interaction, used) = &mut interactions[index];
+        *used = true;
+        let body = if interaction.base64 {
+            base64::engine::general_purpose::STANDARD
+                .decode(&interaction.body)?
+                .into()
+        } else {
+            bytes::Bytes::from(interaction.body.clone())
+        };
+
+        build_response(
+            interaction.status,
+            interaction.content_type.as_deref(),
+            body,
+        )
+    }
+
+    /// writes the cassette to its storage, this is done automatically after each recorded
+    /// interaction
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some((storage, key)) = &self.store {
+            storage.put_json(key, &self.interactions())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Cassette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cassette")
+            .field("key", &self.store.as_ref().map(|(_, key)| key))
+            .field("mode", &self.mode)
+            .field("secrets", &self.secrets.len())
+            .finish_non_exhaustive()
+    }
+}
+
+fn build_response(
+    status: u16,
+    content_type: Option<&str>,
+    body: bytes::Bytes,
+) -> anyhow::Result<reqwest::Response> {
+    let mut response = http::Response::builder().status(status);
+    if let Some(content_type) = content_type {
+        response = response.header(reqwest::header::CONTENT_TYPE, content_type);
+    }
+    Ok(response.body(body)?.into())
+}
+
+fn from_env() -> Result<Option<Arc<Cassette>>, String> {
+    if !crate::settings::env_lookup() {
+        return Ok(None);
+    }
+    let Ok(path) = dotenvy::var("HA_CASSETTE") else {
+        return Ok(None);
+    };
+    let mode = match dotenvy::var("HA_CASSETTE_MODE").as_deref() {
+        Ok("record") => Mode::Record,
+        _ => Mode::Replay,
+    };
+    match Cassette::open(&path, mode) {
+        Ok(cassette) => Ok(Some(Arc::new(cassette))),
+        Err(e) => Err(format!("HA_CASSETTE {path:?} can't be opened: {e}")),
+    }
+}
+
+/// installs a cassette, which will be used for all following requests
+pub fn install(cassette: Cassette) {
+    *INSTALLED.write().unwrap_or_else(|p| p.into_inner()) = Ok(Some(Arc::new(cassette)));
+}
+
+/// removes the installed cassette and returns it, this also clears the error of a cassette set in
+/// `HA_CASSETTE` which can't be opened
+pub fn eject() -> Option<Arc<Cassette>> {
+    let mut installed = INSTALLED.write().unwrap_or_else(|p| p.into_inner());
+    std::mem::replace(&mut *installed, Ok(None)).ok().flatten()
+}
+
+/// the installed cassette, an error if the one set in `HA_CASSETTE` can't be opened
+pub(crate) fn active() -> anyhow::Result<Option<Arc<Cassette>>> {
+    INSTALLED
+        .read()
+        .unwrap_or_else(|p| p.into_inner())
+        .clone()
+        .map_err(anyhow::Error::msg)
+}
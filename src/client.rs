@@ -0,0 +1,291 @@
+//! [`Client`], which stores the url and token instead of taking them with every call
+//!
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example() -> anyhow::Result<()> {
+//! use homeassistant_rs::client::Client;
+//!
+//! let client = Client::new("http://localhost:8123", "api_token_from_hass")?;
+//! println!("{}", client.config().await?.version);
+//!
+//! // missing values are read from `HA_URL` and `HA_TOKEN`
+//! let client = Client::builder().url("http://localhost:8123").build()?;
+//! client
+//!     .request()
+//!     .service("light", "turn_on", json!({"entity_id": "light.kitchen"}), false)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every method corresponds to the [`HomeAssistant`](crate::HomeAssistant) or
+//! [`HomeAssistantPost`](crate::HomeAssistantPost) method of the same name, [`hass()`](crate::hass)
+//! stays available for passing credentials per call.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::responses::ServiceResponse;
+use crate::scene::{Scene, SceneState};
+use crate::secret::SecretString;
+use crate::{
+    Api, ApiPost, camera, codec, correlation, credentials, customize, download, error_log, format,
+    history, mjpeg, structs, stt, timestamp, ws,
+};
+
+/// the url and token of an instance, cheap to clone
+#[derive(Debug, Clone)]
+pub struct Client {
+    url: String,
+    token: SecretString,
+    limits: ws::Limits,
+    /// see [`Client::shared_websocket`], shared by all clones
+    connection: Arc<tokio::sync::Mutex<Option<ws::HomeAssistantWs>>>,
+}
+
+/// builds a [`Client`], see [`Client::builder`]
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    url: Option<String>,
+    token: Option<SecretString>,
+    limits: ws::Limits,
+}
+
+impl ClientBuilder {
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn token(mut self, token: impl Into<SecretString>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// largest accepted WebSocket message in bytes, see [`Limits`](ws::Limits)
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.limits.max_message_size = bytes;
+        self
+    }
+
+    /// falls back to `HA_URL` and `HA_TOKEN` for missing values, unless in
+    /// [pure-parameter mode](crate::settings::set_pure_parameters)
+    pub fn build(self) -> anyhow::Result<Client> {
+        let token = self.token.map(|token| token.expose_secret().to_owned());
+        let (url, token) = credentials(self.url, token)?;
+        Ok(Client {
+            url: url.trim_end_matches('/').to_owned(),
+            token: token.into_owned().into(),
+            limits: self.limits,
+            connection: Arc::default(),
+        })
+    }
+}
+
+/// methods calling the method of the same name on `$target` with the stored credentials, which are
+/// borrowed instead of copied for every call
+macro_rules! delegate {
+    ($target:expr; $($(#[$meta:meta])* fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(
+            $(#[$meta])*
+            #[doc = concat!(
+                "see [`", stringify!($target), "::", stringify!($name), "`](crate::",
+                stringify!($target), "::", stringify!($name), ")"
+            )]
+            pub async fn $name(&self, $($arg: $ty),*) -> anyhow::Result<$ret> {
+                self.api().$name($($arg),*).await
+            }
+        )*
+    };
+}
+
+impl Client {
+    /// a client for the instance at `url`
+    pub fn new(url: impl Into<String>, token: impl Into<SecretString>) -> anyhow::Result<Self> {
+        Self::builder().url(url).token(token).build()
+    }
+
+    /// a client for `HA_URL` and `HA_TOKEN`
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// the `ha_url` and `ha_token` parameters of [`HomeAssistant`](crate::HomeAssistant) methods
+    pub fn credentials(&self) -> (Option<String>, Option<String>) {
+        (
+            Some(self.url.clone()),
+            Some(self.token.expose_secret().to_owned()),
+        )
+    }
+
+    fn api(&self) -> Api<'_> {
+        Api::new(&self.url, self.token.expose_secret())
+    }
+
+    /// methods posting to the instance, like
+    /// [`HomeAssistant::request`](crate::HomeAssistant::request)
+    pub fn request(&self) -> ClientPost<'_> {
+        ClientPost { client: self }
+    }
+
+    /// see [`HomeAssistant::websocket`](crate::HomeAssistant::websocket), with the limits of the
+    /// client
+    pub async fn websocket(&self) -> anyhow::Result<ws::HomeAssistantWs> {
+        self.websocket_with_codec(codec::Json).await
+    }
+
+    /// one connection shared by this client and its clones, connected on first use
+    ///
+    /// the connection reconnects with the default [`ReconnectOptions`](ws::ReconnectOptions) and
+    /// is replaced after a [`shutdown`](ws::HomeAssistantWs::shutdown). Unlike
+    /// [`websocket`](Self::websocket), tasks sharing a client thereby share a single connection
+    pub async fn shared_websocket(&self) -> anyhow::Result<ws::HomeAssistantWs> {
+        let mut connection = self.connection.lock().await;
+        if let Some(ws) = connection.as_ref()
+            && !ws.is_closed()
+        {
+            return Ok(ws.clone());
+        }
+        let ws = self.websocket().await?;
+        ws.set_reconnect(Some(ws::ReconnectOptions::default()));
+        *connection = Some(ws.clone());
+        Ok(ws)
+    }
+
+    /// see [`HomeAssistant::websocket_with_codec`](crate::HomeAssistant::websocket_with_codec),
+    /// with the limits of the client
+    pub async fn websocket_with_codec(
+        &self,
+        codec: impl codec::Codec,
+    ) -> anyhow::Result<ws::HomeAssistantWs> {
+        ws::HomeAssistantWs::connect(
+            &self.url,
+            self.token.expose_secret(),
+            Arc::new(codec),
+            self.limits,
+        )
+        .await
+    }
+
+    delegate! { HomeAssistant;
+        fn config() -> structs::ConfigResponse;
+        fn require_components(domains: &[&str]) -> ();
+        fn core_state() -> structs::CoreState;
+        fn wait_until_ready(timeout: std::time::Duration) -> structs::CoreState;
+        fn instance_info() -> structs::InstanceInfo;
+        fn events() -> Vec<structs::EventResponse>;
+        fn event_stream(
+            restrict: Option<&[&str]>
+        ) -> futures_util::stream::BoxStream<'static, anyhow::Result<structs::Event>>;
+        fn services() -> Vec<structs::ServicesResponse>;
+        fn history(
+            ha_entity_id: Option<&str>,
+            minimal_response: bool,
+            no_attributes: bool,
+            significant_changes_only: bool
+        ) -> Vec<structs::HistoryResponse>;
+        fn history_query(query: &history::HistoryQuery) -> Vec<Vec<structs::HistoryResponse>>;
+        fn correlate(query: &correlation::CorrelationQuery) -> Option<correlation::Correlation>;
+        fn logbook(
+            ha_entity_id: Option<&str>,
+            start: Option<chrono::DateTime<chrono::Utc>>,
+            end: Option<chrono::DateTime<chrono::Utc>>
+        ) -> Vec<structs::LogBook>;
+        fn states(ha_entity_id: Option<&str>) -> Vec<structs::StatesResponse>;
+        fn error_log() -> String;
+        fn error_log_entries() -> Vec<error_log::ErrorLogEntry>;
+        fn error_log_download() -> download::Download;
+        fn download_error_log(writer: impl tokio::io::AsyncWrite + Unpin) -> u64;
+        fn download_error_log_with_progress(
+            writer: impl tokio::io::AsyncWrite + Unpin,
+            progress: impl FnMut(structs::Progress)
+        ) -> u64;
+        #[cfg(feature = "gzip")]
+        fn download_error_log_gzip(writer: impl tokio::io::AsyncWrite + Unpin) -> u64;
+        fn camera_proxy(ha_entity_id: &str, time: impl Into<timestamp::Timestamp>) -> bytes::Bytes;
+        fn camera_snapshot(
+            ha_entity_id: &str,
+            options: camera::SnapshotOptions
+        ) -> structs::Snapshot;
+        fn camera_snapshot_to(
+            ha_entity_id: &str,
+            path: impl AsRef<std::path::Path>
+        ) -> structs::SnapshotInfo;
+        fn camera_snapshot_to_with_progress(
+            ha_entity_id: &str,
+            path: impl AsRef<std::path::Path>,
+            progress: impl FnMut(structs::Progress)
+        ) -> structs::SnapshotInfo;
+        fn camera_download(
+            ha_entity_id: &str,
+            options: camera::SnapshotOptions
+        ) -> download::Download;
+        fn backup_download(backup_id: &str, agent_id: &str) -> download::Download;
+        fn camera_stream(ha_entity_id: &str) -> mjpeg::MjpegStream;
+        fn calendars() -> Vec<structs::CalendarResponse>;
+        fn calendar_events(
+            ha_entity_id: &str,
+            start: impl Into<timestamp::Timestamp>,
+            end: impl Into<timestamp::Timestamp>
+        ) -> Vec<structs::CalendarEvent>;
+        fn formatter() -> format::Formatter;
+        fn scenes() -> Vec<SceneState>;
+        fn scene_config(id: &str) -> Scene;
+        fn customize_yaml(entity_id: &str) -> customize::CustomizeYaml;
+        fn stt_provider_info(provider: &str) -> stt::SttProviderInfo;
+        fn speech_to_text(
+            provider: &str,
+            metadata: stt::SpeechMetadata,
+            audio: impl Into<bytes::Bytes>
+        ) -> stt::Transcription;
+    }
+}
+
+/// the posting methods of a [`Client`], see [`Client::request`]
+#[derive(Debug, Clone, Copy)]
+pub struct ClientPost<'a> {
+    client: &'a Client,
+}
+
+impl ClientPost<'_> {
+    fn api(&self) -> ApiPost<'_> {
+        ApiPost::new(&self.client.url, self.client.token.expose_secret())
+    }
+
+    delegate! { HomeAssistantPost;
+        fn state(ha_entity_id: &str, request: structs::StatesRequest) -> structs::StatePostResult;
+        fn events(ha_event_type: &str, request: Value) -> structs::SimpleResponse;
+        fn service(
+            ha_domain: &str,
+            ha_service: &str,
+            request: Value,
+            return_response: bool
+        ) -> Value;
+        fn log_entry(
+            name: &str,
+            message: &str,
+            entity_id: Option<&str>,
+            domain: Option<&str>
+        ) -> ();
+        fn template(request: structs::TemplateRequest) -> String;
+        fn config_check() -> structs::ConfigCheckResponse;
+        fn intent(request: Value) -> String;
+        fn save_scene(scene: &Scene) -> ();
+        fn delete_scene(id: &str) -> ();
+        fn save_customize_yaml(entity_id: &str, attributes: &serde_json::Map<String, Value>) -> ();
+    }
+
+    /// see [`HomeAssistantPost::service_typed`](crate::HomeAssistantPost::service_typed)
+    pub async fn service_typed<R: ServiceResponse>(&self, request: Value) -> anyhow::Result<R> {
+        self.api().service_typed(request).await
+    }
+}
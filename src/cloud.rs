@@ -0,0 +1,81 @@
+//! Home Assistant Cloud (Nabu Casa) webhooks (WebSocket only)
+//!
+//! A cloudhook makes a webhook of the instance reachable from the internet, without exposing the
+//! instance itself. Requires an active Home Assistant Cloud subscription.
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! let cloudhook = ws.create_cloudhook("my_app_callback").await?;
+//! println!("register {} with the third-party service", cloudhook.cloudhook_url);
+//!
+//! ws.delete_cloudhook("my_app_callback").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::urls;
+use crate::ws::{CommandError, HomeAssistantWs};
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cloudhook {
+    #[serde(default)]
+    pub webhook_id: String,
+    pub cloudhook_id: String,
+    /// the externally reachable url, forwarded to `/api/webhook/<webhook_id>`
+    pub cloudhook_url: String,
+    /// created by an integration, can not be deleted by users
+    #[serde(default)]
+    pub managed: bool,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+/// the local url of the webhook `webhook_id` of the instance at `url`
+pub fn webhook_url(url: &str, webhook_id: &str) -> anyhow::Result<String> {
+    let path = format!("/api/webhook/{}", urls::encode_segment(webhook_id));
+    Ok(urls::join(url, &path)?.to_string())
+}
+
+/// fails with a readable error if the cloud integration is not loaded
+fn cloud_error(e: anyhow::Error) -> anyhow::Error {
+    if e.downcast_ref::<CommandError>()
+        .is_some_and(|e| e.code == "unknown_command")
+    {
+        e.context("Home Assistant Cloud is not set up")
+    } else {
+        e
+    }
+}
+
+impl HomeAssistantWs {
+    /// `cloud/cloudhook/create`, returns the [`Cloudhook`] for `webhook_id`
+    ///
+    /// the webhook itself is not registered, an automation or integration has to handle it
+    pub async fn create_cloudhook(&self, webhook_id: &str) -> anyhow::Result<Cloudhook> {
+        let mut cloudhook: Cloudhook = self
+            .command_as(json!({"type": "cloud/cloudhook/create", "webhook_id": webhook_id}))
+            .await
+            .map_err(cloud_error)?;
+        if cloudhook.webhook_id.is_empty() {
+            cloudhook.webhook_id = webhook_id.to_owned();
+        }
+        Ok(cloudhook)
+    }
+
+    /// `cloud/cloudhook/delete`, the webhook stays reachable locally
+    pub async fn delete_cloudhook(&self, webhook_id: &str) -> anyhow::Result<()> {
+        self.command(json!({"type": "cloud/cloudhook/delete", "webhook_id": webhook_id}))
+            .await
+            .map_err(cloud_error)?;
+        Ok(())
+    }
+
+    /// the local url of the webhook `webhook_id`, see [`webhook_url`]
+    pub fn webhook_url(&self, webhook_id: &str) -> anyhow::Result<String> {
+        webhook_url(self.url(), webhook_id)
+    }
+}
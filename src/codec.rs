@@ -0,0 +1,53 @@
+//! Decoding of WebSocket payloads
+//!
+//! Homeassistant sends JSON text frames, but some setups (proxies, add-ons, custom components) use
+//! binary frames or alternative encodings. The [`Codec`] used by a
+//! [`HomeAssistantWs`](crate::ws::HomeAssistantWs) connection decides how frames are turned into
+//! [`Value`]s, which makes it possible to plug in cheaper decoders on constrained devices.
+//!
+//! Enable the `msgpack` feature for [`MessagePack`].
+
+use serde_json::Value;
+
+pub trait Codec: Send + Sync + 'static {
+    /// name of the codec, used in error messages
+    fn name(&self) -> &'static str;
+
+    /// decodes a text frame
+    fn decode_text(&self, text: &str) -> anyhow::Result<Value> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// decodes a binary frame
+    fn decode_binary(&self, bytes: &[u8]) -> anyhow::Result<Value>;
+}
+
+/// plain JSON, binary frames are expected to contain UTF-8 encoded JSON as well
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn decode_binary(&self, bytes: &[u8]) -> anyhow::Result<Value> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// JSON text frames, MessagePack encoded binary frames
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn decode_binary(&self, bytes: &[u8]) -> anyhow::Result<Value> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
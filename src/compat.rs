@@ -0,0 +1,197 @@
+//! Supported Homeassistant releases
+//!
+//! The structs are checked against hand-written responses of the releases in [`TESTED_VERSIONS`]
+//! (`tests/fixtures/<version>/`). Fields which are not returned by every supported release are
+//! [`Option`]s or fall back to their default, unknown fields are kept in the `other` field of the
+//! response structs, so no feature flags are required to talk to older or newer instances.
+//!
+//! Code depending on newer releases branches on the [`HaVersion`] of the instance:
+//! ```
+//! use homeassistant_rs::compat::HaVersion;
+//!
+//! let version: HaVersion = "2024.12.0b3".parse().unwrap();
+//! assert!(version.is_at_least(2024, 6));
+//! assert!(version < "2024.12.0".parse().unwrap());
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// oldest supported release
+pub const MIN_SUPPORTED_VERSION: &str = "2024.1";
+
+/// releases with fixtures, newest last
+pub const TESTED_VERSIONS: &[&str] = &["2024.1", "2024.12", "2025.6"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// version of this crate
+    pub crate_version: &'static str,
+    pub min_supported_version: &'static str,
+    pub tested_versions: &'static [&'static str],
+    /// enabled optional features
+    pub features: Vec<&'static str>,
+}
+
+impl Capabilities {
+    /// whether `version` (e.g. `2024.12.5`, as in [`ConfigResponse`](crate::structs::ConfigResponse))
+    /// is at least [`MIN_SUPPORTED_VERSION`]
+    ///
+    /// releases newer than the last tested one are considered supported
+    pub fn is_supported(&self, version: &str) -> bool {
+        version.parse().is_ok_and(|version| self.supports(&version))
+    }
+
+    /// like [`is_supported`](Self::is_supported) for a parsed version
+    pub fn supports(&self, version: &HaVersion) -> bool {
+        self.min_supported()
+            .is_some_and(|min| version.is_at_least(min.year, min.month))
+    }
+
+    /// [`min_supported_version`](Self::min_supported_version) as a [`HaVersion`]
+    pub fn min_supported(&self) -> Option<HaVersion> {
+        self.min_supported_version.parse().ok()
+    }
+
+    /// [`tested_versions`](Self::tested_versions) as [`HaVersion`]s
+    pub fn tested(&self) -> Vec<HaVersion> {
+        self.tested_versions
+            .iter()
+            .filter_map(|version| version.parse().ok())
+            .collect()
+    }
+}
+
+/// a Homeassistant release, e.g. `2024.12.5`, `2025.1.0b2` or `2025.2.0.dev20250101`
+///
+/// ordered by year, month and patch, pre-releases come before the release
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HaVersion {
+    pub year: u32,
+    pub month: u32,
+    /// `0` if not given, e.g. for `2024.1`
+    pub patch: u32,
+    /// e.g. `b2` or `dev20250101`
+    pub pre: Option<String>,
+}
+
+impl HaVersion {
+    pub fn new(year: u32, month: u32, patch: u32) -> Self {
+        Self {
+            year,
+            month,
+            patch,
+            pre: None,
+        }
+    }
+
+    /// whether this is the release of `year.month` or newer, pre-releases of that month included
+    pub fn is_at_least(&self, year: u32, month: u32) -> bool {
+        (self.year, self.month) >= (year, month)
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        self.pre.is_some()
+    }
+}
+
+impl FromStr for HaVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(version: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::Error::msg(format!("invalid Homeassistant version {version:?}"));
+        let mut parts = version.trim().splitn(3, '.');
+        let year = parts
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        let month = parts
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        let (patch, pre) = match parts.next() {
+            None => (0, None),
+            Some(rest) => {
+                let digits = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let patch = rest[..digits].parse().map_err(|_| invalid())?;
+                let pre = rest[digits..].trim_start_matches('.');
+                (patch, (!pre.is_empty()).then(|| pre.to_owned()))
+            }
+        };
+        Ok(Self {
+            year,
+            month,
+            patch,
+            pre,
+        })
+    }
+}
+
+impl fmt::Display for HaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.year, self.month, self.patch)?;
+        match self.pre.as_deref() {
+            Some(pre) if pre.starts_with("dev") => write!(f, ".{pre}"),
+            Some(pre) => f.write_str(pre),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Ord for HaVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.year, self.month, self.patch)
+            .cmp(&(other.year, other.month, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => pre_release_key(a).cmp(&pre_release_key(b)),
+            })
+    }
+}
+
+impl PartialOrd for HaVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// dev builds come before betas, betas are ordered by number
+fn pre_release_key(pre: &str) -> (u8, u64, &str) {
+    let (rank, number) = match pre {
+        pre if pre.starts_with("dev") => (0, &pre[3..]),
+        pre if pre.starts_with('b') => (1, &pre[1..]),
+        pre => (2, pre),
+    };
+    (rank, number.parse().unwrap_or(0), pre)
+}
+
+pub(crate) fn capabilities() -> Capabilities {
+    let features = [
+        ("audit", cfg!(feature = "audit")),
+        ("control", cfg!(feature = "control")),
+        ("gzip", cfg!(feature = "gzip")),
+        ("msgpack", cfg!(feature = "msgpack")),
+        ("satellite", cfg!(feature = "satellite")),
+        ("secrecy", cfg!(feature = "secrecy")),
+        ("simd-json", cfg!(feature = "simd-json")),
+        ("time", cfg!(feature = "time")),
+        ("tz", cfg!(feature = "tz")),
+    ];
+
+    Capabilities {
+        crate_version: env!("CARGO_PKG_VERSION"),
+        min_supported_version: MIN_SUPPORTED_VERSION,
+        tested_versions: TESTED_VERSIONS,
+        features: features
+            .into_iter()
+            .filter_map(|(name, enabled)| enabled.then_some(name))
+            .collect(),
+    }
+}
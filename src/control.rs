@@ -0,0 +1,206 @@
+//! PID control loops bound to entities
+//!
+//! A [`Pid`] is sampled with each numeric state of an input sensor and sets an output, e.g. the
+//! opening of a radiator valve, through a service call described by [`Output`].
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use homeassistant_rs::control::{Output, Pid};
+//!
+//! let events = ws.subscribe_events(Some("state_changed")).await?;
+//! Pid::new(8.0, 0.05, 0.0)
+//!     .setpoint(21.0)
+//!     .output_limits(0.0, 100.0)
+//!     .drive(
+//!         &ws,
+//!         events,
+//!         "sensor.living_room_temperature",
+//!         Output::valve_position("valve.living_room_radiator"),
+//!     )
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::stream::BoxStream;
+use futures_util::{Stream, StreamExt, future};
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::structs::Event;
+use crate::ws::HomeAssistantWs;
+
+#[derive(Debug, Clone)]
+pub struct Pid {
+    kp: f64,
+    ki: f64,
+    kd: f64,
+    setpoint: f64,
+    min: f64,
+    max: f64,
+    integral: f64,
+    last: Option<(f64, Instant)>,
+}
+
+impl Pid {
+    /// gains for the proportional, integral (per second) and derivative (seconds) terms
+    pub fn new(kp: f64, ki: f64, kd: f64) -> Self {
+        Self {
+            kp,
+            ki,
+            kd,
+            setpoint: 0.0,
+            min: f64::NEG_INFINITY,
+            max: f64::INFINITY,
+            integral: 0.0,
+            last: None,
+        }
+    }
+
+    pub fn setpoint(mut self, setpoint: f64) -> Self {
+        self.setpoint = setpoint;
+        self
+    }
+
+    /// clamps the output, the integral term is limited to the same range to prevent windup
+    pub fn output_limits(mut self, min: f64, max: f64) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    /// changes the setpoint of a running controller
+    pub fn set_setpoint(&mut self, setpoint: f64) {
+        self.setpoint = setpoint;
+    }
+
+    /// clears the integral and derivative state
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last = None;
+    }
+
+    /// feeds a measurement, returns the new output
+    pub fn update(&mut self, measurement: f64) -> f64 {
+        self.update_at(measurement, Instant::now())
+    }
+
+    /// like [`update`](Self::update), with the time the measurement was taken
+    pub fn update_at(&mut self, measurement: f64, at: Instant) -> f64 {
+        let error = self.setpoint - measurement;
+        let mut derivative = 0.0;
+        if let Some((last, last_at)) = self.last {
+            let dt = at.duration_since(last_at).as_secs_f64();
+            if dt > 0.0 {
+                self.integral += self.ki * error * dt;
+                // derivative on the measurement, so setpoint changes do not cause a spike
+                derivative = -self.kd * (measurement - last) / dt;
+            }
+        }
+        self.integral = self.integral.clamp(self.min, self.max);
+        self.last = Some((measurement, at));
+        (self.kp * error + self.integral + derivative).clamp(self.min, self.max)
+    }
+
+    /// consumes `state_changed` events and emits the output for every numeric state of
+    /// `entity_id`, other events are ignored
+    pub fn outputs<S>(
+        mut self,
+        events: S,
+        entity_id: &str,
+    ) -> BoxStream<'static, anyhow::Result<f64>>
+    where
+        S: Stream<Item = anyhow::Result<Event>> + Send + 'static,
+    {
+        let entity_id = entity_id.to_owned();
+        events
+            .filter_map(move |item| {
+                let item = match item {
+                    Ok(event) => event
+                        .state_changed()
+                        .filter(|data| data.entity_id == entity_id)
+                        .and_then(|data| data.new_state?.state.parse().ok())
+                        .map(|value| Ok(self.update(value))),
+                    Err(e) => Some(Err(e)),
+                };
+                future::ready(item)
+            })
+            .boxed()
+    }
+
+    /// sets `output` following the numeric states of `entity_id`, until `events` ends or a service
+    /// call fails. Unchanged outputs are not sent again
+    pub async fn drive<S>(
+        self,
+        ws: &HomeAssistantWs,
+        events: S,
+        entity_id: &str,
+        output: Output,
+    ) -> anyhow::Result<()>
+    where
+        S: Stream<Item = anyhow::Result<Event>> + Send + 'static,
+    {
+        let mut outputs = self.outputs(events, entity_id);
+        let mut last = None;
+        while let Some(value) = outputs.next().await {
+            let value = output.round(value?);
+            if last == Some(value) {
+                continue;
+            }
+            ws.call_service(
+                &output.domain,
+                &output.service,
+                json!({ output.field.as_str(): value }),
+                Some(json!({ "entity_id": output.entity_id })),
+                false,
+            )
+            .await?;
+            last = Some(value);
+        }
+        Ok(())
+    }
+}
+
+/// the service call setting the output of a control loop
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub domain: String,
+    pub service: String,
+    pub entity_id: String,
+    /// service data field receiving the value
+    pub field: String,
+    /// decimal places the value is rounded to
+    pub precision: u32,
+}
+
+impl Output {
+    pub fn new(domain: &str, service: &str, entity_id: &str, field: &str) -> Self {
+        Self {
+            domain: domain.to_owned(),
+            service: service.to_owned(),
+            entity_id: entity_id.to_owned(),
+            field: field.to_owned(),
+            precision: 0,
+        }
+    }
+
+    /// `number.set_value`
+    pub fn number(entity_id: &str) -> Self {
+        Self::new("number", "set_value", entity_id, "value")
+    }
+
+    /// `valve.set_valve_position`, the position is in percent
+    pub fn valve_position(entity_id: &str) -> Self {
+        Self::new("valve", "set_valve_position", entity_id, "position")
+    }
+
+    pub fn precision(mut self, precision: u32) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub(crate) fn round(&self, value: f64) -> f64 {
+        let factor = 10f64.powi(self.precision as i32);
+        (value * factor).round() / factor
+    }
+}
@@ -0,0 +1,184 @@
+//! Correlation of the numeric histories of two entities
+//!
+//! Answers questions like "does the heater actually warm this room, and how long does it take":
+//! both histories are resampled onto a common grid, then the history of the second entity is
+//! shifted against the first to find the lag with the strongest correlation.
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use chrono::{TimeDelta, Utc};
+//! use homeassistant_rs::correlation::CorrelationQuery;
+//! use homeassistant_rs::prelude::*;
+//!
+//! let end = Utc::now();
+//! let query = CorrelationQuery::new(
+//!     "sensor.heater_power",
+//!     "sensor.bedroom_temperature",
+//!     end - TimeDelta::days(7),
+//!     end,
+//! )
+//! .step(TimeDelta::minutes(10))
+//! .max_lag(TimeDelta::hours(3));
+//! if let Some(correlation) = hass().correlate(None, None, &query).await.unwrap() {
+//!     println!("r = {:.2} after {} minutes", correlation.coefficient, correlation.lag.num_minutes());
+//! }
+//! # });
+//! ```
+//!
+//! Correlation is not causation: two rooms warmed by the sun correlate just as well.
+
+use chrono::{DateTime, TimeDelta, TimeZone, Utc};
+
+use crate::analysis::{self, Sample};
+use crate::history::HistoryQuery;
+use crate::{Api, HomeAssistant, credentials};
+
+/// the correlation found by [`CorrelationQuery::correlate`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correlation {
+    /// Pearson coefficient at [`lag`](Self::lag), from `-1.0` to `1.0`
+    pub coefficient: f64,
+    /// how long the second entity trails the first, negative if it leads
+    pub lag: TimeDelta,
+    /// the number of grid points compared at [`lag`](Self::lag)
+    pub samples: usize,
+    /// the coefficient at every lag tried, from `-max_lag` to `max_lag`
+    pub by_lag: Vec<(TimeDelta, f64)>,
+}
+
+/// two entities and the time range to correlate, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorrelationQuery {
+    entity_a: String,
+    entity_b: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    step: TimeDelta,
+    max_lag: TimeDelta,
+}
+
+/// fewer points can't give a meaningful coefficient
+const MIN_SAMPLES: usize = 3;
+
+impl CorrelationQuery {
+    /// correlates `entity_a` and `entity_b` between `start` and `end`, every 5 minutes without lag
+    pub fn new<Tz: TimeZone>(
+        entity_a: &str,
+        entity_b: &str,
+        start: DateTime<Tz>,
+        end: DateTime<Tz>,
+    ) -> Self {
+        Self {
+            entity_a: entity_a.to_owned(),
+            entity_b: entity_b.to_owned(),
+            start: start.with_timezone(&Utc),
+            end: end.with_timezone(&Utc),
+            step: TimeDelta::minutes(5),
+            max_lag: TimeDelta::zero(),
+        }
+    }
+
+    /// the spacing of the grid both histories are resampled onto
+    pub fn step(mut self, step: TimeDelta) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// the largest shift tried in either direction, rounded down to whole steps
+    pub fn max_lag(mut self, max_lag: TimeDelta) -> Self {
+        self.max_lag = max_lag.abs();
+        self
+    }
+
+    /// the history request fetching both entities
+    pub fn history_query(&self) -> HistoryQuery {
+        HistoryQuery::new()
+            .start(self.start)
+            .end(self.end)
+            .entity(&self.entity_a)
+            .entity(&self.entity_b)
+            .no_attributes()
+    }
+
+    /// correlates the samples of both entities, see [`analysis::numeric_series`]
+    ///
+    /// [`None`] if no lag has enough overlapping samples with variance on both sides, e.g. a
+    /// heater which stayed off the whole time
+    pub fn correlate(&self, a: &[Sample], b: &[Sample]) -> Option<Correlation> {
+        let grid_a = analysis::grid(a, self.start, self.end, self.step);
+        let grid_b = analysis::grid(b, self.start, self.end, self.step);
+        let max_steps = match self.step.num_milliseconds() {
+            step if step > 0 => self.max_lag.num_milliseconds() / step,
+            _ => 0,
+        };
+
+        let mut best: Option<(TimeDelta, f64, usize)> = None;
+        let mut by_lag = Vec::new();
+        for steps in -max_steps..=max_steps {
+            // pairs a at t with b at t + lag
+            let pairs: Vec<(f64, f64)> = grid_a
+                .iter()
+                .enumerate()
+                .filter_map(|(i, a)| {
+                    let j = usize::try_from(i as i64 + steps).ok()?;
+                    Some(((*a)?, (*grid_b.get(j)?)?))
+                })
+                .collect();
+            if pairs.len() < MIN_SAMPLES {
+                continue;
+            }
+            let Some(coefficient) = analysis::pearson(&pairs) else {
+                continue;
+            };
+            let lag = self.step * steps as i32;
+            by_lag.push((lag, coefficient));
+            if best.is_none_or(|(_, best, _)| coefficient.abs() > best.abs()) {
+                best = Some((lag, coefficient, pairs.len()));
+            }
+        }
+
+        let (lag, coefficient, samples) = best?;
+        Some(Correlation {
+            coefficient,
+            lag,
+            samples,
+            by_lag,
+        })
+    }
+}
+
+impl HomeAssistant {
+    /// fetches the history of both entities of `query` and correlates their numeric states, see
+    /// [`CorrelationQuery::correlate`]
+    pub async fn correlate(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        query: &CorrelationQuery,
+    ) -> anyhow::Result<Option<Correlation>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).correlate(query).await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn correlate(
+        &self,
+        query: &CorrelationQuery,
+    ) -> anyhow::Result<Option<Correlation>> {
+        let groups = self.history_query(&query.history_query()).await?;
+        let series = |entity_id: &str| {
+            groups
+                .iter()
+                .find(|group| {
+                    group
+                        .first()
+                        .is_some_and(|row| row.entity_id.as_deref() == Some(entity_id))
+                })
+                .map(|group| analysis::numeric_series(group))
+                .unwrap_or_default()
+        };
+        Ok(query.correlate(&series(&query.entity_a), &series(&query.entity_b)))
+    }
+}
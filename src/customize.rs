@@ -0,0 +1,204 @@
+//! Entity customizations
+//!
+//! Entities with a unique id are customized through the entity registry, like the UI does:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use homeassistant_rs::customize::Customization;
+//!
+//! let customization = Customization::new().friendly_name("Desk").icon("mdi:desk-lamp");
+//! ws.set_customization("light.desk_lamp_1", &customization).await?;
+//!
+//! for (entity_id, customization) in ws.customizations().await? {
+//!     println!("{entity_id}: {customization:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Entities without a unique id can only be customized in `customize.yaml`, which is read and
+//! written with [`customize_yaml`](HomeAssistant::customize_yaml) and
+//! [`save_customize_yaml`](HomeAssistantPost::save_customize_yaml).
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::registry::{EntityRegistryEntry, EntityRegistryUpdate};
+use crate::ws::HomeAssistantWs;
+use crate::{
+    Api, ApiPost, HomeAssistant, HomeAssistantPost, credentials, decode, post, request, urls,
+};
+
+/// overrides of the name and icon of an entity, [`None`] uses the name or icon of the integration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Customization {
+    pub friendly_name: Option<String>,
+    pub icon: Option<String>,
+}
+
+impl Customization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn friendly_name(mut self, friendly_name: &str) -> Self {
+        self.friendly_name = Some(friendly_name.to_owned());
+        self
+    }
+
+    /// e.g. `mdi:desk-lamp`
+    pub fn icon(mut self, icon: &str) -> Self {
+        self.icon = Some(icon.to_owned());
+        self
+    }
+
+    /// the overrides of a registry entry, [`None`] if it has none
+    pub fn from_entry(entry: &EntityRegistryEntry) -> Option<Self> {
+        let customization = Self {
+            friendly_name: entry.name.clone(),
+            icon: entry.icon.clone(),
+        };
+        (customization != Self::default()).then_some(customization)
+    }
+
+    fn update(&self) -> EntityRegistryUpdate {
+        EntityRegistryUpdate {
+            name: Some(self.friendly_name.clone()),
+            icon: Some(self.icon.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+/// an entity in `customize.yaml`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustomizeYaml {
+    /// the attributes set for this entity
+    #[serde(default)]
+    pub local: Map<String, Value>,
+    /// the attributes applied from `customize_glob` and `customize_domain`
+    #[serde(default)]
+    pub global: Map<String, Value>,
+}
+
+impl HomeAssistantWs {
+    /// `config/entity_registry/get`, returns the [`Customization`] of `entity_id`, [`None`] if it
+    /// has none
+    pub async fn customization(&self, entity_id: &str) -> anyhow::Result<Option<Customization>> {
+        let entry: EntityRegistryEntry = self
+            .command_as(json!({"type": "config/entity_registry/get", "entity_id": entity_id}))
+            .await?;
+        Ok(Customization::from_entry(&entry))
+    }
+
+    /// all customized entities of the entity registry
+    pub async fn customizations(&self) -> anyhow::Result<BTreeMap<String, Customization>> {
+        let registries = self.cached_registries().await?;
+        Ok(registries
+            .entities
+            .iter()
+            .filter_map(|(entity_id, entry)| {
+                Customization::from_entry(entry)
+                    .map(|customization| (entity_id.clone(), customization))
+            })
+            .collect())
+    }
+
+    /// replaces the [`Customization`] of `entity_id`, unset fields are reset
+    pub async fn set_customization(
+        &self,
+        entity_id: &str,
+        customization: &Customization,
+    ) -> anyhow::Result<EntityRegistryEntry> {
+        self.update_entity(entity_id, customization.update()).await
+    }
+
+    /// sets the customizations of several entities, stops at the first failing entity
+    pub async fn set_customizations(
+        &self,
+        customizations: &BTreeMap<String, Customization>,
+    ) -> anyhow::Result<()> {
+        for (entity_id, customization) in customizations {
+            self.set_customization(entity_id, customization)
+                .await
+                .map_err(|e| e.context(format!("customizing {entity_id}")))?;
+        }
+        Ok(())
+    }
+}
+
+impl HomeAssistant {
+    /// queries `/api/config/customize/config/<entity_id>` and returns the entry of `entity_id` in
+    /// `customize.yaml`
+    pub async fn customize_yaml(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        entity_id: &str,
+    ) -> anyhow::Result<CustomizeYaml> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).customize_yaml(entity_id).await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn customize_yaml(&self, entity_id: &str) -> anyhow::Result<CustomizeYaml> {
+        let client = request(
+            self.url,
+            self.token,
+            &format!(
+                "/api/config/customize/config/{}",
+                urls::encode_segment(entity_id)
+            ),
+        )
+        .await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            decode::json::<CustomizeYaml>(client).await
+        }
+    }
+}
+
+impl HomeAssistantPost {
+    /// posts to `/api/config/customize/config/<entity_id>` to replace the attributes of
+    /// `entity_id` in `customize.yaml`, e.g. `{"friendly_name": "Desk", "icon": "mdi:desk-lamp"}`
+    pub async fn save_customize_yaml(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        entity_id: &str,
+        attributes: &Map<String, Value>,
+    ) -> anyhow::Result<()> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token)
+            .save_customize_yaml(entity_id, attributes)
+            .await
+    }
+}
+
+impl ApiPost<'_> {
+    pub(crate) async fn save_customize_yaml(
+        &self,
+        entity_id: &str,
+        attributes: &Map<String, Value>,
+    ) -> anyhow::Result<()> {
+        let client = post(
+            self.url,
+            self.token,
+            &format!(
+                "/api/config/customize/config/{}",
+                urls::encode_segment(entity_id)
+            ),
+            attributes,
+        )
+        .await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Parsing of REST responses
+//!
+//! Responses are parsed with `serde_json`. With the `simd-json` feature enabled they are parsed
+//! with [simd-json](https://docs.rs/simd-json) instead.
+//!
+//! Most of the time is spent allocating the parsed structs rather than tokenizing, which limits
+//! what a faster tokenizer gains. `cargo run --release --example decode --features simd-json`
+//! compares both parsers on large `/api/states` and history payloads, measure on the target
+//! device before enabling it.
+
+use serde::de::DeserializeOwned;
+
+/// the parser in use, `serde_json` or `simd-json`
+pub const BACKEND: &str = if cfg!(feature = "simd-json") {
+    "simd-json"
+} else {
+    "serde_json"
+};
+
+/// parses `bytes` with the [`BACKEND`] in use, simd-json parses in place and leaves `bytes`
+/// modified
+pub fn from_slice<T: DeserializeOwned>(bytes: &mut [u8]) -> anyhow::Result<T> {
+    #[cfg(feature = "simd-json")]
+    {
+        Ok(simd_json::serde::from_slice(bytes)?)
+    }
+    #[cfg(not(feature = "simd-json"))]
+    {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// reads and parses the body of `response`
+pub(crate) async fn json<T: DeserializeOwned>(response: reqwest::Response) -> anyhow::Result<T> {
+    let bytes = response.bytes().await?;
+    #[cfg(feature = "simd-json")]
+    {
+        from_slice(&mut bytes.to_vec())
+    }
+    #[cfg(not(feature = "simd-json"))]
+    {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
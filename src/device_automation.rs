@@ -0,0 +1,105 @@
+//! Device triggers, conditions and actions (WebSocket only)
+//!
+//! These are the device-centric options the Homeassistant automation editor offers, e.g. "remote
+//! button short pressed". The returned [`DeviceAutomation`]s can be used as-is in automations.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::ws::HomeAssistantWs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceAutomationKind {
+    Trigger,
+    Condition,
+    Action,
+}
+
+impl DeviceAutomationKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            DeviceAutomationKind::Trigger => "trigger",
+            DeviceAutomationKind::Condition => "condition",
+            DeviceAutomationKind::Action => "action",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceAutomation {
+    pub device_id: String,
+    pub domain: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity_id: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtype: Option<String>,
+    /// set on triggers, usually `device`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    /// set on conditions, usually `device`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub other_fields: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceAutomationCapabilities {
+    /// additional fields (as voluptuous-serialize schema) the automation accepts, e.g. `for`
+    #[serde(default)]
+    pub extra_fields: Vec<serde_json::Value>,
+}
+
+impl HomeAssistantWs {
+    /// `device_automation/<kind>/list`, returns a Vec containing [`DeviceAutomation`]
+    pub async fn device_automations(
+        &self,
+        kind: DeviceAutomationKind,
+        device_id: &str,
+    ) -> anyhow::Result<Vec<DeviceAutomation>> {
+        self.command_as(json!({
+            "type": format!("device_automation/{}/list", kind.as_str()),
+            "device_id": device_id,
+        }))
+        .await
+    }
+
+    /// `device_automation/trigger/list`
+    pub async fn device_triggers(&self, device_id: &str) -> anyhow::Result<Vec<DeviceAutomation>> {
+        self.device_automations(DeviceAutomationKind::Trigger, device_id)
+            .await
+    }
+
+    /// `device_automation/condition/list`
+    pub async fn device_conditions(
+        &self,
+        device_id: &str,
+    ) -> anyhow::Result<Vec<DeviceAutomation>> {
+        self.device_automations(DeviceAutomationKind::Condition, device_id)
+            .await
+    }
+
+    /// `device_automation/action/list`
+    pub async fn device_actions(&self, device_id: &str) -> anyhow::Result<Vec<DeviceAutomation>> {
+        self.device_automations(DeviceAutomationKind::Action, device_id)
+            .await
+    }
+
+    /// `device_automation/<kind>/capabilities`, returns the extra fields a [`DeviceAutomation`]
+    /// accepts
+    pub async fn device_automation_capabilities(
+        &self,
+        kind: DeviceAutomationKind,
+        automation: &DeviceAutomation,
+    ) -> anyhow::Result<DeviceAutomationCapabilities> {
+        let mut payload = json!({
+            "type": format!("device_automation/{}/capabilities", kind.as_str()),
+        });
+        payload[kind.as_str()] = serde_json::to_value(automation)?;
+        self.command_as(payload).await
+    }
+}
@@ -0,0 +1,405 @@
+//! Typed service calls for common domains
+//!
+//! Builders for the services of lights, switches, climate devices and media players, which are
+//! awaited directly and call the service through [`ClientPost::service`](crate::client::ClientPost::service):
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(client: Client) -> anyhow::Result<()> {
+//! use std::time::Duration;
+//! use homeassistant_rs::domains::HvacMode;
+//!
+//! client
+//!     .light()
+//!     .turn_on("light.desk")
+//!     .brightness(128)
+//!     .rgb((0, 0, 255))
+//!     .transition(Duration::from_secs(2))
+//!     .await?;
+//! client.switch().toggle("switch.fan").await?;
+//! client
+//!     .climate()
+//!     .set_temperature("climate.living_room", 21.5)
+//!     .hvac_mode(HvacMode::Heat)
+//!     .await?;
+//! client.media_player().volume_set("media_player.kitchen", 0.3).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every builder returns the states changed by the call, like
+//! [`HomeAssistantPost::service`](crate::HomeAssistantPost::service). Fields not covered by a
+//! builder are set with [`ServiceCall::data`].
+
+use std::future::IntoFuture;
+use std::time::Duration;
+
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::client::Client;
+
+/// a call of `domain.service`, awaiting it performs the call
+#[derive(Debug, Clone)]
+#[must_use = "a service call does nothing until awaited"]
+pub struct ServiceCall<'a> {
+    client: &'a Client,
+    domain: &'static str,
+    service: &'static str,
+    data: Map<String, Value>,
+}
+
+impl<'a> ServiceCall<'a> {
+    fn new(
+        client: &'a Client,
+        domain: &'static str,
+        service: &'static str,
+        entity_id: &str,
+    ) -> Self {
+        let mut data = Map::new();
+        data.insert("entity_id".to_owned(), entity_id.into());
+        Self {
+            client,
+            domain,
+            service,
+            data,
+        }
+    }
+
+    /// sets a field of the service data, replacing a value set by a builder method
+    pub fn data(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.data.insert(key.to_owned(), value.into());
+        self
+    }
+
+    /// the service data sent
+    pub fn service_data(&self) -> Value {
+        Value::Object(self.data.clone())
+    }
+}
+
+impl<'a> IntoFuture for ServiceCall<'a> {
+    type Output = anyhow::Result<Value>;
+    type IntoFuture = BoxFuture<'a, anyhow::Result<Value>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        async move {
+            self.client
+                .request()
+                .service(self.domain, self.service, Value::Object(self.data), false)
+                .await
+        }
+        .boxed()
+    }
+}
+
+/// builders wrapping a [`ServiceCall`], awaited like it
+macro_rules! builder {
+    ($($(#[$meta:meta])* $name:ident;)*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone)]
+            #[must_use = "a service call does nothing until awaited"]
+            pub struct $name<'a> {
+                call: ServiceCall<'a>,
+            }
+
+            impl<'a> $name<'a> {
+                /// see [`ServiceCall::data`]
+                pub fn data(mut self, key: &str, value: impl Into<Value>) -> Self {
+                    self.call = self.call.data(key, value);
+                    self
+                }
+
+                /// see [`ServiceCall::service_data`]
+                pub fn service_data(&self) -> Value {
+                    self.call.service_data()
+                }
+            }
+
+            impl<'a> IntoFuture for $name<'a> {
+                type Output = anyhow::Result<Value>;
+                type IntoFuture = BoxFuture<'a, anyhow::Result<Value>>;
+
+                fn into_future(self) -> Self::IntoFuture {
+                    self.call.into_future()
+                }
+            }
+        )*
+    };
+}
+
+builder! {
+    /// `light.turn_on`, see [`Light::turn_on`]
+    LightTurnOn;
+    /// `climate.set_temperature`, see [`Climate::set_temperature`]
+    SetTemperature;
+    /// `media_player.play_media`, see [`MediaPlayer::play_media`]
+    PlayMedia;
+}
+
+impl Client {
+    /// the services of the `light` domain
+    pub fn light(&self) -> Light<'_> {
+        Light { client: self }
+    }
+
+    /// the services of the `switch` domain
+    pub fn switch(&self) -> Switch<'_> {
+        Switch { client: self }
+    }
+
+    /// the services of the `climate` domain
+    pub fn climate(&self) -> Climate<'_> {
+        Climate { client: self }
+    }
+
+    /// the services of the `media_player` domain
+    pub fn media_player(&self) -> MediaPlayer<'_> {
+        MediaPlayer { client: self }
+    }
+}
+
+/// services of the `light` domain, see [`Client::light`]
+#[derive(Debug, Clone, Copy)]
+pub struct Light<'a> {
+    client: &'a Client,
+}
+
+impl<'a> Light<'a> {
+    pub fn turn_on(&self, entity_id: &str) -> LightTurnOn<'a> {
+        LightTurnOn {
+            call: ServiceCall::new(self.client, "light", "turn_on", entity_id),
+        }
+    }
+
+    pub fn turn_off(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "light", "turn_off", entity_id)
+    }
+
+    pub fn toggle(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "light", "toggle", entity_id)
+    }
+}
+
+impl LightTurnOn<'_> {
+    /// 0 to 255
+    pub fn brightness(self, brightness: u8) -> Self {
+        self.data("brightness", brightness)
+    }
+
+    /// 0 to 100, values above 100 are clamped
+    pub fn brightness_pct(self, percent: u8) -> Self {
+        self.data("brightness_pct", percent.min(100))
+    }
+
+    pub fn rgb(self, (red, green, blue): (u8, u8, u8)) -> Self {
+        self.data("rgb_color", vec![red, green, blue])
+    }
+
+    pub fn color_temp_kelvin(self, kelvin: u32) -> Self {
+        self.data("color_temp_kelvin", kelvin)
+    }
+
+    /// e.g. `colorloop`, as listed in the `effect_list` attribute
+    pub fn effect(self, effect: &str) -> Self {
+        self.data("effect", effect)
+    }
+
+    /// how long the light takes to reach the new state
+    pub fn transition(self, transition: Duration) -> Self {
+        self.data("transition", transition.as_secs_f64())
+    }
+}
+
+/// services of the `switch` domain, see [`Client::switch`]
+#[derive(Debug, Clone, Copy)]
+pub struct Switch<'a> {
+    client: &'a Client,
+}
+
+impl<'a> Switch<'a> {
+    pub fn turn_on(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "switch", "turn_on", entity_id)
+    }
+
+    pub fn turn_off(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "switch", "turn_off", entity_id)
+    }
+
+    pub fn toggle(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "switch", "toggle", entity_id)
+    }
+}
+
+/// `hvac_mode` of climate devices
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum HvacMode {
+    Off,
+    Heat,
+    Cool,
+    HeatCool,
+    Auto,
+    Dry,
+    FanOnly,
+}
+
+impl HvacMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Heat => "heat",
+            Self::Cool => "cool",
+            Self::HeatCool => "heat_cool",
+            Self::Auto => "auto",
+            Self::Dry => "dry",
+            Self::FanOnly => "fan_only",
+        }
+    }
+}
+
+/// services of the `climate` domain, see [`Client::climate`]
+#[derive(Debug, Clone, Copy)]
+pub struct Climate<'a> {
+    client: &'a Client,
+}
+
+impl<'a> Climate<'a> {
+    /// sets the target temperature, in the unit of the instance
+    pub fn set_temperature(&self, entity_id: &str, temperature: f64) -> SetTemperature<'a> {
+        SetTemperature {
+            call: ServiceCall::new(self.client, "climate", "set_temperature", entity_id),
+        }
+        .data("temperature", temperature)
+    }
+
+    /// sets a target range for devices in [`HvacMode::HeatCool`] or [`HvacMode::Auto`]
+    pub fn set_temperature_range(
+        &self,
+        entity_id: &str,
+        low: f64,
+        high: f64,
+    ) -> SetTemperature<'a> {
+        SetTemperature {
+            call: ServiceCall::new(self.client, "climate", "set_temperature", entity_id),
+        }
+        .data("target_temp_low", low)
+        .data("target_temp_high", high)
+    }
+
+    pub fn set_hvac_mode(&self, entity_id: &str, mode: HvacMode) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "climate", "set_hvac_mode", entity_id)
+            .data("hvac_mode", mode.as_str())
+    }
+
+    /// e.g. `eco` or `away`, as listed in the `preset_modes` attribute
+    pub fn set_preset_mode(&self, entity_id: &str, preset: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "climate", "set_preset_mode", entity_id)
+            .data("preset_mode", preset)
+    }
+
+    pub fn turn_on(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "climate", "turn_on", entity_id)
+    }
+
+    pub fn turn_off(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "climate", "turn_off", entity_id)
+    }
+}
+
+impl SetTemperature<'_> {
+    /// switches the mode along with the temperature
+    pub fn hvac_mode(self, mode: HvacMode) -> Self {
+        self.data("hvac_mode", mode.as_str())
+    }
+}
+
+/// services of the `media_player` domain, see [`Client::media_player`]
+#[derive(Debug, Clone, Copy)]
+pub struct MediaPlayer<'a> {
+    client: &'a Client,
+}
+
+impl<'a> MediaPlayer<'a> {
+    pub fn turn_on(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "turn_on", entity_id)
+    }
+
+    pub fn turn_off(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "turn_off", entity_id)
+    }
+
+    pub fn play(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "media_play", entity_id)
+    }
+
+    pub fn pause(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "media_pause", entity_id)
+    }
+
+    pub fn play_pause(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "media_play_pause", entity_id)
+    }
+
+    pub fn stop(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "media_stop", entity_id)
+    }
+
+    pub fn next_track(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "media_next_track", entity_id)
+    }
+
+    pub fn previous_track(&self, entity_id: &str) -> ServiceCall<'a> {
+        ServiceCall::new(
+            self.client,
+            "media_player",
+            "media_previous_track",
+            entity_id,
+        )
+    }
+
+    /// 0.0 to 1.0, values outside are clamped
+    pub fn volume_set(&self, entity_id: &str, volume: f64) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "volume_set", entity_id)
+            .data("volume_level", volume.clamp(0.0, 1.0))
+    }
+
+    pub fn volume_mute(&self, entity_id: &str, muted: bool) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "volume_mute", entity_id)
+            .data("is_volume_muted", muted)
+    }
+
+    /// e.g. `HDMI 1`, as listed in the `source_list` attribute
+    pub fn select_source(&self, entity_id: &str, source: &str) -> ServiceCall<'a> {
+        ServiceCall::new(self.client, "media_player", "select_source", entity_id)
+            .data("source", source)
+    }
+
+    /// plays `content_id` (e.g. a URL) of `content_type` (e.g. `music` or `playlist`)
+    pub fn play_media(
+        &self,
+        entity_id: &str,
+        content_id: &str,
+        content_type: &str,
+    ) -> PlayMedia<'a> {
+        PlayMedia {
+            call: ServiceCall::new(self.client, "media_player", "play_media", entity_id),
+        }
+        .data("media_content_id", content_id)
+        .data("media_content_type", content_type)
+    }
+}
+
+impl PlayMedia<'_> {
+    /// `add`, `next`, `play` or `replace` the queue instead of playing immediately
+    pub fn enqueue(self, enqueue: &str) -> Self {
+        self.data("enqueue", enqueue)
+    }
+
+    /// announces the media over the current playback, e.g. for TTS
+    pub fn announce(self, announce: bool) -> Self {
+        self.data("announce", announce)
+    }
+}
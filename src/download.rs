@@ -0,0 +1,171 @@
+//! Binary downloads which are read on demand
+//!
+//! Camera snapshots, the error log and backups are returned as a [`Download`], which has not read
+//! the body yet. [`into_stream`](Download::into_stream) yields the chunks as they arrive, so a
+//! 4K snapshot or a backup is never held in memory as a whole:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(client: Client) -> anyhow::Result<()> {
+//! use homeassistant_rs::camera::SnapshotOptions;
+//!
+//! let download = client
+//!     .camera_download("camera.door", SnapshotOptions::new())
+//!     .await?;
+//! println!("{:?}, {:?} bytes", download.content_type(), download.content_length());
+//!
+//! let mut chunks = download.into_stream();
+//! while let Some(chunk) = chunks.next().await {
+//!     let chunk: bytes::Bytes = chunk?;
+//!     println!("{} bytes", chunk.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Chunks are the [`Bytes`] received from the connection, they are passed on without being copied.
+//! [`bytes`](Download::bytes) copies them into one buffer of the announced length, which is what
+//! e.g. [`camera_snapshot`](HomeAssistant::camera_snapshot) returns.
+
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+use futures_util::stream::BoxStream;
+
+use crate::{Api, HomeAssistant, credentials, request_stream, structs, urls};
+
+/// the largest `Content-Length` allocated up front by [`Download::bytes`], longer bodies grow the
+/// buffer as they arrive
+const MAX_PREALLOCATION: u64 = 256 << 20;
+
+/// a successful response whose body has not been read yet
+#[derive(Debug)]
+pub struct Download {
+    response: reqwest::Response,
+}
+
+impl Download {
+    /// fails with the status of unsuccessful responses
+    pub(crate) fn new(response: reqwest::Response) -> anyhow::Result<Self> {
+        if !response.status().is_success() {
+            return Err(anyhow::Error::msg(response.status()));
+        }
+        Ok(Self { response })
+    }
+
+    /// the `Content-Type` header, e.g. `image/jpeg`
+    pub fn content_type(&self) -> Option<&str> {
+        self.response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+    }
+
+    /// the `Content-Length` header, [`None`] for chunked responses
+    pub fn content_length(&self) -> Option<u64> {
+        self.response.content_length()
+    }
+
+    /// reads the whole body into one buffer
+    ///
+    /// with a `Content-Length` the buffer is allocated once and every chunk is copied into it as
+    /// it arrives, instead of holding all chunks until the body is complete
+    pub async fn bytes(mut self) -> anyhow::Result<Bytes> {
+        let Some(length) = self.content_length() else {
+            return Ok(self.response.bytes().await?);
+        };
+        let mut buffer = BytesMut::with_capacity(length.min(MAX_PREALLOCATION) as usize);
+        while let Some(chunk) = self.response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+        }
+        Ok(buffer.freeze())
+    }
+
+    /// the chunks of the body as they are received
+    pub fn into_stream(self) -> BoxStream<'static, anyhow::Result<Bytes>> {
+        futures_util::stream::try_unfold(self.response, |mut response| async move {
+            Ok(response.chunk().await?.map(|chunk| (chunk, response)))
+        })
+        .boxed()
+    }
+
+    /// writes the body into `writer` chunk by chunk, returns the number of bytes written
+    pub async fn write_to(self, writer: impl tokio::io::AsyncWrite + Unpin) -> anyhow::Result<u64> {
+        self.write_to_with_progress(writer, |_| ()).await
+    }
+
+    /// like [`write_to`](Self::write_to), calls `progress` with the bytes written so far after
+    /// every chunk
+    pub async fn write_to_with_progress(
+        mut self,
+        mut writer: impl tokio::io::AsyncWrite + Unpin,
+        mut progress: impl FnMut(structs::Progress),
+    ) -> anyhow::Result<u64> {
+        use tokio::io::AsyncWriteExt;
+
+        let total = self.content_length();
+        let mut written = 0;
+        while let Some(chunk) = self.response.chunk().await? {
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+            progress(structs::Progress {
+                done: written,
+                total,
+            });
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+}
+
+impl HomeAssistant {
+    /// queries `/api/error_log` and returns it as [`Download`]
+    pub async fn error_log_download(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<Download> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).error_log_download().await
+    }
+
+    /// queries `/api/backup/download/<backup_id>?agent_id=<agent_id>` and returns the backup
+    /// archive as [`Download`]
+    ///
+    /// `agent_id` is the location the backup is stored at, e.g. `backup.local`. Requires an
+    /// administrator token
+    pub async fn backup_download(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        backup_id: &str,
+        agent_id: &str,
+    ) -> anyhow::Result<Download> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .backup_download(backup_id, agent_id)
+            .await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn error_log_download(&self) -> anyhow::Result<Download> {
+        Download::new(request_stream(self.url, self.token, "/api/error_log").await?)
+    }
+
+    pub(crate) async fn backup_download(
+        &self,
+        backup_id: &str,
+        agent_id: &str,
+    ) -> anyhow::Result<Download> {
+        let client = request_stream(
+            self.url,
+            self.token,
+            &format!(
+                "/api/backup/download/{}{}",
+                urls::encode_segment(backup_id),
+                urls::Query::new().param("agent_id", agent_id)
+            ),
+        )
+        .await?;
+        Download::new(client)
+    }
+}
@@ -0,0 +1,118 @@
+//! Parsed entries of `/api/error_log`
+//!
+//! The error log is the plain text `home-assistant.log`, [`HomeAssistant::error_log_entries`]
+//! splits it into entries which can be filtered by severity:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::error_log::LogLevel;
+//! use homeassistant_rs::prelude::*;
+//!
+//! for entry in hass().error_log_entries(None, None).await.unwrap() {
+//!     if entry.level >= LogLevel::Error {
+//!         println!("{} [{}] {}", entry.timestamp, entry.logger, entry.message);
+//!     }
+//! }
+//! # });
+//! ```
+//!
+//! Tracebacks and other lines following an entry are part of its message.
+
+use chrono::NaiveDateTime;
+
+use crate::{Api, HomeAssistant, credentials};
+
+/// the severity of an entry, ordered from [`Debug`](LogLevel::Debug) to
+/// [`Critical`](LogLevel::Critical)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "DEBUG" => Ok(Self::Debug),
+            "INFO" => Ok(Self::Info),
+            "WARNING" | "WARN" => Ok(Self::Warning),
+            "ERROR" => Ok(Self::Error),
+            "CRITICAL" | "FATAL" => Ok(Self::Critical),
+            _ => Err(anyhow::Error::msg(format!("unknown log level {s:?}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLogEntry {
+    /// in the time zone of the instance, the log doesn't name it
+    pub timestamp: NaiveDateTime,
+    pub level: LogLevel,
+    /// e.g. `homeassistant.components.zha`
+    pub logger: String,
+    /// the message, followed by the traceback if any
+    pub message: String,
+}
+
+/// parses the first line of an entry, e.g.
+/// `2025-07-01 12:00:00.123 ERROR (MainThread) [homeassistant.core] Error doing job`
+fn parse_line(line: &str) -> Option<ErrorLogEntry> {
+    let (date, rest) = line.split_once(' ')?;
+    let (time, rest) = rest.split_once(' ')?;
+    let timestamp =
+        NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%Y-%m-%d %H:%M:%S%.f").ok()?;
+    let (level, rest) = rest.split_once(' ')?;
+    let level = level.parse().ok()?;
+    // the thread, e.g. `(SyncWorker_3)`
+    let rest = match rest.strip_prefix('(') {
+        Some(rest) => rest.split_once(") ")?.1,
+        None => rest,
+    };
+    let (logger, message) = rest.strip_prefix('[')?.split_once(']')?;
+    Some(ErrorLogEntry {
+        timestamp,
+        level,
+        logger: logger.to_owned(),
+        message: message.strip_prefix(' ').unwrap_or(message).to_owned(),
+    })
+}
+
+/// splits the text of `/api/error_log` into entries, lines before the first entry are skipped
+pub fn parse(log: &str) -> Vec<ErrorLogEntry> {
+    let mut entries: Vec<ErrorLogEntry> = Vec::new();
+    for line in log.lines() {
+        match (parse_line(line), entries.last_mut()) {
+            (Some(entry), _) => entries.push(entry),
+            (None, Some(entry)) => {
+                entry.message.push('\n');
+                entry.message.push_str(line);
+            }
+            (None, None) => {}
+        }
+    }
+    entries
+}
+
+impl HomeAssistant {
+    /// queries `/api/error_log` and returns its entries, see [`parse`]
+    pub async fn error_log_entries(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<Vec<ErrorLogEntry>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).error_log_entries().await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn error_log_entries(&self) -> anyhow::Result<Vec<ErrorLogEntry>> {
+        Ok(parse(&self.error_log().await?))
+    }
+}
@@ -0,0 +1,109 @@
+//! Helpers around the event listener counts returned by [`HomeAssistant::events`](crate::HomeAssistant::events)
+//!
+//! Comparing the listener counts over time is an easy way to detect leaked subscriptions:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::events::EventCatalog;
+//!
+//! let mut catalog = EventCatalog::fetch(None, None).await.unwrap();
+//! // ... later
+//! for change in catalog.refresh(None, None).await.unwrap() {
+//!     println!("{}: {:+}", change.event_type, change.delta());
+//! }
+//! # });
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::{hass, structs};
+
+/// returns the listener count of `event_type`, [`None`] if nobody listens to it
+pub fn listener_count_for(events: &[structs::EventResponse], event_type: &str) -> Option<u16> {
+    events
+        .iter()
+        .find(|event| event.event == event_type)
+        .map(|event| event.listener_count)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerChange {
+    pub event_type: String,
+    /// [`None`] if the event type was not listened to before
+    pub before: Option<u16>,
+    /// [`None`] if the event type is no longer listened to
+    pub after: Option<u16>,
+}
+
+impl ListenerChange {
+    pub fn delta(&self) -> i32 {
+        i32::from(self.after.unwrap_or(0)) - i32::from(self.before.unwrap_or(0))
+    }
+}
+
+/// snapshot of the listener counts of all event types
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventCatalog {
+    counts: BTreeMap<String, u16>,
+}
+
+impl EventCatalog {
+    pub fn from_events(events: Vec<structs::EventResponse>) -> Self {
+        Self {
+            counts: events
+                .into_iter()
+                .map(|event| (event.event, event.listener_count))
+                .collect(),
+        }
+    }
+
+    /// queries `/api/events` and returns a new [`EventCatalog`]
+    pub async fn fetch(ha_url: Option<String>, ha_token: Option<String>) -> anyhow::Result<Self> {
+        Ok(Self::from_events(hass().events(ha_url, ha_token).await?))
+    }
+
+    /// queries `/api/events` again, replaces the snapshot and returns what changed
+    pub async fn refresh(
+        &mut self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<Vec<ListenerChange>> {
+        let current = Self::fetch(ha_url, ha_token).await?;
+        let changes = self.diff(&current);
+        *self = current;
+        Ok(changes)
+    }
+
+    pub fn listener_count(&self, event_type: &str) -> Option<u16> {
+        self.counts.get(event_type).copied()
+    }
+
+    pub fn total_listeners(&self) -> u32 {
+        self.counts.values().map(|count| u32::from(*count)).sum()
+    }
+
+    pub fn event_types(&self) -> impl Iterator<Item = &str> {
+        self.counts.keys().map(String::as_str)
+    }
+
+    /// lists all event types whose listener count differs between `self` and `other`
+    pub fn diff(&self, other: &EventCatalog) -> Vec<ListenerChange> {
+        let mut event_types: Vec<&String> = self.counts.keys().chain(other.counts.keys()).collect();
+        event_types.sort();
+        event_types.dedup();
+
+        event_types
+            .into_iter()
+            .filter_map(|event_type| {
+                let before = self.counts.get(event_type).copied();
+                let after = other.counts.get(event_type).copied();
+                (before != after).then(|| ListenerChange {
+                    event_type: event_type.clone(),
+                    before,
+                    after,
+                })
+            })
+            .collect()
+    }
+}
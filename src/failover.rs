@@ -0,0 +1,141 @@
+//! Fallback between multiple base urls, e.g. the local address and the Nabu Casa remote url
+//!
+//! The url passed to a function (or `HA_URL`) is always tried first, followed by the configured
+//! fallback urls in order. A url which could not be connected to is remembered as unreachable for
+//! the health TTL (default 60 seconds) and moved to the end of the list until then. GET requests
+//! also fall back after a timeout, other requests may have been executed already and are not sent
+//! again.
+//!
+//! Fallback urls can be set in code or through the environment:
+//! ```text
+//! HA_REMOTE_URL="https://xxxxxxxx.ui.nabu.casa"
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+lazy_static::lazy_static! {
+    static ref FAILOVER: RwLock<Failover> = RwLock::new(Failover {
+        fallback_urls: if crate::settings::env_lookup() {
+            dotenvy::var("HA_REMOTE_URL").ok().into_iter().collect()
+        } else {
+            Vec::new()
+        },
+        health_ttl: Duration::from_secs(60),
+        unreachable: HashMap::new(),
+    });
+}
+
+struct Failover {
+    fallback_urls: Vec<String>,
+    health_ttl: Duration,
+    unreachable: HashMap<String, Instant>,
+}
+
+fn write() -> RwLockWriteGuard<'static, Failover> {
+    FAILOVER.write().unwrap_or_else(|p| p.into_inner())
+}
+
+/// sets the urls to fall back to, in order of preference
+pub fn set_fallback_urls(urls: Vec<String>) {
+    let mut failover = write();
+    failover.fallback_urls = urls;
+    failover.unreachable.clear();
+}
+
+/// the urls set with [`set_fallback_urls`] or `HA_REMOTE_URL`
+pub(crate) fn fallback_urls() -> Vec<String> {
+    FAILOVER
+        .read()
+        .unwrap_or_else(|p| p.into_inner())
+        .fallback_urls
+        .clone()
+}
+
+/// sets for how long an unreachable url is skipped
+pub fn set_health_ttl(ttl: Duration) {
+    write().health_ttl = ttl;
+}
+
+/// whether `url` is currently considered reachable
+pub fn is_reachable(url: &str) -> bool {
+    let mut failover = write();
+    let ttl = failover.health_ttl;
+    failover
+        .unreachable
+        .retain(|_, since| since.elapsed() < ttl);
+    !failover.unreachable.contains_key(url)
+}
+
+/// probes `primary` and all fallback urls by querying `/api/`, updates the health cache and returns
+/// the first reachable url
+///
+/// the probes carry the default and auth provider headers of real requests. A url is reachable if
+/// Homeassistant answers, with success or 401 for a rejected token; a 403 of an access proxy or a
+/// 5xx of a relay in front of it count as unreachable
+pub async fn probe(primary: &str, token: &str) -> Option<String> {
+    let mut reachable = None;
+    for url in candidates(primary) {
+        let Ok(endpoint) = crate::urls::join(&url, "/api/") else {
+            mark(&url, false);
+            continue;
+        };
+        let request = crate::settings::http_client()
+            .get(endpoint)
+            .bearer_auth(token)
+            .timeout(Duration::from_secs(5));
+        // without the headers of the auth provider the url cannot be judged
+        let Ok(request) = crate::settings::apply(request).await else {
+            continue;
+        };
+        let healthy = request.send().await.is_ok_and(|response| {
+            response.status().is_success() || response.status() == reqwest::StatusCode::UNAUTHORIZED
+        });
+        mark(&url, healthy);
+        if healthy && reachable.is_none() {
+            reachable = Some(url);
+        }
+    }
+    reachable
+}
+
+/// the urls to try for a request to `primary`, like [`candidates`], but empty if no fallback urls
+/// are set, in which case requests go to `primary` only
+pub(crate) fn fallback_candidates(primary: &str) -> Vec<String> {
+    let configured = !FAILOVER
+        .read()
+        .unwrap_or_else(|p| p.into_inner())
+        .fallback_urls
+        .is_empty();
+    if configured {
+        candidates(primary)
+    } else {
+        Vec::new()
+    }
+}
+
+/// `primary` followed by the fallback urls, unreachable urls last
+pub(crate) fn candidates(primary: &str) -> Vec<String> {
+    let fallback_urls = fallback_urls();
+
+    let mut urls = vec![primary.trim_end_matches('/').to_owned()];
+    for url in fallback_urls {
+        let url = url.trim_end_matches('/').to_owned();
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    // stable sort, keeps the preference order within reachable and unreachable urls
+    urls.sort_by_key(|url| !is_reachable(url));
+    urls
+}
+
+pub(crate) fn mark(url: &str, reachable: bool) {
+    let mut failover = write();
+    if reachable {
+        failover.unreachable.remove(url);
+    } else {
+        failover.unreachable.insert(url.to_owned(), Instant::now());
+    }
+}
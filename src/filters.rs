@@ -0,0 +1,144 @@
+//! Smoothing filters for noisy numeric sensors
+//!
+//! Filters are fed one value at a time and return the filtered value, or [`None`] to drop it. Use
+//! them directly, or per entity on an event stream with
+//! [`EventStreamExt::smooth_per_entity`](crate::streams::EventStreamExt::smooth_per_entity).
+
+use std::collections::VecDeque;
+
+pub trait Filter: Send + 'static {
+    /// feeds `value` into the filter, returns the filtered value or [`None`] if it was rejected
+    fn update(&mut self, value: f64) -> Option<f64>;
+}
+
+/// exponential moving average
+#[derive(Debug, Clone)]
+pub struct Ema {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ema {
+    /// `alpha` between 0 (ignore new values) and 1 (no smoothing), it is clamped into that range
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            value: None,
+        }
+    }
+
+    /// smoothing equivalent to a simple moving average over `samples` values
+    pub fn with_span(samples: usize) -> Self {
+        Self::new(2.0 / (samples.max(1) as f64 + 1.0))
+    }
+}
+
+impl Filter for Ema {
+    fn update(&mut self, value: f64) -> Option<f64> {
+        let next = match self.value {
+            Some(previous) => previous + self.alpha * (value - previous),
+            None => value,
+        };
+        self.value = Some(next);
+        Some(next)
+    }
+}
+
+fn median(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let mut values: Vec<f64> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    })
+}
+
+/// median of the last `window` values, removes spikes without lagging as much as an average
+#[derive(Debug, Clone)]
+pub struct Median {
+    window: usize,
+    values: VecDeque<f64>,
+}
+
+impl Median {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            values: VecDeque::new(),
+        }
+    }
+}
+
+impl Filter for Median {
+    fn update(&mut self, value: f64) -> Option<f64> {
+        if self.values.len() == self.window {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+        median(self.values.iter().copied())
+    }
+}
+
+/// drops values deviating from the median of the last `window` accepted values by more than
+/// `threshold` times the median absolute deviation
+///
+/// values are accepted until the window is filled
+#[derive(Debug, Clone)]
+pub struct OutlierFilter {
+    window: usize,
+    threshold: f64,
+    min_deviation: f64,
+    values: VecDeque<f64>,
+}
+
+impl OutlierFilter {
+    pub fn new(window: usize, threshold: f64) -> Self {
+        Self {
+            window: window.max(1),
+            threshold,
+            min_deviation: 0.0,
+            values: VecDeque::new(),
+        }
+    }
+
+    /// values closer than `deviation` to the median are always accepted, which avoids rejecting
+    /// everything once a sensor reported the same value for a while
+    pub fn min_deviation(mut self, deviation: f64) -> Self {
+        self.min_deviation = deviation;
+        self
+    }
+}
+
+impl Filter for OutlierFilter {
+    fn update(&mut self, value: f64) -> Option<f64> {
+        if self.values.len() == self.window {
+            let center = median(self.values.iter().copied())?;
+            let mad = median(self.values.iter().map(|v| (v - center).abs()))?;
+            let deviation = (value - center).abs();
+            if deviation > self.min_deviation && deviation > self.threshold * mad {
+                return None;
+            }
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+        Some(value)
+    }
+}
+
+impl Filter for Box<dyn Filter> {
+    fn update(&mut self, value: f64) -> Option<f64> {
+        (**self).update(value)
+    }
+}
+
+/// applies the first filter, then the second one
+impl<A: Filter, B: Filter> Filter for (A, B) {
+    fn update(&mut self, value: f64) -> Option<f64> {
+        self.0.update(value).and_then(|value| self.1.update(value))
+    }
+}
@@ -0,0 +1,210 @@
+//! Formatting of state values for display
+//!
+//! A [`Formatter`] follows the language and unit system of the instance and picks a precision per
+//! device class, e.g. temperatures with one decimal and energy with two:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::hass;
+//!
+//! let formatter = hass().formatter(None, None).await.unwrap();
+//! for state in hass().states(None, None, None).await.unwrap() {
+//!     println!("{:?}: {}", state.entity_id, formatter.state(&state));
+//! }
+//! # });
+//! ```
+
+use crate::structs::{ConfigResponse, StatesResponse};
+use crate::{Api, HomeAssistant, credentials};
+
+/// languages which use a decimal comma
+const DECIMAL_COMMA: &[&str] = &[
+    "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr", "hu", "id", "is",
+    "it", "lt", "lv", "nb", "nl", "nn", "pl", "pt", "ro", "ru", "sk", "sl", "sr", "sv", "tr", "uk",
+    "vi",
+];
+
+/// decimal comma languages which group thousands with a space instead of a dot
+const SPACE_GROUPING: &[&str] = &[
+    "bg", "cs", "et", "fi", "fr", "hu", "lt", "lv", "nb", "nn", "pl", "ru", "sk", "sv", "uk",
+];
+
+/// decimals shown for a device class, [`None`] if the class has no fixed precision
+///
+/// `unit` distinguishes classes whose precision depends on the magnitude of the unit, e.g. power
+/// in `W` or `kW`
+pub fn precision(device_class: &str, unit: Option<&str>) -> Option<usize> {
+    let precision = match device_class {
+        "temperature"
+        | "voltage"
+        | "distance"
+        | "precipitation"
+        | "precipitation_intensity"
+        | "speed"
+        | "wind_speed"
+        | "sound_pressure" => 1,
+        "energy" | "gas" | "water" | "volume" | "volume_storage" | "monetary" | "current"
+        | "weight" | "power_factor" => 2,
+        "power" | "apparent_power" | "reactive_power" => match unit {
+            Some("kW" | "kVA" | "kvar" | "MW") => 2,
+            _ => 0,
+        },
+        "pressure" => match unit {
+            Some("hPa" | "mbar" | "Pa") => 0,
+            _ => 2,
+        },
+        "humidity"
+        | "moisture"
+        | "battery"
+        | "illuminance"
+        | "carbon_dioxide"
+        | "carbon_monoxide"
+        | "pm1"
+        | "pm10"
+        | "pm25"
+        | "volatile_organic_compounds"
+        | "volatile_organic_compounds_parts"
+        | "signal_strength"
+        | "irradiance"
+        | "frequency"
+        | "aqi" => 0,
+        _ => return None,
+    };
+    Some(precision)
+}
+
+/// formats numbers and states for a language and unit system
+#[derive(Debug, Clone)]
+pub struct Formatter {
+    decimal: char,
+    /// thousands separator
+    group: char,
+    temperature_unit: String,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new("en")
+    }
+}
+
+impl Formatter {
+    /// a formatter for `language`, e.g. `de` or `en-GB`, with °C as the temperature unit
+    pub fn new(language: &str) -> Self {
+        let language = language
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let (decimal, group) = if SPACE_GROUPING.contains(&language.as_str()) {
+            (',', '\u{202f}')
+        } else if DECIMAL_COMMA.contains(&language.as_str()) {
+            (',', '.')
+        } else {
+            ('.', ',')
+        };
+        Self {
+            decimal,
+            group,
+            temperature_unit: "°C".to_owned(),
+        }
+    }
+
+    /// a formatter for the language and unit system of the instance
+    pub fn from_config(config: &ConfigResponse) -> Self {
+        let mut formatter = Self::new(&config.language);
+        if !config.unit_system.temperature.is_empty() {
+            formatter.temperature_unit = config.unit_system.temperature.clone();
+        }
+        formatter
+    }
+
+    /// `value` with `decimals` decimals, digits grouped by thousands
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (integer, fraction) = formatted
+            .split_once('.')
+            .map_or((formatted.as_str(), None), |(integer, fraction)| {
+                (integer, Some(fraction))
+            });
+
+        let mut out = String::new();
+        // -0.0 and values rounded to zero are shown without a sign
+        if value < 0.0 && formatted.bytes().any(|b| (b'1'..=b'9').contains(&b)) {
+            out.push('-');
+        }
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                out.push(self.group);
+            }
+            out.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+
+    /// `value` with the precision of `device_class` and `unit` appended
+    ///
+    /// values without a known precision are shown with up to 2 decimals, trailing zeros removed
+    pub fn value(&self, value: f64, device_class: Option<&str>, unit: Option<&str>) -> String {
+        let number = match device_class.and_then(|class| precision(class, unit)) {
+            Some(decimals) => self.number(value, decimals),
+            None => {
+                let number = self.number(value, 2);
+                number
+                    .trim_end_matches('0')
+                    .trim_end_matches(self.decimal)
+                    .to_owned()
+            }
+        };
+        match unit {
+            Some(unit) if unit == "%" || unit.starts_with('°') => format!("{number}{unit}"),
+            Some(unit) if !unit.is_empty() => format!("{number} {unit}"),
+            _ => number,
+        }
+    }
+
+    /// the state of an entity, numeric states are formatted with [`value`](Self::value)
+    ///
+    /// temperatures without a unit get the temperature unit of the instance, non-numeric states
+    /// (e.g. `on` or `unavailable`) are returned as is
+    pub fn state(&self, state: &StatesResponse) -> String {
+        let Ok(value) = state.state.parse::<f64>() else {
+            return state.state.clone();
+        };
+        let device_class = state
+            .attributes
+            .as_ref()
+            .and_then(|attributes| attributes.device_class());
+        let unit = state
+            .attributes
+            .as_ref()
+            .and_then(|attributes| attributes.unit_of_measurement())
+            .or((device_class == Some("temperature")).then_some(self.temperature_unit.as_str()));
+        self.value(value, device_class, unit)
+    }
+}
+
+impl HomeAssistant {
+    /// a [`Formatter`] for the language and unit system of the instance, see
+    /// [`config`](HomeAssistant::config)
+    pub async fn formatter(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<Formatter> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).formatter().await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn formatter(&self) -> anyhow::Result<Formatter> {
+        let config = self.config().await?;
+        Ok(Formatter::from_config(&config))
+    }
+}
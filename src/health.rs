@@ -0,0 +1,234 @@
+//! Reports about the health of entities and integrations
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::registry::EntityRegistryEntry;
+use crate::structs::{self, EntityId};
+use crate::ws::HomeAssistantWs;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleEntity {
+    pub entity_id: EntityId,
+    pub state: String,
+    pub last_updated: Option<DateTime<Utc>>,
+    /// time since `last_updated`, [`None`] if it is unknown
+    pub age: Option<Duration>,
+}
+
+impl StaleEntity {
+    pub fn is_unavailable(&self) -> bool {
+        self.state == "unavailable"
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StalenessReport {
+    /// stale entities grouped by integration (registry `platform`), entities missing from the
+    /// registry are grouped by their domain
+    pub integrations: BTreeMap<String, Vec<StaleEntity>>,
+}
+
+impl StalenessReport {
+    pub fn is_empty(&self) -> bool {
+        self.integrations.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.integrations.values().map(Vec::len).sum()
+    }
+}
+
+/// lists entities which are `unavailable` or have not been updated for longer than `max_age`
+pub fn staleness_report(
+    states: &[structs::StatesResponse],
+    registry: &[EntityRegistryEntry],
+    max_age: Duration,
+    now: DateTime<Utc>,
+) -> StalenessReport {
+    let platforms: HashMap<&str, &str> = registry
+        .iter()
+        .map(|entry| (entry.entity_id.as_str(), entry.platform.as_str()))
+        .collect();
+
+    let mut report = StalenessReport::default();
+    for state in states {
+        let Some(entity_id) = state.entity_id.as_deref() else {
+            continue;
+        };
+        let last_updated = state
+            .last_updated
+            .as_deref()
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.with_timezone(&Utc));
+        let age = last_updated.and_then(|time| (now - time).to_std().ok());
+
+        let stale = StaleEntity {
+            entity_id: EntityId::from(entity_id),
+            state: state.state.clone(),
+            last_updated,
+            age,
+        };
+        if !stale.is_unavailable() && age.is_none_or(|age| age <= max_age) {
+            continue;
+        }
+
+        let integration = platforms
+            .get(entity_id)
+            .copied()
+            .unwrap_or(stale.entity_id.domain())
+            .to_owned();
+        report
+            .integrations
+            .entry(integration)
+            .or_default()
+            .push(stale);
+    }
+
+    report
+}
+
+/// state of a config entry
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigEntryState {
+    Loaded,
+    SetupError,
+    SetupRetry,
+    #[default]
+    NotLoaded,
+    FailedUnload,
+    MigrationError,
+    SetupInProgress,
+    #[serde(other)]
+    Unknown,
+}
+
+/// an entry of `config_entries/get`, an instance of an integration
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigEntry {
+    pub entry_id: String,
+    /// the integration, e.g. `hue`
+    pub domain: String,
+    pub title: String,
+    pub source: Option<String>,
+    pub state: ConfigEntryState,
+    /// why setup failed or is retried, e.g. `Unable to connect`
+    pub reason: Option<String>,
+    pub disabled_by: Option<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+impl ConfigEntry {
+    /// setup failed or is being retried, disabled entries never fail
+    pub fn is_failing(&self) -> bool {
+        self.disabled_by.is_none()
+            && matches!(
+                self.state,
+                ConfigEntryState::SetupError
+                    | ConfigEntryState::SetupRetry
+                    | ConfigEntryState::FailedUnload
+                    | ConfigEntryState::MigrationError
+            )
+    }
+}
+
+/// health of an integration, see [`integration_health`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrationHealth {
+    pub domain: String,
+    pub entries: Vec<ConfigEntry>,
+    /// entities of the integration with a state
+    pub entities: usize,
+    /// entities which are `unavailable`
+    pub unavailable: usize,
+}
+
+impl IntegrationHealth {
+    /// no entry is failing and no entity is unavailable
+    pub fn is_healthy(&self) -> bool {
+        self.unavailable == 0 && !self.entries.iter().any(ConfigEntry::is_failing)
+    }
+
+    pub fn failing_entries(&self) -> impl Iterator<Item = &ConfigEntry> {
+        self.entries.iter().filter(|entry| entry.is_failing())
+    }
+}
+
+/// summarizes config entries and entity availability per integration, unhealthy integrations
+/// first, then by domain
+///
+/// entities are assigned by their registry `platform`, entities missing from the registry are
+/// left out
+pub fn integration_health(
+    entries: Vec<ConfigEntry>,
+    registry: &[EntityRegistryEntry],
+    states: &[structs::StatesResponse],
+) -> Vec<IntegrationHealth> {
+    let mut integrations: BTreeMap<String, IntegrationHealth> = BTreeMap::new();
+    for entry in entries {
+        integrations
+            .entry(entry.domain.clone())
+            .or_insert_with_key(|domain| IntegrationHealth {
+                domain: domain.clone(),
+                ..Default::default()
+            })
+            .entries
+            .push(entry);
+    }
+
+    let platforms: HashMap<&str, &str> = registry
+        .iter()
+        .map(|entry| (entry.entity_id.as_str(), entry.platform.as_str()))
+        .collect();
+    for state in states {
+        let Some(platform) = state
+            .entity_id
+            .as_deref()
+            .and_then(|entity_id| platforms.get(entity_id))
+        else {
+            continue;
+        };
+        let health = integrations
+            .entry((*platform).to_owned())
+            .or_insert_with_key(|domain| IntegrationHealth {
+                domain: domain.clone(),
+                ..Default::default()
+            });
+        health.entities += 1;
+        if state.state == "unavailable" {
+            health.unavailable += 1;
+        }
+    }
+
+    let mut integrations: Vec<IntegrationHealth> = integrations.into_values().collect();
+    integrations.sort_by_key(|health| health.is_healthy());
+    integrations
+}
+
+impl HomeAssistantWs {
+    /// `config_entries/get`, returns all config entries
+    pub async fn config_entries(&self) -> anyhow::Result<Vec<ConfigEntry>> {
+        self.command_as(json!({"type": "config_entries/get"})).await
+    }
+
+    /// fetches config entries, the entity registry and states and returns the
+    /// [`integration_health`] of every integration
+    pub async fn integration_health(&self) -> anyhow::Result<Vec<IntegrationHealth>> {
+        let (entries, registry, states) =
+            tokio::try_join!(self.config_entries(), self.entity_registry(), self.states())?;
+        Ok(integration_health(entries, &registry, &states))
+    }
+
+    /// fetches states and the entity registry and returns a [`StalenessReport`]
+    pub async fn staleness_report(&self, max_age: Duration) -> anyhow::Result<StalenessReport> {
+        let (states, registry) = tokio::try_join!(self.states(), self.entity_registry())?;
+        Ok(staleness_report(&states, &registry, max_age, Utc::now()))
+    }
+}
@@ -0,0 +1,321 @@
+//! History over a time range, and history with a client-side attribute whitelist
+//!
+//! [`HistoryQuery`] selects the period and entities, the result is grouped per entity:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example() -> anyhow::Result<()> {
+//! use homeassistant_rs::history::HistoryQuery;
+//!
+//! let end = chrono::Utc::now();
+//! let query = HistoryQuery::new()
+//!     .start(end - chrono::TimeDelta::days(7))
+//!     .end(end)
+//!     .entities(["sensor.outside_temperature", "sensor.inside_temperature"])
+//!     .minimal_response();
+//! for rows in hass().history_query(None, None, &query).await? {
+//!     println!("{:?}: {} changes", rows[0].entity_id, rows.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `/api/history/period` can only return all attributes or none. With a whitelist, attributes are
+//! dropped while the response is parsed, so unneeded attributes (e.g. artwork urls of media
+//! players) never take up memory:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example() -> anyhow::Result<()> {
+//! use homeassistant_rs::history::HistoryQuery;
+//!
+//! let query = HistoryQuery::new()
+//!     .entity("media_player.living_room")
+//!     .attributes(["volume_level"])
+//!     .significant_changes_only();
+//! let history = hass().history_query(None, None, &query).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+
+use crate::structs::{Attributes, HistoryResponse};
+use crate::{Api, HomeAssistant, credentials, decode, request, urls};
+
+/// `/api/history/period/<start>`, by default the day before now for all entities
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryQuery {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    entity_ids: Vec<String>,
+    minimal_response: bool,
+    no_attributes: bool,
+    significant_changes_only: bool,
+    attributes: Option<Vec<String>>,
+}
+
+impl HistoryQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start<Tz: TimeZone>(mut self, start: DateTime<Tz>) -> Self {
+        self.start = Some(start.with_timezone(&Utc));
+        self
+    }
+
+    /// one day after the start if not set
+    pub fn end<Tz: TimeZone>(mut self, end: DateTime<Tz>) -> Self {
+        self.end = Some(end.with_timezone(&Utc));
+        self
+    }
+
+    pub fn entity(mut self, entity_id: &str) -> Self {
+        self.entity_ids.push(entity_id.to_owned());
+        self
+    }
+
+    pub fn entities<S: Into<String>>(mut self, entity_ids: impl IntoIterator<Item = S>) -> Self {
+        self.entity_ids
+            .extend(entity_ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// only the first and last state of each entity contain `attributes` and `last_updated`
+    pub fn minimal_response(mut self) -> Self {
+        self.minimal_response = true;
+        self
+    }
+
+    pub fn no_attributes(mut self) -> Self {
+        self.no_attributes = true;
+        self
+    }
+
+    /// skips changes of attributes only, for entities which record them
+    pub fn significant_changes_only(mut self) -> Self {
+        self.significant_changes_only = true;
+        self
+    }
+
+    /// keeps only these attributes, the others are dropped while the response is parsed
+    ///
+    /// has no effect together with [`no_attributes`](Self::no_attributes)
+    pub fn attributes<S: Into<String>>(mut self, whitelist: impl IntoIterator<Item = S>) -> Self {
+        self.attributes
+            .get_or_insert_with(Vec::new)
+            .extend(whitelist.into_iter().map(Into::into));
+        self
+    }
+
+    /// the path and query string of the request
+    pub fn path(&self) -> anyhow::Result<String> {
+        if let (Some(start), Some(end)) = (self.start, self.end)
+            && end < start
+        {
+            return Err(anyhow::Error::msg(format!(
+                "history end {end} is before its start {start}"
+            )));
+        }
+        let start = self
+            .start
+            .map(|start| format!("/{}", urls::encode_segment(&start.to_rfc3339())))
+            .unwrap_or_default();
+        let query = urls::Query::new()
+            .opt("end_time", self.end.map(|end| end.to_rfc3339()))
+            .opt(
+                "filter_entity_id",
+                (!self.entity_ids.is_empty()).then(|| self.entity_ids.join(",")),
+            )
+            .flag("minimal_response", self.minimal_response)
+            .flag("no_attributes", self.no_attributes)
+            .flag("significant_changes_only", self.significant_changes_only);
+        Ok(format!("/api/history/period{start}{query}"))
+    }
+}
+
+/// the `[[row, ...], ...]` response
+struct Groups<'a>(&'a [&'a str]);
+/// the rows of one entity
+struct Rows<'a>(&'a [&'a str]);
+struct Row<'a>(&'a [&'a str]);
+struct FilteredAttributes<'a>(&'a [&'a str]);
+
+impl<'de> DeserializeSeed<'de> for Groups<'_> {
+    type Value = Vec<Vec<HistoryResponse>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Groups<'_> {
+    type Value = Vec<Vec<HistoryResponse>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of history lists")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut groups = Vec::new();
+        while let Some(rows) = seq.next_element_seed(Rows(self.0))? {
+            groups.push(rows);
+        }
+        Ok(groups)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Rows<'_> {
+    type Value = Vec<HistoryResponse>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Rows<'_> {
+    type Value = Vec<HistoryResponse>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of states")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut rows = Vec::new();
+        while let Some(row) = seq.next_element_seed(Row(self.0))? {
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Row<'_> {
+    type Value = HistoryResponse;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Row<'_> {
+    type Value = HistoryResponse;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a state")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut row = HistoryResponse::default();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "entity_id" => row.entity_id = map.next_value()?,
+                "state" => row.state = map.next_value()?,
+                "last_changed" => row.last_changed = map.next_value()?,
+                "last_updated" => row.last_updated = map.next_value()?,
+                "attributes" => row.attributes = map.next_value_seed(FilteredAttributes(self.0))?,
+                _ => {
+                    let value = map.next_value()?;
+                    match &mut row.other {
+                        Value::Object(other) => {
+                            other.insert(key, value);
+                        }
+                        other => *other = Value::Object(Map::from_iter([(key, value)])),
+                    }
+                }
+            }
+        }
+        Ok(row)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for FilteredAttributes<'_> {
+    type Value = Option<Attributes>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FilteredAttributes<'_> {
+    type Value = Option<Attributes>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("attributes")
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut attributes = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if self.0.contains(&key.as_str()) {
+                attributes.insert(key, map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        serde_json::from_value(Value::Object(attributes))
+            .map(Some)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// parses a `/api/history/period` response, keeping only the attributes in `whitelist`
+pub fn parse_filtered(
+    body: &[u8],
+    whitelist: &[&str],
+) -> anyhow::Result<Vec<Vec<HistoryResponse>>> {
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    let groups = Groups(whitelist).deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(groups)
+}
+
+impl HomeAssistant {
+    /// queries `/api/history/period/<start>` and returns a Vec containing the
+    /// [`HistoryResponse`]s of each entity, oldest first
+    ///
+    /// with an attribute whitelist (see [`HistoryQuery::attributes`]) the other attributes are
+    /// dropped while parsing
+    pub async fn history_query(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        query: &HistoryQuery,
+    ) -> anyhow::Result<Vec<Vec<HistoryResponse>>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).history_query(query).await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn history_query(
+        &self,
+        query: &HistoryQuery,
+    ) -> anyhow::Result<Vec<Vec<HistoryResponse>>> {
+        let client = request(self.url, self.token, &query.path()?).await?;
+        if !client.status().is_success() {
+            return Err(anyhow::Error::msg(client.status()));
+        }
+        match &query.attributes {
+            Some(whitelist) if !query.no_attributes => {
+                let whitelist: Vec<&str> = whitelist.iter().map(String::as_str).collect();
+                parse_filtered(&client.bytes().await?, &whitelist)
+            }
+            _ => decode::json::<Vec<Vec<HistoryResponse>>>(client).await,
+        }
+    }
+}
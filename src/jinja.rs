@@ -0,0 +1,220 @@
+//! Builders for Jinja template expressions
+//!
+//! Entity ids, attributes and values are quoted and escaped, so they can not break out of the
+//! generated template.
+//! ```
+//! use homeassistant_rs::jinja::{is_state, relative_time, last_changed, states};
+//!
+//! let template = is_state("binary_sensor.door", "on")
+//!     .if_else(relative_time(last_changed("binary_sensor.door")), states("sensor.outside").float(0));
+//! assert_eq!(
+//!     template.template(),
+//!     "{{ (relative_time(states.binary_sensor.door.last_changed) if is_state('binary_sensor.door', 'on') else (states('sensor.outside') | float(0))) }}"
+//! );
+//! ```
+
+use serde_json::Value;
+
+/// a Jinja expression, [`Display`](std::fmt::Display) prints it without braces
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Expr(String);
+
+/// quotes `value` as a Jinja string literal
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// a literal, strings are quoted and JSON arrays and objects become lists and dicts
+pub fn value(value: impl Into<Value>) -> Expr {
+    fn render(value: &Value) -> String {
+        match value {
+            Value::Null => "none".to_owned(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) => value.to_string(),
+            Value::String(value) => quote(value),
+            Value::Array(values) => {
+                let values: Vec<_> = values.iter().map(render).collect();
+                format!("[{}]", values.join(", "))
+            }
+            Value::Object(map) => {
+                let pairs: Vec<_> = map
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", quote(key), render(value)))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+        }
+    }
+    Expr(render(&value.into()))
+}
+
+/// an expression written by hand, it is not escaped
+pub fn raw(expression: &str) -> Expr {
+    Expr(expression.to_owned())
+}
+
+/// `states('<entity_id>')`
+pub fn states(entity_id: &str) -> Expr {
+    Expr(format!("states({})", quote(entity_id)))
+}
+
+/// `state_attr('<entity_id>', '<attribute>')`
+pub fn state_attr(entity_id: &str, attribute: &str) -> Expr {
+    Expr(format!(
+        "state_attr({}, {})",
+        quote(entity_id),
+        quote(attribute)
+    ))
+}
+
+/// `is_state('<entity_id>', '<state>')`
+pub fn is_state(entity_id: &str, state: &str) -> Expr {
+    Expr(format!("is_state({}, {})", quote(entity_id), quote(state)))
+}
+
+/// `is_state_attr('<entity_id>', '<attribute>', <value>)`
+pub fn is_state_attr(entity_id: &str, attribute: &str, expected: impl Into<Value>) -> Expr {
+    Expr(format!(
+        "is_state_attr({}, {}, {})",
+        quote(entity_id),
+        quote(attribute),
+        value(expected)
+    ))
+}
+
+/// `states.<domain>.<object_id>.last_changed`
+///
+/// entity ids which are no valid identifiers are looked up with `expand` instead
+pub fn last_changed(entity_id: &str) -> Expr {
+    let identifier = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+    match entity_id.split_once('.') {
+        Some((domain, object_id)) if identifier(domain) && identifier(object_id) => {
+            Expr(format!("states.{domain}.{object_id}.last_changed"))
+        }
+        _ => Expr(format!(
+            "(expand({}) | first).last_changed",
+            quote(entity_id)
+        )),
+    }
+}
+
+/// `relative_time(<datetime>)`, e.g. `5 minutes`
+pub fn relative_time(datetime: Expr) -> Expr {
+    Expr(format!("relative_time({datetime})"))
+}
+
+/// `now()`
+pub fn now() -> Expr {
+    Expr("now()".to_owned())
+}
+
+impl Expr {
+    /// the expression as template, `{{ <expression> }}`
+    pub fn template(&self) -> String {
+        format!("{{{{ {} }}}}", self.0)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// applies the filter `name`, with optional arguments
+    pub fn filter(self, name: &str, args: &[Expr]) -> Expr {
+        if args.is_empty() {
+            Expr(format!("({} | {name})", self.0))
+        } else {
+            let args: Vec<_> = args.iter().map(Expr::as_str).collect();
+            Expr(format!("({} | {name}({}))", self.0, args.join(", ")))
+        }
+    }
+
+    /// `| float(<default>)`
+    pub fn float(self, default: impl Into<Value>) -> Expr {
+        self.filter("float", &[value(default)])
+    }
+
+    /// `| int(<default>)`
+    pub fn int(self, default: impl Into<Value>) -> Expr {
+        self.filter("int", &[value(default)])
+    }
+
+    /// `| round(<precision>)`
+    pub fn round(self, precision: u32) -> Expr {
+        self.filter("round", &[value(precision)])
+    }
+
+    /// `| default(<fallback>)`
+    pub fn default(self, fallback: impl Into<Value>) -> Expr {
+        self.filter("default", &[value(fallback)])
+    }
+
+    fn binary(self, operator: &str, other: Expr) -> Expr {
+        Expr(format!("({} {operator} {})", self.0, other.0))
+    }
+
+    pub fn eq(self, other: impl Into<Value>) -> Expr {
+        self.binary("==", value(other))
+    }
+
+    pub fn ne(self, other: impl Into<Value>) -> Expr {
+        self.binary("!=", value(other))
+    }
+
+    pub fn gt(self, other: impl Into<Value>) -> Expr {
+        self.binary(">", value(other))
+    }
+
+    pub fn lt(self, other: impl Into<Value>) -> Expr {
+        self.binary("<", value(other))
+    }
+
+    pub fn and(self, other: Expr) -> Expr {
+        self.binary("and", other)
+    }
+
+    pub fn or(self, other: Expr) -> Expr {
+        self.binary("or", other)
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Expr {
+        Expr(format!("(not {})", self.0))
+    }
+
+    /// `(<then> if <self> else <otherwise>)`
+    pub fn if_else(self, then: Expr, otherwise: Expr) -> Expr {
+        Expr(format!("({then} if {} else {otherwise})", self.0))
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<Expr> for crate::structs::TemplateRequest {
+    fn from(expr: Expr) -> Self {
+        Self {
+            template: expr.template(),
+        }
+    }
+}
@@ -6,7 +6,16 @@
 //!
 //! These arguments do not have to be filled with actual data, they can be `None`, but in this case you will need to use environment variables.
 //!
-//! Under the hood we use dotenvy.
+//! A [`Client`](client::Client) stores both instead, so calls become `client.config().await`.
+//!
+//! Clients, connections, subscriptions and streams are `Send + Sync`, a
+//! [`Client`](client::Client) or [`HomeAssistantWs`](ws::HomeAssistantWs) can be cloned into every
+//! task (e.g. as axum state) and used concurrently. Process-wide state ([`settings`], [`cassette`],
+//! [`failover`], [`ratelimit`], the `audit` log) is behind locks, which stay usable even if a
+//! thread panicked while holding them.
+//!
+//! Under the hood we use dotenvy. Environment lookups can be disabled entirely with
+//! [`settings::set_pure_parameters`].
 //!
 //! Example env:
 //! ```text
@@ -15,7 +24,7 @@
 //! ```
 //!
 //! - Easily get HA's config:
-//! ```
+//! ```no_run
 //! # use tokio::runtime::Runtime;
 //! # let rt = Runtime::new().unwrap();
 //! # rt.block_on(async {
@@ -28,10 +37,12 @@
 //!
 //! You can check all available endpoints here: [`HomeAssistant`]
 //!
-//! - More Examples:
+//! The commonly needed types are available through `use homeassistant_rs::prelude::*;`
 //!
+//! - More Examples, runnable ones are in the `examples` directory:
 //!
-//! ```
+//!
+//! ```no_run
 //! # use tokio::runtime::Runtime;
 //! # let rt = Runtime::new().unwrap();
 //! # rt.block_on(async {
@@ -53,7 +64,7 @@
 //!         true,
 //!     )
 //!     .await.unwrap();
-//! hass().logbook(None, None, Some("light.bedroom_light_shelly")).await.unwrap();
+//! hass().logbook(None, None, Some("light.bedroom_light_shelly"), None, None).await.unwrap();
 //! hass().states(None, None, Some("light.bedroom_light_shelly")).await.unwrap();
 //! hass().states(None, None, None).await.unwrap();
 //! hass().error_log(None, None).await.unwrap();
@@ -62,14 +73,77 @@
 
 #[cfg(test)]
 mod tests;
+// re-exported dependencies, so users can name the types appearing in our API without depending on
+// the exact same versions themselves. Most code only needs the [`prelude`]
 pub use ::bytes;
+pub use ::futures_util;
 pub use ::lazy_static;
 pub use ::reqwest;
 pub use ::serde;
 pub use ::serde_json;
+use std::borrow::Cow;
+
 use serde_json::json;
 
+pub mod analysis;
+#[cfg(feature = "audit")]
+pub mod audit;
+pub mod backfill;
+pub mod battery;
+pub mod camera;
+pub mod cassette;
+pub mod client;
+pub mod cloud;
+pub mod codec;
+pub mod compat;
+#[cfg(feature = "control")]
+pub mod control;
+pub mod correlation;
+pub mod customize;
+pub mod decode;
+pub mod device_automation;
+pub mod domains;
+pub mod download;
+pub mod error_log;
+pub mod events;
+pub mod failover;
+pub mod filters;
+pub mod format;
+pub mod health;
+pub mod history;
+pub mod jinja;
+pub mod meters;
+pub mod mjpeg;
+pub mod occupancy;
+pub mod orchestrate;
+pub mod prelude;
+pub mod ratelimit;
+pub mod registry;
+pub mod rename;
+pub mod responses;
+pub mod retry;
+#[cfg(feature = "satellite")]
+pub mod satellite;
+pub mod scene;
+pub mod secret;
+pub mod services;
+pub mod settings;
+pub mod sse;
+pub mod stats;
+pub mod storage;
+pub mod streams;
 pub mod structs;
+pub mod stt;
+pub mod system_log;
+pub mod tags;
+pub mod templates;
+pub mod threshold;
+pub mod timestamp;
+pub mod urls;
+pub mod watch;
+pub mod windowed;
+pub mod ws;
+pub mod zones;
 
 // ### BEGIN INTERNAL USE ONLY ###
 
@@ -81,20 +155,19 @@ lazy_static::lazy_static! {
 
 struct GlobalVars {
     url: Option<String>,
-    token: Option<String>,
+    token: Option<secret::SecretString>,
 }
 
 impl GlobalVars {
     fn new() -> Self {
         Self {
             url: dotenvy::var("HA_URL").ok(),
-            token: dotenvy::var("HA_TOKEN").ok(),
+            token: dotenvy::var("HA_TOKEN").ok().map(Into::into),
         }
     }
 }
 
 fn globalvars() -> &'static GlobalVars {
-    GlobalVars::new();
     &GLOBAL_VARS
 }
 
@@ -114,36 +187,235 @@ fn validate() -> Validate {
     Validate
 }
 
-async fn request(url: String, token: String, path: &str) -> anyhow::Result<reqwest::Response> {
-    Ok(CLIENT
-        .get(url.to_owned() + path)
-        .bearer_auth(token)
-        .send()
-        .await?)
+/// returns the url and token, falling back to `HA_URL`/`HA_TOKEN` if not provided and not in
+/// pure-parameter mode
+///
+/// values from the environment are borrowed instead of copied for every call
+fn credentials(
+    ha_url: Option<String>,
+    ha_token: Option<String>,
+) -> anyhow::Result<(Cow<'static, str>, Cow<'static, str>)> {
+    let env_lookup = settings::env_lookup();
+    let missing = |name| settings::MissingParameter { name, env_lookup };
+    if !env_lookup {
+        let url = validate().arg(ha_url).map_err(|_| missing("HA_URL"))?;
+        let token = validate().arg(ha_token).map_err(|_| missing("HA_TOKEN"))?;
+        return Ok((url.into(), token.into()));
+    }
+    let vars = globalvars();
+    let url = match validate().arg(ha_url) {
+        Ok(url) => Cow::Owned(url),
+        Err(_) => Cow::Borrowed(vars.url.as_deref().ok_or(missing("HA_URL"))?),
+    };
+    let token = match validate().arg(ha_token) {
+        Ok(token) => Cow::Owned(token),
+        Err(_) => Cow::Borrowed(
+            vars.token
+                .as_ref()
+                .map(|token| token.expose_secret())
+                .ok_or(missing("HA_TOKEN"))?,
+        ),
+    };
+    Ok((url, token))
+}
+
+/// the url and token of a call, borrowed instead of passed as `ha_url` and `ha_token`, e.g. by a
+/// [`Client`](client::Client)
+///
+/// implements the endpoints, the [`HomeAssistant`] methods of the same name resolve their
+/// credentials and call them
+#[derive(Clone, Copy)]
+pub(crate) struct Api<'a> {
+    url: &'a str,
+    token: &'a str,
+}
+
+impl<'a> Api<'a> {
+    pub(crate) fn new(url: &'a str, token: &'a str) -> Self {
+        Self { url, token }
+    }
+}
+
+/// like [`Api`], for the [`HomeAssistantPost`] methods
+#[derive(Clone, Copy)]
+pub(crate) struct ApiPost<'a> {
+    url: &'a str,
+    token: &'a str,
+}
+
+impl<'a> ApiPost<'a> {
+    pub(crate) fn new(url: &'a str, token: &'a str) -> Self {
+        Self { url, token }
+    }
+}
+
+async fn request(url: &str, token: &str, path: &str) -> anyhow::Result<reqwest::Response> {
+    send(reqwest::Method::GET, url, token, path, Body::Empty).await
+}
+
+/// like [`request`], without the total timeout of a [`ClientConfig`](settings::ClientConfig), for
+/// responses which are read for a long time
+async fn request_stream(url: &str, token: &str, path: &str) -> anyhow::Result<reqwest::Response> {
+    send_with(
+        settings::stream_client(),
+        reqwest::Method::GET,
+        url,
+        token,
+        path,
+        Body::Empty,
+    )
+    .await
 }
 
 async fn post<T: serde::Serialize>(
-    url: String,
-    token: String,
+    url: &str,
+    token: &str,
     path: &str,
     json: T,
 ) -> anyhow::Result<reqwest::Response> {
-    if !serde_json::to_string(&json)?.is_empty() {
-        Ok(CLIENT
-            .post(url.to_owned() + path)
-            .bearer_auth(token)
-            .json(&json)
-            .send()
-            .await?)
-    } else {
-        Ok(CLIENT
-            .post(url.to_owned() + path)
-            .bearer_auth(token)
-            .send()
-            .await?)
+    let body = serde_json::to_vec(&json)?;
+    send(
+        reqwest::Method::POST,
+        url,
+        token,
+        path,
+        Body::Json(body.into()),
+    )
+    .await
+}
+
+/// request body of [`send`]
+#[derive(Clone)]
+enum Body {
+    Empty,
+    /// cheap to clone for retries
+    Json(bytes::Bytes),
+    /// binary data with its own headers (e.g. audio), not recorded by cassettes
+    Raw {
+        headers: reqwest::header::HeaderMap,
+        data: bytes::Bytes,
+    },
+}
+
+impl Body {
+    /// textual representation for cassettes
+    fn text(&self) -> Option<String> {
+        match self {
+            Body::Json(body) => Some(String::from_utf8_lossy(body).into_owned()),
+            Body::Empty | Body::Raw { .. } => None,
+        }
     }
 }
 
+async fn send(
+    method: reqwest::Method,
+    url: &str,
+    token: &str,
+    path: &str,
+    body: Body,
+) -> anyhow::Result<reqwest::Response> {
+    send_with(settings::http_client(), method, url, token, path, body).await
+}
+
+async fn send_with(
+    client: reqwest::Client,
+    method: reqwest::Method,
+    url: &str,
+    token: &str,
+    path: &str,
+    body: Body,
+) -> anyhow::Result<reqwest::Response> {
+    stats::add(&stats::COUNTERS.requests_sent, 1);
+    #[cfg(feature = "audit")]
+    let audited = audit::is_enabled()
+        .then(|| audit::classify_rest(&method, path))
+        .flatten()
+        .map(|mutation| (mutation, body.text().unwrap_or_default()));
+    let response = dispatch(client, method, url, token, path, body).await;
+    if let Ok(response) = &response {
+        stats::add(
+            &stats::COUNTERS.bytes_received,
+            response.content_length().unwrap_or(0),
+        );
+    }
+    #[cfg(feature = "audit")]
+    if let Some(((kind, target), payload)) = audited {
+        let success = response.as_ref().is_ok_and(|r| r.status().is_success());
+        audit::record(
+            kind,
+            target,
+            audit::Transport::Rest,
+            payload.as_bytes(),
+            success,
+        );
+    }
+    response
+}
+
+async fn dispatch(
+    client: reqwest::Client,
+    method: reqwest::Method,
+    url: &str,
+    token: &str,
+    path: &str,
+    body: Body,
+) -> anyhow::Result<reqwest::Response> {
+    let build = async |url: &str| -> anyhow::Result<reqwest::RequestBuilder> {
+        let builder = client
+            .request(method.clone(), urls::join(url, path)?)
+            .bearer_auth(token);
+        let builder = match body.clone() {
+            Body::Json(body) if !body.is_empty() => builder
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body),
+            Body::Raw { headers, data } => builder.headers(headers).body(data),
+            _ => builder,
+        };
+        settings::apply(builder).await
+    };
+
+    if let Some(cassette) = cassette::active()? {
+        let builder = build(url).await?;
+        return cassette
+            .handle(token, method.as_str(), path, body.text(), builder)
+            .await;
+    }
+
+    let policy = settings::retry_policy();
+    retry::run(policy.as_ref(), &method, || async {
+        // outgoing calls are paused while Homeassistant restarts
+        ws::wait_for_restart(url).await;
+
+        let mut error = None;
+        let fallbacks = failover::fallback_candidates(url);
+        let candidates = fallbacks
+            .iter()
+            .map(String::as_str)
+            .chain(fallbacks.is_empty().then_some(url));
+        for candidate in candidates {
+            match build(candidate).await?.send().await {
+                Ok(response) => {
+                    failover::mark(candidate, true);
+                    // a reverse proxy answers 502 while Homeassistant is stopped
+                    if response.status() == reqwest::StatusCode::BAD_GATEWAY {
+                        ws::check_restart(url).await;
+                    }
+                    return Ok(response);
+                }
+                // a request which timed out may have been executed, only GET requests are safe to
+                // send to another url then
+                Err(e) if e.is_connect() || (method == reqwest::Method::GET && e.is_timeout()) => {
+                    failover::mark(candidate, false);
+                    error = Some(e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(error.map_or(anyhow::Error::msg("no url to connect to"), Into::into))
+    })
+    .await
+}
+
 // ### END INTERNAL USE ONLY ###
 
 pub struct HomeAssistant;
@@ -153,30 +425,115 @@ impl HomeAssistant {
         &HomeAssistantPost
     }
 
+    /// returns counters about requests, connections and caches, see [`stats`]
+    pub fn stats(&self) -> stats::Stats {
+        stats::stats()
+    }
+
+    /// returns the supported Homeassistant releases and enabled features, see [`compat`]
+    pub fn capabilities(&self) -> compat::Capabilities {
+        compat::capabilities()
+    }
+
+    /// connects to `/api/websocket` and returns an authenticated [`HomeAssistantWs`](ws::HomeAssistantWs)
+    pub async fn websocket(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<ws::HomeAssistantWs> {
+        self.websocket_with_codec(ha_url, ha_token, codec::Json)
+            .await
+    }
+
+    /// like [`websocket`](Self::websocket), but decodes incoming frames with a custom [`Codec`](codec::Codec)
+    pub async fn websocket_with_codec(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        codec: impl codec::Codec,
+    ) -> anyhow::Result<ws::HomeAssistantWs> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+
+        ws::HomeAssistantWs::connect(
+            &url,
+            &token,
+            std::sync::Arc::new(codec),
+            ws::Limits::default(),
+        )
+        .await
+    }
+
+    /// like [`websocket`](Self::websocket), with custom [`Limits`](ws::Limits), e.g. a smaller
+    /// maximum message size
+    pub async fn websocket_with_limits(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        limits: ws::Limits,
+    ) -> anyhow::Result<ws::HomeAssistantWs> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+
+        ws::HomeAssistantWs::connect(&url, &token, std::sync::Arc::new(codec::Json), limits).await
+    }
+
     /// queries `/api/config` and returns [`ConfigResponse`](structs::ConfigResponse) struct
     pub async fn config(
         &self,
         ha_url: Option<String>,
         ha_token: Option<String>,
     ) -> anyhow::Result<structs::ConfigResponse> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).config().await
+    }
 
-        let client = request(url, token, "/api/config").await?;
-        if !client.status().is_success() {
-            Err(anyhow::Error::msg(client.status()))
-        } else {
-            Ok(client.json::<structs::ConfigResponse>().await?)
-        }
+    /// queries `/api/config` and fails with a list of the missing integrations unless all of
+    /// `domains` are loaded
+    pub async fn require_components(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        domains: &[&str],
+    ) -> anyhow::Result<()> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).require_components(domains).await
+    }
+
+    /// queries `/api/core/state` and returns [`CoreState`](structs::CoreState) struct
+    pub async fn core_state(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<structs::CoreState> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).core_state().await
+    }
+
+    /// polls `/api/` and `/api/core/state` until Homeassistant reports `RUNNING`, meant to be
+    /// awaited before the rest of an application starts
+    ///
+    /// connection errors and errors while starting are retried with a backoff from 250ms up to
+    /// 10s, a rejected token fails immediately. Fails with the last error after `timeout`
+    pub async fn wait_until_ready(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<structs::CoreState> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).wait_until_ready(timeout).await
+    }
+
+    /// concurrently queries the config, core state and states and returns an
+    /// [`InstanceInfo`](structs::InstanceInfo) summary
+    ///
+    /// fails if any of them fails, except for a 404 of the core state on older instances
+    pub async fn instance_info(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<structs::InstanceInfo> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).instance_info().await
     }
 
     /// queries `/api/events` and returns a Vec containing [`EventResponse`](structs::EventResponse) struct    
@@ -185,51 +542,23 @@ impl HomeAssistant {
         ha_url: Option<String>,
         ha_token: Option<String>,
     ) -> anyhow::Result<Vec<structs::EventResponse>> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
-        let client = request(url, token, "/api/events").await?;
-
-        if !client.status().is_success() {
-            Err(anyhow::Error::msg(client.status()))
-        } else {
-            Ok(client.json::<Vec<structs::EventResponse>>().await?)
-        }
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).events().await
     }
 
-    /// queries `/api/services` and returns a Vec containing [`ServicesResponse`](structs::ServicesResponse) (subject to possibly change in the future)
+    /// queries `/api/services` and returns a Vec containing [`ServicesResponse`](structs::ServicesResponse) per domain
     pub async fn services(
         &self,
         ha_url: Option<String>,
         ha_token: Option<String>,
     ) -> anyhow::Result<Vec<structs::ServicesResponse>> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
-        let client = request(url, token, "/api/services").await?.json::<Vec<structs::ServicesResponse>>().await?;
-
-        Ok(client)
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).services().await
     }
 
     /// queries `/api/history/period/<optionalargs>` and returns a Vec containing [`HistoryResponse`](structs::HistoryResponse) struct
+    ///
+    /// covers the last day, see [`history_query`](Self::history_query) for other periods or several entities
     pub async fn history(
         &self,
         ha_url: Option<String>,
@@ -239,41 +568,323 @@ impl HomeAssistant {
         no_attributes: bool,
         significant_changes_only: bool,
     ) -> anyhow::Result<Vec<structs::HistoryResponse>> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
-        let path = format!(
-            "?filter_entity_id={0}{1}{2}{3}",
-            ha_entity_id.unwrap_or(""),
-            if minimal_response {
-                "&minimal_response"
-            } else {
-                ""
-            },
-            if no_attributes { "&no_attributes" } else { "" },
-            if significant_changes_only {
-                "&significant_changes_only"
-            } else {
-                ""
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .history(
+                ha_entity_id,
+                minimal_response,
+                no_attributes,
+                significant_changes_only,
+            )
+            .await
+    }
+
+    /// queries `/api/logbook/<start>?entity=<entity_id>&end_time=<end>` and returns a Vec containing [`LogBook`](structs::LogBook) struct
+    ///
+    /// without `start` Homeassistant returns the entries of the last day, without `end` the
+    /// entries up to now. Times in other zones are converted with `with_timezone(&Utc)`
+    pub async fn logbook(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: Option<&str>,
+        start: Option<chrono::DateTime<chrono::Utc>>,
+        end: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> anyhow::Result<Vec<structs::LogBook>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .logbook(ha_entity_id, start, end)
+            .await
+    }
+
+    /// queries `/api/states/<optional_entity_id>` and returns a Vec containing [`StatesResponse`](structs::StatesResponse) struct
+    pub async fn states(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: Option<&str>,
+    ) -> anyhow::Result<Vec<structs::StatesResponse>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).states(ha_entity_id).await
+    }
+
+    /// queries `/api/error_log` and returns a [`String`]
+    pub async fn error_log(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<String> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).error_log().await
+    }
+
+    /// queries `/api/error_log` and streams it into `writer` without buffering the whole log, returns the number of bytes written
+    pub async fn download_error_log(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        writer: impl tokio::io::AsyncWrite + Unpin,
+    ) -> anyhow::Result<u64> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).download_error_log(writer).await
+    }
+
+    /// like [`download_error_log`](Self::download_error_log), calls `progress` with the bytes written so far after every chunk
+    pub async fn download_error_log_with_progress(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        writer: impl tokio::io::AsyncWrite + Unpin,
+        progress: impl FnMut(structs::Progress),
+    ) -> anyhow::Result<u64> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .download_error_log_with_progress(writer, progress)
+            .await
+    }
+
+    /// like [`download_error_log`](Self::download_error_log), but gzip compresses the log on the fly, returns the number of uncompressed bytes
+    #[cfg(feature = "gzip")]
+    pub async fn download_error_log_gzip(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        writer: impl tokio::io::AsyncWrite + Unpin,
+    ) -> anyhow::Result<u64> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).download_error_log_gzip(writer).await
+    }
+
+    /// queries `/api/camera_proxy/<camera_entity_id>?time=<timestamp>` and returns [`Bytes`](bytes::Bytes)
+    ///
+    /// input parameter `time` as anything convertible into a [`Timestamp`](timestamp::Timestamp), e.g. `unix_time` in seconds ([`u64`])
+    ///
+    /// for scaled images or the content type see [`camera_snapshot`](HomeAssistant::camera_snapshot)
+    ///
+    /// <sub>WARNING: Further testing is required for this function, as i (Blexyel) am not able to test it myself</sub>
+    pub async fn camera_proxy(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: &str,
+        time: impl Into<timestamp::Timestamp>,
+    ) -> anyhow::Result<bytes::Bytes> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .camera_proxy(ha_entity_id, time)
+            .await
+    }
+
+    /// queries `/api/camera_proxy/<camera_entity_id>` and streams the image into the file at `path`, returns its [`SnapshotInfo`](structs::SnapshotInfo)
+    pub async fn camera_snapshot_to(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: &str,
+        path: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<structs::SnapshotInfo> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .camera_snapshot_to(ha_entity_id, path)
+            .await
+    }
+
+    /// like [`camera_snapshot_to`](Self::camera_snapshot_to), calls `progress` with the bytes written so far after every chunk
+    pub async fn camera_snapshot_to_with_progress(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: &str,
+        path: impl AsRef<std::path::Path>,
+        progress: impl FnMut(structs::Progress),
+    ) -> anyhow::Result<structs::SnapshotInfo> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .camera_snapshot_to_with_progress(ha_entity_id, path, progress)
+            .await
+    }
+
+    /// queries `/api/camera_proxy_stream/<camera_entity_id>` and returns a [`MjpegStream`](mjpeg::MjpegStream)
+    pub async fn camera_stream(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: &str,
+    ) -> anyhow::Result<mjpeg::MjpegStream> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).camera_stream(ha_entity_id).await
+    }
+
+    /// queries `/api/calendars` and returns a Vec containing [`CalendarResponse`](structs::CalendarResponse)
+    pub async fn calendars(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<Vec<structs::CalendarResponse>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).calendars().await
+    }
+
+    /// queries `/api/calendars/<calendar entity_id>?start=<timestamp>&end=<timestamp>` and returns a Vec containing the [`CalendarEvent`](structs::CalendarEvent)s overlapping `start..end`
+    pub async fn calendar_events(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        ha_entity_id: &str,
+        start: impl Into<timestamp::Timestamp>,
+        end: impl Into<timestamp::Timestamp>,
+    ) -> anyhow::Result<Vec<structs::CalendarEvent>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .calendar_events(ha_entity_id, start, end)
+            .await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn config(&self) -> anyhow::Result<structs::ConfigResponse> {
+        let client = request(self.url, self.token, "/api/config").await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            decode::json::<structs::ConfigResponse>(client).await
+        }
+    }
+
+    pub(crate) async fn require_components(&self, domains: &[&str]) -> anyhow::Result<()> {
+        let config = self.config().await?;
+        let missing = config.missing_components(domains);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg(format!(
+                "missing integrations: {} (Homeassistant {})",
+                missing.join(", "),
+                config.version
+            )))
+        }
+    }
+
+    pub(crate) async fn core_state(&self) -> anyhow::Result<structs::CoreState> {
+        let client = request(self.url, self.token, "/api/core/state").await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            decode::json::<structs::CoreState>(client).await
+        }
+    }
+
+    pub(crate) async fn wait_until_ready(
+        &self,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<structs::CoreState> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut delay = std::time::Duration::from_millis(250);
+
+        loop {
+            let error = match request(self.url, self.token, "/api/").await {
+                Ok(client) if client.status().is_success() => match self.core_state().await {
+                    Ok(state) if state.state == "RUNNING" => return Ok(state),
+                    Ok(state) => anyhow::Error::msg(format!("Homeassistant is {}", state.state)),
+                    Err(e) => e,
+                },
+                Ok(client)
+                    if matches!(
+                        client.status(),
+                        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+                    ) =>
+                {
+                    return Err(anyhow::Error::msg(client.status()));
+                }
+                Ok(client) => anyhow::Error::msg(client.status()),
+                Err(e) => e,
+            };
+
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Err(error.context(format!("Homeassistant not ready after {timeout:?}")));
             }
-        );
+            tokio::time::sleep(delay.min(deadline - now)).await;
+            delay = (delay * 2).min(std::time::Duration::from_secs(10));
+        }
+    }
+
+    pub(crate) async fn instance_info(&self) -> anyhow::Result<structs::InstanceInfo> {
+        let (config, core_state, states) =
+            tokio::join!(self.config(), self.core_state(), self.states(None));
+        let (config, states) = (config?, states?);
+
+        let mut entities_per_domain = std::collections::BTreeMap::new();
+        for state in &states {
+            let domain = state
+                .entity_id
+                .as_deref()
+                .and_then(|entity_id| entity_id.split_once('.'))
+                .map_or("", |(domain, _)| domain);
+            *entities_per_domain.entry(domain.to_owned()).or_insert(0) += 1;
+        }
+        let mut components: Vec<String> = config
+            .components
+            .iter()
+            .filter(|component| !component.contains('.'))
+            .cloned()
+            .collect();
+        components.sort();
+
+        let core_state = match core_state {
+            Ok(core_state) => Some(core_state),
+            // older instances do not have this endpoint
+            Err(e) if e.downcast_ref() == Some(&reqwest::StatusCode::NOT_FOUND) => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(structs::InstanceInfo {
+            core_state,
+            components,
+            entity_count: states.len(),
+            entities_per_domain,
+            config,
+        })
+    }
 
-        let client = request(url, token, &format!("/api/history/period{path}")).await?;
+    pub(crate) async fn events(&self) -> anyhow::Result<Vec<structs::EventResponse>> {
+        let client = request(self.url, self.token, "/api/events").await?;
 
         if !client.status().is_success() {
             Err(anyhow::Error::msg(client.status()))
         } else {
-            Ok(client
-                .json::<Vec<Vec<structs::HistoryResponse>>>()
+            decode::json::<Vec<structs::EventResponse>>(client).await
+        }
+    }
+
+    pub(crate) async fn services(&self) -> anyhow::Result<Vec<structs::ServicesResponse>> {
+        let client = decode::json::<Vec<structs::ServicesResponse>>(
+            request(self.url, self.token, "/api/services").await?,
+        )
+        .await?;
+
+        Ok(client)
+    }
+
+    pub(crate) async fn history(
+        &self,
+        ha_entity_id: Option<&str>,
+        minimal_response: bool,
+        no_attributes: bool,
+        significant_changes_only: bool,
+    ) -> anyhow::Result<Vec<structs::HistoryResponse>> {
+        let path = urls::Query::new()
+            .opt("filter_entity_id", ha_entity_id)
+            .flag("minimal_response", minimal_response)
+            .flag("no_attributes", no_attributes)
+            .flag("significant_changes_only", significant_changes_only);
+
+        let client = request(self.url, self.token, &format!("/api/history/period{path}")).await?;
+
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            Ok(decode::json::<Vec<Vec<structs::HistoryResponse>>>(client)
                 .await?
                 .into_iter()
                 .flatten()
@@ -281,130 +892,113 @@ impl HomeAssistant {
         }
     }
 
-    /// queries `/api/logbook` and returns a Vec containing [`LogBook`](structs::LogBook) struct
-    pub async fn logbook(
+    pub(crate) async fn logbook(
         &self,
-        ha_url: Option<String>,
-        ha_token: Option<String>,
         ha_entity_id: Option<&str>,
+        start: Option<chrono::DateTime<chrono::Utc>>,
+        end: Option<chrono::DateTime<chrono::Utc>>,
     ) -> anyhow::Result<Vec<structs::LogBook>> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
+        let start = start
+            .map(|start| format!("/{}", urls::encode_segment(&start.to_rfc3339())))
+            .unwrap_or_default();
+        let end = end.map(|end| end.to_rfc3339());
         let client = request(
-            url,
-            token,
+            self.url,
+            self.token,
             &format!(
-                "/api/logbook{0}",
-                ("?".to_owned() + ha_entity_id.unwrap_or(""))
+                "/api/logbook{start}{}",
+                urls::Query::new()
+                    .opt("entity", ha_entity_id)
+                    .opt("end_time", end)
             ),
         )
         .await?;
         if !client.status().is_success() {
             Err(anyhow::Error::msg(client.status()))
         } else {
-            Ok(client.json::<Vec<structs::LogBook>>().await?)
+            decode::json::<Vec<structs::LogBook>>(client).await
         }
     }
 
-    /// queries `/api/states/<optional_entity_id>` and returns a Vec containing [`StatesResponse`](structs::StatesResponse) struct
-    pub async fn states(
+    pub(crate) async fn states(
         &self,
-        ha_url: Option<String>,
-        ha_token: Option<String>,
         ha_entity_id: Option<&str>,
     ) -> anyhow::Result<Vec<structs::StatesResponse>> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
         let entity_id = ha_entity_id.unwrap_or_default();
 
         let client = if entity_id.is_empty() {
-            request(url, token, "/api/states")
-                .await?
-                .json::<Vec<structs::StatesResponse>>()
-                .await?
+            decode::json::<Vec<structs::StatesResponse>>(
+                request(self.url, self.token, "/api/states").await?,
+            )
+            .await?
         } else {
             vec![
-                request(url, token, &format!("/api/states/{entity_id}"))
-                    .await?
-                    .json::<structs::StatesResponse>()
+                decode::json::<structs::StatesResponse>(
+                    request(
+                        self.url,
+                        self.token,
+                        &format!("/api/states/{}", urls::encode_segment(entity_id)),
+                    )
                     .await?,
+                )
+                .await?,
             ]
         };
 
         Ok(client)
     }
 
-    /// queries `/api/error_log` and returns a [`String`]
-    pub async fn error_log(
+    pub(crate) async fn error_log(&self) -> anyhow::Result<String> {
+        let client = request(self.url, self.token, "/api/error_log").await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            Ok(client.text().await?)
+        }
+    }
+
+    pub(crate) async fn download_error_log(
         &self,
-        ha_url: Option<String>,
-        ha_token: Option<String>,
-    ) -> anyhow::Result<String> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
+        writer: impl tokio::io::AsyncWrite + Unpin,
+    ) -> anyhow::Result<u64> {
+        self.download_error_log_with_progress(writer, |_| ()).await
+    }
 
-        let client = request(url, token, "/api/states").await?.text().await?;
+    pub(crate) async fn download_error_log_with_progress(
+        &self,
+        writer: impl tokio::io::AsyncWrite + Unpin,
+        progress: impl FnMut(structs::Progress),
+    ) -> anyhow::Result<u64> {
+        self.error_log_download()
+            .await?
+            .write_to_with_progress(writer, progress)
+            .await
+    }
 
-        Ok(client)
+    #[cfg(feature = "gzip")]
+    pub(crate) async fn download_error_log_gzip(
+        &self,
+        writer: impl tokio::io::AsyncWrite + Unpin,
+    ) -> anyhow::Result<u64> {
+        let mut encoder = async_compression::tokio::write::GzipEncoder::new(writer);
+        let written = self.download_error_log(&mut encoder).await?;
+        tokio::io::AsyncWriteExt::shutdown(&mut encoder).await?;
+        Ok(written)
     }
 
-    /// queries `/api/camera_proxy/<camera_entity_id>?time=<timestamp>` and returns [`Bytes`](bytes::Bytes)
-    ///
-    /// input parameter `time` as `unix_time` in seconds ([`u64`])
-    ///
-    /// <sub>WARNING: Further testing is required for this function, as i (Blexyel) am not able to test it myself</sub>
-    pub async fn camera_proxy(
+    pub(crate) async fn camera_proxy(
         &self,
-        ha_url: Option<String>,
-        ha_token: Option<String>,
         ha_entity_id: &str,
-        time: u64,
+        time: impl Into<timestamp::Timestamp>,
     ) -> anyhow::Result<bytes::Bytes> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
         let client = request(
-            url,
-            token,
-            &format!("/api/camera_proxy/{ha_entity_id}?time={time}"),
+            self.url,
+            self.token,
+            &format!(
+                "/api/camera_proxy/{}{}",
+                urls::encode_segment(ha_entity_id),
+                urls::Query::new().param("time", time.into().unix_seconds()?)
+            ),
         )
         .await?
         .bytes()
@@ -413,35 +1007,83 @@ impl HomeAssistant {
         Ok(client)
     }
 
-    /// queries `/api/calendars/<calendar entity_id>?start=<timestamp>&end=<timestamp>` and returns a Vec containing `[CalendarResponse`](structs::CalendarResponse)
-    #[allow(unreachable_code, unused_variables)]
-    pub async fn calendars(
+    pub(crate) async fn camera_snapshot_to(
         &self,
-        ha_url: Option<String>,
-        ha_token: Option<String>,
-    ) -> anyhow::Result<Vec<structs::CalendarResponse>> {
-        unimplemented!(
-            "I (Blexyel) am unable to implement this function, as (apparently) my HASS instance does not have calendars. Feel free to make a PR to implement this feature"
-        );
-        {
-            let vars = globalvars();
-            let url = validate().arg(ha_url).or_else(|_| {
-                vars.url
-                    .clone()
-                    .ok_or(anyhow::Error::msg("HA_URL is required"))
-            })?;
-            let token = validate().arg(ha_token).or_else(|_| {
-                vars.token
-                    .clone()
-                    .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-            })?;
-
-            let client = request(url, token, "/api/calendars").await?.bytes().await?;
-
-            Ok(vec![structs::CalendarResponse {
-                entity_id: todo!(),
-                name: todo!(),
-            }])
+        ha_entity_id: &str,
+        path: impl AsRef<std::path::Path>,
+    ) -> anyhow::Result<structs::SnapshotInfo> {
+        self.camera_snapshot_to_with_progress(ha_entity_id, path, |_| ())
+            .await
+    }
+
+    pub(crate) async fn camera_snapshot_to_with_progress(
+        &self,
+        ha_entity_id: &str,
+        path: impl AsRef<std::path::Path>,
+        progress: impl FnMut(structs::Progress),
+    ) -> anyhow::Result<structs::SnapshotInfo> {
+        let download = self
+            .camera_download(ha_entity_id, camera::SnapshotOptions::new())
+            .await?;
+
+        let content_type = download.content_type().map(str::to_owned);
+        let file = tokio::fs::File::create(path).await?;
+        let size = download.write_to_with_progress(file, progress).await?;
+
+        Ok(structs::SnapshotInfo { content_type, size })
+    }
+
+    pub(crate) async fn camera_stream(
+        &self,
+        ha_entity_id: &str,
+    ) -> anyhow::Result<mjpeg::MjpegStream> {
+        let client = request_stream(
+            self.url,
+            self.token,
+            &format!(
+                "/api/camera_proxy_stream/{}",
+                urls::encode_segment(ha_entity_id)
+            ),
+        )
+        .await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            mjpeg::MjpegStream::new(client)
+        }
+    }
+
+    pub(crate) async fn calendars(&self) -> anyhow::Result<Vec<structs::CalendarResponse>> {
+        let client = request(self.url, self.token, "/api/calendars").await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            decode::json::<Vec<structs::CalendarResponse>>(client).await
+        }
+    }
+
+    pub(crate) async fn calendar_events(
+        &self,
+        ha_entity_id: &str,
+        start: impl Into<timestamp::Timestamp>,
+        end: impl Into<timestamp::Timestamp>,
+    ) -> anyhow::Result<Vec<structs::CalendarEvent>> {
+        let client = request(
+            self.url,
+            self.token,
+            &format!(
+                "/api/calendars/{}{}",
+                urls::encode_segment(ha_entity_id),
+                urls::Query::new()
+                    .param("start", start.into().to_rfc3339()?)
+                    .param("end", end.into().to_rfc3339()?)
+            ),
+        )
+        .await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            decode::json::<Vec<structs::CalendarEvent>>(client).await
         }
     }
 }
@@ -449,32 +1091,19 @@ impl HomeAssistant {
 pub struct HomeAssistantPost;
 
 impl HomeAssistantPost {
-    /// posts to `/api/states/<entity_id>` to update/create a state and returns [`StatesResponse`](structs::StatesResponse)
+    /// posts to `/api/states/<entity_id>` to update/create a state and returns a [`StatePostResult`](structs::StatePostResult),
+    /// which tells whether the entity was created
     pub async fn state(
         &self,
         ha_url: Option<String>,
         ha_token: Option<String>,
         ha_entity_id: &str,
         request: structs::StatesRequest,
-    ) -> anyhow::Result<structs::StatesResponse> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
-        let client = post(url, token, &format!("/api/states/{ha_entity_id}"), request).await?;
-        if !client.status().is_success() {
-            Err(anyhow::Error::msg(client.status()))
-        } else {
-            Ok(client.json::<structs::StatesResponse>().await?)
-        }
+    ) -> anyhow::Result<structs::StatePostResult> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token)
+            .state(ha_entity_id, request)
+            .await
     }
     // I have been programming for ~7 Hours straight, I'm tired
 
@@ -491,29 +1120,17 @@ impl HomeAssistantPost {
         ha_event_type: &str,
         request: serde_json::Value,
     ) -> anyhow::Result<structs::SimpleResponse> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
-        let client = post(url, token, &format!("/api/events/{ha_event_type}"), request).await?;
-
-        if !client.status().is_success() {
-            Err(anyhow::Error::msg(client.status()))
-        } else {
-            Ok(client.json::<structs::SimpleResponse>().await?)
-        }
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token)
+            .events(ha_event_type, request)
+            .await
     }
 
     /// posts to `/api/services/<domain>/<service>` to call a service within a specific domain and returns [`Value`](serde_json::Value)
     ///
+    /// errors are returned as [`ServiceCallError`](services::ServiceCallError), calls to rate
+    /// limited entities may be delayed or [`Superseded`](ratelimit::Superseded)
+    ///
     /// request param does not need to have data, it can be empty, e.g.:
     /// ```ignore
     /// json!({})
@@ -527,38 +1144,27 @@ impl HomeAssistantPost {
         request: serde_json::Value,
         return_response: bool,
     ) -> anyhow::Result<serde_json::Value> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
-        let client = post(
-            url,
-            token,
-            &format!(
-                "/api/services/{ha_domain}/{ha_service}{0}",
-                if return_response {
-                    "?return_response"
-                } else {
-                    ""
-                }
-            ),
-            request,
-        )
-        .await?;
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token)
+            .service(ha_domain, ha_service, request, return_response)
+            .await
+    }
 
-        if !client.status().is_success() {
-            Err(anyhow::Error::msg(client.status()))
-        } else {
-            Ok(client.json::<serde_json::Value>().await?)
-        }
+    /// posts to `/api/services/logbook/log` to write a custom logbook entry, optionally attributed
+    /// to an `entity_id` and `domain`
+    pub async fn log_entry(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        name: &str,
+        message: &str,
+        entity_id: Option<&str>,
+        domain: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token)
+            .log_entry(name, message, entity_id, domain)
+            .await
     }
 
     /// posts to `/api/template` and renders a HASS template and returns [`String`]
@@ -568,24 +1174,8 @@ impl HomeAssistantPost {
         ha_token: Option<String>,
         request: structs::TemplateRequest,
     ) -> anyhow::Result<String> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
-        let client = post(url, token, "/api/template", request)
-            .await?
-            .text()
-            .await?;
-
-        Ok(client)
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token).template(request).await
     }
 
     /// posts to `/api/config/core/check_config` and checks the config and returns [`ConfigCheckResponse`](structs::ConfigCheckResponse)
@@ -594,25 +1184,8 @@ impl HomeAssistantPost {
         ha_url: Option<String>,
         ha_token: Option<String>,
     ) -> anyhow::Result<structs::ConfigCheckResponse> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
-
-        let client = post(url, token, "/api/config/core/check_config", json!({})).await?;
-
-        if !client.status().is_success() {
-            Err(anyhow::Error::msg(client.status()))
-        } else {
-            Ok(client.json::<structs::ConfigCheckResponse>().await?)
-        }
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token).config_check().await
     }
 
     /// posts to `/api/intent/handle` and handles an Intent and returns a [`String`]
@@ -624,19 +1197,140 @@ impl HomeAssistantPost {
         ha_token: Option<String>,
         request: serde_json::Value,
     ) -> anyhow::Result<String> {
-        let vars = globalvars();
-        let url = validate().arg(ha_url).or_else(|_| {
-            vars.url
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_URL is required"))
-        })?;
-        let token = validate().arg(ha_token).or_else(|_| {
-            vars.token
-                .clone()
-                .ok_or(anyhow::Error::msg("HA_TOKEN is required"))
-        })?;
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token).intent(request).await
+    }
+}
+
+impl ApiPost<'_> {
+    pub(crate) async fn state(
+        &self,
+        ha_entity_id: &str,
+        request: structs::StatesRequest,
+    ) -> anyhow::Result<structs::StatePostResult> {
+        let client = post(
+            self.url,
+            self.token,
+            &format!("/api/states/{}", urls::encode_segment(ha_entity_id)),
+            request,
+        )
+        .await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            let created = client.status() == reqwest::StatusCode::CREATED;
+            let location = client
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .map(str::to_owned);
+            Ok(structs::StatePostResult {
+                response: decode::json::<structs::StatesResponse>(client).await?,
+                created,
+                location,
+            })
+        }
+    }
+
+    pub(crate) async fn events(
+        &self,
+        ha_event_type: &str,
+        request: serde_json::Value,
+    ) -> anyhow::Result<structs::SimpleResponse> {
+        let client = post(
+            self.url,
+            self.token,
+            &format!("/api/events/{}", urls::encode_segment(ha_event_type)),
+            request,
+        )
+        .await?;
+
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            decode::json::<structs::SimpleResponse>(client).await
+        }
+    }
+
+    pub(crate) async fn service(
+        &self,
+        ha_domain: &str,
+        ha_service: &str,
+        request: serde_json::Value,
+        return_response: bool,
+    ) -> anyhow::Result<serde_json::Value> {
+        let entity_ids = ratelimit::entity_ids(&request, None);
+        if settings::verify_targets() && !entity_ids.is_empty() {
+            let states = Api::new(self.url, self.token).states(None).await?;
+            let known = states.iter().filter_map(|state| state.entity_id.as_deref());
+            services::ServiceCallError::verify_targets(&entity_ids, known)?;
+        }
+        ratelimit::acquire(ha_domain, ha_service, &entity_ids).await?;
+
+        let client = post(
+            self.url,
+            self.token,
+            &format!(
+                "/api/services/{}/{}{}",
+                urls::encode_segment(ha_domain),
+                urls::encode_segment(ha_service),
+                urls::Query::new().flag("return_response", return_response)
+            ),
+            request,
+        )
+        .await?;
+
+        if !client.status().is_success() {
+            let status = client.status();
+            let body = client.text().await.unwrap_or_default();
+            Err(services::ServiceCallError::from_response(status, &body).into())
+        } else {
+            decode::json::<serde_json::Value>(client).await
+        }
+    }
+
+    pub(crate) async fn log_entry(
+        &self,
+        name: &str,
+        message: &str,
+        entity_id: Option<&str>,
+        domain: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let data = services::log_entry_data(name, message, entity_id, domain);
+        self.service("logbook", "log", data, false).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn template(
+        &self,
+        request: structs::TemplateRequest,
+    ) -> anyhow::Result<String> {
+        let client = post(self.url, self.token, "/api/template", request)
+            .await?
+            .text()
+            .await?;
+
+        Ok(client)
+    }
+
+    pub(crate) async fn config_check(&self) -> anyhow::Result<structs::ConfigCheckResponse> {
+        let client = post(
+            self.url,
+            self.token,
+            "/api/config/core/check_config",
+            json!({}),
+        )
+        .await?;
+
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            decode::json::<structs::ConfigCheckResponse>(client).await
+        }
+    }
 
-        let client = post(url, token, "/api/intent/handle", request)
+    pub(crate) async fn intent(&self, request: serde_json::Value) -> anyhow::Result<String> {
+        let client = post(self.url, self.token, "/api/intent/handle", request)
             .await?
             .text()
             .await?;
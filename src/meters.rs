@@ -0,0 +1,151 @@
+//! Resetting utility meters and counters on schedules aligned to local time
+//!
+//! A [`ResetCycle`] computes the next reset in a given time zone, which should be the time zone of
+//! the Homeassistant instance (with the `tz` feature see [`ConfigResponse::tz`](crate::structs::ConfigResponse::tz)),
+//! so resets happen at local midnight across daylight saving time changes.
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs, tz: chrono::FixedOffset) -> anyhow::Result<()> {
+//! use homeassistant_rs::meters::ResetCycle;
+//!
+//! // billing cycle starting on the 15th of every month
+//! ResetCycle::Monthly { day: 15, hour: 0 }
+//!     .run(&ws, &["sensor.energy_billing_cycle", "counter.heating_starts"], tz)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::{
+    DateTime, Datelike, Days, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday,
+};
+use serde_json::json;
+
+use crate::ws::HomeAssistantWs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetCycle {
+    /// every day at `hour`
+    Daily { hour: u32 },
+    /// every week on `weekday` at `hour`
+    Weekly { weekday: Weekday, hour: u32 },
+    /// every month on `day` at `hour`, `day` is clamped to the last day of shorter months.
+    /// `day: 1` resets at the start of calendar months
+    Monthly { day: u32, hour: u32 },
+    /// every year on `day` of `month` at `hour`
+    Yearly { month: u32, day: u32, hour: u32 },
+}
+
+/// the last valid day of `year`-`month` not after `day`
+fn clamped_date(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    (1..=day.clamp(1, 31))
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+/// the instant of a local time, skipping forward over gaps caused by daylight saving time
+fn resolve<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> DateTime<Tz> {
+    (0..=24)
+        .find_map(|minutes| {
+            tz.from_local_datetime(&(local + Duration::minutes(minutes * 15)))
+                .earliest()
+        })
+        .unwrap_or_else(|| tz.from_utc_datetime(&local))
+}
+
+impl ResetCycle {
+    /// the local date and time of the occurrence in the period containing `date`
+    fn occurrence(&self, date: NaiveDate) -> Option<NaiveDateTime> {
+        let (date, hour) = match *self {
+            ResetCycle::Daily { hour } => (date, hour),
+            ResetCycle::Weekly { weekday, hour } => {
+                let days = (7 + weekday.num_days_from_monday() as i64
+                    - date.weekday().num_days_from_monday() as i64)
+                    % 7;
+                (date.checked_add_days(Days::new(days as u64))?, hour)
+            }
+            ResetCycle::Monthly { day, hour } => {
+                (clamped_date(date.year(), date.month(), day)?, hour)
+            }
+            ResetCycle::Yearly { month, day, hour } => {
+                (clamped_date(date.year(), month.clamp(1, 12), day)?, hour)
+            }
+        };
+        date.and_hms_opt(hour.min(23), 0, 0)
+    }
+
+    /// the first day of the period following the one containing `date`
+    fn next_period(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            ResetCycle::Daily { .. } => date.succ_opt(),
+            ResetCycle::Weekly { .. } => date.checked_add_days(Days::new(7)),
+            ResetCycle::Monthly { .. } => {
+                let (year, month) = match date.month() {
+                    12 => (date.year() + 1, 1),
+                    month => (date.year(), month + 1),
+                };
+                NaiveDate::from_ymd_opt(year, month, 1)
+            }
+            ResetCycle::Yearly { .. } => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+        }
+    }
+
+    /// the first reset strictly after `after`, in the time zone of `after`
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> DateTime<Tz> {
+        let tz = after.timezone();
+        let mut date = after.date_naive();
+        loop {
+            if let Some(local) = self.occurrence(date) {
+                let at = resolve(&tz, local);
+                if at > *after {
+                    return at;
+                }
+            }
+            match self.next_period(date) {
+                Some(next) => date = next,
+                None => return after.clone(),
+            }
+        }
+    }
+
+    /// resets `entity_ids` with [`HomeAssistantWs::reset_meter`] on every occurrence in `tz`, until
+    /// a reset fails
+    pub async fn run<Tz: TimeZone>(
+        &self,
+        ws: &HomeAssistantWs,
+        entity_ids: &[&str],
+        tz: Tz,
+    ) -> anyhow::Result<()> {
+        loop {
+            let now = Utc::now().with_timezone(&tz);
+            let next = self.next_after(&now);
+            let wait = (next.with_timezone(&Utc) - now.with_timezone(&Utc))
+                .to_std()
+                .unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            for entity_id in entity_ids {
+                ws.reset_meter(entity_id).await?;
+            }
+        }
+    }
+}
+
+impl HomeAssistantWs {
+    /// resets a counter (`counter.reset`) or utility meter (`utility_meter.reset`), depending on the
+    /// domain of `entity_id`
+    pub async fn reset_meter(&self, entity_id: &str) -> anyhow::Result<()> {
+        let domain = match entity_id.split_once('.') {
+            Some(("counter", _)) => "counter",
+            _ => "utility_meter",
+        };
+        self.call_service(
+            domain,
+            "reset",
+            json!({}),
+            Some(json!({"entity_id": entity_id})),
+            false,
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+//! MJPEG camera streams (`/api/camera_proxy_stream/<camera_entity_id>`)
+//!
+//! Frames can be consumed directly with [`MjpegStream::next_frame`], or handed to a background task
+//! with [`MjpegStream::spawn_with`], which drops frames instead of buffering them when the consumer
+//! falls behind:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::hass;
+//!
+//! let stream = hass()
+//!     .camera_stream(None, None, "camera.front_door")
+//!     .await
+//!     .unwrap();
+//! // only decode frames which will actually be consumed
+//! let mut frames = stream.spawn_with(2, |frame| Some(frame.data.len()));
+//!
+//! while let Some(size) = frames.recv().await {
+//!     println!("{size} bytes, {} frames dropped so far", frames.dropped());
+//! }
+//! # });
+//! ```
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::Stream;
+use tokio::sync::mpsc;
+
+use crate::structs::Progress;
+
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    /// e.g. `image/jpeg`
+    pub content_type: Option<String>,
+    pub data: Bytes,
+}
+
+type ProgressFn = Box<dyn FnMut(Progress) + Send>;
+
+/// the default of [`MjpegStream::max_frame_size`]
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 << 20;
+
+pub struct MjpegStream {
+    response: reqwest::Response,
+    boundary: Vec<u8>,
+    buffer: BytesMut,
+    max_frame_size: usize,
+    received: u64,
+    /// only used through `&mut self`, the mutex makes the stream `Sync` without locking
+    progress: Option<Mutex<ProgressFn>>,
+}
+
+impl MjpegStream {
+    pub(crate) fn new(response: reqwest::Response) -> anyhow::Result<Self> {
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        let boundary = content_type
+            .split(';')
+            .find_map(|param| param.trim().strip_prefix("boundary="))
+            .map(|boundary| boundary.trim_matches('"').trim_start_matches("--"))
+            .ok_or_else(|| {
+                anyhow::Error::msg(format!("not a multipart stream: {content_type:?}"))
+            })?;
+
+        Ok(Self {
+            boundary: format!("--{boundary}").into_bytes(),
+            response,
+            buffer: BytesMut::new(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            received: 0,
+            progress: None,
+        })
+    }
+
+    /// calls `progress` with the bytes received so far after every chunk, the total of a live
+    /// stream is unknown
+    pub fn on_progress(mut self, progress: impl FnMut(Progress) + Send + 'static) -> Self {
+        self.progress = Some(Mutex::new(Box::new(progress)));
+        self
+    }
+
+    /// largest frame in bytes, including its multipart headers, [`DEFAULT_MAX_FRAME_SIZE`] by
+    /// default
+    ///
+    /// [`next_frame`](Self::next_frame) fails once more is buffered without a complete frame, e.g.
+    /// because the camera never sends a boundary
+    pub fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// bytes received so far, including multipart headers
+    pub fn bytes_received(&self) -> u64 {
+        self.received
+    }
+
+    /// returns the next frame, [`None`] once the stream ended
+    pub async fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        loop {
+            if let Some(frame) = parse_frame(&mut self.buffer, &self.boundary) {
+                return Ok(Some(frame));
+            }
+            if self.buffer.len() > self.max_frame_size {
+                return Err(anyhow::Error::msg(format!(
+                    "no complete frame within {} bytes, see MjpegStream::max_frame_size",
+                    self.buffer.len()
+                )));
+            }
+            match self.response.chunk().await? {
+                Some(chunk) => {
+                    self.received += chunk.len() as u64;
+                    if let Some(progress) = &mut self.progress {
+                        let progress = progress.get_mut().unwrap_or_else(|p| p.into_inner());
+                        progress(Progress {
+                            done: self.received,
+                            total: None,
+                        });
+                    }
+                    self.buffer.extend_from_slice(&chunk);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// reads frames in a background task, passes them through `transform` and queues up to
+    /// `capacity` results
+    ///
+    /// if the queue is full, new frames are dropped without calling `transform`, so slow consumers
+    /// never cause frames to pile up in memory. Returning [`None`] from `transform` skips a frame
+    pub fn spawn_with<T, F>(mut self, capacity: usize, mut transform: F) -> FrameReceiver<T>
+    where
+        T: Send + 'static,
+        F: FnMut(Frame) -> Option<T> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let counter = dropped.clone();
+
+        tokio::spawn(async move {
+            while let Ok(Some(frame)) = self.next_frame().await {
+                match tx.try_reserve() {
+                    Ok(permit) => {
+                        if let Some(value) = transform(frame) {
+                            permit.send(value);
+                        }
+                    }
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        counter.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => break,
+                }
+            }
+        });
+
+        FrameReceiver { rx, dropped }
+    }
+}
+
+/// receiving end of [`MjpegStream::spawn_with`]
+pub struct FrameReceiver<T> {
+    rx: mpsc::Receiver<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> FrameReceiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        self.rx.recv().await
+    }
+
+    /// number of frames dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Stream for FrameReceiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// removes the first complete part from `buffer` and returns it
+pub(crate) fn parse_frame(buffer: &mut BytesMut, boundary: &[u8]) -> Option<Frame> {
+    let start = find(buffer, boundary)?;
+    let headers_start = start + boundary.len();
+    let headers_len = find(&buffer[headers_start..], b"\r\n\r\n")?;
+    let body_start = headers_start + headers_len + 4;
+
+    let mut content_type = None;
+    let mut content_length = None;
+    for line in String::from_utf8_lossy(&buffer[headers_start..headers_start + headers_len]).lines()
+    {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-type" => content_type = Some(value.trim().to_owned()),
+            "content-length" => content_length = value.trim().parse::<usize>().ok(),
+            _ => {}
+        }
+    }
+
+    let body_len = match content_length {
+        Some(len) if buffer.len() >= body_start + len => len,
+        Some(_) => return None,
+        None => {
+            let mut next = b"\r\n".to_vec();
+            next.extend_from_slice(boundary);
+            find(&buffer[body_start..], &next)?
+        }
+    };
+
+    buffer.advance(body_start);
+    let data = buffer.split_to(body_len).freeze();
+    Some(Frame { content_type, data })
+}
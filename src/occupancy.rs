@@ -0,0 +1,261 @@
+//! Room occupancy derived from motion, occupancy and presence sensors and device trackers
+//!
+//! An area is occupied while one of its sources is active, and for a decay time after the last
+//! source became inactive, which bridges the gaps of motion sensors when nobody moves.
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use std::time::Duration;
+//!
+//! let occupancy = ws.occupancy(Duration::from_secs(300)).await?;
+//! let mut changes = occupancy.watch(ws.subscribe_events(Some("state_changed")).await?);
+//! while let Some(change) = changes.next().await {
+//!     let change = change?;
+//!     println!("{}: {}", change.area_id, change.occupied);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+use futures_util::stream::{self, BoxStream};
+use futures_util::{Stream, StreamExt};
+use tokio::time::Instant;
+
+use crate::structs::Event;
+use crate::ws::HomeAssistantWs;
+
+/// `device_class`es of binary sensors which indicate presence
+const PRESENCE_CLASSES: [&str; 3] = ["motion", "occupancy", "presence"];
+
+/// the occupancy of an area changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AreaOccupancy {
+    pub area_id: String,
+    pub occupied: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Source {
+    area_id: String,
+    decay: Duration,
+    active: bool,
+    /// when the source became inactive, [`None`] if it never was active
+    inactive_since: Option<Instant>,
+}
+
+impl Source {
+    fn occupied_at(&self, at: Instant) -> bool {
+        self.active
+            || self
+                .inactive_since
+                .is_some_and(|since| at < since + self.decay)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Occupancy {
+    decay: Duration,
+    sources: HashMap<String, Source>,
+    occupied: HashMap<String, bool>,
+}
+
+/// whether a state of `entity_id` means someone is present
+fn is_active(entity_id: &str, state: &str) -> bool {
+    match entity_id.split_once('.').map(|(domain, _)| domain) {
+        Some("device_tracker" | "person") => state == "home",
+        _ => state == "on",
+    }
+}
+
+impl Occupancy {
+    /// empty aggregation, `decay` is the default time an area stays occupied after its sources
+    /// became inactive
+    pub fn new(decay: Duration) -> Self {
+        Self {
+            decay,
+            sources: HashMap::new(),
+            occupied: HashMap::new(),
+        }
+    }
+
+    /// adds `entity_id` as a source of `area_id`, with the default decay time
+    pub fn source(self, entity_id: &str, area_id: &str) -> Self {
+        let decay = self.decay;
+        self.source_with_decay(entity_id, area_id, decay)
+    }
+
+    /// adds `entity_id` as a source of `area_id`, e.g. a shorter decay for presence sensors which
+    /// do not miss people sitting still
+    pub fn source_with_decay(mut self, entity_id: &str, area_id: &str, decay: Duration) -> Self {
+        self.sources.insert(
+            entity_id.to_owned(),
+            Source {
+                area_id: area_id.to_owned(),
+                decay,
+                active: false,
+                inactive_since: None,
+            },
+        );
+        self.occupied.entry(area_id.to_owned()).or_default();
+        self
+    }
+
+    /// areas with at least one source
+    pub fn areas(&self) -> BTreeSet<&str> {
+        self.occupied.keys().map(String::as_str).collect()
+    }
+
+    /// whether `area_id` is occupied, as of the last update
+    pub fn is_occupied(&self, area_id: &str) -> bool {
+        self.occupied.get(area_id).copied().unwrap_or_default()
+    }
+
+    /// feeds the state of a source, returns the resulting change of its area. States of unknown
+    /// entities are ignored
+    pub fn update(&mut self, entity_id: &str, state: &str) -> Option<AreaOccupancy> {
+        self.update_at(entity_id, state, Instant::now())
+    }
+
+    /// like [`update`](Self::update), with the time the state changed
+    pub fn update_at(
+        &mut self,
+        entity_id: &str,
+        state: &str,
+        at: Instant,
+    ) -> Option<AreaOccupancy> {
+        let source = self.sources.get_mut(entity_id)?;
+        let active = is_active(entity_id, state);
+        if source.active && !active {
+            source.inactive_since = Some(at);
+        }
+        source.active = active;
+        let area_id = source.area_id.clone();
+        self.refresh(&area_id, at)
+    }
+
+    /// marks areas whose decay time elapsed as free, returns the changes
+    pub fn expire_at(&mut self, at: Instant) -> Vec<AreaOccupancy> {
+        let areas: Vec<String> = self.occupied.keys().cloned().collect();
+        areas
+            .iter()
+            .filter_map(|area_id| self.refresh(area_id, at))
+            .collect()
+    }
+
+    /// the next time an area may become free
+    fn next_expiry(&self, at: Instant) -> Option<Instant> {
+        self.sources
+            .values()
+            .filter(|source| !source.active)
+            .filter_map(|source| Some(source.inactive_since? + source.decay))
+            .filter(|expiry| *expiry > at)
+            .min()
+    }
+
+    fn refresh(&mut self, area_id: &str, at: Instant) -> Option<AreaOccupancy> {
+        let occupied = self
+            .sources
+            .values()
+            .any(|source| source.area_id == area_id && source.occupied_at(at));
+        let previous = self.occupied.insert(area_id.to_owned(), occupied);
+        (previous != Some(occupied)).then(|| AreaOccupancy {
+            area_id: area_id.to_owned(),
+            occupied,
+        })
+    }
+
+    /// consumes `state_changed` events and emits the occupancy changes of areas, including areas
+    /// becoming free once their decay time elapsed
+    pub fn watch<S>(self, events: S) -> BoxStream<'static, anyhow::Result<AreaOccupancy>>
+    where
+        S: Stream<Item = anyhow::Result<Event>> + Send + 'static,
+    {
+        let state = (self, Some(events.boxed()), Vec::new());
+        stream::unfold(state, |(mut occupancy, mut input, mut pending)| async move {
+            loop {
+                if !pending.is_empty() {
+                    let change = pending.remove(0);
+                    return Some((Ok(change), (occupancy, input, pending)));
+                }
+                let events = input.as_mut()?;
+                let expiry = occupancy.next_expiry(Instant::now());
+                tokio::select! {
+                    item = events.next() => match item {
+                        Some(Ok(event)) => {
+                            let Some(data) = event.state_changed() else {
+                                continue;
+                            };
+                            let state = data.new_state.map(|state| state.state).unwrap_or_default();
+                            pending.extend(occupancy.update(&data.entity_id, &state));
+                        }
+                        Some(Err(e)) => return Some((Err(e), (occupancy, input, pending))),
+                        None => input = None,
+                    },
+                    _ = tokio::time::sleep_until(expiry.unwrap_or_else(Instant::now)), if expiry.is_some() => {
+                        pending = occupancy.expire_at(Instant::now());
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+impl HomeAssistantWs {
+    /// builds an [`Occupancy`] of all areas from the motion, occupancy and presence binary sensors
+    /// and the device trackers assigned to them, directly or through their device. Disabled
+    /// entities are skipped
+    ///
+    /// sources start with their current state, areas occupied right now report as occupied
+    /// without a decay time
+    pub async fn occupancy(&self, decay: Duration) -> anyhow::Result<Occupancy> {
+        let (entities, devices, states) = tokio::try_join!(
+            self.entity_registry(),
+            self.device_registry(),
+            self.states()
+        )?;
+        let device_areas: HashMap<_, _> = devices
+            .into_iter()
+            .filter_map(|device| Some((device.id, device.area_id?)))
+            .collect();
+        let states: HashMap<_, _> = states
+            .into_iter()
+            .filter_map(|state| Some((state.entity_id.clone()?, state)))
+            .collect();
+
+        let now = Instant::now();
+        let mut occupancy = Occupancy::new(decay);
+        for entity in entities {
+            if entity.disabled_by.is_some() {
+                continue;
+            }
+            let area_id = entity
+                .area_id
+                .clone()
+                .or_else(|| device_areas.get(entity.device_id.as_deref()?).cloned());
+            let (Some(area_id), Some(state)) = (area_id, states.get(entity.entity_id.as_str()))
+            else {
+                continue;
+            };
+            let device_class = state
+                .attributes
+                .as_ref()
+                .and_then(|attributes| attributes.device_class());
+            let is_source = match entity.entity_id.domain() {
+                "binary_sensor" => {
+                    device_class.is_some_and(|class| PRESENCE_CLASSES.contains(&class))
+                }
+                "device_tracker" => true,
+                _ => false,
+            };
+            if is_source {
+                occupancy = occupancy.source(entity.entity_id.as_str(), &area_id);
+                occupancy.update_at(entity.entity_id.as_str(), &state.state, now);
+            }
+        }
+        Ok(occupancy)
+    }
+}
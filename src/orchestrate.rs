@@ -0,0 +1,395 @@
+//! Multi-step routines with timeouts, cancellation and compensation
+//!
+//! An [`Orchestrator`] runs [`Step`]s one after another. When a step fails, times out or the run
+//! is cancelled, the compensations of all completed steps are run in reverse order:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use std::time::Duration;
+//! use homeassistant_rs::orchestrate::{Action, Orchestrator, Step};
+//!
+//! let covers = json!({"entity_id": ["cover.living_room", "cover.kitchen"]});
+//! Orchestrator::new(&ws)
+//!     .timeout(Duration::from_secs(180))
+//!     .step(Step::snapshot(&["light.hallway"]))
+//!     .step(
+//!         Step::call("cover", "close_cover", covers.clone())
+//!             .compensate(Action::new("cover", "open_cover", covers)),
+//!     )
+//!     .step(
+//!         Step::all([
+//!             Step::wait_state("cover.living_room", "closed"),
+//!             Step::wait_state("cover.kitchen", "closed"),
+//!         ])
+//!         .timeout(Duration::from_secs(90)),
+//!     )
+//!     .step(Step::call(
+//!         "alarm_control_panel",
+//!         "alarm_arm_away",
+//!         json!({"entity_id": "alarm_control_panel.home"}),
+//!     ))
+//!     .run()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Failed runs return an [`OrchestrationError`] (wrapped in [`anyhow::Error`]), which names the
+//! failed step and the compensations which failed themselves.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use futures_util::future::{self, BoxFuture};
+use futures_util::stream::FuturesUnordered;
+use futures_util::{FutureExt, StreamExt};
+use serde_json::{Value, json};
+
+use crate::ws::HomeAssistantWs;
+
+/// a service call, as step or as compensation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    pub domain: String,
+    pub service: String,
+    pub data: Value,
+}
+
+impl Action {
+    pub fn new(domain: &str, service: &str, data: Value) -> Self {
+        Self {
+            domain: domain.to_owned(),
+            service: service.to_owned(),
+            data,
+        }
+    }
+
+    async fn call(&self, ws: &HomeAssistantWs) -> anyhow::Result<()> {
+        ws.call_service(&self.domain, &self.service, self.data.clone(), None, false)
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Call(Action),
+    WaitState { entity_id: String, state: String },
+    Delay(Duration),
+    All(Vec<Step>),
+    Snapshot(Vec<String>),
+}
+
+/// a step of an [`Orchestrator`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    label: String,
+    kind: Kind,
+    timeout: Option<Duration>,
+    compensations: Vec<Action>,
+}
+
+impl Step {
+    fn new(label: String, kind: Kind) -> Self {
+        Self {
+            label,
+            kind,
+            timeout: None,
+            compensations: Vec::new(),
+        }
+    }
+
+    /// calls `domain.service` with `data`
+    pub fn call(domain: &str, service: &str, data: Value) -> Self {
+        Self::new(
+            format!("{domain}.{service}"),
+            Kind::Call(Action::new(domain, service, data)),
+        )
+    }
+
+    /// waits until `entity_id` is in `state`, completes immediately if it already is
+    pub fn wait_state(entity_id: &str, state: &str) -> Self {
+        Self::new(
+            format!("wait for {entity_id} to be {state}"),
+            Kind::WaitState {
+                entity_id: entity_id.to_owned(),
+                state: state.to_owned(),
+            },
+        )
+    }
+
+    pub fn delay(duration: Duration) -> Self {
+        Self::new(format!("delay {duration:?}"), Kind::Delay(duration))
+    }
+
+    /// runs `steps` concurrently, the first failure cancels the steps still running
+    pub fn all(steps: impl IntoIterator<Item = Step>) -> Self {
+        let steps: Vec<Step> = steps.into_iter().collect();
+        let labels: Vec<&str> = steps.iter().map(|step| step.label.as_str()).collect();
+        let label = format!("all of [{}]", labels.join(", "));
+        Self::new(label, Kind::All(steps))
+    }
+
+    /// records the current states of `entity_ids` with `scene.create`, a failed run restores them
+    ///
+    /// the scene is deleted again at the end of the run
+    pub fn snapshot(entity_ids: &[&str]) -> Self {
+        Self::new(
+            format!("snapshot {}", entity_ids.join(", ")),
+            Kind::Snapshot(entity_ids.iter().map(|id| id.to_string()).collect()),
+        )
+    }
+
+    /// the name of the step in errors, e.g. `close covers`
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_owned();
+        self
+    }
+
+    /// fails the step with [`Cause::TimedOut`] if it does not complete within `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// undoes the step if a later step fails, several compensations run in reverse order
+    pub fn compensate(mut self, action: Action) -> Self {
+        self.compensations.push(action);
+        self
+    }
+}
+
+/// why a run stopped
+#[derive(Debug)]
+pub enum Cause {
+    Failed(anyhow::Error),
+    TimedOut(Duration),
+    Cancelled,
+}
+
+/// a failed run of an [`Orchestrator`], after its compensations ran
+#[derive(Debug)]
+pub struct OrchestrationError {
+    /// the label of the step which failed or was running
+    pub step: String,
+    pub cause: Cause,
+    /// the number of compensations run
+    pub compensated: usize,
+    /// compensations which failed, with their error
+    pub compensation_failures: Vec<(Action, String)>,
+}
+
+impl std::fmt::Display for OrchestrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.cause {
+            Cause::Failed(e) => write!(f, "step `{}` failed: {e}", self.step)?,
+            Cause::TimedOut(timeout) => {
+                write!(f, "step `{}` timed out after {timeout:?}", self.step)?
+            }
+            Cause::Cancelled => write!(f, "cancelled during step `{}`", self.step)?,
+        }
+        if !self.compensation_failures.is_empty() {
+            write!(
+                f,
+                ", {} of {} compensations failed",
+                self.compensation_failures.len(),
+                self.compensated
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for OrchestrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.cause {
+            Cause::Failed(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// runs [`Step`]s in order, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct Orchestrator {
+    ws: HomeAssistantWs,
+    steps: Vec<Step>,
+    timeout: Option<Duration>,
+}
+
+/// compensations of completed steps and scenes to delete, shared by concurrent steps
+#[derive(Default)]
+struct Run {
+    compensations: Mutex<Vec<Action>>,
+    snapshots: Mutex<Vec<String>>,
+}
+
+impl Orchestrator {
+    pub fn new(ws: &HomeAssistantWs) -> Self {
+        Self {
+            ws: ws.clone(),
+            steps: Vec::new(),
+            timeout: None,
+        }
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// limits the whole run, the running step fails with [`Cause::TimedOut`]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub async fn run(&self) -> anyhow::Result<()> {
+        self.run_until(future::pending()).await
+    }
+
+    /// like [`run`](Self::run), but cancels the run once `cancel` completes, e.g.
+    /// `tokio::signal::ctrl_c()` or a oneshot receiver
+    pub async fn run_until(&self, cancel: impl Future<Output = ()>) -> anyhow::Result<()> {
+        let run = Run::default();
+        let current = Mutex::new(String::new());
+
+        let steps = async {
+            for step in &self.steps {
+                *current.lock().unwrap() = step.label.clone();
+                self.run_step(step, &run).await?;
+            }
+            Ok(())
+        };
+        let deadline = async {
+            match self.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            result = steps => result,
+            _ = deadline => Err(Cause::TimedOut(self.timeout.unwrap_or_default())),
+            _ = cancel => Err(Cause::Cancelled),
+        };
+
+        let result = match result {
+            Ok(()) => Ok(()),
+            Err(cause) => {
+                let compensations = std::mem::take(&mut *run.compensations.lock().unwrap());
+                let mut compensation_failures = Vec::new();
+                for action in compensations.iter().rev() {
+                    if let Err(e) = action.call(&self.ws).await {
+                        compensation_failures.push((action.clone(), e.to_string()));
+                    }
+                }
+                Err(OrchestrationError {
+                    step: current.into_inner().unwrap(),
+                    cause,
+                    compensated: compensations.len(),
+                    compensation_failures,
+                }
+                .into())
+            }
+        };
+
+        let snapshots = std::mem::take(&mut *run.snapshots.lock().unwrap());
+        for scene in snapshots {
+            // scenes created by `scene.create` are not persisted, a failed delete is harmless
+            let _ = Action::new("scene", "delete", json!({"entity_id": scene}))
+                .call(&self.ws)
+                .await;
+        }
+        result
+    }
+
+    fn run_step<'a>(&'a self, step: &'a Step, run: &'a Run) -> BoxFuture<'a, Result<(), Cause>> {
+        async move {
+            let execute = self.execute(step, run);
+            match step.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, execute)
+                    .await
+                    .unwrap_or(Err(Cause::TimedOut(timeout)))?,
+                None => execute.await?,
+            }
+            run.compensations
+                .lock()
+                .unwrap()
+                .extend(step.compensations.iter().cloned());
+            Ok(())
+        }
+        .boxed()
+    }
+
+    async fn execute(&self, step: &Step, run: &Run) -> Result<(), Cause> {
+        match &step.kind {
+            Kind::Call(action) => action.call(&self.ws).await.map_err(Cause::Failed),
+            Kind::WaitState { entity_id, state } => self
+                .wait_state(entity_id, state)
+                .await
+                .map_err(Cause::Failed),
+            Kind::Delay(duration) => {
+                tokio::time::sleep(*duration).await;
+                Ok(())
+            }
+            Kind::All(steps) => {
+                let mut running: FuturesUnordered<_> =
+                    steps.iter().map(|step| self.run_step(step, run)).collect();
+                while let Some(result) = running.next().await {
+                    result?;
+                }
+                Ok(())
+            }
+            Kind::Snapshot(entity_ids) => {
+                let scene_id = snapshot_id();
+                Action::new(
+                    "scene",
+                    "create",
+                    json!({"scene_id": scene_id, "snapshot_entities": entity_ids}),
+                )
+                .call(&self.ws)
+                .await
+                .map_err(Cause::Failed)?;
+                let scene = format!("scene.{scene_id}");
+                run.snapshots.lock().unwrap().push(scene.clone());
+                run.compensations.lock().unwrap().push(Action::new(
+                    "scene",
+                    "turn_on",
+                    json!({"entity_id": scene}),
+                ));
+                Ok(())
+            }
+        }
+    }
+
+    async fn wait_state(&self, entity_id: &str, state: &str) -> anyhow::Result<()> {
+        // subscribe before reading the state, so no change is missed in between
+        let mut events = self.ws.subscribe_events(Some("state_changed")).await?;
+        let states = self.ws.states().await?;
+        if states
+            .iter()
+            .any(|s| s.entity_id.as_deref() == Some(entity_id) && s.state == state)
+        {
+            return Ok(());
+        }
+        while let Some(event) = events.next().await {
+            let event = event?;
+            if event.data["entity_id"] == entity_id && event.data["new_state"]["state"] == state {
+                return Ok(());
+            }
+        }
+        Err(anyhow::Error::msg("state_changed subscription ended"))
+    }
+}
+
+/// a scene id which is unique within this process
+fn snapshot_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "orchestrator_snapshot_{}_{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
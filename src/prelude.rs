@@ -0,0 +1,24 @@
+//! Commonly needed types and traits
+//!
+//! ```
+//! use homeassistant_rs::prelude::*;
+//! ```
+//!
+//! `serde_json`'s [`Value`] and [`json!`] are part of the public API on purpose: service data,
+//! event data and intents are free-form, so they are accepted and returned as [`Value`].
+
+pub use crate::client::Client;
+pub use crate::codec::Codec;
+pub use crate::settings::AuthProvider;
+pub use crate::streams::EventStreamExt;
+pub use crate::structs::{
+    Attributes, ConfigResponse, Context, EntityId, Event, EventResponse, HistoryResponse, LogBook,
+    StatesRequest, StatesResponse, TemplateRequest,
+};
+pub use crate::timestamp::Timestamp;
+pub use crate::ws::{HomeAssistantWs, Raw, Subscription};
+pub use crate::{HomeAssistant, HomeAssistantPost, hass};
+
+pub use anyhow::{Error, Result};
+pub use futures_util::StreamExt;
+pub use serde_json::{Value, json};
@@ -0,0 +1,192 @@
+//! Per-entity rate limits for service calls
+//!
+//! Service calls targeting a limited entity are delayed until its minimum interval since the last
+//! call of the same service to it elapsed. While a call waits, a newer call of the same service
+//! for the same entity supersedes it: once this happened for every entity it targets, the older
+//! call is not sent and returns a [`Superseded`] error, so a runaway control loop only ever sends
+//! its latest command. Calls of other services (e.g. `logbook.log` mentioning the entity) are
+//! limited separately. Limits apply to [`HomeAssistantWs::call_service`](crate::ws::HomeAssistantWs::call_service)
+//! and [`HomeAssistantPost::service`](crate::HomeAssistantPost::service).
+//! ```
+//! use std::time::Duration;
+//! use homeassistant_rs::ratelimit;
+//!
+//! ratelimit::set_domain_limit("light", Duration::from_millis(500));
+//! ratelimit::set_entity_limit("switch.boiler_relay", Duration::from_secs(30));
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::time::Instant;
+
+lazy_static::lazy_static! {
+    static ref LIMITS: Mutex<Limits> = Mutex::new(Limits::default());
+}
+
+#[derive(Default)]
+struct Limits {
+    entities: HashMap<String, Duration>,
+    domains: HashMap<String, Duration>,
+    /// by domain, service and entity id of the calls
+    state: HashMap<(String, String, String), EntityState>,
+}
+
+struct EntityState {
+    next_allowed: Instant,
+    /// incremented by every call, a waiting call is superseded once it changed
+    generation: u64,
+}
+
+impl Limits {
+    fn interval(&self, entity_id: &str) -> Option<Duration> {
+        self.entities.get(entity_id).copied().or_else(|| {
+            let (domain, _) = entity_id.split_once('.')?;
+            self.domains.get(domain).copied()
+        })
+    }
+}
+
+fn limits() -> MutexGuard<'static, Limits> {
+    LIMITS.lock().unwrap_or_else(|p| p.into_inner())
+}
+
+/// the call was replaced by newer calls of the same service for each of its entities before it was
+/// sent, `entity_id` is the first of them
+///
+/// can be obtained with [`anyhow::Error::downcast_ref`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Superseded {
+    pub entity_id: String,
+}
+
+impl std::fmt::Display for Superseded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "call to {} was superseded by a newer call",
+            self.entity_id
+        )
+    }
+}
+
+impl std::error::Error for Superseded {}
+
+/// at most one call per `min_interval` to `entity_id`, takes precedence over domain limits
+pub fn set_entity_limit(entity_id: &str, min_interval: Duration) {
+    limits().entities.insert(entity_id.to_owned(), min_interval);
+}
+
+/// at most one call per `min_interval` to each entity of `domain`, e.g. `light`
+pub fn set_domain_limit(domain: &str, min_interval: Duration) {
+    limits().domains.insert(domain.to_owned(), min_interval);
+}
+
+/// removes all limits
+pub fn clear_limits() {
+    let mut limits = limits();
+    limits.entities.clear();
+    limits.domains.clear();
+    limits.state.clear();
+}
+
+/// entity ids targeted by a service call, from `target` and `entity_id` in the service data
+///
+/// comma separated ids are split like Homeassistant does, `all` and `none` are left out
+pub(crate) fn entity_ids(data: &Value, target: Option<&Value>) -> Vec<String> {
+    let mut entity_ids = Vec::new();
+    for value in [
+        target.map(|target| &target["entity_id"]),
+        Some(&data["entity_id"]),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let values = match value {
+            Value::String(entity_id) => vec![entity_id.as_str()],
+            Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+            _ => continue,
+        };
+        entity_ids.extend(
+            values
+                .into_iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|entity_id| !matches!(*entity_id, "" | "all" | "none"))
+                .map(str::to_owned),
+        );
+    }
+    entity_ids
+}
+
+/// waits until a call of `domain.service` to `entity_ids` is allowed, or returns [`Superseded`]
+/// if newer calls of it arrived for all of them in the meantime
+pub(crate) async fn acquire(
+    domain: &str,
+    service: &str,
+    entity_ids: &[String],
+) -> anyhow::Result<()> {
+    let key = |entity_id: &str| (domain.to_owned(), service.to_owned(), entity_id.to_owned());
+    let (wait_until, generations) = {
+        let mut limits = limits();
+        let now = Instant::now();
+        let mut wait_until = now;
+        let mut generations = Vec::new();
+        for entity_id in entity_ids {
+            if limits.interval(entity_id).is_none() {
+                continue;
+            }
+            let state = limits.state.entry(key(entity_id)).or_insert(EntityState {
+                next_allowed: now,
+                generation: 0,
+            });
+            state.generation += 1;
+            wait_until = wait_until.max(state.next_allowed);
+            generations.push((entity_id, state.generation));
+        }
+        (wait_until, generations)
+    };
+    if generations.is_empty() {
+        return Ok(());
+    }
+
+    let mut wait_until = wait_until;
+    loop {
+        tokio::time::sleep_until(wait_until).await;
+
+        let mut limits = limits();
+        let superseded = generations.iter().all(|(entity_id, generation)| {
+            limits
+                .state
+                .get(&key(entity_id))
+                .is_some_and(|state| state.generation != *generation)
+        });
+        if superseded {
+            return Err(Superseded {
+                entity_id: generations[0].0.to_string(),
+            }
+            .into());
+        }
+        // a call sharing only some of the entities may have been sent in the meantime
+        let now = Instant::now();
+        let next_allowed = generations
+            .iter()
+            .filter_map(|(entity_id, _)| limits.state.get(&key(entity_id)))
+            .map(|state| state.next_allowed)
+            .max()
+            .unwrap_or(now);
+        if next_allowed > now {
+            wait_until = next_allowed;
+            continue;
+        }
+        for (entity_id, _) in &generations {
+            let interval = limits.interval(entity_id).unwrap_or_default();
+            if let Some(state) = limits.state.get_mut(&key(entity_id)) {
+                state.next_allowed = now + interval;
+            }
+        }
+        return Ok(());
+    }
+}
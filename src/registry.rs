@@ -0,0 +1,755 @@
+//! Entity, device, area and floor registries (WebSocket only)
+//!
+//! The states API can't filter by area, [`HomeAssistantWs::states_in_area`] joins the states with
+//! the registries instead:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::prelude::*;
+//!
+//! let ws = hass().websocket(None, None).await.unwrap();
+//! for state in ws.states_in_area("kitchen").await.unwrap() {
+//!     println!("{:?}: {}", state.entity_id, state.state);
+//! }
+//! let lights = ws.states_by_domain("light").await.unwrap();
+//! # });
+//! ```
+//!
+//! Entities sharing a name (e.g. "Temperature") are told apart by their
+//! [`qualified_name`](HomeAssistantWs::qualified_name), e.g. "Ground Floor / Kitchen / Temperature".
+//!
+//! Configuration and diagnostic entities (e.g. a firmware update button or the signal strength of
+//! a plug), hidden and disabled entities are left out of listings with an [`EntitySelection`],
+//! listings include all of them unless one is passed:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use homeassistant_rs::registry::EntitySelection;
+//!
+//! let primary = ws.primary_states().await?;
+//! let with_diagnostics = ws
+//!     .select_states(EntitySelection::primary().diagnostic(true))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::stats;
+use crate::structs::{EntityId, StatesResponse};
+use crate::ws::{CommandError, HomeAssistantWs};
+
+/// how long [`HomeAssistantWs::states_in_area`] reuses the areas of entities before fetching the
+/// registries again
+pub const AREA_CACHE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityRegistryEntry {
+    pub entity_id: EntityId,
+    pub id: Option<String>,
+    pub unique_id: Option<String>,
+    /// the integration providing the entity, e.g. `zha`
+    pub platform: String,
+    pub config_entry_id: Option<String>,
+    pub device_id: Option<String>,
+    pub area_id: Option<String>,
+    pub name: Option<String>,
+    pub original_name: Option<String>,
+    pub icon: Option<String>,
+    /// [`None`] for primary entities
+    pub entity_category: Option<EntityCategory>,
+    pub disabled_by: Option<String>,
+    pub hidden_by: Option<String>,
+    pub has_entity_name: Option<bool>,
+    pub translation_key: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+impl EntityRegistryEntry {
+    /// whether the entity is neither a configuration nor a diagnostic entity
+    pub fn is_primary(&self) -> bool {
+        self.entity_category.is_none()
+    }
+
+    /// whether the entity was hidden by a user or an integration, e.g. the members of a group
+    pub fn is_hidden(&self) -> bool {
+        self.hidden_by.is_some()
+    }
+
+    /// disabled entities are not loaded and have no state
+    pub fn is_disabled(&self) -> bool {
+        self.disabled_by.is_some()
+    }
+}
+
+/// `entity_category` of secondary entities
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityCategory {
+    /// changes the configuration of a device, e.g. the power-on behavior of a bulb
+    Config,
+    /// reports on a device, e.g. its signal strength or firmware version
+    Diagnostic,
+    #[serde(other)]
+    Unknown,
+}
+
+/// which entities listings include, by default all
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntitySelection {
+    config: bool,
+    diagnostic: bool,
+    hidden: bool,
+    disabled: bool,
+}
+
+impl Default for EntitySelection {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl EntitySelection {
+    pub fn all() -> Self {
+        Self {
+            config: true,
+            diagnostic: true,
+            hidden: true,
+            disabled: true,
+        }
+    }
+
+    /// leaves out hidden and disabled entities, like the entities page and entity pickers of the
+    /// frontend
+    pub fn visible() -> Self {
+        Self {
+            hidden: false,
+            disabled: false,
+            ..Self::all()
+        }
+    }
+
+    /// leaves out configuration, diagnostic, hidden and disabled entities, like dashboards
+    /// generated by Homeassistant
+    pub fn primary() -> Self {
+        Self {
+            config: false,
+            diagnostic: false,
+            hidden: false,
+            disabled: false,
+        }
+    }
+
+    /// whether [`EntityCategory::Config`] entities are included
+    pub fn config(mut self, include: bool) -> Self {
+        self.config = include;
+        self
+    }
+
+    /// whether [`EntityCategory::Diagnostic`] entities are included
+    pub fn diagnostic(mut self, include: bool) -> Self {
+        self.diagnostic = include;
+        self
+    }
+
+    /// whether hidden entities are included, see [`EntityRegistryEntry::is_hidden`]
+    pub fn hidden(mut self, include: bool) -> Self {
+        self.hidden = include;
+        self
+    }
+
+    /// whether disabled entities are included, see [`EntityRegistryEntry::is_disabled`]
+    pub fn disabled(mut self, include: bool) -> Self {
+        self.disabled = include;
+        self
+    }
+
+    /// whether the entity of `entry` is included, entities without a registry entry count as
+    /// primary and visible
+    pub fn includes(&self, entry: Option<&EntityRegistryEntry>) -> bool {
+        let Some(entry) = entry else {
+            return true;
+        };
+        if (entry.is_hidden() && !self.hidden) || (entry.is_disabled() && !self.disabled) {
+            return false;
+        }
+        match entry.entity_category {
+            None => true,
+            Some(EntityCategory::Config) => self.config,
+            Some(EntityCategory::Diagnostic) => self.diagnostic,
+            Some(EntityCategory::Unknown) => self.config && self.diagnostic,
+        }
+    }
+}
+
+/// changes to an entity registry entry, unset fields are left untouched
+///
+/// fields wrapped in two [`Option`]s are cleared by setting them to `Some(None)`
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityRegistryUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_entity_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub area_id: Option<Option<String>>,
+    /// `Some(Some("user"))` disables the entity, `Some(None)` enables it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled_by: Option<Option<String>>,
+    /// `Some(Some("user"))` hides the entity, `Some(None)` shows it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hidden_by: Option<Option<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceRegistryEntry {
+    pub id: String,
+    pub name: Option<String>,
+    pub name_by_user: Option<String>,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub sw_version: Option<String>,
+    pub hw_version: Option<String>,
+    pub area_id: Option<String>,
+    #[serde(default)]
+    pub config_entries: Vec<String>,
+    pub via_device_id: Option<String>,
+    pub disabled_by: Option<String>,
+    pub entry_type: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+impl DeviceRegistryEntry {
+    /// the name shown in the UI, `name_by_user` takes precedence over `name`
+    pub fn display_name(&self) -> Option<&str> {
+        self.name_by_user.as_deref().or(self.name.as_deref())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AreaRegistryEntry {
+    pub area_id: String,
+    pub name: String,
+    pub floor_id: Option<String>,
+    pub icon: Option<String>,
+    pub picture: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FloorRegistryEntry {
+    pub floor_id: String,
+    pub name: String,
+    /// e.g. `0` for the ground floor and `-1` for the basement
+    pub level: Option<i32>,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+/// the area of every entity with one, its own area or else the area of its device
+pub fn entity_areas(
+    entities: &[EntityRegistryEntry],
+    devices: &[DeviceRegistryEntry],
+) -> HashMap<String, String> {
+    let device_areas: HashMap<&str, &str> = devices
+        .iter()
+        .filter_map(|device| Some((device.id.as_str(), device.area_id.as_deref()?)))
+        .collect();
+    entities
+        .iter()
+        .filter_map(|entity| {
+            let area_id = entity
+                .area_id
+                .as_deref()
+                .or_else(|| device_areas.get(entity.device_id.as_deref()?).copied())?;
+            Some((entity.entity_id.0.clone(), area_id.to_owned()))
+        })
+        .collect()
+}
+
+/// a snapshot of the entity, device, area and floor registries, keyed by their ids
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registries {
+    pub entities: HashMap<String, EntityRegistryEntry>,
+    pub devices: HashMap<String, DeviceRegistryEntry>,
+    pub areas: HashMap<String, AreaRegistryEntry>,
+    pub floors: HashMap<String, FloorRegistryEntry>,
+}
+
+impl Registries {
+    pub fn new(
+        entities: Vec<EntityRegistryEntry>,
+        devices: Vec<DeviceRegistryEntry>,
+        areas: Vec<AreaRegistryEntry>,
+        floors: Vec<FloorRegistryEntry>,
+    ) -> Self {
+        Self {
+            entities: entities
+                .into_iter()
+                .map(|entity| (entity.entity_id.0.clone(), entity))
+                .collect(),
+            devices: devices
+                .into_iter()
+                .map(|device| (device.id.clone(), device))
+                .collect(),
+            areas: areas
+                .into_iter()
+                .map(|area| (area.area_id.clone(), area))
+                .collect(),
+            floors: floors
+                .into_iter()
+                .map(|floor| (floor.floor_id.clone(), floor))
+                .collect(),
+        }
+    }
+
+    /// the area id of an entity, its own or else the one of its device
+    pub fn area_id(&self, entity_id: &str) -> Option<&str> {
+        let entity = self.entities.get(entity_id)?;
+        entity.area_id.as_deref().or_else(|| {
+            self.devices
+                .get(entity.device_id.as_deref()?)?
+                .area_id
+                .as_deref()
+        })
+    }
+
+    /// the area of every entity with one, like [`entity_areas`]
+    pub fn entity_areas(&self) -> HashMap<String, String> {
+        self.entities
+            .keys()
+            .filter_map(|entity_id| Some((entity_id.clone(), self.area_id(entity_id)?.to_owned())))
+            .collect()
+    }
+
+    /// the name of an entity as shown in the UI, prefixed by its device name if the entity
+    /// [`has_entity_name`](EntityRegistryEntry::has_entity_name)
+    pub fn entity_name(&self, entity_id: &str) -> Option<String> {
+        let entity = self.entities.get(entity_id)?;
+        let name = entity.name.as_deref().or(entity.original_name.as_deref());
+        if entity.name.is_some() || entity.has_entity_name != Some(true) {
+            return name.map(str::to_owned);
+        }
+        let device = entity
+            .device_id
+            .as_deref()
+            .and_then(|device_id| self.devices.get(device_id))
+            .and_then(DeviceRegistryEntry::display_name);
+        match (device, name) {
+            (Some(device), Some(name)) => Some(format!("{device} {name}")),
+            (device, name) => device.or(name).map(str::to_owned),
+        }
+    }
+
+    /// the [`EntityCategory`] of an entity, [`None`] for primary entities and entities which are
+    /// not in the registry
+    pub fn entity_category(&self, entity_id: &str) -> Option<EntityCategory> {
+        self.entities.get(entity_id)?.entity_category
+    }
+
+    /// whether `entity_id` is included in `selection`, see [`EntitySelection::includes`]
+    pub fn includes(&self, entity_id: &str, selection: EntitySelection) -> bool {
+        selection.includes(self.entities.get(entity_id))
+    }
+
+    /// "Floor / Area / Name", leaving out the floor or area if the entity has none
+    ///
+    /// `friendly_name` overrides the name from the registry, e.g. the `friendly_name` attribute of
+    /// the state. Entities without any name fall back to their entity id
+    pub fn qualified_name(&self, entity_id: &str, friendly_name: Option<&str>) -> String {
+        let area = self
+            .area_id(entity_id)
+            .and_then(|area_id| self.areas.get(area_id));
+        let floor = area
+            .and_then(|area| area.floor_id.as_deref())
+            .and_then(|floor_id| self.floors.get(floor_id));
+        let name = friendly_name
+            .map(str::to_owned)
+            .or_else(|| self.entity_name(entity_id))
+            .unwrap_or_else(|| entity_id.to_owned());
+
+        let mut parts: Vec<&str> = Vec::new();
+        parts.extend(floor.map(|floor| floor.name.as_str()));
+        parts.extend(area.map(|area| area.name.as_str()));
+        parts.push(&name);
+        parts.join(" / ")
+    }
+}
+
+/// an entity with its current state, see [`DeviceView`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityView {
+    pub entry: EntityRegistryEntry,
+    /// [`None`] for disabled entities, which have no state
+    pub state: Option<StatesResponse>,
+}
+
+/// a device with its entities, see [`HomeAssistantWs::devices_with_entities`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceView {
+    pub device: DeviceRegistryEntry,
+    /// sorted by entity id
+    pub entities: Vec<EntityView>,
+}
+
+impl DeviceView {
+    /// the entities included in `selection`
+    pub fn selected(&self, selection: EntitySelection) -> impl Iterator<Item = &EntityView> {
+        self.entities
+            .iter()
+            .filter(move |entity| selection.includes(Some(&entity.entry)))
+    }
+
+    /// joins devices with their entities and states, in the order of `devices`
+    ///
+    /// entities without a device are left out
+    pub fn join(
+        devices: Vec<DeviceRegistryEntry>,
+        entities: Vec<EntityRegistryEntry>,
+        states: Vec<StatesResponse>,
+    ) -> Vec<Self> {
+        let mut states: HashMap<String, StatesResponse> = states
+            .into_iter()
+            .filter_map(|state| Some((state.entity_id.clone()?, state)))
+            .collect();
+        let mut by_device: HashMap<String, Vec<EntityView>> = HashMap::new();
+        for entry in entities {
+            let Some(device_id) = entry.device_id.clone() else {
+                continue;
+            };
+            let state = states.remove(entry.entity_id.as_str());
+            by_device
+                .entry(device_id)
+                .or_default()
+                .push(EntityView { entry, state });
+        }
+
+        devices
+            .into_iter()
+            .map(|device| {
+                let mut entities = by_device.remove(&device.id).unwrap_or_default();
+                entities.sort_by(|a, b| a.entry.entity_id.cmp(&b.entry.entity_id));
+                Self { device, entities }
+            })
+            .collect()
+    }
+}
+
+impl HomeAssistantWs {
+    /// all devices with their entities and current states, see [`DeviceView::join`]
+    pub async fn devices_with_entities(&self) -> anyhow::Result<Vec<DeviceView>> {
+        let (devices, entities, states) = tokio::try_join!(
+            self.device_registry(),
+            self.entity_registry(),
+            self.states()
+        )?;
+        Ok(DeviceView::join(devices, entities, states))
+    }
+
+    /// `get_states`, filtered to entities of `domain`, e.g. `light`
+    pub async fn states_by_domain(&self, domain: &str) -> anyhow::Result<Vec<StatesResponse>> {
+        let mut states = self.states().await?;
+        states.retain(|state| {
+            state
+                .entity_id
+                .as_deref()
+                .and_then(|entity_id| entity_id.split_once('.'))
+                .is_some_and(|(entity_domain, _)| entity_domain == domain)
+        });
+        Ok(states)
+    }
+
+    /// `get_states`, filtered to entities in `area_id`, directly or through their device
+    ///
+    /// includes hidden and disabled entities, see
+    /// [`select_states_in_area`](Self::select_states_in_area) to leave them out
+    pub async fn states_in_area(&self, area_id: &str) -> anyhow::Result<Vec<StatesResponse>> {
+        self.select_states_in_area(area_id, EntitySelection::default())
+            .await
+    }
+
+    /// `get_states`, filtered to the entities in `area_id` which are included in `selection`
+    ///
+    /// the registries are cached for [`AREA_CACHE_TTL`], states are always fetched
+    pub async fn select_states_in_area(
+        &self,
+        area_id: &str,
+        selection: EntitySelection,
+    ) -> anyhow::Result<Vec<StatesResponse>> {
+        let (registries, mut states) = tokio::try_join!(self.cached_registries(), self.states())?;
+        states.retain(|state| {
+            state.entity_id.as_deref().is_some_and(|entity_id| {
+                registries.area_id(entity_id) == Some(area_id)
+                    && registries.includes(entity_id, selection)
+            })
+        });
+        Ok(states)
+    }
+
+    /// `get_states`, filtered to the entities included in `selection`
+    ///
+    /// the registries are cached for [`AREA_CACHE_TTL`], states are always fetched
+    pub async fn select_states(
+        &self,
+        selection: EntitySelection,
+    ) -> anyhow::Result<Vec<StatesResponse>> {
+        let (registries, mut states) = tokio::try_join!(self.cached_registries(), self.states())?;
+        states.retain(|state| {
+            state
+                .entity_id
+                .as_deref()
+                .is_none_or(|entity_id| registries.includes(entity_id, selection))
+        });
+        Ok(states)
+    }
+
+    /// `get_states` without configuration, diagnostic and hidden entities, see
+    /// [`EntitySelection::primary`]
+    pub async fn primary_states(&self) -> anyhow::Result<Vec<StatesResponse>> {
+        self.select_states(EntitySelection::primary()).await
+    }
+
+    /// "Floor / Area / Friendly Name" of an entity, see [`Registries::qualified_name`]
+    ///
+    /// uses the cached registries, the friendly name is read from the registry
+    pub async fn qualified_name(&self, entity_id: &str) -> anyhow::Result<String> {
+        Ok(self
+            .cached_registries()
+            .await?
+            .qualified_name(entity_id, None))
+    }
+
+    /// all registries, cached for [`AREA_CACHE_TTL`]
+    pub async fn cached_registries(&self) -> anyhow::Result<Arc<Registries>> {
+        if let Some((fetched, registries)) = self.registry_cache().as_ref()
+            && fetched.elapsed() < AREA_CACHE_TTL
+        {
+            stats::add(&stats::COUNTERS.cache_hits, 1);
+            return Ok(registries.clone());
+        }
+        stats::add(&stats::COUNTERS.cache_misses, 1);
+        let generation = self.registry_generation();
+        let (entities, devices, areas, floors) = tokio::try_join!(
+            self.entity_registry(),
+            self.device_registry(),
+            self.area_registry(),
+            self.floor_registry()
+        )?;
+        let registries = Arc::new(Registries::new(entities, devices, areas, floors));
+        let mut cache = self.registry_cache();
+        // invalidated while fetching, the result may predate the change
+        if self.registry_generation() == generation {
+            *cache = Some((Instant::now(), registries.clone()));
+        }
+        Ok(registries)
+    }
+
+    /// the area of every entity, see [`entity_areas`], from the registries cached for
+    /// [`AREA_CACHE_TTL`]
+    pub async fn cached_entity_areas(&self) -> anyhow::Result<Arc<HashMap<String, String>>> {
+        Ok(Arc::new(self.cached_registries().await?.entity_areas()))
+    }
+
+    /// drops the cached areas of entities and registries, e.g. after moving entities or devices
+    pub fn invalidate_area_cache(&self) {
+        self.clear_registry_cache();
+    }
+
+    /// `config/entity_registry/list`, returns a Vec containing [`EntityRegistryEntry`]
+    pub async fn entity_registry(&self) -> anyhow::Result<Vec<EntityRegistryEntry>> {
+        self.command_as(json!({"type": "config/entity_registry/list"}))
+            .await
+    }
+
+    /// `config/device_registry/list`, returns a Vec containing [`DeviceRegistryEntry`]
+    pub async fn device_registry(&self) -> anyhow::Result<Vec<DeviceRegistryEntry>> {
+        self.command_as(json!({"type": "config/device_registry/list"}))
+            .await
+    }
+
+    /// `config/area_registry/list`, returns a Vec containing [`AreaRegistryEntry`]
+    pub async fn area_registry(&self) -> anyhow::Result<Vec<AreaRegistryEntry>> {
+        self.command_as(json!({"type": "config/area_registry/list"}))
+            .await
+    }
+
+    /// `config/floor_registry/list`, returns a Vec containing [`FloorRegistryEntry`]
+    ///
+    /// empty on releases before floors were introduced (2024.4)
+    pub async fn floor_registry(&self) -> anyhow::Result<Vec<FloorRegistryEntry>> {
+        match self
+            .command_as(json!({"type": "config/floor_registry/list"}))
+            .await
+        {
+            Err(e)
+                if e.downcast_ref::<CommandError>()
+                    .is_some_and(|e| e.code == "unknown_command") =>
+            {
+                Ok(Vec::new())
+            }
+            result => result,
+        }
+    }
+
+    /// `config/area_registry/create`, returns the created [`AreaRegistryEntry`]
+    pub async fn create_area(&self, name: &str) -> anyhow::Result<AreaRegistryEntry> {
+        self.command_as(json!({"type": "config/area_registry/create", "name": name}))
+            .await
+    }
+
+    /// `config/entity_registry/update`, returns the updated [`EntityRegistryEntry`]
+    pub async fn update_entity(
+        &self,
+        entity_id: &str,
+        update: EntityRegistryUpdate,
+    ) -> anyhow::Result<EntityRegistryEntry> {
+        let mut payload = serde_json::to_value(update)?;
+        payload["type"] = "config/entity_registry/update".into();
+        payload["entity_id"] = entity_id.into();
+        let result = self.command(payload).await?;
+        self.invalidate_area_cache();
+        Ok(serde_json::from_value(result["entity_entry"].clone())?)
+    }
+
+    /// assigns an entity to an area, or removes it from its area if `area_id` is [`None`]
+    pub async fn assign_entity_area(
+        &self,
+        entity_id: &str,
+        area_id: Option<&str>,
+    ) -> anyhow::Result<EntityRegistryEntry> {
+        self.update_entity(
+            entity_id,
+            EntityRegistryUpdate {
+                area_id: Some(area_id.map(str::to_owned)),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// changes the entity id of an entity
+    pub async fn rename_entity_id(
+        &self,
+        entity_id: &str,
+        new_entity_id: &str,
+    ) -> anyhow::Result<EntityRegistryEntry> {
+        self.update_entity(
+            entity_id,
+            EntityRegistryUpdate {
+                new_entity_id: Some(new_entity_id.to_owned()),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// sets the friendly name of an entity, [`None`] restores the name provided by the integration
+    pub async fn set_entity_name(
+        &self,
+        entity_id: &str,
+        name: Option<&str>,
+    ) -> anyhow::Result<EntityRegistryEntry> {
+        self.update_entity(
+            entity_id,
+            EntityRegistryUpdate {
+                name: Some(name.map(str::to_owned)),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// sets the icon of an entity, [`None`] restores the icon provided by the integration
+    pub async fn set_entity_icon(
+        &self,
+        entity_id: &str,
+        icon: Option<&str>,
+    ) -> anyhow::Result<EntityRegistryEntry> {
+        self.update_entity(
+            entity_id,
+            EntityRegistryUpdate {
+                icon: Some(icon.map(str::to_owned)),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// disables or enables an entity
+    pub async fn set_entity_disabled(
+        &self,
+        entity_id: &str,
+        disabled: bool,
+    ) -> anyhow::Result<EntityRegistryEntry> {
+        self.update_entity(
+            entity_id,
+            EntityRegistryUpdate {
+                disabled_by: Some(disabled.then(|| "user".to_owned())),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// hides or shows an entity
+    pub async fn set_entity_hidden(
+        &self,
+        entity_id: &str,
+        hidden: bool,
+    ) -> anyhow::Result<EntityRegistryEntry> {
+        self.update_entity(
+            entity_id,
+            EntityRegistryUpdate {
+                hidden_by: Some(hidden.then(|| "user".to_owned())),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// `config/device_registry/update`, assigns a device (and thereby its entities) to an area, or
+    /// removes it from its area if `area_id` is [`None`]
+    pub async fn assign_device_area(
+        &self,
+        device_id: &str,
+        area_id: Option<&str>,
+    ) -> anyhow::Result<DeviceRegistryEntry> {
+        let device = self
+            .command_as(json!({
+                "type": "config/device_registry/update",
+                "device_id": device_id,
+                "area_id": area_id,
+            }))
+            .await?;
+        self.invalidate_area_cache();
+        Ok(device)
+    }
+}
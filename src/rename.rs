@@ -0,0 +1,222 @@
+//! Renaming many entity ids at once (WebSocket only)
+//!
+//! Replacing a coordinator or re-pairing devices leaves entities with new ids (e.g.
+//! `sensor.kitchen_temperature_2`), [`HomeAssistantWs::rename_entities`] moves them back in one go.
+//! The whole mapping is checked against the entity registry before anything is renamed:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! let mapping = [
+//!     ("sensor.kitchen_temperature_2", "sensor.kitchen_temperature"),
+//!     ("light.desk_2", "light.desk"),
+//! ];
+//! for rename in ws.rename_entities(mapping, true).await? {
+//!     println!("{} -> {}: {:?}", rename.entity_id, rename.new_entity_id, rename.outcome);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A chain of renames (`a` to `b` while `b` moves to `c`) is applied in the order which keeps every
+//! new id free, cycles (swapping two ids) are rejected.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ws::HomeAssistantWs;
+
+/// what happened to one entry of the mapping, see [`HomeAssistantWs::rename_entities`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameOutcome {
+    Renamed,
+    /// the entry is valid, nothing was renamed since it was a dry run
+    Planned,
+    /// the new id equals the current id
+    Unchanged,
+    /// the entry failed validation, with the reason
+    Rejected(String),
+    /// the registry update failed, with its error
+    Failed(String),
+    /// not applied because another entry was rejected or failed
+    Skipped,
+}
+
+impl RenameOutcome {
+    /// whether the entry was (or in a dry run, would be) applied
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Renamed | Self::Planned | Self::Unchanged)
+    }
+}
+
+/// one entry of the mapping with its outcome
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityRename {
+    pub entity_id: String,
+    pub new_entity_id: String,
+    pub outcome: RenameOutcome,
+}
+
+/// whether `entity_id` is accepted by Homeassistant: lowercase letters, digits and single
+/// underscores, not at the start or end of the domain or object id
+fn is_valid_entity_id(entity_id: &str) -> bool {
+    let valid = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with('_')
+            && !part.ends_with('_')
+            && !part.contains("__")
+            && part
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+    entity_id
+        .split_once('.')
+        .is_some_and(|(domain, object_id)| valid(domain) && valid(object_id))
+}
+
+/// the reason `entity_id` can't be renamed to `new_entity_id`, checked against the `registered`
+/// entity ids and the other entries of the mapping
+fn rejection(
+    entity_id: &str,
+    new_entity_id: &str,
+    registered: &HashSet<&str>,
+    sources: &HashMap<&str, usize>,
+    targets: &HashMap<&str, usize>,
+) -> Option<String> {
+    if !registered.contains(entity_id) {
+        return Some(format!("{entity_id} is not in the entity registry"));
+    }
+    if sources[entity_id] > 1 {
+        return Some(format!("{entity_id} is renamed more than once"));
+    }
+    if !is_valid_entity_id(new_entity_id) {
+        return Some(format!("{new_entity_id} is not a valid entity id"));
+    }
+    if entity_id.split_once('.').map(|(domain, _)| domain)
+        != new_entity_id.split_once('.').map(|(domain, _)| domain)
+    {
+        return Some("the domain of an entity can't be changed".to_owned());
+    }
+    if targets[new_entity_id] > 1 {
+        return Some(format!("{new_entity_id} is the new id of several entities"));
+    }
+    if registered.contains(new_entity_id) && !sources.contains_key(new_entity_id) {
+        return Some(format!("{new_entity_id} is already registered"));
+    }
+    None
+}
+
+impl HomeAssistantWs {
+    /// renames the entity ids in `mapping` (current id, new id) with `config/entity_registry/update`,
+    /// returns the outcome of every entry in the order of `mapping`
+    ///
+    /// nothing is renamed if any entry is rejected, e.g. because its new id is already registered.
+    /// A failed update stops the renames still pending, the ones already applied are kept.
+    /// `dry_run` only validates
+    pub async fn rename_entities<I, A, B>(
+        &self,
+        mapping: I,
+        dry_run: bool,
+    ) -> anyhow::Result<Vec<EntityRename>>
+    where
+        I: IntoIterator<Item = (A, B)>,
+        A: AsRef<str>,
+        B: AsRef<str>,
+    {
+        let mut renames: Vec<EntityRename> = mapping
+            .into_iter()
+            .map(|(entity_id, new_entity_id)| EntityRename {
+                entity_id: entity_id.as_ref().to_owned(),
+                new_entity_id: new_entity_id.as_ref().to_owned(),
+                outcome: RenameOutcome::Skipped,
+            })
+            .collect();
+        let entities = self.entity_registry().await?;
+        let registered: HashSet<&str> = entities
+            .iter()
+            .map(|entry| entry.entity_id.as_str())
+            .collect();
+
+        let mut pending = Vec::new();
+        {
+            let moving = renames
+                .iter()
+                .filter(|rename| rename.entity_id != rename.new_entity_id);
+            let mut sources: HashMap<&str, usize> = HashMap::new();
+            let mut targets: HashMap<&str, usize> = HashMap::new();
+            for rename in moving {
+                *sources.entry(&rename.entity_id).or_default() += 1;
+                *targets.entry(&rename.new_entity_id).or_default() += 1;
+            }
+            let outcomes: Vec<RenameOutcome> = renames
+                .iter()
+                .map(|rename| {
+                    if rename.entity_id == rename.new_entity_id {
+                        return RenameOutcome::Unchanged;
+                    }
+                    match rejection(
+                        &rename.entity_id,
+                        &rename.new_entity_id,
+                        &registered,
+                        &sources,
+                        &targets,
+                    ) {
+                        Some(reason) => RenameOutcome::Rejected(reason),
+                        None => RenameOutcome::Skipped,
+                    }
+                })
+                .collect();
+            for (index, (rename, outcome)) in renames.iter_mut().zip(outcomes).enumerate() {
+                if outcome == RenameOutcome::Skipped {
+                    pending.push(index);
+                }
+                rename.outcome = outcome;
+            }
+        }
+
+        // an entry can be applied once no pending entry still holds its new id
+        let mut order = Vec::new();
+        loop {
+            let held: HashSet<&str> = pending
+                .iter()
+                .map(|&index| renames[index].entity_id.as_str())
+                .collect();
+            let (ready, blocked): (Vec<usize>, Vec<usize>) = pending
+                .iter()
+                .partition(|&&index| !held.contains(renames[index].new_entity_id.as_str()));
+            if ready.is_empty() {
+                break;
+            }
+            order.extend(ready);
+            pending = blocked;
+        }
+        for index in pending {
+            renames[index].outcome = RenameOutcome::Rejected(
+                "the renames form a cycle, rename through an unused entity id".to_owned(),
+            );
+        }
+
+        if renames
+            .iter()
+            .any(|rename| matches!(rename.outcome, RenameOutcome::Rejected(_)))
+        {
+            return Ok(renames);
+        }
+        for index in order {
+            let rename = &mut renames[index];
+            if dry_run {
+                rename.outcome = RenameOutcome::Planned;
+                continue;
+            }
+            match self
+                .rename_entity_id(&rename.entity_id, &rename.new_entity_id)
+                .await
+            {
+                Ok(_) => rename.outcome = RenameOutcome::Renamed,
+                Err(e) => {
+                    rename.outcome = RenameOutcome::Failed(e.to_string());
+                    break;
+                }
+            }
+        }
+        Ok(renames)
+    }
+}
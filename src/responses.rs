@@ -0,0 +1,253 @@
+//! Typed responses of response-returning services
+//!
+//! Every type implementing [`ServiceResponse`] knows the service it belongs to, so
+//! [`service_typed`](HomeAssistantWs::service_typed) only needs the service data:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use homeassistant_rs::responses::Forecasts;
+//!
+//! let forecasts: Forecasts = ws
+//!     .service_typed(json!({"type": "daily"}), Some(json!({"entity_id": "weather.home"})))
+//!     .await?;
+//! for forecast in &forecasts["weather.home"].forecast {
+//!     println!("{}: {:?}", forecast.datetime, forecast.temperature);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Responses of arbitrary calls can be matched against the [`KNOWN`] services with
+//! [`KnownResponse::parse`].
+
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::structs::CalendarEvent;
+use crate::ws::HomeAssistantWs;
+use crate::{ApiPost, HomeAssistantPost, credentials};
+
+/// response of a service called with `return_response`
+pub trait ServiceResponse: DeserializeOwned {
+    const DOMAIN: &'static str;
+    const SERVICE: &'static str;
+}
+
+/// the services with a typed response, as `(domain, service)`
+pub const KNOWN: &[(&str, &str)] = &[
+    (Forecasts::DOMAIN, Forecasts::SERVICE),
+    (CalendarEvents::DOMAIN, CalendarEvents::SERVICE),
+    (TodoItems::DOMAIN, TodoItems::SERVICE),
+    (ConversationResult::DOMAIN, ConversationResult::SERVICE),
+];
+
+/// whether `domain.service` has a typed response
+pub fn is_known(domain: &str, service: &str) -> bool {
+    KNOWN.contains(&(domain, service))
+}
+
+/// responses which are keyed by the targeted entity
+macro_rules! per_entity {
+    ($name:ident, $inner:ty, $domain:literal, $service:literal) => {
+        #[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+        #[serde(transparent)]
+        pub struct $name(pub HashMap<String, $inner>);
+
+        impl Deref for $name {
+            type Target = HashMap<String, $inner>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl ServiceResponse for $name {
+            const DOMAIN: &'static str = $domain;
+            const SERVICE: &'static str = $service;
+        }
+    };
+}
+
+per_entity!(Forecasts, EntityForecast, "weather", "get_forecasts");
+per_entity!(CalendarEvents, EntityEvents, "calendar", "get_events");
+per_entity!(TodoItems, EntityItems, "todo", "get_items");
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EntityForecast {
+    #[serde(default)]
+    pub forecast: Vec<Forecast>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Forecast {
+    pub datetime: String,
+    pub condition: Option<String>,
+    pub temperature: Option<f64>,
+    /// lowest temperature, for daily and twice daily forecasts
+    pub templow: Option<f64>,
+    pub humidity: Option<f64>,
+    pub precipitation: Option<f64>,
+    pub precipitation_probability: Option<f64>,
+    pub wind_speed: Option<f64>,
+    pub wind_bearing: Option<f64>,
+    /// only set for twice daily forecasts
+    pub is_daytime: Option<bool>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityEvents {
+    #[serde(default)]
+    pub events: Vec<CalendarEvent>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityItems {
+    #[serde(default)]
+    pub items: Vec<TodoItem>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoStatus {
+    #[default]
+    NeedsAction,
+    Completed,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TodoItem {
+    pub summary: String,
+    pub uid: Option<String>,
+    pub status: TodoStatus,
+    /// a date or a datetime
+    pub due: Option<String>,
+    pub description: Option<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+/// response of `conversation.process`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversationResult {
+    pub response: ConversationResponse,
+    pub conversation_id: Option<String>,
+    #[serde(default)]
+    pub continue_conversation: bool,
+}
+
+impl ServiceResponse for ConversationResult {
+    const DOMAIN: &'static str = "conversation";
+    const SERVICE: &'static str = "process";
+}
+
+impl ConversationResult {
+    /// the plain text answer, if any
+    pub fn speech(&self) -> Option<&str> {
+        self.response.speech["plain"]["speech"].as_str()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversationResponse {
+    /// `action_done`, `query_answer` or `error`
+    pub response_type: String,
+    pub language: String,
+    #[serde(default)]
+    pub speech: Value,
+    /// targets and results for `action_done`, the error `code` for `error`
+    #[serde(default)]
+    pub data: Value,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+/// a response of one of the [`KNOWN`] services
+#[derive(Debug, Clone, PartialEq)]
+pub enum KnownResponse {
+    Forecasts(Forecasts),
+    CalendarEvents(CalendarEvents),
+    TodoItems(TodoItems),
+    Conversation(ConversationResult),
+}
+
+impl KnownResponse {
+    /// parses the `response` of `domain.service`, `None` if the service has no typed response
+    pub fn parse(domain: &str, service: &str, response: Value) -> anyhow::Result<Option<Self>> {
+        Ok(Some(match (domain, service) {
+            (Forecasts::DOMAIN, Forecasts::SERVICE) => Self::Forecasts(typed(response)?),
+            (CalendarEvents::DOMAIN, CalendarEvents::SERVICE) => {
+                Self::CalendarEvents(typed(response)?)
+            }
+            (TodoItems::DOMAIN, TodoItems::SERVICE) => Self::TodoItems(typed(response)?),
+            (ConversationResult::DOMAIN, ConversationResult::SERVICE) => {
+                Self::Conversation(typed(response)?)
+            }
+            _ => return Ok(None),
+        }))
+    }
+}
+
+fn typed<R: ServiceResponse>(response: Value) -> anyhow::Result<R> {
+    serde_json::from_value(response).map_err(|e| {
+        anyhow::Error::msg(format!(
+            "unexpected response of {}.{}: {e}",
+            R::DOMAIN,
+            R::SERVICE
+        ))
+    })
+}
+
+fn parse<R: ServiceResponse>(response: Option<Value>) -> anyhow::Result<R> {
+    let response = response.ok_or_else(|| {
+        anyhow::Error::msg(format!("{}.{} returned no response", R::DOMAIN, R::SERVICE))
+    })?;
+    typed(response)
+}
+
+impl HomeAssistantWs {
+    /// calls the service of `R` with `return_response` and parses the response, see
+    /// [`responses`](crate::responses)
+    pub async fn service_typed<R: ServiceResponse>(
+        &self,
+        service_data: Value,
+        target: Option<Value>,
+    ) -> anyhow::Result<R> {
+        let response = self
+            .call_service(R::DOMAIN, R::SERVICE, service_data, target, true)
+            .await?;
+        parse(response)
+    }
+}
+
+impl HomeAssistantPost {
+    /// posts to `/api/services/<domain>/<service>` of `R` with `return_response` and parses the
+    /// `service_response`, targets are passed in `request`
+    pub async fn service_typed<R: ServiceResponse>(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        request: Value,
+    ) -> anyhow::Result<R> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token).service_typed(request).await
+    }
+}
+
+impl ApiPost<'_> {
+    pub(crate) async fn service_typed<R: ServiceResponse>(
+        &self,
+        request: Value,
+    ) -> anyhow::Result<R> {
+        let mut body = self.service(R::DOMAIN, R::SERVICE, request, true).await?;
+        parse(Some(body["service_response"].take()).filter(|r| !r.is_null()))
+    }
+}
@@ -0,0 +1,154 @@
+//! Retries of failed REST requests
+//!
+//! Opt-in with [`settings::set_retry_policy`](crate::settings::set_retry_policy), every REST
+//! request is then retried on connection errors and on the statuses a reverse proxy returns while
+//! Homeassistant is unreachable or restarting:
+//! ```
+//! use std::time::Duration;
+//! use homeassistant_rs::retry::RetryPolicy;
+//! use homeassistant_rs::settings;
+//!
+//! settings::set_retry_policy(Some(RetryPolicy {
+//!     max_attempts: 5,
+//!     max_delay: Duration::from_secs(10),
+//!     ..Default::default()
+//! }));
+//! ```
+//!
+//! A `Retry-After` header replaces the computed delay. POST requests (e.g. service calls) may have
+//! been executed by Homeassistant before a proxy gave up on them, so they are only retried after
+//! connection errors and `429 Too Many Requests` unless [`RetryPolicy::retry_posts`] is set.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Method, StatusCode};
+
+use crate::stats;
+
+/// when and how often failed requests are retried
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// attempts including the first one, `1` disables retries
+    pub max_attempts: u32,
+    /// delay before the first retry, doubled after every further failed attempt
+    pub initial_delay: Duration,
+    /// longest delay between attempts, a longer `Retry-After` returns the response instead
+    pub max_delay: Duration,
+    /// share of each delay which is randomized, from `0.0` to `1.0`, so clients failing together
+    /// don't retry together
+    pub jitter: f64,
+    /// responses with these statuses are retried
+    pub statuses: Vec<StatusCode>,
+    /// retry POST requests after timeouts and on the other [`statuses`](Self::statuses) than
+    /// `429` as well, which may call a service twice
+    pub retry_posts: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_posts: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// the delay after the failed attempt number `attempt` (starting at 1), before jitter
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+
+    /// [`backoff`](Self::backoff) shortened by up to [`jitter`](Self::jitter)
+    fn delay(&self, attempt: u32) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0) * random_fraction();
+        self.backoff(attempt).mul_f64(1.0 - jitter)
+    }
+
+    /// the delay before retrying after `result`, [`None`] if it is final
+    fn retry_delay(
+        &self,
+        method: &Method,
+        result: &anyhow::Result<reqwest::Response>,
+        attempt: u32,
+    ) -> Option<Duration> {
+        let idempotent = *method != Method::POST || self.retry_posts;
+        match result {
+            // a request rejected with 429 was not executed
+            Ok(response)
+                if self.statuses.contains(&response.status())
+                    && (idempotent || response.status() == StatusCode::TOO_MANY_REQUESTS) =>
+            {
+                match retry_after(response.headers()) {
+                    Some(delay) => (delay <= self.max_delay).then_some(delay),
+                    None => Some(self.delay(attempt)),
+                }
+            }
+            Ok(_) => None,
+            Err(e) => {
+                let e = e.downcast_ref::<reqwest::Error>()?;
+                (e.is_connect() || (idempotent && e.is_timeout())).then(|| self.delay(attempt))
+            }
+        }
+    }
+}
+
+/// the `Retry-After` header, in seconds or as an HTTP date
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// a number from `0.0` to `1.0`, different for every call
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    // every `RandomState` is seeded differently
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u32(Utc::now().timestamp_subsec_nanos());
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// calls `send` until it succeeds, fails for good or `policy` runs out of attempts, returns the last
+/// result
+pub(crate) async fn run<F: Future<Output = anyhow::Result<reqwest::Response>>>(
+    policy: Option<&RetryPolicy>,
+    method: &Method,
+    mut send: impl FnMut() -> F,
+) -> anyhow::Result<reqwest::Response> {
+    let mut attempt = 1;
+    loop {
+        let result = send().await;
+        let Some(policy) = policy.filter(|policy| attempt < policy.max_attempts) else {
+            return result;
+        };
+        let Some(delay) = policy.retry_delay(method, &result, attempt) else {
+            return result;
+        };
+        stats::add(&stats::COUNTERS.retries, 1);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
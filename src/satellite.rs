@@ -0,0 +1,236 @@
+//! Assist satellites, requires the `satellite` feature
+//!
+//! Runs an assist pipeline over the WebSocket connection, which lets a Rust process act as a voice
+//! satellite: audio is streamed in, pipeline events (detected wake word, transcription, intent,
+//! TTS audio URL) are streamed back.
+//!
+//! Audio is sent as binary frames prefixed with the `stt_binary_handler_id` announced in the
+//! `run-start` event, an empty frame signals the end of the audio. It has to be 16 kHz, 16 bit,
+//! mono PCM.
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::hass;
+//! use homeassistant_rs::satellite::{PipelineOptions, PipelineStage};
+//!
+//! let ws = hass().websocket(None, None).await.unwrap();
+//! let mut run = ws
+//!     .run_assist_pipeline(PipelineOptions::new(PipelineStage::WakeWord, PipelineStage::Tts))
+//!     .await
+//!     .unwrap();
+//!
+//! # let microphone: Vec<Vec<u8>> = vec![];
+//! for chunk in microphone {
+//!     run.send_audio(&chunk).unwrap();
+//! }
+//! run.end_audio().unwrap();
+//!
+//! while let Some(event) = run.next_event().await {
+//!     let event = event.unwrap();
+//!     if let Some(url) = event.tts_url() {
+//!         println!("play {url}");
+//!     }
+//! }
+//! # });
+//! ```
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::ws::{HomeAssistantWs, Subscription};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStage {
+    WakeWord,
+    Stt,
+    Intent,
+    Tts,
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineOptions {
+    pub start_stage: PipelineStage,
+    pub end_stage: PipelineStage,
+    /// id of the pipeline, the preferred pipeline is used if unset
+    pub pipeline: Option<String>,
+    /// sample rate of the streamed audio
+    pub sample_rate: u32,
+    /// continue an earlier conversation
+    pub conversation_id: Option<String>,
+    /// timeout of the whole run in seconds
+    pub timeout: Option<u64>,
+}
+
+impl PipelineOptions {
+    pub fn new(start_stage: PipelineStage, end_stage: PipelineStage) -> Self {
+        Self {
+            start_stage,
+            end_stage,
+            pipeline: None,
+            sample_rate: 16000,
+            conversation_id: None,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PipelineEventType {
+    RunStart,
+    RunEnd,
+    #[serde(rename = "wake_word-start")]
+    WakeWordStart,
+    #[serde(rename = "wake_word-end")]
+    WakeWordEnd,
+    SttStart,
+    SttVadStart,
+    SttVadEnd,
+    SttEnd,
+    IntentStart,
+    IntentProgress,
+    IntentEnd,
+    TtsStart,
+    TtsEnd,
+    Error,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PipelineEvent {
+    #[serde(rename = "type")]
+    pub event_type: PipelineEventType,
+    #[serde(default)]
+    pub data: Value,
+    pub timestamp: Option<String>,
+}
+
+impl PipelineEvent {
+    /// handler id audio frames have to be prefixed with, set on `run-start`
+    pub fn stt_binary_handler_id(&self) -> Option<u8> {
+        self.data["runner_data"]["stt_binary_handler_id"]
+            .as_u64()
+            .and_then(|id| u8::try_from(id).ok())
+    }
+
+    /// detected wake word, set on `wake_word-end`
+    pub fn wake_word_id(&self) -> Option<&str> {
+        self.data["wake_word_output"]["wake_word_id"].as_str()
+    }
+
+    /// transcription, set on `stt-end`
+    pub fn stt_text(&self) -> Option<&str> {
+        self.data["stt_output"]["text"].as_str()
+    }
+
+    /// spoken response as text, set on `intent-end`
+    pub fn speech(&self) -> Option<&str> {
+        self.data["intent_output"]["response"]["speech"]["plain"]["speech"].as_str()
+    }
+
+    /// (relative) URL of the TTS audio, set on `tts-end`
+    pub fn tts_url(&self) -> Option<&str> {
+        self.data["tts_output"]["url"].as_str()
+    }
+
+    /// `code: message` of an `error` event
+    pub fn error(&self) -> Option<String> {
+        (self.event_type == PipelineEventType::Error).then(|| {
+            format!(
+                "{}: {}",
+                self.data["code"].as_str().unwrap_or("unknown_error"),
+                self.data["message"].as_str().unwrap_or_default()
+            )
+        })
+    }
+}
+
+/// a running assist pipeline, created by [`HomeAssistantWs::run_assist_pipeline`]
+pub struct PipelineRun {
+    ws: HomeAssistantWs,
+    handler_id: Option<u8>,
+    events: Subscription<PipelineEvent>,
+    finished: bool,
+}
+
+impl PipelineRun {
+    /// handler id of the audio frames, [`None`] if the pipeline does not start with audio
+    pub fn handler_id(&self) -> Option<u8> {
+        self.handler_id
+    }
+
+    /// sends a chunk of audio
+    pub fn send_audio(&self, audio: &[u8]) -> anyhow::Result<()> {
+        let handler_id = self
+            .handler_id
+            .ok_or(anyhow::Error::msg("pipeline does not accept audio"))?;
+        let mut frame = Vec::with_capacity(audio.len() + 1);
+        frame.push(handler_id);
+        frame.extend_from_slice(audio);
+        self.ws.send_binary(frame)
+    }
+
+    /// signals the end of the audio
+    pub fn end_audio(&self) -> anyhow::Result<()> {
+        let handler_id = self
+            .handler_id
+            .ok_or(anyhow::Error::msg("pipeline does not accept audio"))?;
+        self.ws.send_binary(vec![handler_id])
+    }
+
+    /// returns the next event, [`None`] after `run-end`
+    pub async fn next_event(&mut self) -> Option<anyhow::Result<PipelineEvent>> {
+        if self.finished {
+            return None;
+        }
+        let event = self.events.next().await;
+        if let Some(Ok(event)) = &event {
+            self.finished = event.event_type == PipelineEventType::RunEnd;
+        }
+        event
+    }
+}
+
+impl HomeAssistantWs {
+    /// `assist_pipeline/run`, starts a pipeline and waits for its `run-start` event
+    pub async fn run_assist_pipeline(
+        &self,
+        options: PipelineOptions,
+    ) -> anyhow::Result<PipelineRun> {
+        let mut payload = json!({
+            "type": "assist_pipeline/run",
+            "start_stage": options.start_stage,
+            "end_stage": options.end_stage,
+            "input": {"sample_rate": options.sample_rate},
+        });
+        if let Some(pipeline) = options.pipeline {
+            payload["pipeline"] = pipeline.into();
+        }
+        if let Some(conversation_id) = options.conversation_id {
+            payload["conversation_id"] = conversation_id.into();
+        }
+        if let Some(timeout) = options.timeout {
+            payload["timeout"] = timeout.into();
+        }
+
+        let mut events = self.subscribe::<PipelineEvent>(payload).await?;
+        let start = events
+            .next()
+            .await
+            .ok_or(anyhow::Error::msg("websocket connection closed"))??;
+        if let Some(error) = start.error() {
+            return Err(anyhow::Error::msg(error));
+        }
+
+        Ok(PipelineRun {
+            ws: self.clone(),
+            handler_id: start.stt_binary_handler_id(),
+            events,
+            finished: false,
+        })
+    }
+}
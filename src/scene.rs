@@ -0,0 +1,344 @@
+//! Scenes defined in Rust
+//!
+//! A [`Scene`] maps entities to their desired state and attributes. It can be applied directly with
+//! `scene.apply`, or stored as a Homeassistant scene through the scene config API, which makes it
+//! show up in the UI.
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use homeassistant_rs::scene::{EntityState, Scene};
+//!
+//! let movie = Scene::new("movie_night", "Movie night")
+//!     .entity("light.living_room", EntityState::on().brightness(40).color_temp_kelvin(2700))
+//!     .entity("light.kitchen", EntityState::off())
+//!     .entity("media_player.tv", EntityState::on());
+//!
+//! ws.apply_scene(&movie, Some(2.0)).await?;
+//! hass().request().save_scene(None, None, &movie).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Existing scenes are listed with [`scenes`](HomeAssistantWs::scenes), parts of a stored scene
+//! can be applied with [`apply_scene_subset`](HomeAssistantWs::apply_scene_subset).
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::structs::StatesResponse;
+use crate::ws::HomeAssistantWs;
+use crate::{
+    Api, ApiPost, Body, HomeAssistant, HomeAssistantPost, credentials, decode, post, request, send,
+    urls,
+};
+
+/// desired state of an entity within a [`Scene`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EntityState {
+    pub state: String,
+    #[serde(flatten)]
+    pub attributes: Map<String, Value>,
+}
+
+impl EntityState {
+    pub fn new(state: &str) -> Self {
+        Self {
+            state: state.to_owned(),
+            attributes: Map::new(),
+        }
+    }
+
+    pub fn on() -> Self {
+        Self::new("on")
+    }
+
+    pub fn off() -> Self {
+        Self::new("off")
+    }
+
+    /// sets the attribute `key`
+    pub fn attr(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.attributes.insert(key.to_owned(), value.into());
+        self
+    }
+
+    /// light brightness, 0 to 255
+    pub fn brightness(self, brightness: u8) -> Self {
+        self.attr("brightness", brightness)
+    }
+
+    pub fn color_temp_kelvin(self, kelvin: u16) -> Self {
+        self.attr("color_temp_kelvin", kelvin)
+    }
+
+    pub fn rgb_color(self, red: u8, green: u8, blue: u8) -> Self {
+        self.attr("rgb_color", json!([red, green, blue]))
+    }
+
+    /// cover or valve position in percent
+    pub fn position(self, position: u8) -> Self {
+        self.attr("current_position", position.min(100))
+    }
+
+    /// climate target temperature
+    pub fn temperature(self, temperature: f64) -> Self {
+        self.attr("temperature", temperature)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    /// id within the scene config, the entity id is derived from the name
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    pub entities: BTreeMap<String, EntityState>,
+}
+
+impl Scene {
+    pub fn new(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn icon(mut self, icon: &str) -> Self {
+        self.icon = Some(icon.to_owned());
+        self
+    }
+
+    /// sets the desired state of `entity_id`, replacing an earlier one
+    pub fn entity(mut self, entity_id: &str, state: EntityState) -> Self {
+        self.entities.insert(entity_id.to_owned(), state);
+        self
+    }
+
+    /// checks that the scene has entities and all of them are valid entity ids
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.id.is_empty() {
+            anyhow::bail!("scene has no id");
+        }
+        if self.entities.is_empty() {
+            anyhow::bail!("scene {} has no entities", self.id);
+        }
+        if let Some(entity_id) = self.entities.keys().find(|entity_id| {
+            !matches!(entity_id.split_once('.'), Some((domain, object_id)) if !domain.is_empty() && !object_id.is_empty())
+        }) {
+            anyhow::bail!("scene {} contains invalid entity id {entity_id}", self.id);
+        }
+        Ok(())
+    }
+
+    /// the scene with only the `entities` in it, fails if one of them is not part of the scene
+    pub fn subset(&self, entities: &[&str]) -> anyhow::Result<Self> {
+        let mut subset = Self {
+            entities: BTreeMap::new(),
+            ..self.clone()
+        };
+        for entity_id in entities {
+            let Some(state) = self.entities.get(*entity_id) else {
+                anyhow::bail!("scene {} does not contain {entity_id}", self.id);
+            };
+            subset
+                .entities
+                .insert((*entity_id).to_owned(), state.clone());
+        }
+        Ok(subset)
+    }
+}
+
+/// a `scene.*` entity
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SceneState {
+    pub entity_id: String,
+    pub name: Option<String>,
+    /// id within the scene config, [`None`] for scenes not created in the UI
+    pub id: Option<String>,
+    /// the member entities
+    pub entities: Vec<String>,
+    /// [`None`] if the scene was never activated
+    pub last_activated: Option<DateTime<Utc>>,
+}
+
+impl SceneState {
+    /// parses a `scene.*` state, [`None`] for other entities
+    pub fn from_state(state: &StatesResponse) -> Option<Self> {
+        let entity_id = state.entity_id.as_deref()?;
+        if !entity_id.starts_with("scene.") {
+            return None;
+        }
+        let attributes = state.attributes.as_ref();
+        let entities = attributes
+            .and_then(|attributes| attributes.other_fields["entity_id"].as_array())
+            .map(|entities| {
+                entities
+                    .iter()
+                    .filter_map(|entity_id| entity_id.as_str().map(str::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Self {
+            entity_id: entity_id.to_owned(),
+            name: attributes.and_then(|attributes| attributes.friendly_name.clone()),
+            id: attributes.and_then(|attributes| attributes.id.clone()),
+            entities,
+            // the state is the time of the last activation
+            last_activated: DateTime::parse_from_rfc3339(&state.state)
+                .ok()
+                .map(|at| at.with_timezone(&Utc)),
+        })
+    }
+
+    pub fn contains(&self, entity_id: &str) -> bool {
+        self.entities.iter().any(|member| member == entity_id)
+    }
+}
+
+/// all scenes within `states`, sorted by entity id
+pub fn scenes(states: &[StatesResponse]) -> Vec<SceneState> {
+    let mut scenes: Vec<SceneState> = states.iter().filter_map(SceneState::from_state).collect();
+    scenes.sort_by(|a, b| a.entity_id.cmp(&b.entity_id));
+    scenes
+}
+
+impl HomeAssistant {
+    /// queries `/api/states` and returns all scenes with their member entities
+    pub async fn scenes(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+    ) -> anyhow::Result<Vec<SceneState>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).scenes().await
+    }
+
+    /// queries `/api/config/scene/config/<id>` and returns the stored [`Scene`], only available
+    /// for scenes created in the UI
+    pub async fn scene_config(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        id: &str,
+    ) -> anyhow::Result<Scene> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).scene_config(id).await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn scenes(&self) -> anyhow::Result<Vec<SceneState>> {
+        Ok(scenes(&self.states(None).await?))
+    }
+
+    pub(crate) async fn scene_config(&self, id: &str) -> anyhow::Result<Scene> {
+        let client = request(
+            self.url,
+            self.token,
+            &format!("/api/config/scene/config/{}", urls::encode_segment(id)),
+        )
+        .await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            decode::json::<Scene>(client).await
+        }
+    }
+}
+
+impl HomeAssistantWs {
+    /// `get_states`, returns all scenes with their member entities
+    pub async fn scenes(&self) -> anyhow::Result<Vec<SceneState>> {
+        Ok(scenes(&self.states().await?))
+    }
+
+    /// `scene.apply`, sets the entities of `scene` without storing it, optionally with a
+    /// `transition` in seconds
+    pub async fn apply_scene(&self, scene: &Scene, transition: Option<f64>) -> anyhow::Result<()> {
+        scene.validate()?;
+        let mut data = json!({ "entities": scene.entities });
+        if let Some(transition) = transition {
+            data["transition"] = transition.into();
+        }
+        self.call_service("scene", "apply", data, None, false)
+            .await?;
+        Ok(())
+    }
+
+    /// `scene.apply` with only the `entities` of `scene`, e.g. the lights of a stored scene
+    /// loaded with [`scene_config`](HomeAssistant::scene_config)
+    pub async fn apply_scene_subset(
+        &self,
+        scene: &Scene,
+        entities: &[&str],
+        transition: Option<f64>,
+    ) -> anyhow::Result<()> {
+        self.apply_scene(&scene.subset(entities)?, transition).await
+    }
+}
+
+impl HomeAssistantPost {
+    /// posts to `/api/config/scene/config/<id>` to create or replace a scene in `scenes.yaml`
+    pub async fn save_scene(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        scene: &Scene,
+    ) -> anyhow::Result<()> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token).save_scene(scene).await
+    }
+
+    /// deletes `/api/config/scene/config/<id>`, removing a scene from `scenes.yaml`
+    pub async fn delete_scene(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        id: &str,
+    ) -> anyhow::Result<()> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        ApiPost::new(&url, &token).delete_scene(id).await
+    }
+}
+
+impl ApiPost<'_> {
+    pub(crate) async fn save_scene(&self, scene: &Scene) -> anyhow::Result<()> {
+        scene.validate()?;
+        let client = post(
+            self.url,
+            self.token,
+            &format!(
+                "/api/config/scene/config/{}",
+                urls::encode_segment(&scene.id)
+            ),
+            scene,
+        )
+        .await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) async fn delete_scene(&self, id: &str) -> anyhow::Result<()> {
+        let client = send(
+            reqwest::Method::DELETE,
+            self.url,
+            self.token,
+            &format!("/api/config/scene/config/{}", urls::encode_segment(id)),
+            Body::Empty,
+        )
+        .await?;
+        if !client.status().is_success() {
+            Err(anyhow::Error::msg(client.status()))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Tokens which never show up in logs
+//!
+//! [`SecretString`] prints `[REDACTED]` with both [`Debug`](std::fmt::Debug) and
+//! [`Display`](std::fmt::Display), the value is only available through
+//! [`expose_secret`](SecretString::expose_secret). With the `secrecy` feature it converts from and
+//! into [`secrecy::SecretString`].
+//! ```
+//! use homeassistant_rs::secret::SecretString;
+//!
+//! let token = SecretString::from("eyJhbGciOiJIUzI1NiJ9");
+//! assert_eq!(format!("{token:?}"), "SecretString([REDACTED])");
+//! assert_eq!(token.expose_secret(), "eyJhbGciOiJIUzI1NiJ9");
+//! ```
+
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+
+    /// the secret itself, keep it out of logs and error messages
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretString([REDACTED])")
+    }
+}
+
+impl std::fmt::Display for SecretString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(secret: &str) -> Self {
+        Self(secret.to_owned())
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl From<secrecy::SecretString> for SecretString {
+    fn from(secret: secrecy::SecretString) -> Self {
+        use secrecy::ExposeSecret;
+        Self(secret.expose_secret().to_owned())
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl From<SecretString> for secrecy::SecretString {
+    fn from(secret: SecretString) -> Self {
+        secret.0.into()
+    }
+}
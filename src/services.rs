@@ -0,0 +1,270 @@
+//! Service calls
+//!
+//! Failed service calls return a [`ServiceCallError`] (wrapped in [`anyhow::Error`]), which tells
+//! a wrong payload apart from a missing service or a failing integration:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::prelude::*;
+//! use homeassistant_rs::services::ServiceCallError;
+//!
+//! let ws = hass().websocket(None, None).await.unwrap();
+//! let result = ws
+//!     .call_service(
+//!         "light",
+//!         "turn_on",
+//!         json!({"brightness": "max"}),
+//!         Some(json!({"entity_id": "light.kitchen"})),
+//!         false,
+//!     )
+//!     .await;
+//!
+//! if let Err(e) = result {
+//!     match e.downcast_ref::<ServiceCallError>() {
+//!         Some(ServiceCallError::InvalidFormat { field, .. }) => println!("invalid field {field:?}"),
+//!         Some(ServiceCallError::NotFound { .. }) => println!("integration not loaded"),
+//!         _ => println!("{e}"),
+//!     }
+//! }
+//! # });
+//! ```
+
+use serde_json::{Value, json};
+
+use crate::ws::{CommandError, HomeAssistantWs};
+use crate::{ratelimit, settings};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceCallError {
+    /// the service does not exist, usually because the integration is not loaded (WebSocket only)
+    NotFound {
+        message: String,
+    },
+    /// the service data does not match the schema of the service
+    InvalidFormat {
+        message: String,
+        /// the offending field, if reported, e.g. `brightness`
+        field: Option<String>,
+    },
+    /// the service rejected the call, e.g. an invalid combination of values
+    Validation {
+        message: String,
+        translation_key: Option<String>,
+    },
+    /// the integration failed to perform the call, e.g. because the device is offline
+    HomeAssistant {
+        message: String,
+        translation_key: Option<String>,
+    },
+    Unauthorized {
+        message: String,
+    },
+    /// targeted entities do not exist, only returned with
+    /// [`verify_targets`](crate::settings::set_verify_targets) enabled
+    UnknownEntities {
+        message: String,
+        entity_ids: Vec<String>,
+    },
+    /// any other error, `code` is the WebSocket error code or the HTTP status
+    Other {
+        code: String,
+        message: String,
+    },
+}
+
+/// extracts `brightness` from voluptuous messages like
+/// `expected int for dictionary value @ data['brightness']`
+fn invalid_field(message: &str) -> Option<String> {
+    let (_, path) = message.rsplit_once("@ data")?;
+    let field = path.trim_start_matches("['").split("']").next()?;
+    (!field.is_empty()).then(|| field.to_owned())
+}
+
+impl ServiceCallError {
+    pub fn message(&self) -> &str {
+        match self {
+            Self::NotFound { message }
+            | Self::InvalidFormat { message, .. }
+            | Self::Validation { message, .. }
+            | Self::HomeAssistant { message, .. }
+            | Self::Unauthorized { message }
+            | Self::UnknownEntities { message, .. }
+            | Self::Other { message, .. } => message,
+        }
+    }
+
+    /// fails with [`UnknownEntities`](Self::UnknownEntities) if one of `entity_ids` is missing in
+    /// `known`, the special targets `all` and `none` are always accepted
+    pub fn verify_targets<'a>(
+        entity_ids: &[String],
+        known: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), Self> {
+        let known: std::collections::HashSet<&str> = known.into_iter().collect();
+        let unknown: Vec<String> = entity_ids
+            .iter()
+            .filter(|entity_id| !matches!(entity_id.as_str(), "all" | "none"))
+            .filter(|entity_id| !known.contains(entity_id.as_str()))
+            .cloned()
+            .collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        Err(Self::UnknownEntities {
+            message: unknown.join(", "),
+            entity_ids: unknown,
+        })
+    }
+
+    /// maps the error of a `call_service` command
+    pub fn from_command_error(error: &CommandError) -> Self {
+        let message = error.message.clone();
+        let translation_key = error.details["translation_key"].as_str().map(str::to_owned);
+        match error.code.as_str() {
+            "not_found" => Self::NotFound { message },
+            "invalid_format" => Self::InvalidFormat {
+                field: invalid_field(&message),
+                message,
+            },
+            "service_validation_error" => Self::Validation {
+                message,
+                translation_key,
+            },
+            "home_assistant_error" => Self::HomeAssistant {
+                message,
+                translation_key,
+            },
+            "unauthorized" => Self::Unauthorized { message },
+            code => Self::Other {
+                code: code.to_owned(),
+                message,
+            },
+        }
+    }
+
+    /// maps the response of `/api/services/<domain>/<service>`, `body` may be empty
+    ///
+    /// Homeassistant answers unknown services and invalid service data alike with a plain
+    /// `400: Bad Request`, both are mapped to [`InvalidFormat`](Self::InvalidFormat) without a
+    /// field. Only the WebSocket API tells them apart
+    pub fn from_response(status: reqwest::StatusCode, body: &str) -> Self {
+        let message = serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|body| body["message"].as_str().map(str::to_owned))
+            .unwrap_or_else(|| body.trim().to_owned());
+        match status.as_u16() {
+            400 => Self::InvalidFormat {
+                field: invalid_field(&message),
+                message,
+            },
+            401 | 403 => Self::Unauthorized { message },
+            500 => Self::HomeAssistant {
+                message,
+                translation_key: None,
+            },
+            _ => Self::Other {
+                code: status.as_u16().to_string(),
+                message,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for ServiceCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Self::NotFound { .. } => "service not found",
+            Self::InvalidFormat { .. } => "invalid service data",
+            Self::Validation { .. } => "service validation failed",
+            Self::HomeAssistant { .. } => "service call failed",
+            Self::Unauthorized { .. } => "unauthorized",
+            Self::UnknownEntities { .. } => "unknown entities",
+            Self::Other { code, .. } => code,
+        };
+        write!(f, "{kind}: {}", self.message())
+    }
+}
+
+impl std::error::Error for ServiceCallError {}
+
+/// service data of `logbook.log`
+pub(crate) fn log_entry_data(
+    name: &str,
+    message: &str,
+    entity_id: Option<&str>,
+    domain: Option<&str>,
+) -> Value {
+    let mut data = json!({"name": name, "message": message});
+    if let Some(entity_id) = entity_id {
+        data["entity_id"] = entity_id.into();
+    }
+    if let Some(domain) = domain {
+        data["domain"] = domain.into();
+    }
+    data
+}
+
+impl HomeAssistantWs {
+    /// `call_service`, returns the service response if `return_response` is set
+    ///
+    /// errors are returned as [`ServiceCallError`], with
+    /// [`verify_targets`](crate::settings::set_verify_targets) unknown entities are rejected before
+    /// the call. Calls to rate limited entities may be delayed
+    /// or [`Superseded`](crate::ratelimit::Superseded), see [`ratelimit`](crate::ratelimit)
+    pub async fn call_service(
+        &self,
+        domain: &str,
+        service: &str,
+        service_data: Value,
+        target: Option<Value>,
+        return_response: bool,
+    ) -> anyhow::Result<Option<Value>> {
+        let entity_ids = ratelimit::entity_ids(&service_data, target.as_ref());
+        if settings::verify_targets() && !entity_ids.is_empty() {
+            let states = self.states().await?;
+            let known = states.iter().filter_map(|state| state.entity_id.as_deref());
+            ServiceCallError::verify_targets(&entity_ids, known)?;
+        }
+        ratelimit::acquire(domain, service, &entity_ids).await?;
+        let mut payload = json!({
+            "type": "call_service",
+            "domain": domain,
+            "service": service,
+            "service_data": service_data,
+        });
+        if let Some(target) = target {
+            payload["target"] = target;
+        }
+        if return_response {
+            payload["return_response"] = true.into();
+        }
+
+        match self.command(payload).await {
+            Ok(result) => Ok(result.get("response").cloned().filter(|r| !r.is_null())),
+            Err(e) => match e.downcast_ref::<CommandError>() {
+                Some(error) => Err(ServiceCallError::from_command_error(error).into()),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// `logbook.log`, writes a custom entry into the logbook, optionally attributed to an
+    /// `entity_id` and `domain`
+    pub async fn log_entry(
+        &self,
+        name: &str,
+        message: &str,
+        entity_id: Option<&str>,
+        domain: Option<&str>,
+    ) -> anyhow::Result<()> {
+        self.call_service(
+            "logbook",
+            "log",
+            log_entry_data(name, message, entity_id, domain),
+            None,
+            false,
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,581 @@
+//! Process-wide settings applied to every REST request and WebSocket handshake
+//!
+//! ```
+//! use homeassistant_rs::settings;
+//!
+//! settings::set_user_agent("my-app/1.0");
+//! settings::set_default_header("CF-Access-Client-Id", "client_id.access").unwrap();
+//! settings::set_default_header("CF-Access-Client-Secret", "secret").unwrap();
+//! ```
+//!
+//! In pure-parameter mode the environment and `.env` files are never read, missing urls and tokens
+//! are an immediate [`MissingParameter`] error instead:
+//! ```
+//! use homeassistant_rs::{hass, settings};
+//!
+//! settings::set_pure_parameters(true);
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let error = hass().config(None, None).await.unwrap_err();
+//! assert!(error.downcast_ref::<settings::MissingParameter>().is_some());
+//! # });
+//! ```
+//!
+//! Credentials which change over time (e.g. short-lived JWTs issued by a zero-trust proxy) are
+//! attached by an [`AuthProvider`]:
+//! ```
+//! use homeassistant_rs::futures_util::future::BoxFuture;
+//! use homeassistant_rs::reqwest::header::{HeaderMap, HeaderValue};
+//! use homeassistant_rs::settings::{self, AuthProvider};
+//!
+//! struct AccessJwt;
+//!
+//! impl AuthProvider for AccessJwt {
+//!     fn headers(&self) -> BoxFuture<'_, anyhow::Result<HeaderMap>> {
+//!         Box::pin(async {
+//!             let mut headers = HeaderMap::new();
+//!             headers.insert("cf-access-token", HeaderValue::from_static("jwt"));
+//!             Ok(headers)
+//!         })
+//!     }
+//! }
+//!
+//! settings::set_auth_provider(AccessJwt).unwrap();
+//! ```
+//!
+//! Timeouts, TLS and proxies of REST requests are set with a [`ClientConfig`], e.g. for an
+//! instance on the LAN with a self-signed certificate. Its TLS options apply to WebSocket
+//! connections as well:
+//! ```no_run
+//! use std::time::Duration;
+//! use homeassistant_rs::reqwest::Proxy;
+//! use homeassistant_rs::settings::{self, ClientConfig, RootCertificate};
+//!
+//! let ca = RootCertificate::from_pem(&std::fs::read("ha-ca.pem").unwrap()).unwrap();
+//! settings::set_client_config(
+//!     ClientConfig::new()
+//!         .timeout(Duration::from_secs(10))
+//!         .add_root_certificate(ca)
+//!         .proxy(Proxy::all("http://proxy.lan:3128").unwrap()),
+//! )
+//! .unwrap();
+//! ```
+
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+
+use crate::retry::RetryPolicy;
+
+lazy_static::lazy_static! {
+    static ref SETTINGS: RwLock<Settings> = RwLock::new(Settings::default());
+}
+
+/// attaches dynamic credentials to requests, in addition to the Homeassistant token
+pub trait AuthProvider: Send + Sync + 'static {
+    /// headers added to a request, called before every request and WebSocket handshake
+    fn headers(&self) -> BoxFuture<'_, anyhow::Result<HeaderMap>>;
+
+    /// client certificate for mTLS, used for REST requests and WebSocket handshakes
+    fn identity(&self) -> Option<ClientIdentity> {
+        None
+    }
+}
+
+/// a client certificate with its private key, see [`AuthProvider::identity`]
+#[derive(Clone)]
+pub struct ClientIdentity(IdentityFormat);
+
+#[derive(Clone)]
+enum IdentityFormat {
+    Pkcs8 { certificate: Vec<u8>, key: Vec<u8> },
+    Pkcs12 { der: Vec<u8>, password: String },
+}
+
+impl ClientIdentity {
+    /// a PEM encoded certificate chain and PKCS #8 private key
+    pub fn from_pkcs8_pem(certificate: &[u8], key: &[u8]) -> anyhow::Result<Self> {
+        let identity = Self(IdentityFormat::Pkcs8 {
+            certificate: certificate.to_vec(),
+            key: key.to_vec(),
+        });
+        identity.native_tls()?;
+        Ok(identity)
+    }
+
+    /// a DER encoded PKCS #12 archive, e.g. a `.p12` or `.pfx` file
+    pub fn from_pkcs12_der(der: &[u8], password: &str) -> anyhow::Result<Self> {
+        let identity = Self(IdentityFormat::Pkcs12 {
+            der: der.to_vec(),
+            password: password.to_owned(),
+        });
+        identity.native_tls()?;
+        Ok(identity)
+    }
+
+    pub(crate) fn reqwest(&self) -> anyhow::Result<reqwest::Identity> {
+        Ok(match &self.0 {
+            IdentityFormat::Pkcs8 { certificate, key } => {
+                reqwest::Identity::from_pkcs8_pem(certificate, key)?
+            }
+            IdentityFormat::Pkcs12 { der, password } => {
+                reqwest::Identity::from_pkcs12_der(der, password)?
+            }
+        })
+    }
+
+    pub(crate) fn native_tls(&self) -> anyhow::Result<native_tls::Identity> {
+        Ok(match &self.0 {
+            IdentityFormat::Pkcs8 { certificate, key } => {
+                native_tls::Identity::from_pkcs8(certificate, key)?
+            }
+            IdentityFormat::Pkcs12 { der, password } => {
+                native_tls::Identity::from_pkcs12(der, password)?
+            }
+        })
+    }
+}
+
+impl std::fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ClientIdentity(..)")
+    }
+}
+
+/// a certificate to trust, see [`ClientConfig::add_root_certificate`]
+#[derive(Debug, Clone)]
+pub struct RootCertificate(CertificateFormat);
+
+#[derive(Debug, Clone)]
+enum CertificateFormat {
+    Pem(Vec<u8>),
+    Der(Vec<u8>),
+}
+
+impl RootCertificate {
+    /// a PEM encoded certificate
+    pub fn from_pem(pem: &[u8]) -> anyhow::Result<Self> {
+        let certificate = Self(CertificateFormat::Pem(pem.to_vec()));
+        certificate.native_tls()?;
+        Ok(certificate)
+    }
+
+    /// a DER encoded certificate
+    pub fn from_der(der: &[u8]) -> anyhow::Result<Self> {
+        let certificate = Self(CertificateFormat::Der(der.to_vec()));
+        certificate.native_tls()?;
+        Ok(certificate)
+    }
+
+    pub(crate) fn reqwest(&self) -> anyhow::Result<reqwest::Certificate> {
+        Ok(match &self.0 {
+            CertificateFormat::Pem(pem) => reqwest::Certificate::from_pem(pem)?,
+            CertificateFormat::Der(der) => reqwest::Certificate::from_der(der)?,
+        })
+    }
+
+    pub(crate) fn native_tls(&self) -> anyhow::Result<native_tls::Certificate> {
+        Ok(match &self.0 {
+            CertificateFormat::Pem(pem) => native_tls::Certificate::from_pem(pem)?,
+            CertificateFormat::Der(der) => native_tls::Certificate::from_der(der)?,
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Settings {
+    pub user_agent: Option<String>,
+    pub default_headers: HeaderMap,
+    pub auth_provider: Option<Arc<dyn AuthProvider>>,
+    /// never read `HA_URL`, `HA_TOKEN` or other variables from the environment or `.env` files
+    pub pure_parameters: bool,
+    /// check that the entities targeted by service calls exist before calling
+    pub verify_targets: bool,
+    /// timeouts, TLS and proxy of REST requests, the shared default client if [`None`]
+    pub client_config: Option<ClientConfig>,
+    /// retries of failed REST requests, none by default
+    pub retry_policy: Option<RetryPolicy>,
+    http_clients: HttpClients,
+}
+
+impl std::fmt::Debug for Settings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Settings")
+            .field("user_agent", &self.user_agent)
+            .field("default_headers", &self.default_headers)
+            .field("auth_provider", &self.auth_provider.is_some())
+            .field("pure_parameters", &self.pure_parameters)
+            .field("verify_targets", &self.verify_targets)
+            .field("client_config", &self.client_config)
+            .field("retry_policy", &self.retry_policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Settings {
+    /// all headers which are added to outgoing requests, including the user agent
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = self.default_headers.clone();
+        if let Some(user_agent) = self
+            .user_agent
+            .as_deref()
+            .and_then(|ua| HeaderValue::from_str(ua).ok())
+        {
+            headers.insert(USER_AGENT, user_agent);
+        }
+        headers
+    }
+
+    /// like [`headers`](Self::headers), plus the headers of the [`AuthProvider`]
+    pub async fn request_headers(&self) -> anyhow::Result<HeaderMap> {
+        let mut headers = self.headers();
+        if let Some(provider) = &self.auth_provider {
+            headers.extend(provider.headers().await?);
+        }
+        Ok(headers)
+    }
+}
+
+/// options of the HTTP client used for all REST requests, see [`set_client_config`]
+///
+/// the root certificates and [`danger_accept_invalid_certs`](Self::danger_accept_invalid_certs)
+/// also apply to `wss://` WebSocket connections, the timeouts and the proxy do not
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfig {
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    danger_accept_invalid_certs: bool,
+    root_certificates: Vec<RootCertificate>,
+    proxy: Option<reqwest::Proxy>,
+    client: Option<reqwest::Client>,
+}
+
+impl ClientConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// limits a whole request, from connecting until the body is read
+    ///
+    /// responses which are read for a long time, i.e. event streams, MJPEG streams and the
+    /// [`Download`](crate::download::Download)s of the error log and backups, are only limited by
+    /// the [`connect_timeout`](Self::connect_timeout)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// accepts any certificate, including expired and self-signed ones
+    ///
+    /// prefer [`add_root_certificate`](Self::add_root_certificate), this also accepts the
+    /// certificate of anyone intercepting the connection
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.danger_accept_invalid_certs = accept;
+        self
+    }
+
+    /// trusts `certificate` in addition to the system roots, e.g. the CA of a self-signed
+    /// certificate
+    pub fn add_root_certificate(mut self, certificate: RootCertificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// uses `client` as is, the other options and the identity of an [`AuthProvider`] are ignored
+    /// for REST requests
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// builds the client, with the mTLS `identity` of an [`AuthProvider`] if any
+    pub(crate) fn build(
+        &self,
+        identity: Option<reqwest::Identity>,
+    ) -> anyhow::Result<reqwest::Client> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        let mut builder = reqwest::Client::builder()
+            .danger_accept_invalid_certs(self.danger_accept_invalid_certs);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.reqwest()?);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(identity) = identity {
+            builder = builder.identity(identity);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// the clients built from a [`ClientConfig`], [`None`] where the shared [`CLIENT`](crate::CLIENT)
+/// does
+#[derive(Clone, Default)]
+struct HttpClients {
+    requests: Option<reqwest::Client>,
+    /// without the total timeout, see [`ClientConfig::timeout`]
+    streams: Option<reqwest::Client>,
+}
+
+/// the clients for `config` and the identity of `provider`
+fn build_http_client(
+    config: Option<&ClientConfig>,
+    provider: Option<&dyn AuthProvider>,
+) -> anyhow::Result<HttpClients> {
+    let identity = provider
+        .and_then(AuthProvider::identity)
+        .map(|identity| identity.reqwest())
+        .transpose()?;
+    if config.is_none() && identity.is_none() {
+        return Ok(HttpClients::default());
+    }
+    let config = config.cloned().unwrap_or_default();
+    let requests = config.build(identity.clone())?;
+    let streams = match config.timeout {
+        Some(_) if config.client.is_none() => ClientConfig {
+            timeout: None,
+            ..config
+        }
+        .build(identity)?,
+        _ => requests.clone(),
+    };
+    Ok(HttpClients {
+        requests: Some(requests),
+        streams: Some(streams),
+    })
+}
+
+fn read() -> RwLockReadGuard<'static, Settings> {
+    SETTINGS.read().unwrap_or_else(|p| p.into_inner())
+}
+
+fn write() -> RwLockWriteGuard<'static, Settings> {
+    SETTINGS.write().unwrap_or_else(|p| p.into_inner())
+}
+
+/// returns a copy of the current settings
+pub fn get() -> Settings {
+    read().clone()
+}
+
+/// sets the `User-Agent` sent with every request
+pub fn set_user_agent(user_agent: impl Into<String>) {
+    write().user_agent = Some(user_agent.into());
+}
+
+/// adds a header sent with every request, replacing an existing header with the same name
+pub fn set_default_header(name: &str, value: &str) -> anyhow::Result<()> {
+    let name = HeaderName::from_bytes(name.as_bytes())?;
+    let value = HeaderValue::from_str(value)?;
+    write().default_headers.insert(name, value);
+    Ok(())
+}
+
+/// removes all default headers and the user agent
+pub fn clear_default_headers() {
+    let mut settings = write();
+    settings.default_headers.clear();
+    settings.user_agent = None;
+}
+
+/// sets the [`AuthProvider`] used for all following requests
+pub fn set_auth_provider(provider: impl AuthProvider) -> anyhow::Result<()> {
+    let mut settings = write();
+    let http_clients = build_http_client(settings.client_config.as_ref(), Some(&provider))?;
+    settings.auth_provider = Some(Arc::new(provider));
+    settings.http_clients = http_clients;
+    Ok(())
+}
+
+/// removes the [`AuthProvider`]
+pub fn clear_auth_provider() {
+    let mut settings = write();
+    settings.auth_provider = None;
+    // the same options without an identity, which already built once
+    settings.http_clients =
+        build_http_client(settings.client_config.as_ref(), None).unwrap_or_default();
+}
+
+/// uses `config` for all following REST requests, fails if the client can't be built, e.g.
+/// because of an invalid certificate
+pub fn set_client_config(config: ClientConfig) -> anyhow::Result<()> {
+    let mut settings = write();
+    let http_clients = build_http_client(Some(&config), settings.auth_provider.as_deref())?;
+    settings.client_config = Some(config);
+    settings.http_clients = http_clients;
+    Ok(())
+}
+
+/// sets the [`RetryPolicy`] of all following REST requests, [`None`] disables retries
+pub fn set_retry_policy(policy: Option<RetryPolicy>) {
+    write().retry_policy = policy;
+}
+
+/// goes back to the default HTTP client
+pub fn clear_client_config() {
+    let mut settings = write();
+    settings.client_config = None;
+    settings.http_clients =
+        build_http_client(None, settings.auth_provider.as_deref()).unwrap_or_default();
+}
+
+/// enables or disables pure-parameter mode, see [`Settings::pure_parameters`]
+///
+/// fallback urls (`HA_REMOTE_URL`) and cassettes (`HA_CASSETTE`) are read from the environment on
+/// first use, so this should be set before the first request
+pub fn set_pure_parameters(enabled: bool) {
+    write().pure_parameters = enabled;
+}
+
+/// enables or disables checking service call targets, see [`Settings::verify_targets`]
+///
+/// calls targeting unknown entities fail with
+/// [`ServiceCallError::UnknownEntities`](crate::services::ServiceCallError::UnknownEntities) instead
+/// of silently doing nothing. Every checked call fetches all states first
+pub fn set_verify_targets(enabled: bool) {
+    write().verify_targets = enabled;
+}
+
+/// whether variables may be read from the environment
+pub(crate) fn env_lookup() -> bool {
+    !read().pure_parameters
+}
+
+/// see [`Settings::verify_targets`]
+pub(crate) fn verify_targets() -> bool {
+    read().verify_targets
+}
+
+/// see [`Settings::retry_policy`]
+pub(crate) fn retry_policy() -> Option<RetryPolicy> {
+    read().retry_policy.clone()
+}
+
+/// a url or token was neither passed nor, unless in pure-parameter mode, set in the environment
+///
+/// can be obtained with [`anyhow::Error::downcast_ref`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingParameter {
+    /// the environment variable, `HA_URL` or `HA_TOKEN`
+    pub name: &'static str,
+    /// whether the environment was consulted
+    pub env_lookup: bool,
+}
+
+impl std::fmt::Display for MissingParameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.env_lookup {
+            write!(f, "{} is required", self.name)
+        } else {
+            write!(
+                f,
+                "{} is required and has to be passed, environment lookups are disabled",
+                self.name
+            )
+        }
+    }
+}
+
+impl std::error::Error for MissingParameter {}
+
+/// the HTTP client to use, the shared [`CLIENT`](crate::CLIENT) unless a [`ClientConfig`] or an mTLS
+/// identity is configured
+pub(crate) fn http_client() -> reqwest::Client {
+    read()
+        .http_clients
+        .requests
+        .clone()
+        .unwrap_or_else(|| crate::CLIENT.clone())
+}
+
+/// like [`http_client`], without the total timeout for responses which are read for a long time
+pub(crate) fn stream_client() -> reqwest::Client {
+    read()
+        .http_clients
+        .streams
+        .clone()
+        .unwrap_or_else(|| crate::CLIENT.clone())
+}
+
+/// the TLS connector of WebSocket handshakes, see [`build_tls_connector`]
+pub(crate) fn tls_connector() -> anyhow::Result<Option<tokio_tungstenite::Connector>> {
+    let (config, provider) = {
+        let settings = read();
+        (
+            settings.client_config.clone(),
+            settings.auth_provider.clone(),
+        )
+    };
+    build_tls_connector(
+        config.as_ref(),
+        provider.as_deref().and_then(AuthProvider::identity),
+    )
+}
+
+/// a connector with the TLS options of `config` and the mTLS `identity`, [`None`] where the
+/// default connector does
+pub(crate) fn build_tls_connector(
+    config: Option<&ClientConfig>,
+    identity: Option<ClientIdentity>,
+) -> anyhow::Result<Option<tokio_tungstenite::Connector>> {
+    let (accept_invalid_certs, root_certificates) = config.map_or((false, &[][..]), |config| {
+        (
+            config.danger_accept_invalid_certs,
+            &config.root_certificates[..],
+        )
+    });
+    if identity.is_none() && root_certificates.is_empty() && !accept_invalid_certs {
+        return Ok(None);
+    }
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.danger_accept_invalid_certs(accept_invalid_certs);
+    for certificate in root_certificates {
+        builder.add_root_certificate(certificate.native_tls()?);
+    }
+    if let Some(identity) = identity {
+        builder.identity(identity.native_tls()?);
+    }
+    Ok(Some(tokio_tungstenite::Connector::NativeTls(
+        builder.build()?,
+    )))
+}
+
+/// adds the headers of [`Settings::request_headers`], without copying the settings
+pub(crate) async fn apply(
+    mut request: reqwest::RequestBuilder,
+) -> anyhow::Result<reqwest::RequestBuilder> {
+    let (headers, provider) = {
+        let settings = read();
+        let headers = (!settings.default_headers.is_empty() || settings.user_agent.is_some())
+            .then(|| settings.headers());
+        (headers, settings.auth_provider.clone())
+    };
+    if let Some(headers) = headers {
+        request = request.headers(headers);
+    }
+    // replaces default headers of the same name
+    if let Some(provider) = provider {
+        request = request.headers(provider.headers().await?);
+    }
+    Ok(request)
+}
@@ -0,0 +1,196 @@
+//! Server-sent events (`/api/stream`)
+//!
+//! A lightweight alternative to [`subscribe_events`](crate::ws::HomeAssistantWs::subscribe_events)
+//! for daemons which only watch events, over a single long-running HTTP request:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::prelude::*;
+//!
+//! let mut events = hass()
+//!     .event_stream(None, None, Some(&["state_changed"]))
+//!     .await
+//!     .unwrap();
+//! while let Some(event) = events.next().await {
+//!     if let Some(change) = event.unwrap().state_changed() {
+//!         println!("{}: {:?}", change.entity_id, change.new_state.map(|state| state.state));
+//!     }
+//! }
+//! # });
+//! ```
+//!
+//! Dropped connections are re-established with the delays of the default
+//! [`ReconnectOptions`](crate::ws::ReconnectOptions), events fired in the meantime are missed.
+//! Rejected credentials end the stream with an error.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+
+use crate::structs::Event;
+use crate::ws::ReconnectOptions;
+use crate::{Api, HomeAssistant, credentials, request_stream, urls};
+
+/// splits a `text/event-stream` body into the data of its events
+#[derive(Debug, Default)]
+pub(crate) struct SseParser {
+    line: Vec<u8>,
+    data: Option<String>,
+}
+
+impl SseParser {
+    /// feeds the next chunk of the body, returns the data of the events it completed
+    pub(crate) fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut events = Vec::new();
+        for &byte in chunk {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.line);
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+            if line.is_empty() {
+                events.extend(self.data.take());
+            } else if let Some(value) = line.strip_prefix("data:") {
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                match &mut self.data {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => self.data = Some(value.to_owned()),
+                }
+            }
+            // comments (`:`) and the `event`, `id` and `retry` fields are not used by Homeassistant
+            self.line.clear();
+        }
+        events
+    }
+}
+
+struct State {
+    url: String,
+    token: String,
+    path: String,
+    response: Option<reqwest::Response>,
+    parser: SseParser,
+    pending: VecDeque<String>,
+    delay: Duration,
+    options: ReconnectOptions,
+    ended: bool,
+}
+
+/// connects to the stream, unsuccessful responses return their status
+async fn connect(
+    url: &str,
+    token: &str,
+    path: &str,
+) -> anyhow::Result<Result<reqwest::Response, reqwest::StatusCode>> {
+    let response = request_stream(url, token, path).await?;
+    if !response.status().is_success() {
+        return Ok(Err(response.status()));
+    }
+    Ok(Ok(response))
+}
+
+/// whether reconnecting can't succeed, e.g. because the token was revoked
+fn is_fatal(status: reqwest::StatusCode) -> bool {
+    use reqwest::StatusCode;
+
+    matches!(
+        status,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+    )
+}
+
+impl HomeAssistant {
+    /// connects to `/api/stream` and yields the events fired from now on, reconnecting when the
+    /// connection drops
+    ///
+    /// `restrict` limits the stream to the given event types, e.g. `state_changed`, [`None`]
+    /// streams all events
+    pub async fn event_stream(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        restrict: Option<&[&str]>,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<Event>>> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).event_stream(restrict).await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn event_stream(
+        &self,
+        restrict: Option<&[&str]>,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<Event>>> {
+        let path = format!(
+            "/api/stream{}",
+            urls::Query::new().opt("restrict", restrict.map(|types| types.join(",")))
+        );
+
+        let response = connect(self.url, self.token, &path)
+            .await?
+            .map_err(anyhow::Error::msg)?;
+        let options = ReconnectOptions::default();
+        let state = State {
+            url: self.url.to_owned(),
+            token: self.token.to_owned(),
+            path,
+            response: Some(response),
+            parser: SseParser::default(),
+            pending: VecDeque::new(),
+            delay: options.initial_delay,
+            options,
+            ended: false,
+        };
+
+        Ok(stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(data) = state.pending.pop_front() {
+                    // sent every 50 seconds to keep the connection open
+                    if data == "ping" {
+                        continue;
+                    }
+                    let event = serde_json::from_str::<Event>(&data).map_err(Into::into);
+                    return Some((event, state));
+                }
+                if state.ended {
+                    return None;
+                }
+                let Some(response) = state.response.as_mut() else {
+                    tokio::time::sleep(state.delay).await;
+                    match connect(&state.url, &state.token, &state.path).await {
+                        Ok(Ok(response)) => {
+                            state.response = Some(response);
+                            state.delay = state.options.initial_delay;
+                        }
+                        Ok(Err(status)) if is_fatal(status) => {
+                            state.ended = true;
+                            return Some((Err(anyhow::Error::msg(status)), state));
+                        }
+                        Ok(Err(_)) | Err(_) => {
+                            state.delay = (state.delay * 2).min(state.options.max_delay)
+                        }
+                    }
+                    continue;
+                };
+                match response.chunk().await {
+                    Ok(Some(chunk)) => {
+                        let events = state.parser.feed(&chunk);
+                        state.pending.extend(events);
+                    }
+                    Ok(None) | Err(_) => {
+                        state.response = None;
+                        state.parser = SseParser::default();
+                    }
+                }
+            }
+        })
+        .boxed())
+    }
+}
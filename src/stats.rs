@@ -0,0 +1,92 @@
+//! Counters about the client itself, e.g. for diagnostics pages
+//!
+//! ```
+//! use homeassistant_rs::hass;
+//!
+//! let stats = hass().stats();
+//! println!("{} requests, {} bytes received", stats.requests_sent, stats.bytes_received);
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub(crate) struct Counters {
+    pub(crate) requests_sent: AtomicU64,
+    pub(crate) commands_sent: AtomicU64,
+    pub(crate) messages_received: AtomicU64,
+    pub(crate) bytes_received: AtomicU64,
+    pub(crate) reconnects: AtomicU64,
+    pub(crate) retries: AtomicU64,
+    pub(crate) cache_hits: AtomicU64,
+    pub(crate) cache_misses: AtomicU64,
+    pub(crate) active_subscriptions: AtomicU64,
+}
+
+pub(crate) static COUNTERS: Counters = Counters {
+    requests_sent: AtomicU64::new(0),
+    commands_sent: AtomicU64::new(0),
+    messages_received: AtomicU64::new(0),
+    bytes_received: AtomicU64::new(0),
+    reconnects: AtomicU64::new(0),
+    retries: AtomicU64::new(0),
+    cache_hits: AtomicU64::new(0),
+    cache_misses: AtomicU64::new(0),
+    active_subscriptions: AtomicU64::new(0),
+};
+
+/// adds `value` to `counter`
+pub(crate) fn add(counter: &AtomicU64, value: u64) {
+    counter.fetch_add(value, Ordering::Relaxed);
+}
+
+/// snapshot of the counters since the start of the process (or the last [`reset`]), across all
+/// connections
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// REST requests, including replayed ones
+    pub requests_sent: u64,
+    /// WebSocket commands, including subscriptions
+    pub commands_sent: u64,
+    /// WebSocket messages, coalesced messages count individually
+    pub messages_received: u64,
+    /// size of WebSocket messages and REST responses (as announced by `Content-Length`)
+    pub bytes_received: u64,
+    /// successful WebSocket reconnects
+    pub reconnects: u64,
+    /// REST requests retried by the [`RetryPolicy`](crate::retry::RetryPolicy)
+    pub retries: u64,
+    /// [`TemplateCache`](crate::templates::TemplateCache) and area lookup hits and misses
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub active_subscriptions: u64,
+}
+
+pub fn stats() -> Stats {
+    let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    Stats {
+        requests_sent: get(&COUNTERS.requests_sent),
+        commands_sent: get(&COUNTERS.commands_sent),
+        messages_received: get(&COUNTERS.messages_received),
+        bytes_received: get(&COUNTERS.bytes_received),
+        reconnects: get(&COUNTERS.reconnects),
+        retries: get(&COUNTERS.retries),
+        cache_hits: get(&COUNTERS.cache_hits),
+        cache_misses: get(&COUNTERS.cache_misses),
+        active_subscriptions: get(&COUNTERS.active_subscriptions),
+    }
+}
+
+/// sets all counters except `active_subscriptions` to zero
+pub fn reset() {
+    for counter in [
+        &COUNTERS.requests_sent,
+        &COUNTERS.commands_sent,
+        &COUNTERS.messages_received,
+        &COUNTERS.bytes_received,
+        &COUNTERS.reconnects,
+        &COUNTERS.retries,
+        &COUNTERS.cache_hits,
+        &COUNTERS.cache_misses,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,175 @@
+//! Pluggable key-value storage
+//!
+//! Stateful features persist their data through the [`Storage`] trait, e.g.
+//! [`Cassette::load`](crate::cassette::Cassette::load). [`MemoryStorage`] and [`FileStorage`] are
+//! included, other backends (sled, redb, sqlite, ...) only have to implement four methods.
+//!
+//! Keys are `/` separated paths like `cassettes/config.json`.
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+pub trait Storage: Send + Sync {
+    /// returns the value of `key`, [`None`] if it does not exist
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// creates or replaces `key`
+    fn put(&self, key: &str, value: &[u8]) -> anyhow::Result<()>;
+
+    /// removes `key`, does nothing if it does not exist
+    fn delete(&self, key: &str) -> anyhow::Result<()>;
+
+    /// returns all keys starting with `prefix`, sorted
+    fn list(&self, prefix: &str) -> anyhow::Result<Vec<String>>;
+}
+
+/// JSON helpers for every [`Storage`]
+pub trait StorageExt: Storage {
+    fn get_json<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        match self.get(key)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put_json<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> anyhow::Result<()> {
+        self.put(key, &serde_json::to_vec_pretty(value)?)
+    }
+}
+
+impl<S: Storage + ?Sized> StorageExt for S {}
+
+/// keeps everything in memory, e.g. for tests
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let entries = self.entries.read().unwrap_or_else(|p| p.into_inner());
+        Ok(entries.get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        let mut entries = self.entries.write().unwrap_or_else(|p| p.into_inner());
+        entries.insert(key.to_owned(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let mut entries = self.entries.write().unwrap_or_else(|p| p.into_inner());
+        entries.remove(key);
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        let entries = self.entries.read().unwrap_or_else(|p| p.into_inner());
+        Ok(entries
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+/// stores every key as a file below a directory, writes are atomic
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// maps `key` to a path below the root, keys escaping the root are rejected
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let relative = Path::new(key);
+        if key.is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow::Error::msg(format!("invalid storage key {key:?}")));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    fn collect(&self, dir: &Path, keys: &mut Vec<String>) -> anyhow::Result<()> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.collect(&path, keys)?;
+            } else if let Ok(relative) = path.strip_prefix(&self.root)
+                && !relative.to_string_lossy().ends_with(".tmp")
+            {
+                let key = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                keys.push(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(key)?) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // write to a temporary file first, so readers never see a partially written value
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, value)?;
+        std::fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match std::fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        let mut keys = vec![];
+        self.collect(&self.root, &mut keys)?;
+        keys.retain(|key| key.starts_with(prefix));
+        keys.sort();
+        Ok(keys)
+    }
+}
@@ -0,0 +1,194 @@
+//! Combinators for event streams, e.g. a [`Subscription`](crate::ws::Subscription)
+//!
+//! All combinators work per entity: events are keyed by `data.entity_id`, events without an
+//! entity id are keyed by their `event_type`.
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use std::time::Duration;
+//! use homeassistant_rs::prelude::*;
+//!
+//! let ws = hass().websocket(None, None).await.unwrap();
+//! let mut events = ws
+//!     .subscribe_events(Some("state_changed"))
+//!     .await
+//!     .unwrap()
+//!     .distinct_until_changed()
+//!     .debounce_per_entity(Duration::from_millis(500));
+//!
+//! while let Some(event) = events.next().await {
+//!     println!("{:?}", event.unwrap().state_changed());
+//! }
+//! # });
+//! ```
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures_util::stream::{self, BoxStream};
+use futures_util::{Stream, StreamExt, future};
+use tokio::time::Instant;
+
+use crate::filters::Filter;
+use crate::structs::Event;
+
+/// the key used to group events, `data.entity_id` or, if not present, the `event_type`
+pub fn entity_key(event: &Event) -> String {
+    event.data["entity_id"]
+        .as_str()
+        .unwrap_or(&event.event_type)
+        .to_owned()
+}
+
+fn state_value(event: &Event) -> Option<&str> {
+    event.data["new_state"]["state"].as_str()
+}
+
+pub trait EventStreamExt: Stream<Item = anyhow::Result<Event>> + Sized + Send + 'static {
+    /// emits the latest event of an entity once no further events for it arrived within `window`
+    ///
+    /// pending events are emitted immediately when the underlying stream ends
+    fn debounce_per_entity(self, window: Duration) -> BoxStream<'static, anyhow::Result<Event>> {
+        struct State<S> {
+            input: Option<S>,
+            pending: HashMap<String, (Instant, Event)>,
+        }
+
+        let state = State {
+            input: Some(self.boxed()),
+            pending: HashMap::new(),
+        };
+
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                let earliest = state
+                    .pending
+                    .iter()
+                    .min_by_key(|(_, (deadline, _))| *deadline)
+                    .map(|(key, (deadline, _))| (key.clone(), *deadline));
+
+                let Some(input) = state.input.as_mut() else {
+                    let (key, _) = earliest?;
+                    let (_, event) = state.pending.remove(&key)?;
+                    return Some((Ok(event), state));
+                };
+
+                let deadline = earliest
+                    .as_ref()
+                    .map_or_else(Instant::now, |(_, deadline)| *deadline);
+                tokio::select! {
+                    item = input.next() => match item {
+                        Some(Ok(event)) => {
+                            state
+                                .pending
+                                .insert(entity_key(&event), (Instant::now() + window, event));
+                        }
+                        Some(Err(e)) => return Some((Err(e), state)),
+                        None => state.input = None,
+                    },
+                    _ = tokio::time::sleep_until(deadline), if earliest.is_some() => {
+                        let (key, _) = earliest?;
+                        let (_, event) = state.pending.remove(&key)?;
+                        return Some((Ok(event), state));
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// emits at most one event per entity within `window`, further events are dropped
+    fn throttle_per_entity(self, window: Duration) -> BoxStream<'static, anyhow::Result<Event>> {
+        let mut last: HashMap<String, Instant> = HashMap::new();
+        self.filter(move |item| {
+            let keep = match item {
+                Ok(event) => {
+                    let now = Instant::now();
+                    let key = entity_key(event);
+                    match last.get(&key) {
+                        Some(at) if now.duration_since(*at) < window => false,
+                        _ => {
+                            last.insert(key, now);
+                            true
+                        }
+                    }
+                }
+                Err(_) => true,
+            };
+            future::ready(keep)
+        })
+        .boxed()
+    }
+
+    /// drops `state_changed` events whose state value equals the last emitted one of the entity,
+    /// e.g. attribute-only changes. Other events are passed through
+    fn distinct_until_changed(self) -> BoxStream<'static, anyhow::Result<Event>> {
+        let mut last: HashMap<String, String> = HashMap::new();
+        self.filter(move |item| {
+            let keep = match item {
+                Ok(event) => match state_value(event) {
+                    Some(value) => {
+                        last.insert(entity_key(event), value.to_owned()).as_deref() != Some(value)
+                    }
+                    None => true,
+                },
+                Err(_) => true,
+            };
+            future::ready(keep)
+        })
+        .boxed()
+    }
+
+    /// passes the numeric state of `state_changed` events through a [`Filter`] per entity and
+    /// replaces it with the filtered value, events rejected by the filter are dropped
+    ///
+    /// `filter` creates the filter of an entity when its first event arrives, so it can be
+    /// parameterized per entity. Non-numeric states and other events are passed through
+    /// ```no_run
+    /// # use homeassistant_rs::prelude::*;
+    /// # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+    /// use homeassistant_rs::filters::{Ema, OutlierFilter};
+    ///
+    /// let mut events = ws
+    ///     .subscribe_events(Some("state_changed"))
+    ///     .await?
+    ///     .smooth_per_entity(|entity_id| match entity_id {
+    ///         "sensor.outside_temperature" => Box::new((OutlierFilter::new(10, 5.0), Ema::new(0.2)))
+    ///             as Box<dyn homeassistant_rs::filters::Filter>,
+    ///         _ => Box::new(Ema::new(0.5)),
+    ///     });
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn smooth_per_entity<F, M>(self, mut filter: M) -> BoxStream<'static, anyhow::Result<Event>>
+    where
+        F: Filter,
+        M: FnMut(&str) -> F + Send + 'static,
+    {
+        let mut filters: HashMap<String, F> = HashMap::new();
+        self.filter_map(move |item| {
+            let item = match item {
+                Ok(mut event) => {
+                    let value = state_value(&event).and_then(|value| value.parse::<f64>().ok());
+                    match value {
+                        Some(value) if event.event_type == "state_changed" => {
+                            let key = entity_key(&event);
+                            let filter = filters.entry(key).or_insert_with_key(|key| filter(key));
+                            filter.update(value).map(|value| {
+                                event.data["new_state"]["state"] = value.to_string().into();
+                                Ok(event)
+                            })
+                        }
+                        _ => Some(Ok(event)),
+                    }
+                }
+                Err(e) => Some(Err(e)),
+            };
+            future::ready(item)
+        })
+        .boxed()
+    }
+}
+
+impl<S> EventStreamExt for S where S: Stream<Item = anyhow::Result<Event>> + Sized + Send + 'static {}
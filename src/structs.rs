@@ -1,55 +1,258 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, Clone, Default)]
+/// an entity id, e.g. `light.bedroom`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct EntityId(pub String);
+
+impl EntityId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// part before the first `.`, e.g. `light`
+    pub fn domain(&self) -> &str {
+        self.0.split_once('.').map_or("", |(domain, _)| domain)
+    }
+
+    /// part after the first `.`, e.g. `bedroom`
+    pub fn object_id(&self) -> &str {
+        self.0
+            .split_once('.')
+            .map_or(&self.0, |(_, object_id)| object_id)
+    }
+}
+
+impl std::fmt::Display for EntityId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for EntityId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for EntityId {
+    fn from(value: &str) -> Self {
+        Self(value.to_owned())
+    }
+}
+
+impl AsRef<str> for EntityId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// missing fields other than `version` fall back to their defaults, so older and newer releases
+/// can be read
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct ConfigResponse {
+    #[serde(default)]
     pub components: Vec<String>,
+    #[serde(default)]
     pub config_dir: String,
+    #[serde(default)]
     pub elevation: f64,
+    #[serde(default)]
     pub latitude: f64,
+    #[serde(default)]
     pub location_name: String,
+    #[serde(default)]
     pub longitude: f64,
+    #[serde(default)]
     pub time_zone: String,
+    #[serde(default)]
     pub unit_system: UnitSystem,
     pub version: String,
+    #[serde(default)]
     pub whitelist_external_dirs: Vec<String>,
+    /// e.g. `en` or `de`
+    #[serde(default)]
+    pub language: String,
+    /// ISO 3166 country code, e.g. `DE`
+    pub country: Option<String>,
+    /// ISO 4217 currency code, e.g. `EUR`
+    #[serde(default)]
+    pub currency: String,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+impl ConfigResponse {
+    /// whether the integration `domain` (e.g. `zwave_js`) is loaded
+    pub fn has_component(&self, domain: &str) -> bool {
+        self.components.iter().any(|component| component == domain)
+    }
+
+    /// loaded integrations mapped to the platforms they provide, e.g. `hue` to `["light", "sensor"]`
+    pub fn components_by_domain(&self) -> std::collections::BTreeMap<&str, Vec<&str>> {
+        let mut domains = std::collections::BTreeMap::<&str, Vec<&str>>::new();
+        for component in &self.components {
+            match component.split_once('.') {
+                // platforms are listed as `<platform>.<integration>`
+                Some((platform, domain)) => domains.entry(domain).or_default().push(platform),
+                None => {
+                    domains.entry(component).or_default();
+                }
+            }
+        }
+        domains.values_mut().for_each(|platforms| platforms.sort());
+        domains
+    }
+
+    /// the parsed [`version`](Self::version)
+    pub fn ha_version(&self) -> anyhow::Result<crate::compat::HaVersion> {
+        self.version.parse()
+    }
+
+    /// the time zone of the instance, e.g. to align [`ResetCycle`](crate::meters::ResetCycle)s
+    #[cfg(feature = "tz")]
+    pub fn tz(&self) -> anyhow::Result<chrono_tz::Tz> {
+        self.time_zone
+            .parse()
+            .map_err(|e| anyhow::Error::msg(format!("unknown time zone {:?}: {e}", self.time_zone)))
+    }
+
+    /// returns the integrations of `domains` which are not loaded
+    pub fn missing_components<'a>(&self, domains: &[&'a str]) -> Vec<&'a str> {
+        domains
+            .iter()
+            .copied()
+            .filter(|domain| !self.has_component(domain))
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoreState {
+    /// e.g. `RUNNING`, `STARTING` or `STOPPING`
+    pub state: String,
+    pub recorder_state: Option<RecorderState>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecorderState {
+    pub migration_in_progress: bool,
+    pub migration_is_live: bool,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+/// summary of an instance, see [`HomeAssistant::instance_info`](crate::HomeAssistant::instance_info)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstanceInfo {
+    pub config: ConfigResponse,
+    /// [`None`] if the instance does not provide `/api/core/state`
+    pub core_state: Option<CoreState>,
+    /// loaded integrations, without platforms like `light.hue`
+    pub components: Vec<String>,
+    pub entity_count: usize,
+    pub entities_per_domain: std::collections::BTreeMap<String, usize>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
 pub struct UnitSystem {
     pub length: String,
     pub mass: String,
     pub temperature: String,
     pub volume: String,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct EventResponse {
     pub event: String,
     pub listener_count: u16,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct HistoryResponse {
     pub entity_id: Option<String>,
     pub state: String,
     pub attributes: Option<Attributes>,
     pub last_changed: String,
     pub last_updated: Option<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct Attributes {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub friendly_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub editable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub icon: Option<String>,
     #[serde(flatten)]
     pub other_fields: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+/// `state_class` attribute of sensors
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StateClass {
+    Measurement,
+    MeasurementAngle,
+    Total,
+    TotalIncreasing,
+    #[serde(other)]
+    Unknown,
+}
+
+impl Attributes {
+    fn str(&self, key: &str) -> Option<&str> {
+        self.other_fields.get(key)?.as_str()
+    }
+
+    /// e.g. `temperature` or `battery`
+    pub fn device_class(&self) -> Option<&str> {
+        self.str("device_class")
+    }
+
+    pub fn state_class(&self) -> Option<StateClass> {
+        serde_json::from_value(self.other_fields.get("state_class")?.clone()).ok()
+    }
+
+    pub fn unit_of_measurement(&self) -> Option<&str> {
+        self.str("unit_of_measurement")
+    }
+
+    /// `battery_level` or `battery` attribute in percent, as set by many device trackers, vacuums
+    /// and locks
+    pub fn battery_level(&self) -> Option<f64> {
+        ["battery_level", "battery"].iter().find_map(|key| {
+            let value = self.other_fields.get(*key)?;
+            value
+                .as_f64()
+                .or_else(|| value.as_str()?.trim_end_matches('%').trim().parse().ok())
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct LogBook {
     pub name: String,
     pub message: Option<String>,
@@ -59,9 +262,12 @@ pub struct LogBook {
     pub context_id: Option<String>,
     pub domain: Option<String>,
     pub when: String,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct StatesResponse {
     pub entity_id: Option<String>,
     pub state: String,
@@ -70,47 +276,374 @@ pub struct StatesResponse {
     pub last_reported: Option<String>,
     pub last_updated: Option<String>,
     pub context: Option<Context>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+impl StatesResponse {
+    /// a [`StatesRequest`] with the state and attributes of this state, posting it after changing
+    /// e.g. only the state keeps all other attributes
+    pub fn into_request(self) -> StatesRequest {
+        StatesRequest {
+            state: self.state,
+            attributes: self.attributes,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+/// result of posting a state, see [`HomeAssistantPost::state`](crate::HomeAssistantPost::state)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatePostResult {
+    pub response: StatesResponse,
+    /// whether the entity did not exist before (`201 Created`)
+    pub created: bool,
+    /// the `Location` header, e.g. `/api/states/sensor.outside`
+    pub location: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct Context {
     pub id: String,
     pub parent_id: Option<String>,
     pub user_id: Option<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+/// contexts are identified by their `id`
+impl std::hash::Hash for Context {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct CalendarResponse {
     pub entity_id: String,
     pub name: String,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+/// an event of `/api/calendars/<calendar entity_id>` or of the `calendar.get_events` response, see
+/// [`CalendarEvents`](crate::responses::CalendarEvents)
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CalendarEvent {
+    pub summary: String,
+    pub start: CalendarTime,
+    /// exclusive, i.e. the day after the last day for all-day events
+    pub end: CalendarTime,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub uid: Option<String>,
+    /// set for occurrences of recurring events
+    pub recurrence_id: Option<String>,
+    pub rrule: Option<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+impl CalendarEvent {
+    pub fn is_all_day(&self) -> bool {
+        matches!(self.start, CalendarTime::Date(_))
+    }
+}
+
+/// the start or end of a [`CalendarEvent`], a date for all-day events
+///
+/// the REST API returns `{"date": ...}` or `{"dateTime": ...}`, `calendar.get_events` a plain
+/// string, both are accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarTime {
+    Date(chrono::NaiveDate),
+    DateTime(chrono::DateTime<chrono::FixedOffset>),
+}
+
+impl<'de> Deserialize<'de> for CalendarTime {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        enum Tagged {
+            #[serde(rename = "date")]
+            Date(chrono::NaiveDate),
+            #[serde(rename = "dateTime")]
+            DateTime(chrono::DateTime<chrono::FixedOffset>),
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Format {
+            Tagged(Tagged),
+            Plain(String),
+        }
+
+        match Format::deserialize(deserializer)? {
+            Format::Tagged(Tagged::Date(date)) => Ok(CalendarTime::Date(date)),
+            Format::Tagged(Tagged::DateTime(datetime)) => Ok(CalendarTime::DateTime(datetime)),
+            Format::Plain(time) => time
+                .parse()
+                .map(CalendarTime::Date)
+                .or_else(|_| {
+                    chrono::DateTime::parse_from_rfc3339(&time).map(CalendarTime::DateTime)
+                })
+                .map_err(|_| serde::de::Error::custom(format!("invalid calendar time {time:?}"))),
+        }
+    }
+}
+
+impl CalendarTime {
+    /// the day, in the time zone of the calendar
+    pub fn date(&self) -> chrono::NaiveDate {
+        match self {
+            CalendarTime::Date(date) => *date,
+            CalendarTime::DateTime(datetime) => datetime.date_naive(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct StatesRequest {
     pub state: String,
-    #[serde(flatten)]
+    /// replaces all attributes of the entity, see [`StatesResponse::into_request`] for keeping them
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub attributes: Option<Attributes>,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct SimpleResponse {
     pub message: String,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Serialize, Debug, Clone, Default)]
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct TemplateRequest {
     pub template: String,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct ConfigCheckResponse {
     pub errors: Option<String>,
     pub result: String,
     pub warnings: Option<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct ServicesResponse {
     pub domain: String,
-    pub services: serde_json::Value,
-}
\ No newline at end of file
+    /// the services of `domain`, by name
+    pub services: std::collections::BTreeMap<String, ServiceDescription>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+/// a service of [`ServicesResponse`], as described by its `services.yaml`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceDescription {
+    /// empty for services without description, e.g. of some custom integrations
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// the parameters of the service, by name. Sections group further fields, see [`all_fields`](Self::all_fields)
+    #[serde(default)]
+    pub fields: std::collections::BTreeMap<String, ServiceField>,
+    /// [`None`] if the service does not take an entity, device or area as target
+    pub target: Option<ServiceTarget>,
+    /// [`None`] if the service does not return a response
+    pub response: Option<ServiceResponseSupport>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+impl ServiceDescription {
+    /// all fields, including those grouped in sections
+    pub fn all_fields(&self) -> Vec<(&str, &ServiceField)> {
+        fn collect<'a>(
+            fields: &'a std::collections::BTreeMap<String, ServiceField>,
+            all: &mut Vec<(&'a str, &'a ServiceField)>,
+        ) {
+            for (name, field) in fields {
+                if field.fields.is_empty() {
+                    all.push((name, field));
+                } else {
+                    collect(&field.fields, all);
+                }
+            }
+        }
+        let mut all = Vec::new();
+        collect(&self.fields, &mut all);
+        all
+    }
+
+    /// the fields which have to be passed
+    pub fn required_fields(&self) -> Vec<&str> {
+        self.all_fields()
+            .into_iter()
+            .filter(|(_, field)| field.required)
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+/// a parameter of a [`ServiceDescription`], or a section grouping parameters
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceField {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    /// only shown in advanced mode of the frontend
+    #[serde(default)]
+    pub advanced: bool,
+    pub example: Option<serde_json::Value>,
+    pub default: Option<serde_json::Value>,
+    /// the kind of value, e.g. `{"number": {"min": 0, "max": 300}}`
+    pub selector: Option<serde_json::Value>,
+    /// the fields of a section, empty for parameters
+    #[serde(default)]
+    pub fields: std::collections::BTreeMap<String, ServiceField>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+/// the targets a service accepts, empty lists accept any entity or device
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceTarget {
+    #[serde(default)]
+    pub entity: Vec<TargetFilter>,
+    #[serde(default)]
+    pub device: Vec<TargetFilter>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+/// matches entities or devices, e.g. `{"domain": ["light"]}`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetFilter {
+    #[serde(default)]
+    pub domain: Vec<String>,
+    pub integration: Option<String>,
+    /// fields not covered above, e.g. `device_class` or `supported_features`
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServiceResponseSupport {
+    /// whether the response can be omitted, otherwise `return_response` is required
+    #[serde(default)]
+    pub optional: bool,
+}
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    pub event_type: String,
+    pub data: serde_json::Value,
+    pub origin: Option<String>,
+    pub time_fired: Option<String>,
+    pub context: Option<Context>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+impl Event {
+    /// returns the data of a `state_changed` event, [`None`] for all other events
+    pub fn state_changed(&self) -> Option<StateChangedData> {
+        if self.event_type != "state_changed" {
+            return None;
+        }
+        serde_json::from_value(self.data.clone()).ok()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateChangedData {
+    pub entity_id: String,
+    pub old_state: Option<StatesResponse>,
+    pub new_state: Option<StatesResponse>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: serde_json::Value,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitSystemName {
+    Metric,
+    UsCustomary,
+}
+
+/// fields to change in the core config, unset fields are left untouched
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CoreConfigUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elevation: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit_system: Option<UnitSystemName>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internal_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+/// progress of a download, passed to progress callbacks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// bytes or items received so far
+    pub done: u64,
+    /// the expected total, if known, e.g. from the `Content-Length` header
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// `done` relative to `total`, between 0 and 1
+    pub fn fraction(&self) -> Option<f64> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.done as f64 / total as f64).min(1.0))
+    }
+}
+
+/// a camera image, see [`HomeAssistant::camera_snapshot`](crate::HomeAssistant::camera_snapshot)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// e.g. `image/jpeg` or `image/png`
+    pub content_type: Option<String>,
+    pub data: bytes::Bytes,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    /// e.g. `image/jpeg`
+    pub content_type: Option<String>,
+    /// size in bytes
+    pub size: u64,
+}
@@ -0,0 +1,168 @@
+//! Speech-to-text (`/api/stt/<provider>`)
+//!
+//! Audio is posted to a speech-to-text provider (e.g. `stt.faster_whisper` or `stt.home_assistant_cloud`),
+//! the format of the audio has to be described by [`SpeechMetadata`]:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::{hass, stt::SpeechMetadata};
+//!
+//! let audio = std::fs::read("command.wav").unwrap();
+//! let transcription = hass()
+//!     .speech_to_text(None, None, "stt.faster_whisper", SpeechMetadata::new("en-US"), audio)
+//!     .await
+//!     .unwrap();
+//! println!("{:?}", transcription.text);
+//! # });
+//! ```
+
+use serde::Deserialize;
+
+use crate::{Api, Body, HomeAssistant, credentials, decode, request, send, urls};
+
+/// describes the posted audio, sent as `X-Speech-Content` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpeechMetadata {
+    pub language: String,
+    /// `wav` or `ogg`
+    pub format: String,
+    /// `pcm` or `opus`
+    pub codec: String,
+    pub sample_rate: u32,
+    pub bit_rate: u32,
+    pub channel: u32,
+}
+
+impl SpeechMetadata {
+    /// 16 kHz, 16 bit, mono PCM in a WAV container, which every provider supports
+    pub fn new(language: impl Into<String>) -> Self {
+        Self {
+            language: language.into(),
+            format: "wav".to_owned(),
+            codec: "pcm".to_owned(),
+            sample_rate: 16000,
+            bit_rate: 16,
+            channel: 1,
+        }
+    }
+
+    fn header(&self) -> String {
+        format!(
+            "format={}; codec={}; sample_rate={}; bit_rate={}; channel={}; language={}",
+            self.format, self.codec, self.sample_rate, self.bit_rate, self.channel, self.language
+        )
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SttProviderInfo {
+    pub languages: Vec<String>,
+    pub formats: Vec<String>,
+    pub codecs: Vec<String>,
+    pub sample_rates: Vec<u32>,
+    pub bit_rates: Vec<u32>,
+    pub channels: Vec<u32>,
+}
+
+impl SttProviderInfo {
+    /// whether the provider accepts audio described by `metadata`
+    pub fn supports(&self, metadata: &SpeechMetadata) -> bool {
+        self.languages.contains(&metadata.language)
+            && self.formats.contains(&metadata.format)
+            && self.codecs.contains(&metadata.codec)
+            && self.sample_rates.contains(&metadata.sample_rate)
+            && self.bit_rates.contains(&metadata.bit_rate)
+            && self.channels.contains(&metadata.channel)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcription {
+    /// `success` or `error`
+    pub result: String,
+    pub text: Option<String>,
+    /// language of the posted audio
+    #[serde(skip)]
+    pub language: String,
+}
+
+impl Transcription {
+    pub fn is_success(&self) -> bool {
+        self.result == "success"
+    }
+}
+
+impl HomeAssistant {
+    /// queries `/api/stt/<provider>` and returns the supported audio formats as [`SttProviderInfo`]
+    pub async fn stt_provider_info(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        provider: &str,
+    ) -> anyhow::Result<SttProviderInfo> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token).stt_provider_info(provider).await
+    }
+
+    /// posts `audio` to `/api/stt/<provider>` and returns the [`Transcription`]
+    pub async fn speech_to_text(
+        &self,
+        ha_url: Option<String>,
+        ha_token: Option<String>,
+        provider: &str,
+        metadata: SpeechMetadata,
+        audio: impl Into<bytes::Bytes>,
+    ) -> anyhow::Result<Transcription> {
+        let (url, token) = credentials(ha_url, ha_token)?;
+        Api::new(&url, &token)
+            .speech_to_text(provider, metadata, audio)
+            .await
+    }
+}
+
+impl Api<'_> {
+    pub(crate) async fn stt_provider_info(
+        &self,
+        provider: &str,
+    ) -> anyhow::Result<SttProviderInfo> {
+        let client = request(
+            self.url,
+            self.token,
+            &format!("/api/stt/{}", urls::encode_segment(provider)),
+        )
+        .await?;
+        if !client.status().is_success() {
+            return Err(anyhow::Error::msg(client.status()));
+        }
+        decode::json(client).await
+    }
+
+    pub(crate) async fn speech_to_text(
+        &self,
+        provider: &str,
+        metadata: SpeechMetadata,
+        audio: impl Into<bytes::Bytes>,
+    ) -> anyhow::Result<Transcription> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("X-Speech-Content", metadata.header().parse()?);
+        let client = send(
+            reqwest::Method::POST,
+            self.url,
+            self.token,
+            &format!("/api/stt/{}", urls::encode_segment(provider)),
+            Body::Raw {
+                headers,
+                data: audio.into(),
+            },
+        )
+        .await?;
+        if !client.status().is_success() {
+            return Err(anyhow::Error::msg(client.status()));
+        }
+
+        let mut transcription: Transcription = decode::json(client).await?;
+        transcription.language = metadata.language;
+        Ok(transcription)
+    }
+}
@@ -0,0 +1,107 @@
+//! System log and repair issues (WebSocket only)
+//!
+//! Homeassistant returns both lists in a single message. The size of a message is bounded by
+//! [`Limits`](crate::ws::Limits), an oversized system log fails with an error naming its size
+//! instead of being buffered. [`subscribe_system_log`](HomeAssistantWs::subscribe_system_log)
+//! yields new entries one by one instead:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example() -> anyhow::Result<()> {
+//! let client = Client::builder().max_message_size(1 << 20).build()?;
+//! let ws = client.websocket().await?;
+//!
+//! let mut entries = ws.subscribe_system_log().await?;
+//! while let Some(entry) = entries.next().await {
+//!     let entry = entry?;
+//!     println!("{} {}: {}", entry.level, entry.name, entry.message.join("\n"));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::StreamExt;
+use futures_util::stream::BoxStream;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::ws::HomeAssistantWs;
+
+/// an entry of the system log, repeated messages are counted instead of stored again
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SystemLogEntry {
+    /// the logger, e.g. `homeassistant.components.zha`
+    pub name: String,
+    /// the distinct messages of this entry
+    #[serde(default)]
+    pub message: Vec<String>,
+    /// e.g. `ERROR` or `WARNING`
+    pub level: String,
+    /// file and line number
+    pub source: (String, u32),
+    /// unix timestamp of the last occurrence
+    pub timestamp: f64,
+    #[serde(default)]
+    pub exception: String,
+    #[serde(default)]
+    pub count: u32,
+    /// unix timestamp of the first occurrence
+    pub first_occurred: Option<f64>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairIssue {
+    /// the integration which created the issue
+    pub domain: String,
+    pub issue_id: String,
+    /// the integration the issue is about, if different from `domain`
+    pub issue_domain: Option<String>,
+    /// `critical`, `error` or `warning`
+    pub severity: String,
+    #[serde(default)]
+    pub is_fixable: bool,
+    #[serde(default)]
+    pub ignored: bool,
+    pub created: Option<String>,
+    pub breaks_in_ha_version: Option<String>,
+    pub dismissed_version: Option<String>,
+    pub learn_more_url: Option<String>,
+    pub translation_key: Option<String>,
+    #[serde(default)]
+    pub translation_placeholders: Option<Value>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+#[derive(Deserialize)]
+struct Issues {
+    issues: Vec<RepairIssue>,
+}
+
+impl HomeAssistantWs {
+    /// `system_log/list`, returns a Vec containing [`SystemLogEntry`], newest first
+    pub async fn system_log(&self) -> anyhow::Result<Vec<SystemLogEntry>> {
+        self.command_as(json!({"type": "system_log/list"})).await
+    }
+
+    /// subscribes to `system_log_event` events and yields every entry logged from now on
+    pub async fn subscribe_system_log(
+        &self,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<SystemLogEntry>>> {
+        let events = self.subscribe_events(Some("system_log_event")).await?;
+        Ok(events
+            .map(|event| Ok(serde_json::from_value(event?.data)?))
+            .boxed())
+    }
+
+    /// `repairs/list_issues`, returns a Vec containing [`RepairIssue`]
+    pub async fn repair_issues(&self) -> anyhow::Result<Vec<RepairIssue>> {
+        let issues: Issues = self
+            .command_as(json!({"type": "repairs/list_issues"}))
+            .await?;
+        Ok(issues.issues)
+    }
+}
@@ -0,0 +1,138 @@
+//! Tag registry (WebSocket only)
+//!
+//! Tags (e.g. NFC stickers) are provisioned with [`create_tag`](HomeAssistantWs::create_tag),
+//! scanning one fires a `tag_scanned` event:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use homeassistant_rs::tags::TagInput;
+//!
+//! ws.create_tag(TagInput::new("Front door").tag_id("04:a2:5b:1c")).await?;
+//!
+//! let mut scans = ws.subscribe_tag_scanned().await?;
+//! while let Some(scan) = scans.next().await {
+//!     let scan = scan?;
+//!     println!("{} scanned by {:?}", scan.tag_id, scan.device_id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::StreamExt;
+use futures_util::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::structs::Event;
+use crate::ws::HomeAssistantWs;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagInput {
+    /// generated by Homeassistant if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag_id: Option<String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl TagInput {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// the id stored on the tag, e.g. the UID of an NFC tag
+    pub fn tag_id(mut self, tag_id: &str) -> Self {
+        self.tag_id = Some(tag_id.to_owned());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tag {
+    pub id: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub last_scanned: Option<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+/// data of a `tag_scanned` event
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagScanned {
+    pub tag_id: String,
+    pub name: Option<String>,
+    /// the device which scanned the tag, e.g. a phone
+    pub device_id: Option<String>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+impl Event {
+    /// returns the data of a `tag_scanned` event, [`None`] for all other events
+    pub fn tag_scanned(&self) -> Option<TagScanned> {
+        if self.event_type != "tag_scanned" {
+            return None;
+        }
+        serde_json::from_value(self.data.clone()).ok()
+    }
+}
+
+impl HomeAssistantWs {
+    /// `tag/list`, returns a Vec containing [`Tag`]
+    pub async fn tags(&self) -> anyhow::Result<Vec<Tag>> {
+        self.command_as(json!({"type": "tag/list"})).await
+    }
+
+    /// `tag/create`, returns the created [`Tag`]
+    pub async fn create_tag(&self, tag: TagInput) -> anyhow::Result<Tag> {
+        let mut payload = serde_json::to_value(tag)?;
+        payload["type"] = "tag/create".into();
+        self.command_as(payload).await
+    }
+
+    /// `tag/update`, only `name` and `description` can be changed
+    pub async fn update_tag(
+        &self,
+        tag_id: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+    ) -> anyhow::Result<Tag> {
+        let mut payload = json!({"type": "tag/update", "tag_id": tag_id});
+        if let Some(name) = name {
+            payload["name"] = name.into();
+        }
+        if let Some(description) = description {
+            payload["description"] = description.into();
+        }
+        self.command_as(payload).await
+    }
+
+    /// `tag/delete`
+    pub async fn delete_tag(&self, tag_id: &str) -> anyhow::Result<()> {
+        self.command(json!({"type": "tag/delete", "tag_id": tag_id}))
+            .await?;
+        Ok(())
+    }
+
+    /// subscribes to `tag_scanned` events and yields their [`TagScanned`] data
+    pub async fn subscribe_tag_scanned(
+        &self,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<TagScanned>>> {
+        let events = self.subscribe_events(Some("tag_scanned")).await?;
+        Ok(events
+            .map(|event| Ok(serde_json::from_value(event?.data)?))
+            .boxed())
+    }
+}
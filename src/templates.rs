@@ -0,0 +1,270 @@
+//! Batched template rendering with a client-side cache
+//!
+//! Templates are rendered with the `render_template` WebSocket command, which also reports the
+//! entities a template depends on. [`TemplateCache`] keeps rendered templates until one of those
+//! entities changes.
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use std::sync::Arc;
+//! use homeassistant_rs::templates::TemplateCache;
+//!
+//! let cache = Arc::new(TemplateCache::new());
+//! let mut events = ws.subscribe_events(Some("state_changed")).await?;
+//! let invalidate = cache.clone();
+//! tokio::spawn(async move {
+//!     while let Some(Ok(event)) = events.next().await {
+//!         invalidate.invalidate(&event);
+//!     }
+//! });
+//!
+//! let rendered = cache
+//!     .render_all(&ws, &["{{ states('sensor.outside') }}", "{{ states.light | count }}"])
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`watch_template`](HomeAssistantWs::watch_template) keeps the subscription open and yields the
+//! result parsed as any `Deserialize` type every time it changes:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! let mut power = ws
+//!     .watch_template::<f64>("{{ states('sensor.power') | float }}")
+//!     .await?;
+//! while let Some(watts) = power.next().await {
+//!     println!("{} W", watts?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use futures_util::StreamExt;
+use futures_util::future::try_join_all;
+use futures_util::stream::BoxStream;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::stats;
+use crate::streams::entity_key;
+use crate::structs::Event;
+use crate::ws::HomeAssistantWs;
+
+/// what a rendered template depends on
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Listeners {
+    /// depends on all states, e.g. `{{ states | count }}`
+    pub all: bool,
+    pub domains: Vec<String>,
+    pub entities: Vec<String>,
+    /// depends on the current time, e.g. `{{ now() }}`
+    pub time: bool,
+}
+
+impl Listeners {
+    /// whether a change of `entity_id` affects the template
+    pub fn depends_on(&self, entity_id: &str) -> bool {
+        self.all
+            || self.entities.iter().any(|entity| entity == entity_id)
+            || entity_id
+                .split_once('.')
+                .is_some_and(|(domain, _)| self.domains.iter().any(|d| d == domain))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RenderedTemplate {
+    pub result: Value,
+    #[serde(default)]
+    pub listeners: Listeners,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RenderEvent {
+    Rendered(RenderedTemplate),
+    Error { error: String },
+}
+
+impl HomeAssistantWs {
+    /// `render_template`, renders `template` once and returns the result with its dependencies
+    pub async fn render_template(&self, template: &str) -> anyhow::Result<RenderedTemplate> {
+        let mut subscription = self
+            .subscribe::<RenderEvent>(json!({
+                "type": "render_template",
+                "template": template,
+                "report_errors": true,
+            }))
+            .await?;
+        match subscription.next().await {
+            Some(Ok(RenderEvent::Rendered(rendered))) => Ok(rendered),
+            Some(Ok(RenderEvent::Error { error })) => Err(anyhow::Error::msg(error)),
+            Some(Err(e)) => Err(e),
+            None => Err(anyhow::Error::msg("render_template ended without a result")),
+        }
+    }
+
+    /// renders all `templates` concurrently, results are in the same order
+    pub async fn render_templates(
+        &self,
+        templates: &[&str],
+    ) -> anyhow::Result<Vec<RenderedTemplate>> {
+        try_join_all(
+            templates
+                .iter()
+                .map(|template| self.render_template(template)),
+        )
+        .await
+    }
+
+    /// `render_template`, yields the result parsed as `T` whenever it changes, starting with the
+    /// current result
+    ///
+    /// results which don't parse as `T` and errors while rendering (e.g. an unavailable entity
+    /// passed to `float` without a default) are returned as an error for that render only, the
+    /// stream continues
+    pub async fn watch_template<T>(
+        &self,
+        template: &str,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<T>>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let subscription = self
+            .subscribe::<RenderEvent>(json!({
+                "type": "render_template",
+                "template": template,
+                "report_errors": true,
+            }))
+            .await?;
+        Ok(subscription
+            .map(|event| match event? {
+                RenderEvent::Rendered(rendered) => parse_result(rendered.result),
+                RenderEvent::Error { error } => Err(anyhow::Error::msg(error)),
+            })
+            .boxed())
+    }
+}
+
+/// parses the result of a template as `T`
+///
+/// Homeassistant returns results which look like numbers, booleans or JSON as such, other results
+/// as strings, so strings are parsed as JSON and non-strings are also tried as their text
+fn parse_result<T: DeserializeOwned>(result: Value) -> anyhow::Result<T> {
+    let Value::String(text) = result else {
+        return serde_json::from_value(result.clone())
+            .or_else(|_| serde_json::from_value(Value::String(result.to_string())))
+            .map_err(|e| anyhow::Error::msg(format!("template result {result}: {e}")));
+    };
+    serde_json::from_str(&text)
+        .or_else(|_| serde_json::from_value(Value::String(text.clone())))
+        .map_err(|e| anyhow::Error::msg(format!("template result {text:?}: {e}")))
+}
+
+/// rendered templates, keyed by the template
+///
+/// entries are dropped by [`invalidate`](Self::invalidate) once an entity they depend on changed.
+/// Templates depending on the time are never cached
+#[derive(Debug, Default)]
+pub struct TemplateCache {
+    entries: Mutex<HashMap<String, RenderedTemplate>>,
+    /// bumped with `entries` locked on every invalidation, renders started before are not cached
+    generation: AtomicU64,
+}
+
+impl TemplateCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, RenderedTemplate>> {
+        self.entries.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    /// returns the cached result of `template`, or renders and caches it
+    pub async fn render(
+        &self,
+        ws: &HomeAssistantWs,
+        template: &str,
+    ) -> anyhow::Result<RenderedTemplate> {
+        Ok(self.render_all(ws, &[template]).await?.remove(0))
+    }
+
+    /// like [`render`](Self::render) for multiple templates, templates missing in the cache are
+    /// rendered concurrently
+    pub async fn render_all(
+        &self,
+        ws: &HomeAssistantWs,
+        templates: &[&str],
+    ) -> anyhow::Result<Vec<RenderedTemplate>> {
+        let (cached, generation): (Vec<Option<RenderedTemplate>>, u64) = {
+            let entries = self.entries();
+            let cached = templates
+                .iter()
+                .map(|template| entries.get(*template).cloned())
+                .collect();
+            (cached, self.generation.load(Ordering::Relaxed))
+        };
+        let missing: Vec<&str> = templates
+            .iter()
+            .zip(&cached)
+            .filter(|(_, cached)| cached.is_none())
+            .map(|(template, _)| *template)
+            .collect();
+        stats::add(
+            &stats::COUNTERS.cache_hits,
+            (templates.len() - missing.len()) as u64,
+        );
+        stats::add(&stats::COUNTERS.cache_misses, missing.len() as u64);
+        let mut rendered = ws.render_templates(&missing).await?.into_iter();
+
+        let mut entries = self.entries();
+        // invalidated while rendering, the results may predate the change
+        let current = self.generation.load(Ordering::Relaxed) == generation;
+        Ok(templates
+            .iter()
+            .zip(cached)
+            .map(|(template, cached)| {
+                cached.unwrap_or_else(|| {
+                    let result = rendered.next().unwrap_or_default();
+                    if current && !result.listeners.time {
+                        entries.insert((*template).to_owned(), result.clone());
+                    }
+                    result
+                })
+            })
+            .collect())
+    }
+
+    /// drops the entries depending on the entity of a `state_changed` event
+    pub fn invalidate(&self, event: &Event) {
+        if event.event_type != "state_changed" {
+            return;
+        }
+        let entity_id = entity_key(event);
+        let mut entries = self.entries();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        entries.retain(|_, rendered| !rendered.listeners.depends_on(&entity_id));
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
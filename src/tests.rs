@@ -1,9 +1,15 @@
 
 use super::*;
 
+mod endpoints;
+mod mock;
+mod ws_mock;
+
+/// runs against the instance in `HA_URL` and `HA_TOKEN`, everything else runs against mocks
 #[cfg(test)]
 #[tokio::test]
-async fn main() -> anyhow::Result<()> {
+#[ignore = "needs a live instance"]
+async fn live() -> anyhow::Result<()> {
     // allow unused, since it'll cry otherwise, when testing in release mode
     #[allow(unused_imports)]
     use protokoll::log;
@@ -34,7 +40,7 @@ async fn main() -> anyhow::Result<()> {
     protokoll::debug!("finished testing history");
     protokoll::debug!("testing logbook");
     hass()
-        .logbook(None, None, Some("light.bedroom_light_shelly"))
+        .logbook(None, None, Some("light.bedroom_light_shelly"), None, None)
         .await?;
     protokoll::debug!("finished testing logbook");
     protokoll::debug!("testing states");
@@ -53,8 +59,7 @@ async fn main() -> anyhow::Result<()> {
     //hass().camera_proxy(None, None, "", 1).await?;
     protokoll::debug!("finished testing camera_proxy");
     protokoll::debug!("testing calendars");
-    protokoll::debug!("unable to test calendars, see function");
-    //hass().calendars(None, None).await?;
+    hass().calendars(None, None).await?;
     protokoll::debug!("finished testing calendars");
     protokoll::debug!("testing state post request");
     hass()
@@ -112,3 +117,3496 @@ async fn main() -> anyhow::Result<()> {
     protokoll::debug!("finished testing Intent post request");
     Ok(())
 }
+
+#[tokio::test]
+async fn cassette_replay() -> anyhow::Result<()> {
+    use crate::cassette::{Cassette, Interaction, Mode};
+
+    let cassette = Cassette::from_interactions(
+        vec![Interaction {
+            method: "GET".to_string(),
+            path: "/api/config".to_string(),
+            status: 200,
+            content_type: Some("application/json".to_string()),
+            body: r#"{"components":[],"config_dir":"/config","elevation":0,"latitude":0,"location_name":"Home","longitude":0,"time_zone":"UTC","unit_system":{"length":"km","mass":"g","temperature":"°C","volume":"L"},"version":"2025.7.0","whitelist_external_dirs":[]}"#.to_string(),
+            ..Default::default()
+        }],
+        Mode::Replay,
+    )
+    .scrub("secret");
+
+    let config = cassette
+        .replay("GET", "/api/config", None)?
+        .json::<structs::ConfigResponse>()
+        .await?;
+    assert_eq!(config.version, "2025.7.0");
+    assert!(cassette.replay("GET", "/api/states", None).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cassette_record_scrubs_token() -> anyhow::Result<()> {
+    use crate::cassette::{Cassette, Mode};
+
+    let server = mock::MockServer::start().await;
+    server.json(
+        "POST /api/template",
+        200,
+        json!({"echo": "s3cr3t-token", "other": "hunter2"}),
+    );
+    let path = std::env::temp_dir().join(format!(
+        "homeassistant-rs-cassette-{}.json",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+
+    let cassette = Cassette::open(&path, Mode::Record)?.scrub("hunter2");
+    let request = reqwest::Client::new()
+        .post(format!("{}/api/template?token=s3cr3t-token", server.url))
+        .bearer_auth("s3cr3t-token")
+        .body(r#"{"template": "s3cr3t-token"}"#);
+    let response = cassette
+        .handle(
+            "s3cr3t-token",
+            "POST",
+            "/api/template?token=s3cr3t-token",
+            Some(r#"{"template": "s3cr3t-token"}"#.to_owned()),
+            request,
+        )
+        .await?;
+    // the caller still gets the real response
+    assert!(response.text().await?.contains("s3cr3t-token"));
+
+    let recorded = std::fs::read_to_string(&path)?;
+    std::fs::remove_file(&path)?;
+    assert!(!recorded.contains("s3cr3t-token"), "{recorded}");
+    assert!(!recorded.contains("hunter2"), "{recorded}");
+    assert_eq!(recorded.matches("<REDACTED>").count(), 4);
+    Ok(())
+}
+
+#[tokio::test]
+async fn state_post_result() -> anyhow::Result<()> {
+    let server = mock::MockServer::start().await;
+    let route = "POST /api/states/sensor.provisioned";
+    let state = serde_json::json!({"entity_id": "sensor.provisioned", "state": "1", "attributes": {}, "last_changed": "2025-01-01T00:00:00+00:00"});
+    let location = [("Location", "/api/states/sensor.provisioned")];
+    server
+        .json(route, 200, state.clone())
+        .header(route, "Location", location[0].1)
+        .once_json(route, 201, &location, Some(state));
+    let (url, token) = server.credentials();
+
+    let request = || structs::StatesRequest {
+        state: "1".to_string(),
+        attributes: None,
+    };
+    for created in [true, false] {
+        let result = hass()
+            .request()
+            .state(url.clone(), token.clone(), "sensor.provisioned", request())
+            .await?;
+        assert_eq!(result.created, created);
+        assert_eq!(
+            result.location.as_deref(),
+            Some("/api/states/sensor.provisioned")
+        );
+        assert_eq!(result.response.state, "1");
+    }
+    Ok(())
+}
+
+#[test]
+fn websocket_url() {
+    assert_eq!(
+        ws::websocket_url("http://localhost:8123/")
+            .unwrap()
+            .as_str(),
+        "ws://localhost:8123/api/websocket"
+    );
+    assert_eq!(
+        ws::websocket_url("https://example.org/ha")
+            .unwrap()
+            .as_str(),
+        "wss://example.org/ha/api/websocket"
+    );
+}
+
+#[test]
+fn url_resolution() -> anyhow::Result<()> {
+    assert_eq!(
+        urls::join("http://localhost:8123/", "/api/camera_proxy/camera.door")?.as_str(),
+        "http://localhost:8123/api/camera_proxy/camera.door"
+    );
+    assert_eq!(
+        urls::join("http://localhost:8123", "https://example.org/a.png")?.as_str(),
+        "https://example.org/a.png"
+    );
+    assert_eq!(
+        urls::join("https://example.org/ha", "/api/states?a=1")?.as_str(),
+        "https://example.org/ha/api/states?a=1"
+    );
+    assert_eq!(urls::encode_segment("sensor.a b/c"), "sensor.a%20b%2Fc");
+    assert_eq!(
+        urls::encode_query("2025-01-01T00:00:00+01:00"),
+        "2025-01-01T00%3A00%3A00%2B01%3A00"
+    );
+    assert!(urls::needs_signing("/api/image_proxy/image.door"));
+    assert!(!urls::needs_signing(
+        "/api/camera_proxy/camera.door?token=abc"
+    ));
+    assert!(!urls::needs_signing("/local/picture.png"));
+
+    Ok(())
+}
+
+#[test]
+fn attribute_projection() -> anyhow::Result<()> {
+    #[derive(serde::Deserialize)]
+    struct Light {
+        brightness: u8,
+        friendly_name: String,
+    }
+
+    let states: Vec<structs::StatesResponse> = serde_json::from_value(serde_json::json!([
+        {"entity_id": "light.desk", "state": "on", "attributes": {"brightness": 128, "friendly_name": "Desk"}},
+        {"entity_id": "sensor.power", "state": "12", "attributes": {"unit_of_measurement": "W"}},
+    ]))?;
+
+    let rows = analysis::project::<Light>(&states);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].0.domain(), "light");
+    assert_eq!(rows[0].1, "on");
+    assert_eq!(rows[0].2.brightness, 128);
+    assert_eq!(rows[0].2.friendly_name, "Desk");
+
+    Ok(())
+}
+
+#[test]
+fn history_derivative() -> anyhow::Result<()> {
+    use analysis::{TimeUnit, delta, derivative, numeric_series};
+
+    let history: Vec<structs::HistoryResponse> = serde_json::from_value(serde_json::json!([
+        {"state": "10.0", "last_changed": "2025-01-01T00:00:00+00:00"},
+        {"state": "unavailable", "last_changed": "2025-01-01T00:15:00+00:00"},
+        {"state": "10.5", "last_changed": "2025-01-01T00:30:00+00:00"},
+        {"state": "11.5", "last_changed": "2025-01-01T01:30:00+00:00"},
+    ]))?;
+
+    let samples = numeric_series(&history);
+    assert_eq!(samples.len(), 3);
+    assert_eq!(delta(&samples)[1].value, 1.0);
+
+    let power = derivative(&samples, TimeUnit::Hours, 1000.0);
+    assert_eq!(power[0].value, 1000.0);
+    assert_eq!(power[1].value, 1000.0);
+
+    Ok(())
+}
+
+#[test]
+fn history_correlation() {
+    use crate::correlation::CorrelationQuery;
+    use analysis::{Sample, pearson, resample};
+    use chrono::{TimeDelta, TimeZone, Utc};
+
+    let start = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+    let at = |minutes: i64| start + TimeDelta::minutes(minutes);
+    // the heater toggles every 2 hours, the room follows 30 minutes later
+    let heater: Vec<Sample> = (0..12)
+        .map(|i| Sample {
+            time: at(i * 120),
+            value: if i % 2 == 0 { 1500.0 } else { 0.0 },
+        })
+        .collect();
+    let room: Vec<Sample> = (0..12)
+        .map(|i| Sample {
+            time: at(i * 120 + 30),
+            value: if i % 2 == 0 { 22.0 } else { 18.0 },
+        })
+        .collect();
+
+    let resampled = resample(&room, start, at(60), TimeDelta::minutes(20));
+    let values: Vec<_> = resampled
+        .iter()
+        .map(|sample| (sample.time, sample.value))
+        .collect();
+    assert_eq!(values, [(at(40), 22.0), (at(60), 22.0)]);
+    assert_eq!(
+        pearson(&[(1.0, 2.0), (2.0, 4.0), (3.0, 6.5)]).map(|r| r > 0.99),
+        Some(true)
+    );
+    assert_eq!(pearson(&[(1.0, 2.0), (1.0, 4.0)]), None);
+
+    let query = CorrelationQuery::new("sensor.heater_power", "sensor.room", start, at(24 * 60))
+        .step(TimeDelta::minutes(10))
+        .max_lag(TimeDelta::hours(1));
+    let correlation = query.correlate(&heater, &room).unwrap();
+    assert_eq!(correlation.lag, TimeDelta::minutes(30));
+    assert!(correlation.coefficient > 0.99);
+    assert_eq!(correlation.by_lag.len(), 13);
+    let without_lag =
+        CorrelationQuery::new("sensor.heater_power", "sensor.room", start, at(24 * 60))
+            .correlate(&heater, &room)
+            .unwrap();
+    assert!(without_lag.coefficient < correlation.coefficient);
+    assert!(query.correlate(&heater[..1], &room).is_none());
+}
+
+#[test]
+fn history_integration() {
+    use analysis::{IntegrationMethod, Sample, TimeUnit, integrate};
+    use chrono::{TimeZone, Utc};
+
+    let samples = [
+        Sample {
+            time: Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+            value: 1000.0,
+        },
+        Sample {
+            time: Utc.with_ymd_and_hms(2025, 1, 1, 1, 0, 0).unwrap(),
+            value: 3000.0,
+        },
+    ];
+
+    let energy = |method| integrate(&samples, TimeUnit::Hours, method, 0.001)[1].value;
+    assert_eq!(energy(IntegrationMethod::Trapezoidal), 2.0);
+    assert_eq!(energy(IntegrationMethod::Left), 1.0);
+    assert_eq!(energy(IntegrationMethod::Right), 3.0);
+}
+
+#[test]
+fn staleness() -> anyhow::Result<()> {
+    use chrono::{TimeZone, Utc};
+
+    let states: Vec<structs::StatesResponse> = serde_json::from_value(serde_json::json!([
+        {"entity_id": "sensor.fresh", "state": "1", "last_updated": "2025-01-01T11:59:00+00:00"},
+        {"entity_id": "sensor.old", "state": "1", "last_updated": "2025-01-01T06:00:00+00:00"},
+        {"entity_id": "light.gone", "state": "unavailable", "last_updated": "2025-01-01T11:59:00+00:00"},
+    ]))?;
+    let registry: Vec<registry::EntityRegistryEntry> = serde_json::from_value(serde_json::json!([
+        {"entity_id": "sensor.old", "platform": "zha"},
+    ]))?;
+
+    let report = health::staleness_report(
+        &states,
+        &registry,
+        std::time::Duration::from_secs(3600),
+        Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap(),
+    );
+    assert_eq!(report.len(), 2);
+    assert_eq!(
+        report.integrations["zha"][0].entity_id.as_str(),
+        "sensor.old"
+    );
+    assert!(report.integrations["light"][0].is_unavailable());
+
+    Ok(())
+}
+
+/// a `state_changed` event of `entity_id` to `new_state`, as received from a subscription
+fn state_changed_to(
+    entity_id: &str,
+    new_state: serde_json::Value,
+) -> anyhow::Result<structs::Event> {
+    Ok(structs::Event {
+        event_type: "state_changed".to_string(),
+        data: serde_json::json!({"entity_id": entity_id, "new_state": new_state}),
+        ..Default::default()
+    })
+}
+
+/// a `state_changed` event of `entity_id` to `state`, without attributes
+fn state_changed(entity_id: &str, state: &str) -> anyhow::Result<structs::Event> {
+    state_changed_to(entity_id, serde_json::json!({"state": state}))
+}
+
+#[tokio::test(start_paused = true)]
+async fn event_stream_combinators() {
+    use futures_util::StreamExt;
+    use std::time::Duration;
+    use streams::EventStreamExt;
+
+    let events = || {
+        futures_util::stream::iter(vec![
+            state_changed("light.a", "on"),
+            state_changed("light.a", "on"),
+            state_changed("light.b", "off"),
+            state_changed("light.a", "off"),
+        ])
+    };
+
+    let distinct: Vec<_> = events().distinct_until_changed().collect().await;
+    assert_eq!(distinct.len(), 3);
+
+    let throttled: Vec<_> = events()
+        .throttle_per_entity(Duration::from_secs(1))
+        .collect()
+        .await;
+    assert_eq!(throttled.len(), 2);
+
+    let debounced: Vec<_> = events()
+        .debounce_per_entity(Duration::from_secs(1))
+        .map(|event| event.unwrap().data["new_state"]["state"].clone())
+        .collect()
+        .await;
+    assert_eq!(debounced.len(), 2);
+    assert!(debounced.contains(&serde_json::json!("off")));
+}
+
+#[tokio::test]
+async fn smoothing_filters() {
+    use filters::{Ema, Filter, Median, OutlierFilter};
+    use futures_util::StreamExt;
+    use streams::EventStreamExt;
+
+    let mut ema = Ema::new(0.5);
+    assert_eq!(ema.update(10.0), Some(10.0));
+    assert_eq!(ema.update(20.0), Some(15.0));
+
+    let mut median = Median::new(3);
+    let smoothed: Vec<_> = [1.0, 100.0, 2.0, 3.0]
+        .into_iter()
+        .filter_map(|value| median.update(value))
+        .collect();
+    assert_eq!(smoothed, vec![1.0, 50.5, 2.0, 3.0]);
+
+    let mut outliers = OutlierFilter::new(4, 3.0).min_deviation(0.5);
+    let accepted: Vec<_> = [20.0, 20.5, 21.0, 20.5, 85.0, 20.8, 21.0, 21.0, 21.0, 21.2]
+        .into_iter()
+        .filter_map(|value| outliers.update(value))
+        .collect();
+    assert_eq!(
+        accepted,
+        vec![20.0, 20.5, 21.0, 20.5, 20.8, 21.0, 21.0, 21.0, 21.2]
+    );
+
+    let smoothed: Vec<_> = futures_util::stream::iter(vec![
+        state_changed("sensor.a", "10"),
+        state_changed("sensor.b", "1"),
+        state_changed("sensor.a", "20"),
+        state_changed("sensor.b", "unavailable"),
+        state_changed("sensor.b", "3"),
+    ])
+    .smooth_per_entity(|entity_id| Ema::new(if entity_id == "sensor.a" { 0.5 } else { 1.0 }))
+    .map(|event| event.unwrap().data["new_state"]["state"].clone())
+    .collect()
+    .await;
+    assert_eq!(smoothed, vec!["10", "1", "15", "unavailable", "3"]);
+}
+
+#[tokio::test]
+async fn threshold_hysteresis() {
+    use futures_util::StreamExt;
+    use std::time::Duration;
+    use threshold::{Switch, Threshold};
+    use tokio::time::Instant;
+
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut heater = Threshold::on_below(19.0, 21.0).min_dwell(Duration::from_secs(60));
+    assert_eq!(heater.update_at(20.0, at(0)), None);
+    assert_eq!(heater.update_at(18.5, at(10)), Some(Switch::On));
+    assert_eq!(heater.update_at(20.5, at(20)), None);
+    // above the limit, but still within the dwell time
+    assert_eq!(heater.update_at(21.5, at(30)), None);
+    assert_eq!(heater.update_at(21.5, at(70)), Some(Switch::Off));
+    assert_eq!(heater.state(), Some(Switch::Off));
+
+    let switches: Vec<_> = Threshold::on_above(65.0, 55.0)
+        .switches(
+            futures_util::stream::iter(vec![
+                state_changed("sensor.humidity", "70"),
+                state_changed("sensor.other", "10"),
+                state_changed("sensor.humidity", "unavailable"),
+                state_changed("sensor.humidity", "60"),
+                state_changed("sensor.humidity", "50"),
+            ]),
+            "sensor.humidity",
+        )
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(switches, vec![Switch::On, Switch::Off]);
+}
+
+#[cfg(feature = "control")]
+#[test]
+fn pid_control() {
+    use control::{Output, Pid};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut pid = Pid::new(10.0, 1.0, 0.0)
+        .setpoint(21.0)
+        .output_limits(0.0, 100.0);
+    assert_eq!(pid.update_at(20.0, at(0)), 10.0);
+    assert_eq!(pid.update_at(20.0, at(10)), 20.0);
+    // saturated for a long time, the integral must not grow beyond the output range
+    for secs in 11..1000 {
+        assert_eq!(pid.update_at(10.0, at(secs)), 100.0);
+    }
+    assert!(pid.update_at(22.0, at(1000)) < 100.0);
+    assert!(pid.update_at(23.0, at(1100)) == 0.0);
+
+    assert_eq!(
+        Output::number("number.valve").precision(1).round(42.46),
+        42.5
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn area_occupancy() {
+    use futures_util::StreamExt;
+    use occupancy::{AreaOccupancy, Occupancy};
+    use std::time::Duration;
+
+    let occupancy = Occupancy::new(Duration::from_secs(60))
+        .source("binary_sensor.kitchen_motion", "kitchen")
+        .source_with_decay("device_tracker.phone", "kitchen", Duration::ZERO)
+        .source("binary_sensor.hall_motion", "hall");
+    assert_eq!(
+        occupancy.areas().into_iter().collect::<Vec<_>>(),
+        vec!["hall", "kitchen"]
+    );
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let send = |entity_id: &str, state: &str| {
+        tx.send(Ok(structs::Event {
+            event_type: "state_changed".to_string(),
+            data: serde_json::json!({"entity_id": entity_id, "new_state": {"state": state}}),
+            ..Default::default()
+        }))
+        .unwrap()
+    };
+    let change = |area_id: &str, occupied| AreaOccupancy {
+        area_id: area_id.to_string(),
+        occupied,
+    };
+    let mut changes = occupancy.watch(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (event, rx))
+    }));
+
+    send("binary_sensor.kitchen_motion", "on");
+    assert_eq!(
+        changes.next().await.unwrap().unwrap(),
+        change("kitchen", true)
+    );
+    send("device_tracker.phone", "home");
+    send("binary_sensor.kitchen_motion", "off");
+    let next = tokio::time::timeout(Duration::from_secs(30), changes.next());
+    assert!(next.await.is_err());
+
+    // the phone leaving keeps the kitchen occupied until the motion sensor decayed
+    send("device_tracker.phone", "not_home");
+    send("binary_sensor.hall_motion", "on");
+    let start = tokio::time::Instant::now();
+    assert_eq!(changes.next().await.unwrap().unwrap(), change("hall", true));
+    assert_eq!(
+        changes.next().await.unwrap().unwrap(),
+        change("kitchen", false)
+    );
+    assert_eq!(start.elapsed(), Duration::from_secs(30));
+
+    drop(tx);
+    assert!(changes.next().await.is_none());
+}
+
+#[test]
+fn scene_definitions() {
+    use scene::{EntityState, Scene};
+
+    let scene = Scene::new("movie_night", "Movie night")
+        .entity(
+            "light.living_room",
+            EntityState::on().brightness(40).rgb_color(255, 120, 0),
+        )
+        .entity("cover.blinds", EntityState::new("closed").position(0));
+    scene.validate().unwrap();
+    assert_eq!(
+        serde_json::to_value(&scene).unwrap(),
+        serde_json::json!({
+            "id": "movie_night",
+            "name": "Movie night",
+            "entities": {
+                "cover.blinds": {"state": "closed", "current_position": 0},
+                "light.living_room": {"state": "on", "brightness": 40, "rgb_color": [255, 120, 0]},
+            },
+        })
+    );
+
+    assert!(Scene::new("empty", "Empty").validate().is_err());
+    let invalid = Scene::new("invalid", "Invalid").entity("living_room", EntityState::on());
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn jinja_builders() {
+    use jinja::{is_state_attr, last_changed, quote, state_attr, states, value};
+
+    assert_eq!(quote("it's a \\ test\n"), "'it\\'s a \\\\ test\\n'");
+    assert_eq!(
+        states("sensor.x') }}{{ 7*7").template(),
+        "{{ states('sensor.x\\') }}{{ 7*7') }}"
+    );
+    assert_eq!(
+        state_attr("climate.hall", "temperature")
+            .float(0)
+            .gt(20.5)
+            .to_string(),
+        "((state_attr('climate.hall', 'temperature') | float(0)) > 20.5)"
+    );
+    assert_eq!(
+        is_state_attr("light.a", "effect", serde_json::Value::Null)
+            .not()
+            .to_string(),
+        "(not is_state_attr('light.a', 'effect', none))"
+    );
+    assert_eq!(
+        value(serde_json::json!({"on": [1, true]})).to_string(),
+        "{'on': [1, true]}"
+    );
+    assert_eq!(
+        last_changed("sensor.Weird Name").to_string(),
+        "(expand('sensor.Weird Name') | first).last_changed"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn entity_rate_limits() -> anyhow::Result<()> {
+    use ratelimit::{Superseded, acquire, entity_ids};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    assert_eq!(
+        entity_ids(
+            &serde_json::json!({"entity_id": "light.a"}),
+            Some(&serde_json::json!({"entity_id": ["light.b", "light.c"]}))
+        ),
+        ["light.b", "light.c", "light.a"]
+    );
+    // comma separated ids are split
+    assert_eq!(
+        entity_ids(
+            &serde_json::json!({"entity_id": "light.a, light.b"}),
+            Some(&serde_json::json!({"entity_id": ["light.c,light.d"]}))
+        ),
+        ["light.c", "light.d", "light.a", "light.b"]
+    );
+    // all and none don't name entities
+    for special in ["all", "none"] {
+        assert!(entity_ids(&serde_json::json!({"entity_id": special}), None).is_empty());
+        assert!(
+            entity_ids(
+                &serde_json::json!({}),
+                Some(&serde_json::json!({"entity_id": [special]}))
+            )
+            .is_empty()
+        );
+    }
+
+    let relay = vec!["ratelimit_test.relay".to_string()];
+    ratelimit::set_entity_limit("ratelimit_test.relay", Duration::from_secs(1));
+    let turn_on = |entity_ids: Vec<String>| {
+        tokio::spawn(async move { acquire("switch", "turn_on", &entity_ids).await })
+    };
+    let start = Instant::now();
+    acquire("switch", "turn_on", &relay).await?;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+    // other services are limited separately
+    acquire("logbook", "log", &relay).await?;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+
+    let first = turn_on(relay.clone());
+    tokio::task::yield_now().await;
+    let second = turn_on(relay.clone());
+    let superseded = first.await?.unwrap_err();
+    assert_eq!(
+        superseded.downcast_ref::<Superseded>().unwrap().entity_id,
+        "ratelimit_test.relay"
+    );
+    second.await??;
+    assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+    acquire("switch", "turn_on", &relay).await?;
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+    // unlimited entities are not delayed
+    acquire("switch", "turn_on", &["ratelimit_test.other".to_string()]).await?;
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+    // a call is only superseded once all of its entities are
+    let pump = vec!["ratelimit_test.pump".to_string()];
+    ratelimit::set_entity_limit("ratelimit_test.pump", Duration::from_secs(1));
+    acquire("switch", "turn_on", &pump).await?;
+    let both = turn_on(vec![relay[0].clone(), pump[0].clone()]);
+    tokio::task::yield_now().await;
+    let newer = turn_on(pump);
+    both.await??;
+    newer.await??;
+    // both are sent, one interval apart
+    assert_eq!(start.elapsed(), Duration::from_secs(4));
+    Ok(())
+}
+
+#[test]
+fn meter_reset_cycles() {
+    use chrono::{FixedOffset, TimeZone, Weekday};
+    use meters::ResetCycle;
+
+    let tz = FixedOffset::east_opt(3600).unwrap();
+    let at = |y, m, d, h| tz.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap();
+
+    let monthly = ResetCycle::Monthly { day: 31, hour: 0 };
+    assert_eq!(monthly.next_after(&at(2025, 1, 31, 0)), at(2025, 2, 28, 0));
+    assert_eq!(monthly.next_after(&at(2025, 2, 28, 0)), at(2025, 3, 31, 0));
+
+    let billing = ResetCycle::Monthly { day: 15, hour: 6 };
+    assert_eq!(billing.next_after(&at(2025, 12, 15, 7)), at(2026, 1, 15, 6));
+    assert_eq!(
+        billing.next_after(&at(2025, 12, 15, 5)),
+        at(2025, 12, 15, 6)
+    );
+
+    let weekly = ResetCycle::Weekly {
+        weekday: Weekday::Mon,
+        hour: 0,
+    };
+    // 2025-01-01 is a wednesday
+    assert_eq!(weekly.next_after(&at(2025, 1, 1, 12)), at(2025, 1, 6, 0));
+    let daily = ResetCycle::Daily { hour: 0 };
+    assert_eq!(daily.next_after(&at(2025, 1, 1, 0)), at(2025, 1, 2, 0));
+    let yearly = ResetCycle::Yearly {
+        month: 2,
+        day: 29,
+        hour: 0,
+    };
+    assert_eq!(yearly.next_after(&at(2025, 3, 1, 0)), at(2026, 2, 28, 0));
+}
+
+#[cfg(feature = "tz")]
+#[test]
+fn meter_resets_across_dst() {
+    use chrono::{TimeZone, Utc};
+    use meters::ResetCycle;
+
+    let config = structs::ConfigResponse {
+        time_zone: "Europe/Berlin".to_owned(),
+        ..Default::default()
+    };
+    let tz = config.tz().unwrap();
+    let daily = ResetCycle::Daily { hour: 2 };
+    // 02:00 does not exist on 2025-03-30, the reset moves to 03:00 CEST
+    let before = Utc
+        .with_ymd_and_hms(2025, 3, 29, 12, 0, 0)
+        .unwrap()
+        .with_timezone(&tz);
+    assert_eq!(
+        daily.next_after(&before).with_timezone(&Utc),
+        Utc.with_ymd_and_hms(2025, 3, 30, 1, 0, 0).unwrap()
+    );
+    let monthly = ResetCycle::Monthly { day: 1, hour: 0 };
+    let summer = Utc
+        .with_ymd_and_hms(2025, 6, 15, 0, 0, 0)
+        .unwrap()
+        .with_timezone(&tz);
+    assert_eq!(
+        monthly.next_after(&summer).with_timezone(&Utc),
+        Utc.with_ymd_and_hms(2025, 6, 30, 22, 0, 0).unwrap()
+    );
+}
+
+#[test]
+fn event_catalog_diff() {
+    use events::EventCatalog;
+
+    let event = |event: &str, listener_count| structs::EventResponse {
+        event: event.to_string(),
+        listener_count,
+        ..Default::default()
+    };
+    let before =
+        EventCatalog::from_events(vec![event("state_changed", 4), event("call_service", 1)]);
+    let after = EventCatalog::from_events(vec![event("state_changed", 6), event("tag_scanned", 1)]);
+
+    assert_eq!(
+        events::listener_count_for(&[event("state_changed", 4)], "state_changed"),
+        Some(4)
+    );
+    let changes = before.diff(&after);
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0].event_type, "call_service");
+    assert_eq!(changes[0].delta(), -1);
+    assert_eq!(changes[1].delta(), 2);
+}
+
+#[test]
+fn failover_order() {
+    /// restores the fallback urls even if an assertion fails
+    struct Restore(Vec<String>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            failover::set_fallback_urls(std::mem::take(&mut self.0));
+        }
+    }
+    let _restore = Restore(failover::fallback_urls());
+
+    failover::set_fallback_urls(vec!["https://remote.ui.nabu.casa/".to_string()]);
+    assert_eq!(
+        failover::candidates("http://192.168.1.2:8123"),
+        ["http://192.168.1.2:8123", "https://remote.ui.nabu.casa"]
+    );
+
+    failover::mark("http://192.168.1.2:8123", false);
+    assert_eq!(
+        failover::candidates("http://192.168.1.2:8123"),
+        ["https://remote.ui.nabu.casa", "http://192.168.1.2:8123"]
+    );
+}
+
+#[tokio::test]
+async fn failover_probe() {
+    let server = mock::MockServer::start().await;
+    server
+        .once("GET /api/", 503, &[])
+        .once("GET /api/", 403, &[])
+        .once("GET /api/", 401, &[])
+        .text("GET /api/", 200, r#"{"message": "API running."}"#);
+
+    // a relay or an access proxy answering in front of Homeassistant
+    assert_eq!(failover::probe(&server.url, "token").await, None);
+    assert!(!failover::is_reachable(&server.url));
+    assert_eq!(failover::probe(&server.url, "token").await, None);
+
+    let reachable = Some(server.url.clone());
+    assert_eq!(failover::probe(&server.url, "token").await, reachable);
+    assert!(failover::is_reachable(&server.url));
+    assert_eq!(failover::probe(&server.url, "token").await, reachable);
+    assert_eq!(server.last().headers["authorization"], "Bearer token");
+}
+
+#[test]
+fn ipv6_urls() {
+    let base = urls::base_url(
+        std::net::SocketAddr::from((std::net::Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1), 8123)),
+        false,
+    );
+    assert_eq!(base, "http://[fd00::1]:8123");
+    assert_eq!(
+        urls::join(&(base.clone() + "/"), "/api/states/light.desk")
+            .unwrap()
+            .as_str(),
+        "http://[fd00::1]:8123/api/states/light.desk"
+    );
+    assert_eq!(
+        ws::websocket_url("https://[fd00::1]:8123")
+            .unwrap()
+            .as_str(),
+        "wss://[fd00::1]:8123/api/websocket"
+    );
+}
+
+#[test]
+fn query_builder() {
+    let query = urls::Query::new()
+        .opt("filter_entity_id", Some("light.a"))
+        .opt("end_time", None::<&str>)
+        .param("start", "2025-01-01T00:00:00+01:00")
+        .flag("minimal_response", true)
+        .flag("no_attributes", false);
+    assert_eq!(
+        query.to_string(),
+        "?filter_entity_id=light.a&start=2025-01-01T00%3A00%3A00%2B01%3A00&minimal_response"
+    );
+    assert_eq!(urls::Query::new().to_string(), "");
+}
+
+#[test]
+fn mjpeg_frames() {
+    let mut buffer = bytes::BytesMut::from(
+        &b"--frame\r\nContent-Type: image/jpeg\r\nContent-Length: 3\r\n\r\nabc\r\n--frame\r\nContent-Type: image/jpeg\r\n\r\ndefg\r\n--frame\r\nContent-Length: 10\r\n\r\nhij"[..],
+    );
+
+    let first = mjpeg::parse_frame(&mut buffer, b"--frame").unwrap();
+    assert_eq!(first.content_type.as_deref(), Some("image/jpeg"));
+    assert_eq!(&first.data[..], b"abc");
+    // without a content length the part ends at the next boundary
+    assert_eq!(
+        &mjpeg::parse_frame(&mut buffer, b"--frame").unwrap().data[..],
+        b"defg"
+    );
+    // incomplete
+    assert!(mjpeg::parse_frame(&mut buffer, b"--frame").is_none());
+}
+
+#[test]
+fn webrtc_messages() {
+    let message: camera::WebRtcMessage = serde_json::from_value(serde_json::json!({
+        "type": "candidate",
+        "candidate": {"candidate": "candidate:1 1 udp 1 10.0.0.2 5000 typ host", "sdpMLineIndex": 0}
+    }))
+    .unwrap();
+    assert!(matches!(
+        message,
+        camera::WebRtcMessage::Candidate { candidate } if candidate.sdp_m_line_index == Some(0)
+    ));
+
+    let config: camera::WebRtcClientConfig = serde_json::from_value(serde_json::json!({
+        "configuration": {"iceServers": [{"urls": "stun:stun.l.google.com:19302"}]}
+    }))
+    .unwrap();
+    assert_eq!(
+        config.configuration.ice_servers[0].urls,
+        ["stun:stun.l.google.com:19302"]
+    );
+}
+
+#[test]
+fn stt_provider_support() {
+    let info: stt::SttProviderInfo = serde_json::from_value(serde_json::json!({
+        "languages": ["en-US", "de-DE"],
+        "formats": ["wav"],
+        "codecs": ["pcm"],
+        "sample_rates": [16000],
+        "bit_rates": [16],
+        "channels": [1]
+    }))
+    .unwrap();
+
+    assert!(info.supports(&stt::SpeechMetadata::new("de-DE")));
+    assert!(!info.supports(&stt::SpeechMetadata::new("fr-FR")));
+    assert!(!info.supports(&stt::SpeechMetadata {
+        sample_rate: 44100,
+        ..stt::SpeechMetadata::new("en-US")
+    }));
+}
+
+#[cfg(feature = "satellite")]
+#[test]
+fn pipeline_events() {
+    let start: satellite::PipelineEvent = serde_json::from_value(serde_json::json!({
+        "type": "run-start",
+        "data": {"pipeline": "01h", "runner_data": {"stt_binary_handler_id": 1, "timeout": 300}},
+        "timestamp": "2024-01-01T00:00:00+00:00"
+    }))
+    .unwrap();
+    assert_eq!(start.event_type, satellite::PipelineEventType::RunStart);
+    assert_eq!(start.stt_binary_handler_id(), Some(1));
+
+    // the wake word stage keeps its underscore
+    for (event_type, expected) in [
+        (
+            "wake_word-start",
+            satellite::PipelineEventType::WakeWordStart,
+        ),
+        ("wake_word-end", satellite::PipelineEventType::WakeWordEnd),
+    ] {
+        let event: satellite::PipelineEvent =
+            serde_json::from_value(serde_json::json!({"type": event_type})).unwrap();
+        assert_eq!(event.event_type, expected);
+    }
+
+    let unknown: satellite::PipelineEvent =
+        serde_json::from_value(serde_json::json!({"type": "stt-vad-pause"})).unwrap();
+    assert_eq!(unknown.event_type, satellite::PipelineEventType::Unknown);
+}
+
+#[test]
+fn battery_levels() -> anyhow::Result<()> {
+    let states: Vec<structs::StatesResponse> = serde_json::from_value(serde_json::json!([
+        {"entity_id": "lock.door", "state": "locked", "attributes": {"battery_level": 80}},
+        {"entity_id": "binary_sensor.window", "state": "off"},
+        {"entity_id": "sensor.window_battery", "state": "12", "attributes": {"device_class": "battery", "state_class": "measurement"}},
+        {"entity_id": "binary_sensor.motion", "state": "off"},
+        {"entity_id": "sensor.motion_battery", "state": "9", "attributes": {"battery": "9 %"}},
+    ]))?;
+    let registry: Vec<registry::EntityRegistryEntry> = serde_json::from_value(serde_json::json!([
+        {"entity_id": "binary_sensor.window", "platform": "zha", "device_id": "d1"},
+        {"entity_id": "sensor.window_battery", "platform": "zha", "device_id": "d1"},
+    ]))?;
+
+    let window = battery::battery_level("binary_sensor.window", &states, &registry).unwrap();
+    assert_eq!(window.level, 12.0);
+    assert_eq!(window.source.as_str(), "sensor.window_battery");
+    // no device, found by name
+    assert_eq!(
+        battery::battery_level("binary_sensor.motion", &states, &registry)
+            .unwrap()
+            .level,
+        9.0
+    );
+    assert_eq!(
+        states[2].attributes.as_ref().unwrap().state_class(),
+        Some(structs::StateClass::Measurement)
+    );
+
+    let low = battery::low_batteries(&states, &registry, 20.0);
+    let sources: Vec<_> = low.iter().map(|b| b.source.as_str()).collect();
+    assert_eq!(sources, ["sensor.motion_battery", "sensor.window_battery"]);
+
+    Ok(())
+}
+
+#[test]
+fn raw_events() {
+    let event: ws::Raw<structs::Event> = serde_json::from_value(serde_json::json!({
+        "event_type": "zha_event",
+        "data": {"command": "on"},
+        "origin": "LOCAL",
+        "variables": {"trigger": {"platform": "event"}}
+    }))
+    .unwrap();
+    assert_eq!(event.event_type, "zha_event");
+    assert_eq!(event.raw["variables"]["trigger"]["platform"], "event");
+}
+
+macro_rules! fixture {
+    ($version:literal, $name:literal) => {
+        (
+            $version,
+            include_str!(concat!("../tests/fixtures/", $version, "/", $name, ".json")),
+        )
+    };
+}
+
+#[test]
+fn release_fixtures() -> anyhow::Result<()> {
+    let configs = [
+        fixture!("2024.1", "config"),
+        fixture!("2024.12", "config"),
+        fixture!("2025.6", "config"),
+    ];
+    for (version, config) in configs {
+        let config: structs::ConfigResponse = serde_json::from_str(config)?;
+        assert!(config.version.starts_with(version));
+        assert!(hass().capabilities().is_supported(&config.version));
+    }
+
+    for (version, states) in [
+        fixture!("2024.1", "states"),
+        fixture!("2024.12", "states"),
+        fixture!("2025.6", "states"),
+    ] {
+        let states: Vec<structs::StatesResponse> = serde_json::from_str(states)?;
+        assert!(
+            states.iter().all(|state| state.entity_id.is_some()),
+            "{version}"
+        );
+    }
+    for (_, events) in [
+        fixture!("2024.1", "events"),
+        fixture!("2024.12", "events"),
+        fixture!("2025.6", "events"),
+    ] {
+        serde_json::from_str::<Vec<structs::EventResponse>>(events)?;
+    }
+    for (_, services) in [
+        fixture!("2024.1", "services"),
+        fixture!("2024.12", "services"),
+        fixture!("2025.6", "services"),
+    ] {
+        serde_json::from_str::<Vec<structs::ServicesResponse>>(services)?;
+    }
+
+    // every tested release has fixtures
+    assert_eq!(compat::TESTED_VERSIONS, configs.map(|(version, _)| version));
+    assert!(!hass().capabilities().is_supported("2023.12.4"));
+    // the version is required, everything else falls back to its default
+    assert!(serde_json::from_str::<structs::ConfigResponse>("{}").is_err());
+    serde_json::from_str::<structs::ConfigResponse>(r#"{"version": "2025.6.0"}"#)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn websocket_reconnects() -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    // each connection answers all commands and sends one event per subscription, the first one
+    // then closes to simulate a restart
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        while let Some(message) = connection.recv().await {
+            let result = match message["type"].as_str() {
+                Some("get_config") => serde_json::json!({"state": "RUNNING"}),
+                _ => serde_json::Value::Null,
+            };
+            connection.reply(&message, result).await;
+            if message["type"] == "subscribe_events" {
+                let data = serde_json::json!({"connection": connection.index});
+                connection
+                    .event(
+                        &message["id"],
+                        serde_json::json!({"event_type": "test", "data": data}),
+                    )
+                    .await;
+                if connection.index == 0 {
+                    break;
+                }
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    ws.set_reconnect(Some(ws::ReconnectOptions {
+        initial_delay: std::time::Duration::from_millis(10),
+        ..Default::default()
+    }));
+    let mut events = ws.subscribe_events(Some("test")).await?.with_reconnects();
+
+    let first = events.next().await.unwrap()?;
+    assert!(matches!(first, ws::SubscriptionEvent::Event(e) if e.data["connection"] == 0));
+    let marker = events.next().await.unwrap()?;
+    assert!(
+        matches!(marker, ws::SubscriptionEvent::Reconnected { missed_window } if missed_window.from <= missed_window.to)
+    );
+    // the subscription was re-established under a fresh id
+    let second = events.next().await.unwrap()?;
+    assert!(matches!(second, ws::SubscriptionEvent::Event(e) if e.data["connection"] == 1));
+    let subscribed = server.sent("subscribe_events");
+    assert_eq!(subscribed.len(), 2);
+    assert!(subscribed[1]["id"].as_u64() > subscribed[0]["id"].as_u64());
+    assert_eq!(serde_json::json!(events.id()), subscribed[1]["id"]);
+    // waits until Homeassistant is running again
+    ws.ping().await?;
+    assert!(ws.is_connected());
+
+    drop(events);
+    drop(ws);
+    Ok(())
+}
+
+#[tokio::test]
+async fn restart_detected_by_core_state() -> anyhow::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // stopping when checked, starting at the first poll and running at the second
+    let polls = std::sync::Arc::new(AtomicUsize::new(0));
+    let server = ws_mock::MockWebSocket::respond({
+        let polls = polls.clone();
+        move |message| match message["type"].as_str() {
+            Some("get_config") => {
+                let state = ["STOPPING", "STARTING", "RUNNING"]
+                    [polls.fetch_add(1, Ordering::Relaxed).min(2)];
+                serde_json::json!({"state": state})
+            }
+            _ => serde_json::Value::Null,
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+    let url = url.unwrap();
+
+    let ws = hass().websocket(Some(url.clone()), token).await?;
+    // only reconnecting connections are checked
+    ws::check_restart(&url).await;
+    assert!(ws.is_connected());
+    assert_eq!(polls.load(Ordering::Relaxed), 0);
+
+    ws.set_reconnect(Some(ws::ReconnectOptions::default()));
+    ws::check_restart(&url).await;
+    assert!(!ws.is_connected());
+    // REST requests wait as well
+    ws::wait_for_restart(&url).await;
+    assert!(ws.is_connected());
+    assert_eq!(polls.load(Ordering::Relaxed), 3);
+    ws.ping().await?;
+    Ok(())
+}
+
+#[test]
+fn backfilled_history() {
+    use chrono::{TimeZone, Utc};
+
+    let window = ws::MissedWindow {
+        from: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        to: Utc.timestamp_opt(1_700_000_100, 0).unwrap(),
+    };
+    let history = serde_json::json!({
+        "light.kitchen": [
+            {"s": "off", "a": {}, "lu": 1_699_999_000.0},
+            {"s": "on", "a": {"brightness": 255}, "lu": 1_700_000_050.5},
+        ],
+        "sensor.power": [
+            {"s": "12", "a": {}, "lu": 1_700_000_010.0, "lc": 1_700_000_010.0},
+        ],
+    });
+
+    let events = backfill::history_events(&history, window);
+    let changes: Vec<_> = events
+        .iter()
+        .map(|event| event.state_changed().unwrap())
+        .collect();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].entity_id, "sensor.power");
+    assert!(changes[0].old_state.is_none());
+    assert_eq!(changes[1].old_state.as_ref().unwrap().state, "off");
+    assert_eq!(changes[1].new_state.as_ref().unwrap().state, "on");
+}
+
+#[tokio::test]
+async fn sequenced_events() -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        let mut first = None;
+        while let Some(message) = connection.recv().await {
+            connection.reply(&message, serde_json::Value::Null).await;
+            if message["type"] != "subscribe_events" {
+                continue;
+            }
+            let Some(first) = first.replace(message["id"].clone()) else {
+                continue;
+            };
+            // coalesced and single messages, alternating between both subscriptions
+            let event = |id: &serde_json::Value, n: u64| serde_json::json!({"id": id, "type": "event", "event": {"event_type": "test", "data": {"n": n}}});
+            connection
+                .send(serde_json::json!([event(&first, 1), event(&message["id"], 2)]))
+                .await;
+            connection.send(event(&first, 3)).await;
+            connection.send(event(&message["id"], 4)).await;
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let first = ws.subscribe_events(Some("a")).await?.sequenced();
+    let second = ws.subscribe_events(Some("b")).await?.sequenced();
+    // numbered per connection, each subscription sees increasing numbers with gaps
+    for (subscription, expected) in [(first, [1, 3]), (second, [2, 4])] {
+        let events: Vec<_> = subscription.take(2).collect().await;
+        for (event, expected) in events.into_iter().zip(expected) {
+            let event = event?;
+            assert_eq!(event.seq, expected);
+            assert_eq!(event.event.data["n"], event.seq);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn cached_templates() -> anyhow::Result<()> {
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        while let Some(message) = connection.recv().await {
+            connection.reply(&message, serde_json::Value::Null).await;
+            if message["type"] == "render_template" {
+                let template = message["template"].as_str().unwrap();
+                let event = match template {
+                    "{{ now() }}" => {
+                        serde_json::json!({"result": "12:00", "listeners": {"time": true}})
+                    }
+                    "{{ bad" => {
+                        serde_json::json!({"error": "unexpected end of template", "level": "ERROR"})
+                    }
+                    _ => {
+                        serde_json::json!({"result": template.to_uppercase(), "listeners": {"entities": ["sensor.a"], "domains": ["light"]}})
+                    }
+                };
+                connection.event(&message["id"], event).await;
+            }
+        }
+    })
+    .await;
+    let renders = || server.sent("render_template").len();
+
+    let ws = hass()
+        .websocket(Some(server.url.clone()), Some("s3cr3t".to_owned()))
+        .await?;
+    let debug = format!("{ws:?}");
+    assert!(debug.contains("[REDACTED]") && !debug.contains("s3cr3t"));
+    let before = hass().stats();
+    let cache = templates::TemplateCache::new();
+    let rendered = cache.render_all(&ws, &["a", "{{ now() }}", "b"]).await?;
+    let results: Vec<_> = rendered
+        .iter()
+        .map(|rendered| rendered.result.clone())
+        .collect();
+    assert_eq!(results, ["A", "12:00", "B"]);
+    assert_eq!(renders(), 3);
+    assert_eq!(cache.len(), 2);
+
+    cache.render_all(&ws, &["a", "b"]).await?;
+    assert_eq!(renders(), 3);
+    let stats = hass().stats();
+    assert!(stats.cache_hits >= before.cache_hits + 2);
+    assert!(stats.commands_sent >= before.commands_sent + 3);
+    assert!(stats.messages_received >= before.messages_received + 6);
+
+    let changed = |entity_id: &str| structs::Event {
+        event_type: "state_changed".to_string(),
+        data: serde_json::json!({"entity_id": entity_id}),
+        ..Default::default()
+    };
+    cache.invalidate(&changed("sensor.b"));
+    assert_eq!(cache.len(), 2);
+    cache.invalidate(&changed("light.kitchen"));
+    assert!(cache.is_empty());
+
+    assert!(ws.render_template("{{ bad").await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn watch_template() -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        while let Some(message) = connection.recv().await {
+            connection.reply(&message, serde_json::Value::Null).await;
+            let events = match message["template"].as_str().unwrap_or_default() {
+                "" => Vec::new(),
+                "{{ states('sensor.power') | float }}" => vec![
+                    serde_json::json!({"result": 1500.0}),
+                    serde_json::json!({"result": "1520.5"}),
+                    serde_json::json!({"error": "ValueError: Template error: float got invalid input 'unavailable'", "level": "ERROR"}),
+                    serde_json::json!({"result": "unavailable"}),
+                    serde_json::json!({"result": 1490}),
+                ],
+                _ => vec![
+                    serde_json::json!({"result": 3}),
+                    serde_json::json!({"result": "on"}),
+                ],
+            };
+            for event in events {
+                connection.event(&message["id"], event).await;
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let power: Vec<_> = ws
+        .watch_template::<f64>("{{ states('sensor.power') | float }}")
+        .await?
+        .take(5)
+        .collect()
+        .await;
+    assert_eq!(power[0].as_ref().unwrap(), &1500.0);
+    assert_eq!(power[1].as_ref().unwrap(), &1520.5);
+    assert!(
+        power[2]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("float got invalid input")
+    );
+    assert!(power[3].is_err());
+    assert_eq!(power[4].as_ref().unwrap(), &1490.0);
+
+    let text: Vec<String> = ws
+        .watch_template::<String>("{{ states('light.desk') }}")
+        .await?
+        .take(2)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(text, ["3", "on"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn template_cache_invalidated_while_rendering() -> anyhow::Result<()> {
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    let (received, release) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    let (server_received, server_release) = (received.clone(), release.clone());
+    let server = ws_mock::MockWebSocket::start(move |mut connection| {
+        let (received, release) = (server_received.clone(), server_release.clone());
+        async move {
+            while let Some(message) = connection.recv().await {
+                connection.reply(&message, serde_json::Value::Null).await;
+                if message["type"] == "render_template" {
+                    received.notify_one();
+                    release.notified().await;
+                    let event = serde_json::json!({"result": "old", "listeners": {"entities": ["sensor.a"]}});
+                    connection.event(&message["id"], event).await;
+                }
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let cache = templates::TemplateCache::new();
+    let changed = structs::Event {
+        event_type: "state_changed".to_string(),
+        data: serde_json::json!({"entity_id": "sensor.a"}),
+        ..Default::default()
+    };
+    let (rendered, ()) = tokio::join!(cache.render(&ws, "{{ states('sensor.a') }}"), async {
+        received.notified().await;
+        cache.invalidate(&changed);
+        release.notify_one();
+    });
+    assert_eq!(rendered?.result, "old");
+    // the result predates the change, caching it would serve it until the next change
+    assert!(cache.is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "audit")]
+#[tokio::test]
+async fn audit_trail() -> anyhow::Result<()> {
+    use audit::{MutationKind, Transport};
+
+    assert_eq!(
+        audit::classify_rest(
+            &reqwest::Method::POST,
+            "/api/services/light/turn_on?return_response"
+        ),
+        Some((MutationKind::ServiceCall, "light.turn_on".to_string()))
+    );
+    assert_eq!(
+        audit::classify_rest(&reqwest::Method::POST, "/api/states/sensor.a%20b"),
+        Some((MutationKind::State, "sensor.a b".to_string()))
+    );
+    assert_eq!(
+        audit::classify_rest(&reqwest::Method::POST, "/api/template"),
+        None
+    );
+    assert_eq!(
+        audit::classify_rest(&reqwest::Method::GET, "/api/states/sensor.a"),
+        None
+    );
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        while let Some(message) = connection.recv().await {
+            if message["service"] == "fail" {
+                connection
+                    .fail(&message, "not_found", "Service not found")
+                    .await;
+            } else {
+                connection.reply(&message, serde_json::json!({})).await;
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let data = serde_json::json!({"brightness": 10});
+    ws.call_service("audit_test", "ping", data.clone(), None, false)
+        .await?;
+    // the log is process-wide, it must not keep recording the calls of other tests
+    struct Disable;
+    impl Drop for Disable {
+        fn drop(&mut self) {
+            audit::disable();
+        }
+    }
+    let _disable = Disable;
+    audit::enable(100);
+    ws.call_service("audit_test", "ping", data.clone(), None, false)
+        .await?;
+    assert!(
+        ws.call_service("audit_test", "fail", data, None, false)
+            .await
+            .is_err()
+    );
+
+    let entries: Vec<_> = audit::entries()
+        .into_iter()
+        .filter(|entry| entry.target.starts_with("audit_test."))
+        .collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].kind, MutationKind::ServiceCall);
+    assert_eq!(entries[0].transport, Transport::WebSocket);
+    assert_eq!(entries[0].target, "audit_test.ping");
+    assert!(entries[0].success && !entries[1].success);
+    assert_eq!(entries[0].digest.len(), 64);
+
+    let mut exported = Vec::new();
+    assert!(audit::export_jsonl(&mut exported)? >= 2);
+    assert!(String::from_utf8(exported)?.contains("\"target\":\"audit_test.fail\""));
+    Ok(())
+}
+
+#[test]
+fn storage_backends() -> anyhow::Result<()> {
+    use storage::{FileStorage, MemoryStorage, Storage, StorageExt};
+
+    let root =
+        std::env::temp_dir().join(format!("homeassistant-rs-storage-{}", std::process::id()));
+    let backends: [Box<dyn Storage>; 2] = [
+        Box::new(MemoryStorage::new()),
+        Box::new(FileStorage::new(&root)),
+    ];
+    for storage in backends {
+        storage.put("cache/light.kitchen", b"on")?;
+        storage.put_json("cache/sensor.power", &serde_json::json!({"state": "12"}))?;
+        storage.put("queue/1", b"")?;
+
+        assert_eq!(
+            storage.get("cache/light.kitchen")?.as_deref(),
+            Some(&b"on"[..])
+        );
+        assert_eq!(
+            storage
+                .get_json::<serde_json::Value>("cache/sensor.power")?
+                .unwrap()["state"],
+            "12"
+        );
+        assert_eq!(
+            storage.list("cache/")?,
+            ["cache/light.kitchen", "cache/sensor.power"]
+        );
+
+        storage.delete("cache/light.kitchen")?;
+        storage.delete("cache/light.kitchen")?;
+        assert!(storage.get("cache/light.kitchen")?.is_none());
+    }
+    assert!(FileStorage::new(&root).put("../escape", b"").is_err());
+    std::fs::remove_dir_all(root)?;
+
+    // cassettes can be kept in any storage
+    let storage = std::sync::Arc::new(MemoryStorage::new());
+    assert!(
+        cassette::Cassette::load(storage.clone(), "config.json", cassette::Mode::Replay).is_err()
+    );
+    cassette::Cassette::load(storage.clone(), "config.json", cassette::Mode::Record)?.save()?;
+    assert_eq!(storage.list("")?, ["config.json"]);
+
+    Ok(())
+}
+
+#[test]
+fn component_presence() {
+    let config = structs::ConfigResponse {
+        components: ["api", "hue", "light.hue", "sensor.hue", "zwave_js", "light"]
+            .map(str::to_owned)
+            .to_vec(),
+        ..Default::default()
+    };
+
+    assert!(config.has_component("zwave_js"));
+    assert!(!config.has_component("light.hue_bridge"));
+    assert_eq!(config.components_by_domain()["hue"], ["light", "sensor"]);
+    assert!(config.components_by_domain()["api"].is_empty());
+    assert_eq!(
+        config.missing_components(&["hue", "mqtt", "zha"]),
+        ["mqtt", "zha"]
+    );
+}
+
+#[test]
+fn unknown_service_targets() {
+    use services::ServiceCallError;
+
+    let targets = ratelimit::entity_ids(
+        &serde_json::json!({"brightness": 10}),
+        Some(&serde_json::json!({"entity_id": ["light.kitchen", "light.kitchn", "light.hall"]})),
+    );
+    let known = ["light.kitchen", "light.hall", "sensor.outside"];
+    let error = ServiceCallError::verify_targets(&targets, known).unwrap_err();
+    assert_eq!(
+        error,
+        ServiceCallError::UnknownEntities {
+            message: "light.kitchn".to_owned(),
+            entity_ids: vec!["light.kitchn".to_owned()],
+        }
+    );
+    assert_eq!(error.to_string(), "unknown entities: light.kitchn");
+    assert!(ServiceCallError::verify_targets(&targets[..1], known).is_ok());
+    for special in ["all", "none"] {
+        assert!(ServiceCallError::verify_targets(&[special.to_owned()], known).is_ok());
+    }
+    // a comma separated target is checked entity by entity
+    let targets = ratelimit::entity_ids(
+        &serde_json::json!({"entity_id": "light.kitchen,light.kitchn"}),
+        None,
+    );
+    assert_eq!(
+        ServiceCallError::verify_targets(&targets, known),
+        Err(ServiceCallError::UnknownEntities {
+            message: "light.kitchn".to_owned(),
+            entity_ids: vec!["light.kitchn".to_owned()],
+        })
+    );
+}
+
+#[test]
+fn logbook_entry_data() {
+    assert_eq!(
+        services::log_entry_data("Irrigation", "started zone 2", Some("switch.zone_2"), None),
+        serde_json::json!({"name": "Irrigation", "message": "started zone 2", "entity_id": "switch.zone_2"})
+    );
+}
+
+#[test]
+fn service_call_errors() {
+    use services::ServiceCallError;
+
+    let error = ws::CommandError {
+        code: "invalid_format".to_owned(),
+        message: "expected int for dictionary value @ data['brightness']".to_owned(),
+        details: serde_json::Value::Null,
+    };
+    assert_eq!(
+        ServiceCallError::from_command_error(&error),
+        ServiceCallError::InvalidFormat {
+            message: error.message.clone(),
+            field: Some("brightness".to_owned()),
+        }
+    );
+
+    // bodies as sent by Homeassistant, for unknown services and invalid data alike
+    let bad_request =
+        ServiceCallError::from_response(reqwest::StatusCode::BAD_REQUEST, "400: Bad Request");
+    assert_eq!(
+        bad_request,
+        ServiceCallError::InvalidFormat {
+            message: "400: Bad Request".to_owned(),
+            field: None,
+        }
+    );
+    assert_eq!(
+        bad_request.to_string(),
+        "invalid service data: 400: Bad Request"
+    );
+    assert!(matches!(
+        ServiceCallError::from_response(
+            reqwest::StatusCode::BAD_REQUEST,
+            r#"{"message":"Data should be valid JSON."}"#
+        ),
+        ServiceCallError::InvalidFormat { message, field: None } if message == "Data should be valid JSON."
+    ));
+    assert!(matches!(
+        ServiceCallError::from_response(reqwest::StatusCode::UNAUTHORIZED, "401: Unauthorized"),
+        ServiceCallError::Unauthorized { .. }
+    ));
+    assert!(matches!(
+        ServiceCallError::from_response(
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+            "500 Internal Server Error\n\nServer got itself in trouble"
+        ),
+        ServiceCallError::HomeAssistant { .. }
+    ));
+}
+
+#[test]
+fn history_attribute_whitelist() {
+    let body = br#"[[
+        {"entity_id": "media_player.tv", "state": "playing", "last_changed": "2024-01-01T00:00:00+00:00",
+         "last_updated": "2024-01-01T00:00:00+00:00",
+         "attributes": {"friendly_name": "TV", "volume_level": 0.4, "entity_picture": "/api/media_player_proxy/x"}},
+        {"entity_id": "media_player.tv", "state": "paused", "last_changed": "2024-01-01T01:00:00+00:00",
+         "last_updated": "2024-01-01T01:00:00+00:00", "attributes": null}
+    ], [
+        {"entity_id": "sensor.power", "state": "120", "last_changed": "2024-01-01T00:00:00+00:00",
+         "last_updated": "2024-01-01T00:00:00+00:00", "attributes": {"unit_of_measurement": "W"}}
+    ]]"#;
+
+    let groups = history::parse_filtered(body, &["volume_level"]).unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].len(), 2);
+    let rows = groups.concat();
+    assert_eq!(rows[1].state, "paused");
+    let attributes = rows[0].attributes.as_ref().unwrap();
+    assert_eq!(attributes.friendly_name, None);
+    assert_eq!(
+        attributes.other_fields,
+        serde_json::json!({"volume_level": 0.4})
+    );
+    assert!(rows[1].attributes.is_none());
+    assert_eq!(
+        rows[2].attributes.as_ref().unwrap().other_fields,
+        serde_json::json!({})
+    );
+
+    let rows = history::parse_filtered(body, &["friendly_name", "volume_level"])
+        .unwrap()
+        .concat();
+    assert_eq!(
+        rows[0]
+            .attributes
+            .as_ref()
+            .unwrap()
+            .friendly_name
+            .as_deref(),
+        Some("TV")
+    );
+}
+
+#[tokio::test]
+async fn states_by_area() -> anyhow::Result<()> {
+    let server = ws_mock::MockWebSocket::respond(|message| match message["type"].as_str().unwrap() {
+        "get_states" => serde_json::json!([
+            {"entity_id": "light.kitchen", "state": "on"},
+            {"entity_id": "sensor.kitchen_temperature", "state": "21.5"},
+            {"entity_id": "light.hallway", "state": "off"},
+            {"entity_id": "lightning.strikes", "state": "0"},
+            {"entity_id": "switch.kitchen_relay", "state": "on"},
+        ]),
+        "config/entity_registry/list" => serde_json::json!([
+            {"entity_id": "light.kitchen", "platform": "hue", "area_id": "kitchen"},
+            {"entity_id": "sensor.kitchen_temperature", "platform": "zha", "device_id": "d1"},
+            {"entity_id": "light.hallway", "platform": "hue", "area_id": "hallway", "device_id": "d1"},
+            {"entity_id": "switch.kitchen_relay", "platform": "shelly", "area_id": "kitchen", "hidden_by": "user"},
+        ]),
+        "config/device_registry/list" => serde_json::json!([{"id": "d1", "area_id": "kitchen"}]),
+        "config/area_registry/list" | "config/floor_registry/list" => serde_json::json!([]),
+        _ => serde_json::Value::Null,
+    })
+    .await;
+    let registry_fetches = || server.sent("config/entity_registry/list").len();
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let entity_ids = |states: Vec<structs::StatesResponse>| -> Vec<String> {
+        states
+            .into_iter()
+            .filter_map(|state| state.entity_id)
+            .collect()
+    };
+    assert_eq!(
+        entity_ids(ws.states_by_domain("light").await?),
+        ["light.kitchen", "light.hallway"]
+    );
+    assert_eq!(
+        entity_ids(ws.states_in_area("kitchen").await?),
+        [
+            "light.kitchen",
+            "sensor.kitchen_temperature",
+            "switch.kitchen_relay"
+        ]
+    );
+    assert_eq!(
+        entity_ids(ws.states_in_area("hallway").await?),
+        ["light.hallway"]
+    );
+    assert_eq!(
+        entity_ids(
+            ws.select_states_in_area("kitchen", registry::EntitySelection::visible())
+                .await?
+        ),
+        ["light.kitchen", "sensor.kitchen_temperature"]
+    );
+    assert_eq!(registry_fetches(), 1);
+
+    ws.invalidate_area_cache();
+    assert!(ws.states_in_area("garage").await?.is_empty());
+    assert_eq!(registry_fetches(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn scaled_camera_snapshot() -> anyhow::Result<()> {
+    let server = mock::MockServer::start().await;
+    server.bytes(
+        "GET /api/camera_proxy/camera.door",
+        200,
+        "image/png",
+        b"\x89PNG",
+    );
+    let (url, token) = server.credentials();
+
+    let options = camera::SnapshotOptions::new()
+        .width(320)
+        .height(180)
+        .time(1_700_000_000u64);
+    assert_eq!(options.query()?, "?time=1700000000&width=320&height=180");
+    let snapshot = hass()
+        .camera_snapshot(url, token, "camera.door", options)
+        .await?;
+    assert_eq!(snapshot.content_type.as_deref(), Some("image/png"));
+    assert_eq!(snapshot.data.as_ref(), b"\x89PNG");
+
+    assert_eq!(
+        server.last().path,
+        "/api/camera_proxy/camera.door?time=1700000000&width=320&height=180"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn windowed_queries() -> anyhow::Result<()> {
+    use chrono::{DateTime, TimeDelta, TimeZone, Utc};
+    use futures_util::FutureExt;
+    use futures_util::future::BoxFuture;
+    use std::sync::Mutex;
+    use windowed::{HistoryRange, LogbookRange, WindowOptions, WindowedQuery};
+
+    /// one item per hour, the first attempt of every window fails with a server error
+    struct Hours(Mutex<Vec<DateTime<Utc>>>);
+
+    impl WindowedQuery for Hours {
+        type Item = DateTime<Utc>;
+
+        fn fetch_window(
+            &self,
+            start: DateTime<Utc>,
+            end: DateTime<Utc>,
+        ) -> BoxFuture<'_, anyhow::Result<Vec<DateTime<Utc>>>> {
+            let mut attempts = self.0.lock().unwrap();
+            let retried = attempts.contains(&start);
+            attempts.push(start);
+            async move {
+                anyhow::ensure!(retried, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+                Ok(windowed::split(start, end, TimeDelta::hours(1))
+                    .into_iter()
+                    .map(|(hour, _)| hour)
+                    .collect())
+            }
+            .boxed()
+        }
+    }
+
+    let start = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+    let end = start + TimeDelta::hours(60);
+    let query = Hours(Mutex::new(Vec::new()));
+    let options = WindowOptions::new().retry_delay(std::time::Duration::ZERO);
+    let mut progress = Vec::new();
+    let hours = query
+        .fetch_range(start, end, &options, |p| {
+            progress.push((p.window, p.windows, p.items))
+        })
+        .await?;
+    assert_eq!(hours.len(), 60);
+    assert_eq!(hours[59], end - TimeDelta::hours(1));
+    assert_eq!(progress, [(0, 3, 24), (1, 3, 48), (2, 3, 60)]);
+    assert_eq!(query.0.lock().unwrap().len(), 6);
+
+    let failing = Hours(Mutex::new(Vec::new()));
+    let error = failing
+        .fetch_range(start, end, &options.clone().retries(0), |_| ())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("failed after 1 attempts"));
+    assert_eq!(failing.0.lock().unwrap().len(), 1);
+
+    assert_eq!(
+        HistoryRange::new(["sensor.a", "sensor.b"])
+            .no_attributes()
+            .path(start, end)?,
+        "/api/history/period/2025-03-01T00:00:00+00:00?end_time=2025-03-03T12%3A00%3A00%2B00%3A00&filter_entity_id=sensor.a%2Csensor.b&no_attributes"
+    );
+    assert!(HistoryRange::new(["sensor.a"]).path(end, start).is_err());
+    assert_eq!(
+        LogbookRange::new(None).path(start, end),
+        "/api/logbook/2025-03-01T00:00:00+00:00?end_time=2025-03-03T12%3A00%3A00%2B00%3A00"
+    );
+
+    // every window after the first starts with the state the previous one ended with
+    let server = mock::MockServer::start().await;
+    let (url, token) = server.credentials();
+    let middle = start + TimeDelta::days(1);
+    server
+        .json(
+            "GET /api/history/period/2025-03-01T00:00:00+00:00",
+            200,
+            serde_json::json!([[
+                {"entity_id": "sensor.a", "state": "1", "last_changed": "2025-03-01T00:00:00+00:00"},
+                {"state": "2", "last_changed": "2025-03-01T12:00:00+00:00"},
+            ]]),
+        )
+        .json(
+            "GET /api/history/period/2025-03-02T00:00:00+00:00",
+            200,
+            serde_json::json!([[
+                {"entity_id": "sensor.a", "state": "2", "last_changed": "2025-03-02T00:00:00+00:00"},
+                {"state": "3", "last_changed": "2025-03-02T06:00:00+00:00"},
+            ], [
+                {"entity_id": "sensor.b", "state": "on", "last_changed": "2025-03-02T08:00:00+00:00"},
+            ]]),
+        );
+    let history = HistoryRange::new(["sensor.a", "sensor.b"])
+        .credentials(url.clone(), token.clone())
+        .minimal_response()
+        .fetch_range(start, middle + TimeDelta::days(1), &options, |_| ())
+        .await?;
+    let rows: Vec<_> = history
+        .iter()
+        .map(|row| (row.entity_id.as_deref(), row.state.as_str()))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (Some("sensor.a"), "1"),
+            (None, "2"),
+            (Some("sensor.a"), "3"),
+            (Some("sensor.b"), "on"),
+        ]
+    );
+
+    // client errors, here the 404 of a window without route, are not retried
+    let requests = server.requests().len();
+    let error = HistoryRange::new(["sensor.a"])
+        .credentials(url, token)
+        .fetch_range(middle + TimeDelta::days(1), end, &options, |_| ())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("failed after 1 attempts"));
+    assert_eq!(server.requests().len(), requests + 1);
+
+    let rows = windowed::statistics_rows(serde_json::from_value(serde_json::json!({
+        "sensor.b": [{"start": 3600000.0, "end": 7200000.0, "mean": 2.0}],
+        "sensor.a": [{"start": 0.0, "end": 3600000.0, "sum": 1.5}, {"start": 3600000.0, "end": 7200000.0}],
+    }))?);
+    let ids: Vec<_> = rows.iter().map(|row| row.statistic_id.as_str()).collect();
+    assert_eq!(ids, ["sensor.a", "sensor.a", "sensor.b"]);
+    assert_eq!(
+        rows[1].start_time(),
+        Some(Utc.timestamp_opt(3600, 0).unwrap())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn download_progress() -> anyhow::Result<()> {
+    let log =
+        "2025-01-01 00:00:00 ERROR (MainThread) [homeassistant] something failed\n".repeat(200);
+    let server = mock::MockServer::start().await;
+    server.text("GET /api/error_log", 200, &log);
+    let (url, token) = server.credentials();
+
+    let mut updates = Vec::new();
+    let mut written = Vec::new();
+    let size = hass()
+        .download_error_log_with_progress(url, token, &mut written, |progress| {
+            updates.push(progress)
+        })
+        .await?;
+    assert_eq!(size, log.len() as u64);
+    assert_eq!(written, log.as_bytes());
+    let last = updates.last().unwrap();
+    assert_eq!((last.done, last.total), (size, Some(size)));
+    assert_eq!(last.fraction(), Some(1.0));
+    assert!(updates.windows(2).all(|pair| pair[0].done < pair[1].done));
+    assert_eq!(
+        structs::Progress {
+            done: 5,
+            total: None
+        }
+        .fraction(),
+        None
+    );
+    Ok(())
+}
+
+#[test]
+fn state_formatting() {
+    use format::{Formatter, precision};
+
+    let state = |value: &str, attributes: serde_json::Value| structs::StatesResponse {
+        state: value.to_owned(),
+        attributes: serde_json::from_value(attributes).ok(),
+        ..Default::default()
+    };
+
+    let en = Formatter::new("en-GB");
+    assert_eq!(en.number(1234567.891, 2), "1,234,567.89");
+    assert_eq!(en.number(-0.04, 1), "0.0");
+    assert_eq!(en.value(21.456, Some("temperature"), Some("°C")), "21.5°C");
+    assert_eq!(en.value(1.5, None, Some("kWh")), "1.5 kWh");
+    assert_eq!(en.value(3.0, None, None), "3");
+    assert_eq!(
+        en.state(&state("unavailable", serde_json::json!({}))),
+        "unavailable"
+    );
+
+    let de = Formatter::from_config(&structs::ConfigResponse {
+        language: "de".to_owned(),
+        unit_system: structs::UnitSystem {
+            temperature: "°F".to_owned(),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    assert_eq!(
+        de.state(&state(
+            "12345.678",
+            serde_json::json!({"device_class": "energy", "unit_of_measurement": "kWh"})
+        )),
+        "12.345,68 kWh"
+    );
+    assert_eq!(
+        de.state(&state(
+            "70.24",
+            serde_json::json!({"device_class": "temperature"})
+        )),
+        "70,2°F"
+    );
+    assert_eq!(
+        de.state(&state(
+            "48",
+            serde_json::json!({"device_class": "humidity", "unit_of_measurement": "%"})
+        )),
+        "48%"
+    );
+    assert_eq!(Formatter::new("fr").number(-1500.0, 0), "-1\u{202f}500");
+
+    assert_eq!(precision("power", Some("W")), Some(0));
+    assert_eq!(precision("power", Some("kW")), Some(2));
+    assert_eq!(precision("enum", None), None);
+}
+
+#[test]
+fn qualified_names() -> anyhow::Result<()> {
+    use registry::Registries;
+
+    let registries = Registries::new(
+        serde_json::from_value(serde_json::json!([
+            {"entity_id": "sensor.kitchen_temperature", "platform": "zha", "device_id": "d1",
+             "has_entity_name": true, "original_name": "Temperature"},
+            {"entity_id": "sensor.attic_temperature", "platform": "mqtt", "area_id": "attic", "name": "Temperature"},
+            {"entity_id": "switch.pump", "platform": "zha", "device_id": "d2", "has_entity_name": true},
+            {"entity_id": "sensor.outside", "platform": "met"},
+        ]))?,
+        serde_json::from_value(serde_json::json!([
+            {"id": "d1", "name": "Thermometer", "name_by_user": "Kitchen sensor", "area_id": "kitchen"},
+            {"id": "d2", "name": "Pool pump"},
+        ]))?,
+        serde_json::from_value(serde_json::json!([
+            {"area_id": "kitchen", "name": "Kitchen", "floor_id": "ground"},
+            {"area_id": "attic", "name": "Attic"},
+        ]))?,
+        serde_json::from_value(
+            serde_json::json!([{"floor_id": "ground", "name": "Ground Floor", "level": 0}]),
+        )?,
+    );
+
+    assert_eq!(
+        registries.qualified_name("sensor.kitchen_temperature", None),
+        "Ground Floor / Kitchen / Kitchen sensor Temperature"
+    );
+    assert_eq!(
+        registries.qualified_name("sensor.kitchen_temperature", Some("Temperature")),
+        "Ground Floor / Kitchen / Temperature"
+    );
+    assert_eq!(
+        registries.qualified_name("sensor.attic_temperature", None),
+        "Attic / Temperature"
+    );
+    assert_eq!(registries.qualified_name("switch.pump", None), "Pool pump");
+    assert_eq!(
+        registries.qualified_name("sensor.outside", None),
+        "sensor.outside"
+    );
+    assert_eq!(
+        registries.area_id("sensor.kitchen_temperature"),
+        Some("kitchen")
+    );
+    assert_eq!(
+        registries.entity_areas(),
+        std::collections::HashMap::from([
+            (
+                "sensor.kitchen_temperature".to_owned(),
+                "kitchen".to_owned()
+            ),
+            ("sensor.attic_temperature".to_owned(), "attic".to_owned()),
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn device_views() -> anyhow::Result<()> {
+    use registry::DeviceView;
+
+    let views = DeviceView::join(
+        serde_json::from_value(
+            serde_json::json!([{"id": "d1", "name": "Plug"}, {"id": "d2", "name": "Bridge"}]),
+        )?,
+        serde_json::from_value(serde_json::json!([
+            {"entity_id": "switch.plug", "platform": "zha", "device_id": "d1"},
+            {"entity_id": "sensor.plug_power", "platform": "zha", "device_id": "d1", "disabled_by": "user"},
+            {"entity_id": "sun.sun", "platform": "sun"},
+        ]))?,
+        serde_json::from_value(serde_json::json!([
+            {"entity_id": "switch.plug", "state": "on"},
+            {"entity_id": "sun.sun", "state": "above_horizon"},
+        ]))?,
+    );
+
+    assert_eq!(views.len(), 2);
+    let plug = &views[0];
+    assert_eq!(plug.device.display_name(), Some("Plug"));
+    let entities: Vec<_> = plug
+        .entities
+        .iter()
+        .map(|entity| {
+            (
+                entity.entry.entity_id.as_str(),
+                entity.state.as_ref().map(|state| state.state.as_str()),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entities,
+        [("sensor.plug_power", None), ("switch.plug", Some("on"))]
+    );
+    assert!(views[1].entities.is_empty());
+    Ok(())
+}
+
+#[test]
+fn entity_categories() -> anyhow::Result<()> {
+    use registry::{DeviceView, EntityCategory, EntitySelection, Registries};
+
+    let entities: Vec<registry::EntityRegistryEntry> = serde_json::from_value(serde_json::json!([
+        {"entity_id": "switch.plug", "platform": "zha", "device_id": "d1", "entity_category": null},
+        {"entity_id": "sensor.plug_rssi", "platform": "zha", "device_id": "d1", "entity_category": "diagnostic"},
+        {"entity_id": "select.plug_power_on", "platform": "zha", "device_id": "d1", "entity_category": "config"},
+        {"entity_id": "sensor.plug_future", "platform": "zha", "device_id": "d1", "entity_category": "system"},
+        {"entity_id": "light.bulb", "platform": "hue", "hidden_by": "integration"},
+        {"entity_id": "sensor.plug_voltage", "platform": "zha", "device_id": "d1", "disabled_by": "integration"},
+    ]))?;
+    assert_eq!(
+        entities[1].entity_category,
+        Some(EntityCategory::Diagnostic)
+    );
+    assert_eq!(entities[3].entity_category, Some(EntityCategory::Unknown));
+    assert!(entities[0].is_primary() && !entities[2].is_primary());
+    assert!(entities[4].is_hidden() && entities[5].is_disabled() && !entities[0].is_hidden());
+
+    let registries = Registries::new(entities.clone(), Vec::new(), Vec::new(), Vec::new());
+    let included = |selection: EntitySelection| -> Vec<&str> {
+        [
+            "switch.plug",
+            "sensor.plug_rssi",
+            "select.plug_power_on",
+            "sensor.plug_future",
+            "light.bulb",
+            "sensor.plug_voltage",
+            "sun.sun",
+        ]
+        .into_iter()
+        .filter(|entity_id| registries.includes(entity_id, selection))
+        .collect()
+    };
+    assert_eq!(included(EntitySelection::all()).len(), 7);
+    assert_eq!(
+        included(EntitySelection::default()),
+        included(EntitySelection::all())
+    );
+    assert_eq!(included(EntitySelection::visible()).len(), 5);
+    assert_eq!(
+        included(EntitySelection::primary().hidden(true)),
+        ["switch.plug", "light.bulb", "sun.sun"]
+    );
+    assert_eq!(
+        included(EntitySelection::visible().disabled(true).config(false)),
+        [
+            "switch.plug",
+            "sensor.plug_rssi",
+            "sensor.plug_voltage",
+            "sun.sun"
+        ]
+    );
+    assert_eq!(
+        included(EntitySelection::primary()),
+        ["switch.plug", "sun.sun"]
+    );
+    assert_eq!(
+        included(EntitySelection::primary().diagnostic(true)),
+        ["switch.plug", "sensor.plug_rssi", "sun.sun"]
+    );
+    assert_eq!(
+        registries.entity_category("select.plug_power_on"),
+        Some(EntityCategory::Config)
+    );
+
+    let views = DeviceView::join(
+        serde_json::from_value(serde_json::json!([{"id": "d1", "name": "Plug"}]))?,
+        entities,
+        Vec::new(),
+    );
+    let primary: Vec<_> = views[0]
+        .selected(EntitySelection::primary())
+        .map(|entity| entity.entry.entity_id.as_str())
+        .collect();
+    assert_eq!(primary, ["switch.plug"]);
+    Ok(())
+}
+
+#[test]
+fn integration_health_summary() -> anyhow::Result<()> {
+    use health::{ConfigEntryState, integration_health};
+
+    let entries = serde_json::from_value(serde_json::json!([
+        {"entry_id": "1", "domain": "hue", "title": "Bridge", "state": "loaded"},
+        {"entry_id": "2", "domain": "shelly", "title": "Plug", "state": "setup_retry", "reason": "Unable to connect"},
+        {"entry_id": "3", "domain": "shelly", "title": "Relay", "state": "loaded"},
+        {"entry_id": "4", "domain": "sun", "title": "Sun", "state": "not_loaded", "disabled_by": "user"},
+        {"entry_id": "5", "domain": "zha", "title": "Zigbee", "state": "some_future_state"},
+    ]))?;
+    let registry: Vec<registry::EntityRegistryEntry> = serde_json::from_value(serde_json::json!([
+        {"entity_id": "light.desk", "platform": "hue"},
+        {"entity_id": "light.couch", "platform": "hue"},
+        {"entity_id": "switch.relay", "platform": "shelly"},
+        {"entity_id": "sensor.zha_lqi", "platform": "zha"},
+    ]))?;
+    let states: Vec<structs::StatesResponse> = serde_json::from_value(serde_json::json!([
+        {"entity_id": "light.desk", "state": "on"},
+        {"entity_id": "light.couch", "state": "unavailable"},
+        {"entity_id": "switch.relay", "state": "off"},
+        {"entity_id": "sensor.zha_lqi", "state": "200"},
+        {"entity_id": "sun.sun", "state": "above_horizon"},
+    ]))?;
+
+    let health = integration_health(entries, &registry, &states);
+    let summary: Vec<_> = health
+        .iter()
+        .map(|health| {
+            (
+                health.domain.as_str(),
+                health.is_healthy(),
+                health.entities,
+                health.unavailable,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("hue", false, 2, 1),
+            ("shelly", false, 1, 0),
+            ("sun", true, 0, 0),
+            ("zha", true, 1, 0)
+        ]
+    );
+    let failing: Vec<_> = health[1]
+        .failing_entries()
+        .map(|entry| entry.reason.as_deref())
+        .collect();
+    assert_eq!(failing, [Some("Unable to connect")]);
+    assert_eq!(health[3].entries[0].state, ConfigEntryState::Unknown);
+    Ok(())
+}
+
+#[test]
+fn ha_versions() -> anyhow::Result<()> {
+    use compat::HaVersion;
+
+    let parse = |version: &str| version.parse::<HaVersion>();
+    let mut versions = [
+        "2025.1.0",
+        "2024.12.5",
+        "2025.1.0b2",
+        "2025.1.0.dev20241220",
+        "2025.1.0b10",
+        "2024.1",
+    ]
+    .map(|version| parse(version).unwrap());
+    versions.sort();
+    let sorted: Vec<String> = versions.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        sorted,
+        [
+            "2024.1.0",
+            "2024.12.5",
+            "2025.1.0.dev20241220",
+            "2025.1.0b2",
+            "2025.1.0b10",
+            "2025.1.0"
+        ]
+    );
+
+    assert!(parse("2024.6.0b1")?.is_at_least(2024, 6));
+    assert!(!parse("2024.5.4")?.is_at_least(2024, 6));
+    assert_eq!(parse("2024.12")?, HaVersion::new(2024, 12, 0));
+    assert!(parse("2024").is_err() && parse("latest").is_err());
+
+    let capabilities = hass().capabilities();
+    assert!(capabilities.supports(&parse("2025.6.1")?));
+    assert!(!capabilities.supports(&parse("2023.12.4")?));
+    assert_eq!(capabilities.tested().len(), compat::TESTED_VERSIONS.len());
+    let config = structs::ConfigResponse {
+        version: "2025.6.1".to_owned(),
+        ..Default::default()
+    };
+    assert!(config.ha_version()?.is_at_least(2025, 6));
+    Ok(())
+}
+
+#[test]
+fn unknown_fields() -> anyhow::Result<()> {
+    let state: structs::StatesResponse = serde_json::from_value(serde_json::json!({
+        "entity_id": "light.desk",
+        "state": "on",
+        "context": {"id": "01J", "parent_id": null, "user_id": null, "origin_event": "abc"},
+        "last_seen": "2030-01-01T00:00:00+00:00",
+    }))?;
+    assert_eq!(
+        state.other,
+        serde_json::json!({"last_seen": "2030-01-01T00:00:00+00:00"})
+    );
+    assert_eq!(state.context.unwrap().other["origin_event"], "abc");
+
+    let config: structs::ConfigResponse =
+        serde_json::from_value(serde_json::json!({"version": "2025.6.0", "radius": 100}))?;
+    assert_eq!(config.other["radius"], 100);
+
+    let rows = history::parse_filtered(
+        br#"[[{"entity_id": "sensor.a", "state": "1", "last_changed": "", "attributes": {}, "lr": 1}]]"#,
+        &[],
+    )?;
+    assert_eq!(rows[0][0].other, serde_json::json!({"lr": 1}));
+    Ok(())
+}
+
+#[test]
+fn comparable_responses() -> anyhow::Result<()> {
+    use std::collections::HashSet;
+
+    let event = |id: &str| -> anyhow::Result<structs::Event> {
+        Ok(serde_json::from_value(serde_json::json!({
+            "event_type": "state_changed",
+            "data": {"entity_id": "light.desk"},
+            "context": {"id": id, "parent_id": null, "user_id": null},
+        }))?)
+    };
+    // the same event delivered twice, e.g. after a reconnect
+    let events = [event("01A")?, event("01A")?, event("01B")?];
+    assert_eq!(events[0], events[1]);
+    assert_ne!(events[1], events[2]);
+
+    let contexts: HashSet<structs::Context> = events
+        .iter()
+        .filter_map(|event| event.context.clone())
+        .collect();
+    assert_eq!(contexts.len(), 2);
+    let entity_ids: HashSet<structs::EntityId> = ["light.desk", "light.desk"]
+        .map(structs::EntityId::from)
+        .into();
+    assert_eq!(entity_ids.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn typed_entity_states() {
+    use futures_util::StreamExt;
+    use watch::LightAttributes;
+
+    let events = futures_util::stream::iter(vec![
+        state_changed_to(
+            "light.desk",
+            serde_json::json!({"state": "on", "attributes": {"brightness": 128, "color_mode": "hs", "hs_color": [30.0, 80.5]}}),
+        ),
+        state_changed_to(
+            "light.kitchen",
+            serde_json::json!({"state": "on", "attributes": {"brightness": 255}}),
+        ),
+        state_changed_to(
+            "light.desk",
+            serde_json::json!({"state": "on", "attributes": {"brightness": 300}}),
+        ),
+        state_changed_to("light.desk", serde_json::Value::Null),
+        state_changed_to("light.desk", serde_json::json!({"state": "off"})),
+    ]);
+
+    let changes: Vec<_> = watch::typed_states::<LightAttributes, _>(events, "light.desk")
+        .collect()
+        .await;
+    assert_eq!(changes.len(), 3);
+    let (state, attributes) = changes[0].as_ref().unwrap();
+    assert_eq!(state, "on");
+    assert_eq!(attributes.brightness, Some(128));
+    assert_eq!(attributes.hs_color, Some((30.0, 80.5)));
+    let error = changes[1].as_ref().unwrap_err().to_string();
+    assert!(
+        error.starts_with("unexpected attributes of light.desk"),
+        "{error}"
+    );
+    let (state, attributes) = changes[2].as_ref().unwrap();
+    assert_eq!((state.as_str(), attributes.brightness), ("off", None));
+}
+
+#[tokio::test]
+async fn websocket_shutdown() -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    let (closed, closed_frame) = tokio::sync::oneshot::channel();
+    let mut closed = Some(closed);
+    let server = ws_mock::MockWebSocket::start(move |mut connection| {
+        let closed = closed.take();
+        async move {
+            while let Some(message) = connection.recv().await {
+                connection.reply(&message, serde_json::Value::Null).await;
+            }
+            if let Some(closed) = closed {
+                let _ = closed.send(connection.closed);
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    ws.set_reconnect(Some(ws::ReconnectOptions::default()));
+    let mut events = ws.subscribe_events(Some("state_changed")).await?;
+    let id = events.id();
+
+    ws.shutdown().await?;
+    assert!(!ws.is_connected());
+    assert!(events.next().await.is_none());
+    assert!(ws.ping().await.is_err());
+    assert!(closed_frame.await?);
+    let unsubscribed: Vec<_> = server
+        .sent("unsubscribe_events")
+        .iter()
+        .map(|command| command["subscription"].clone())
+        .collect();
+    assert_eq!(unsubscribed, [serde_json::json!(id)]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cloudhooks() -> anyhow::Result<()> {
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        let mut cloud_loaded = true;
+        while let Some(message) = connection.recv().await {
+            match message["type"].as_str().unwrap() {
+                _ if !cloud_loaded => {
+                    connection
+                        .fail(&message, "unknown_command", "Unknown command.")
+                        .await
+                }
+                "cloud/cloudhook/create" => {
+                    let cloudhook = serde_json::json!({
+                        "webhook_id": message["webhook_id"],
+                        "cloudhook_id": "abc",
+                        "cloudhook_url": "https://hooks.nabu.casa/abc",
+                        "managed": false,
+                    });
+                    connection.reply(&message, cloudhook).await
+                }
+                "cloud/cloudhook/delete" => {
+                    cloud_loaded = false;
+                    connection.reply(&message, serde_json::Value::Null).await
+                }
+                _ => connection.reply(&message, serde_json::Value::Null).await,
+            }
+        }
+    })
+    .await;
+    let url = server.url.clone();
+
+    let ws = hass()
+        .websocket(Some(url.clone()), Some("token".to_owned()))
+        .await?;
+    let cloudhook = ws.create_cloudhook("callback").await?;
+    assert_eq!(cloudhook.webhook_id, "callback");
+    assert_eq!(cloudhook.cloudhook_url, "https://hooks.nabu.casa/abc");
+    assert_eq!(
+        ws.webhook_url("callback")?,
+        format!("{url}/api/webhook/callback")
+    );
+    ws.delete_cloudhook("callback").await?;
+
+    let error = ws.create_cloudhook("callback").await.unwrap_err();
+    assert_eq!(error.to_string(), "Home Assistant Cloud is not set up");
+    assert!(error.downcast_ref::<ws::CommandError>().is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn tag_registry() -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        while let Some(message) = connection.recv().await {
+            let result = match message["type"].as_str().unwrap() {
+                "tag/create" => {
+                    serde_json::json!({"id": message["tag_id"], "name": message["name"]})
+                }
+                "tag/list" => {
+                    serde_json::json!([{"id": "04:a2", "name": "Front door", "last_scanned": null}])
+                }
+                _ => serde_json::Value::Null,
+            };
+            connection.reply(&message, result).await;
+            if message["type"] == "subscribe_events" {
+                let event = serde_json::json!({
+                    "event_type": "tag_scanned",
+                    "data": {"tag_id": "04:a2", "name": "Front door", "device_id": "phone"},
+                });
+                connection.event(&message["id"], event).await;
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let tag = ws
+        .create_tag(tags::TagInput::new("Front door").tag_id("04:a2"))
+        .await?;
+    assert_eq!(tag.id, "04:a2");
+    assert_eq!(ws.tags().await?[0].name.as_deref(), Some("Front door"));
+
+    let mut scans = ws.subscribe_tag_scanned().await?;
+    let scan = scans.next().await.unwrap()?;
+    assert_eq!(
+        (scan.tag_id.as_str(), scan.device_id.as_deref()),
+        ("04:a2", Some("phone"))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn raw_commands() -> anyhow::Result<()> {
+    // echoes the command, unknown commands fail like in Homeassistant
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        while let Some(message) = connection.recv().await {
+            if message["type"] == "no/such_command" {
+                connection
+                    .fail(&message, "unknown_command", "Unknown command.")
+                    .await;
+            } else {
+                connection.reply(&message, message.clone()).await;
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let sent = ws
+        .send_command("config_entries/get", serde_json::json!({"domain": "hue"}))
+        .await?;
+    assert_eq!(sent["type"], "config_entries/get");
+    assert_eq!(sent["domain"], "hue");
+    assert!(sent["id"].as_u64().is_some());
+
+    let echoed: std::collections::HashMap<String, serde_json::Value> =
+        ws.send_command_as("ping", serde_json::Value::Null).await?;
+    assert_eq!(echoed["type"], "ping");
+
+    let error = ws
+        .send_command("no/such_command", serde_json::Value::Null)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<ws::CommandError>().unwrap().code,
+        "unknown_command"
+    );
+    assert!(
+        ws.send_command("ping", serde_json::json!([1]))
+            .await
+            .is_err()
+    );
+    assert!(
+        ws.send_command("ping", serde_json::json!({"id": 1}))
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn system_log_limits() -> anyhow::Result<()> {
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        let entry = |message: String| {
+            serde_json::json!({
+                "name": "homeassistant.components.zha", "message": [message], "level": "ERROR",
+                "source": ["components/zha/core.py", 42], "timestamp": 1748736000.5, "exception": "",
+                "count": 3, "first_occurred": 1748730000.0,
+            })
+        };
+        let mut lists = 0;
+        while let Some(message) = connection.recv().await {
+            let result = match message["type"].as_str().unwrap() {
+                "system_log/list" => {
+                    lists += 1;
+                    // an integration flooding the log
+                    let size = if lists == 1 { 10 } else { 10_000 };
+                    serde_json::json!([entry("x".repeat(size))])
+                }
+                "repairs/list_issues" => serde_json::json!({"issues": [{
+                    "domain": "homeassistant", "issue_id": "deprecated_yaml", "severity": "warning",
+                    "is_fixable": false, "ignored": false, "breaks_in_ha_version": "2025.12.0",
+                }]}),
+                _ => serde_json::Value::Null,
+            };
+            connection.reply(&message, result).await;
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass()
+        .websocket_with_limits(
+            url,
+            token,
+            ws::Limits {
+                max_message_size: 4096,
+            },
+        )
+        .await?;
+    let entries = ws.system_log().await?;
+    assert_eq!(entries[0].source, ("components/zha/core.py".to_owned(), 42));
+    assert_eq!(entries[0].count, 3);
+    let issues = ws.repair_issues().await?;
+    assert_eq!(issues[0].breaks_in_ha_version.as_deref(), Some("2025.12.0"));
+
+    let error = ws.system_log().await.unwrap_err().to_string();
+    assert!(error.contains("max_message_size"), "{error}");
+    assert!(ws.ping().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn entity_customizations() -> anyhow::Result<()> {
+    use crate::customize::Customization;
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        let mut entities = serde_json::json!({
+            "light.desk": {"entity_id": "light.desk", "platform": "hue", "name": null, "icon": null},
+            "light.kitchen": {"entity_id": "light.kitchen", "platform": "hue", "name": "Kitchen", "icon": null},
+        });
+        while let Some(message) = connection.recv().await {
+            let entity_id = message["entity_id"].as_str().unwrap_or_default();
+            let result = match message["type"].as_str().unwrap() {
+                "config/entity_registry/list" => {
+                    serde_json::json!([entities["light.desk"], entities["light.kitchen"]])
+                }
+                "config/entity_registry/get" => entities[entity_id].clone(),
+                "config/entity_registry/update" => {
+                    entities[entity_id]["name"] = message["name"].clone();
+                    entities[entity_id]["icon"] = message["icon"].clone();
+                    serde_json::json!({"entity_entry": entities[entity_id]})
+                }
+                "config/device_registry/list"
+                | "config/area_registry/list"
+                | "config/floor_registry/list" => serde_json::json!([]),
+                _ => serde_json::Value::Null,
+            };
+            connection.reply(&message, result).await;
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    assert_eq!(ws.customization("light.desk").await?, None);
+    let customizations = ws.customizations().await?;
+    assert_eq!(customizations.keys().collect::<Vec<_>>(), ["light.kitchen"]);
+    assert_eq!(
+        customizations["light.kitchen"],
+        Customization::new().friendly_name("Kitchen")
+    );
+
+    let desk = Customization::new()
+        .friendly_name("Desk")
+        .icon("mdi:desk-lamp");
+    let entry = ws.set_customization("light.desk", &desk).await?;
+    assert_eq!(entry.icon.as_deref(), Some("mdi:desk-lamp"));
+    assert_eq!(ws.customization("light.desk").await?, Some(desk));
+
+    ws.set_customizations(&[("light.kitchen".to_owned(), Customization::new())].into())
+        .await?;
+    assert_eq!(
+        ws.customizations().await?.keys().collect::<Vec<_>>(),
+        ["light.desk"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn rename_entities() -> anyhow::Result<()> {
+    use crate::rename::RenameOutcome;
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        let mut entities = vec![
+            "sensor.kitchen_2".to_owned(),
+            "light.desk".to_owned(),
+            "light.desk_2".to_owned(),
+            "switch.fan".to_owned(),
+        ];
+        while let Some(message) = connection.recv().await {
+            match message["type"].as_str().unwrap() {
+                "config/entity_registry/list" => {
+                    let list: Vec<_> = entities
+                        .iter()
+                        .map(|id| serde_json::json!({"entity_id": id, "platform": "zha"}))
+                        .collect();
+                    connection.reply(&message, list.into()).await;
+                }
+                "config/entity_registry/update" => {
+                    let new_entity_id = message["new_entity_id"].as_str().unwrap().to_owned();
+                    if entities.contains(&new_entity_id) {
+                        connection
+                            .fail(
+                                &message,
+                                "invalid_info",
+                                "Entity with this ID is already registered",
+                            )
+                            .await;
+                    } else {
+                        entities.retain(|id| id != &message["entity_id"]);
+                        entities.push(new_entity_id.clone());
+                        let entry =
+                            serde_json::json!({"entity_id": new_entity_id, "platform": "zha"});
+                        connection
+                            .reply(&message, serde_json::json!({"entity_entry": entry}))
+                            .await;
+                    }
+                }
+                _ => connection.reply(&message, serde_json::Value::Null).await,
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let outcomes = |renames: Vec<crate::rename::EntityRename>| -> Vec<RenameOutcome> {
+        renames.into_iter().map(|rename| rename.outcome).collect()
+    };
+
+    let rejected = ws
+        .rename_entities(
+            [
+                ("sensor.kitchen_2", "sensor.kitchen"),
+                ("switch.fan", "light.fan"),
+                ("light.missing", "light.found"),
+                ("light.desk_2", "light.Desk"),
+            ],
+            false,
+        )
+        .await?;
+    assert_eq!(rejected[0].outcome, RenameOutcome::Skipped);
+    assert!(
+        rejected[1..]
+            .iter()
+            .all(|rename| matches!(rename.outcome, RenameOutcome::Rejected(_)))
+    );
+
+    let conflicts = ws
+        .rename_entities(
+            [("light.desk_2", "light.desk"), ("switch.fan", "switch.fan")],
+            true,
+        )
+        .await?;
+    assert_eq!(
+        conflicts[0].outcome,
+        RenameOutcome::Rejected("light.desk is already registered".to_owned())
+    );
+    assert_eq!(conflicts[1].outcome, RenameOutcome::Unchanged);
+
+    // light.desk has to move before light.desk_2 can take its id
+    let mapping = [
+        ("light.desk_2", "light.desk"),
+        ("light.desk", "light.desk_old"),
+        ("sensor.kitchen_2", "sensor.kitchen"),
+    ];
+    assert_eq!(
+        outcomes(ws.rename_entities(mapping, true).await?),
+        [
+            RenameOutcome::Planned,
+            RenameOutcome::Planned,
+            RenameOutcome::Planned
+        ]
+    );
+    assert_eq!(
+        outcomes(ws.rename_entities(mapping, false).await?),
+        [
+            RenameOutcome::Renamed,
+            RenameOutcome::Renamed,
+            RenameOutcome::Renamed
+        ]
+    );
+
+    let swap = ws
+        .rename_entities(
+            [
+                ("light.desk", "light.desk_old"),
+                ("light.desk_old", "light.desk"),
+            ],
+            true,
+        )
+        .await?;
+    assert!(
+        swap.iter()
+            .all(|rename| matches!(rename.outcome, RenameOutcome::Rejected(_)))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_config() -> anyhow::Result<()> {
+    use crate::settings::{self, ClientConfig, RootCertificate};
+    use std::time::Duration;
+
+    // accepts connections but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/", listener.local_addr()?);
+
+    let client = ClientConfig::new()
+        .timeout(Duration::from_millis(100))
+        .danger_accept_invalid_certs(true)
+        .build(None)?;
+    let error = client.get(&url).send().await.unwrap_err();
+    assert!(error.is_timeout());
+
+    let supplied = reqwest::Client::builder().user_agent("supplied").build()?;
+    let config = ClientConfig::new()
+        .timeout(Duration::from_millis(1))
+        .client(supplied);
+    assert!(format!("{:?}", config.build(None)?).contains("supplied"));
+    drop(listener);
+
+    // the TLS options apply to WebSocket handshakes as well
+    let ca = RootCertificate::from_pem(include_bytes!("../tests/fixtures/tls/client.pem"))?;
+    assert!(RootCertificate::from_pem(b"not a certificate").is_err());
+    let timeout_only = ClientConfig::new().timeout(Duration::from_secs(1));
+    assert!(settings::build_tls_connector(Some(&timeout_only), None)?.is_none());
+    for config in [
+        ClientConfig::new().add_root_certificate(ca.clone()),
+        ClientConfig::new().danger_accept_invalid_certs(true),
+    ] {
+        config.build(None)?;
+        assert!(matches!(
+            settings::build_tls_connector(Some(&config), None)?,
+            Some(tokio_tungstenite::Connector::NativeTls(_))
+        ));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_config_setting() -> anyhow::Result<()> {
+    use crate::settings::{self, ClientConfig};
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// goes back to the default client even if an assertion fails
+    struct Clear;
+    impl Drop for Clear {
+        fn drop(&mut self) {
+            settings::clear_client_config();
+        }
+    }
+
+    let server = mock::MockServer::start().await;
+    server
+        .json(
+            "GET http://ha.invalid/api/config",
+            200,
+            json!({"version": "2025.6.0"}),
+        )
+        .json(
+            "POST http://ha.invalid/api/services/light/turn_on",
+            200,
+            json!([]),
+        );
+    // sends the body after the timeout
+    let slow = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let slow_url = format!("http://{}", slow.local_addr()?);
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = slow.accept().await {
+            tokio::spawn(async move {
+                let _ = stream.read(&mut [0; 4096]).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n")
+                    .await;
+                tokio::time::sleep(Duration::from_millis(1500)).await;
+                let _ = stream.write_all(b"done").await;
+            });
+        }
+    });
+
+    // only the made up hosts go through the proxies, other tests run in parallel
+    let proxy_url = server.url.clone();
+    settings::set_client_config(ClientConfig::new().timeout(Duration::from_secs(1)).proxy(
+        reqwest::Proxy::custom(move |url| match url.host_str() {
+            Some("ha.invalid") => Some(proxy_url.clone()),
+            Some("slow.invalid") => Some(slow_url.clone()),
+            _ => None,
+        }),
+    ))?;
+    let _clear = Clear;
+    let (url, token) = (
+        Some("http://ha.invalid".to_owned()),
+        Some("token".to_owned()),
+    );
+
+    assert_eq!(
+        hass().config(url.clone(), token.clone()).await?.version,
+        "2025.6.0"
+    );
+    hass()
+        .request()
+        .service(url, token.clone(), "light", "turn_on", json!({}), false)
+        .await?;
+    assert_eq!(server.requests().len(), 2);
+
+    // the timeout limits requests, but not downloads
+    let url = Some("http://slow.invalid".to_owned());
+    let error = hass()
+        .error_log(url.clone(), token.clone())
+        .await
+        .unwrap_err();
+    assert!(
+        error
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_timeout)
+    );
+    let mut log = Vec::new();
+    hass().download_error_log(url, token, &mut log).await?;
+    assert_eq!(log, b"done");
+    Ok(())
+}
+
+#[tokio::test]
+async fn default_headers() -> anyhow::Result<()> {
+    use crate::settings;
+
+    /// removes the headers even if an assertion fails
+    struct Clear;
+    impl Drop for Clear {
+        fn drop(&mut self) {
+            settings::clear_default_headers();
+        }
+    }
+
+    let server = mock::MockServer::start().await;
+    server.json(
+        "GET /api/config",
+        200,
+        serde_json::json!({"version": "2025.6.0"}),
+    );
+    let (url, token) = server.credentials();
+
+    settings::set_user_agent("my-app/1.0");
+    let _clear = Clear;
+    settings::set_default_header("CF-Access-Client-Id", "client_id.access")?;
+    settings::set_default_header("cf-access-client-id", "replaced.access")?;
+    assert!(settings::set_default_header("CF-Access-Client-Secret", "line\nbreak").is_err());
+
+    hass().config(url.clone(), token.clone()).await?;
+    let headers = server.last().headers;
+    assert_eq!(headers["user-agent"], "my-app/1.0");
+    assert_eq!(headers["cf-access-client-id"], "replaced.access");
+    assert_eq!(headers["authorization"], "Bearer token");
+
+    settings::clear_default_headers();
+    hass().config(url, token).await?;
+    let headers = server.last().headers;
+    assert!(!headers.contains_key("cf-access-client-id"));
+    assert_ne!(
+        headers.get("user-agent").map(String::as_str),
+        Some("my-app/1.0")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn mtls_identity() -> anyhow::Result<()> {
+    use crate::settings::{self, AuthProvider, ClientIdentity};
+    use futures_util::future::BoxFuture;
+    use reqwest::header::HeaderMap;
+
+    struct Certificate(ClientIdentity);
+    impl AuthProvider for Certificate {
+        fn headers(&self) -> BoxFuture<'_, anyhow::Result<HeaderMap>> {
+            Box::pin(async { Ok(HeaderMap::new()) })
+        }
+
+        fn identity(&self) -> Option<ClientIdentity> {
+            Some(self.0.clone())
+        }
+    }
+
+    /// removes the provider even if an assertion fails
+    struct Clear;
+    impl Drop for Clear {
+        fn drop(&mut self) {
+            settings::clear_auth_provider();
+        }
+    }
+
+    let certificate = include_bytes!("../tests/fixtures/tls/client.pem");
+    let key = include_bytes!("../tests/fixtures/tls/client.key");
+    assert!(ClientIdentity::from_pkcs8_pem(certificate, b"not a key").is_err());
+    let identity = ClientIdentity::from_pkcs8_pem(certificate, key)?;
+    assert!(settings::tls_connector()?.is_none());
+
+    settings::set_auth_provider(Certificate(identity))?;
+    let _clear = Clear;
+    assert!(matches!(
+        settings::tls_connector()?,
+        Some(tokio_tungstenite::Connector::NativeTls(_))
+    ));
+    // plain connections ignore the identity
+    let server = ws_mock::MockWebSocket::respond(|_| serde_json::Value::Null).await;
+    let (url, token) = server.credentials();
+    hass().websocket(url, token).await?;
+    Ok(())
+}
+
+/// types shared between tasks, e.g. as axum state
+#[test]
+fn public_types_are_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<crate::HomeAssistant>();
+    assert_send_sync::<crate::HomeAssistantPost>();
+    assert_send_sync::<crate::client::Client>();
+    assert_send_sync::<crate::client::ClientBuilder>();
+    assert_send_sync::<crate::client::ClientPost<'static>>();
+    assert_send_sync::<ws::HomeAssistantWs>();
+    assert_send_sync::<ws::ReconnectOptions>();
+    assert_send_sync::<ws::Limits>();
+    assert_send_sync::<ws::CommandError>();
+    assert_send_sync::<crate::templates::TemplateCache>();
+    assert_send_sync::<crate::storage::MemoryStorage>();
+    assert_send_sync::<crate::storage::FileStorage>();
+    assert_send_sync::<crate::cassette::Cassette>();
+    assert_send_sync::<crate::format::Formatter>();
+    assert_send_sync::<crate::events::EventCatalog>();
+    assert_send_sync::<crate::registry::Registries>();
+    assert_send_sync::<crate::settings::Settings>();
+    assert_send_sync::<crate::settings::MissingParameter>();
+    assert_send_sync::<crate::settings::ClientConfig>();
+    assert_send_sync::<crate::retry::RetryPolicy>();
+    assert_send_sync::<crate::services::ServiceCallError>();
+    assert_send_sync::<crate::secret::SecretString>();
+    assert_send_sync::<crate::stats::Stats>();
+    assert_send_sync::<crate::windowed::HistoryRange>();
+    assert_send_sync::<crate::windowed::LogbookRange>();
+    assert_send_sync::<crate::windowed::StatisticsRange>();
+    assert_send_sync::<structs::StatesResponse>();
+    assert_send_sync::<structs::Event>();
+    assert_send_sync::<structs::ConfigResponse>();
+    assert_send_sync::<ws::Subscription<structs::Event>>();
+    assert_send_sync::<ws::WithReconnects<structs::Event>>();
+    assert_send_sync::<ws::Sequenced<structs::Event>>();
+    assert_send_sync::<crate::mjpeg::MjpegStream>();
+    assert_send_sync::<crate::download::Download>();
+    assert_send_sync::<crate::orchestrate::Orchestrator>();
+    assert_send_sync::<crate::orchestrate::OrchestrationError>();
+    assert_send_sync::<crate::mjpeg::FrameReceiver<crate::mjpeg::Frame>>();
+    #[cfg(feature = "satellite")]
+    assert_send_sync::<crate::satellite::PipelineRun>();
+}
+
+#[tokio::test]
+async fn shared_subscriptions() -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        while let Some(message) = connection.recv().await {
+            connection.reply(&message, serde_json::Value::Null).await;
+            if message["type"] == "fire" {
+                let event = serde_json::json!({"event_type": "state_changed", "data": {}});
+                connection.event(&message["subscription"], event).await;
+            }
+        }
+    })
+    .await;
+    let sent = |command_type: &str| server.sent(command_type).len();
+
+    let client = client::Client::new(&server.url, "token")?;
+    let ws = client.shared_websocket().await?;
+    let mut first = ws.subscribe_events(Some("state_changed")).await?;
+    let mut second = client
+        .shared_websocket()
+        .await?
+        .subscribe_events(Some("state_changed"))
+        .await?;
+    let other = ws.subscribe_events(Some("call_service")).await?;
+    assert_eq!(first.id(), second.id());
+    assert_ne!(first.id(), other.id());
+    assert_eq!(sent("subscribe_events"), 2);
+
+    ws.send_command("fire", serde_json::json!({"subscription": first.id()}))
+        .await?;
+    assert_eq!(first.next().await.unwrap()?.event_type, "state_changed");
+    assert_eq!(second.next().await.unwrap()?.event_type, "state_changed");
+
+    drop(first);
+    ws.ping().await?;
+    assert_eq!(sent("unsubscribe_events"), 0);
+    drop(second);
+    ws.ping().await?;
+    assert_eq!(sent("unsubscribe_events"), 1);
+
+    drop(other);
+    Ok(())
+}
+
+/// the `simd-json` feature has to parse the same payloads into the same values
+#[test]
+fn decode_matches_serde_json() -> anyhow::Result<()> {
+    let payload = serde_json::json!([
+        {
+            "entity_id": "light.desk",
+            "state": "on",
+            "attributes": {"friendly_name": "Desk \u{e9}", "brightness": 255, "rgb_color": [255, 180, 107]},
+            "last_changed": "2025-07-01T12:00:00.123456+00:00",
+            "last_updated": "2025-07-01T12:00:00.123456+00:00",
+            "context": {"id": "01J1", "parent_id": null, "user_id": null},
+            "unknown_field": {"nested": 1.5e-3},
+        },
+        {"entity_id": "sensor.power", "state": "-12.25", "attributes": {}},
+    ])
+    .to_string();
+
+    let expected: Vec<structs::StatesResponse> = serde_json::from_str(&payload)?;
+    let decoded: Vec<structs::StatesResponse> =
+        crate::decode::from_slice(&mut payload.clone().into_bytes())?;
+    assert_eq!(decoded, expected);
+
+    let value: serde_json::Value = crate::decode::from_slice(&mut payload.into_bytes())?;
+    assert_eq!(value[1]["state"], "-12.25");
+    Ok(())
+}
+
+#[tokio::test]
+async fn orchestrator_compensates() -> anyhow::Result<()> {
+    use orchestrate::{Action, Cause, OrchestrationError, Orchestrator, Step};
+    use std::time::Duration;
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        let mut subscription = serde_json::Value::Null;
+        while let Some(message) = connection.recv().await {
+            match message["type"].as_str().unwrap() {
+                "call_service" if message["domain"] == "alarm_control_panel" => {
+                    connection
+                        .fail(&message, "home_assistant_error", "Window open")
+                        .await;
+                }
+                "get_states" => {
+                    let states = serde_json::json!([
+                        {"entity_id": "cover.kitchen", "state": "closing"},
+                        {"entity_id": "cover.garage", "state": "open"},
+                    ]);
+                    connection.reply(&message, states).await;
+                    let event = serde_json::json!({
+                        "event_type": "state_changed",
+                        "data": {"entity_id": "cover.kitchen", "new_state": {"entity_id": "cover.kitchen", "state": "closed"}},
+                    });
+                    connection.event(&subscription, event).await;
+                }
+                kind => {
+                    if kind == "subscribe_events" {
+                        subscription = message["id"].clone();
+                    }
+                    connection.reply(&message, serde_json::Value::Null).await;
+                }
+            }
+        }
+    })
+    .await;
+    let calls = || -> Vec<String> {
+        server
+            .sent("call_service")
+            .iter()
+            .map(|call| {
+                format!(
+                    "{}.{}",
+                    call["domain"].as_str().unwrap(),
+                    call["service"].as_str().unwrap()
+                )
+            })
+            .collect()
+    };
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let covers = serde_json::json!({"entity_id": "cover.kitchen"});
+    let error = Orchestrator::new(&ws)
+        .step(Step::snapshot(&["cover.kitchen"]))
+        .step(
+            Step::call("cover", "close_cover", covers.clone()).compensate(Action::new(
+                "cover",
+                "open_cover",
+                covers,
+            )),
+        )
+        .step(
+            Step::all([Step::wait_state("cover.kitchen", "closed")])
+                .timeout(Duration::from_secs(5)),
+        )
+        .step(Step::call(
+            "alarm_control_panel",
+            "alarm_arm_away",
+            serde_json::json!({}),
+        ))
+        .run()
+        .await
+        .unwrap_err();
+    let error = error.downcast::<OrchestrationError>()?;
+    assert_eq!(error.step, "alarm_control_panel.alarm_arm_away");
+    assert!(matches!(&error.cause, Cause::Failed(e) if e.to_string().contains("Window open")));
+    assert_eq!(error.compensated, 2);
+    assert!(error.compensation_failures.is_empty());
+    assert_eq!(
+        calls(),
+        [
+            "scene.create",
+            "cover.close_cover",
+            "alarm_control_panel.alarm_arm_away",
+            "cover.open_cover",
+            "scene.turn_on",
+            "scene.delete",
+        ]
+    );
+
+    let called = calls().len();
+    let garage = || Step::wait_state("cover.garage", "closed").label("close garage");
+    let error = Orchestrator::new(&ws)
+        .step(garage().timeout(Duration::from_millis(50)))
+        .run()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "step `close garage` timed out after 50ms"
+    );
+    let error = Orchestrator::new(&ws)
+        .step(
+            Step::call("light", "turn_on", serde_json::json!({})).compensate(Action::new(
+                "light",
+                "turn_off",
+                serde_json::json!({}),
+            )),
+        )
+        .step(garage())
+        .run_until(tokio::time::sleep(Duration::from_millis(50)))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref(),
+        Some(OrchestrationError {
+            cause: Cause::Cancelled,
+            ..
+        })
+    ));
+    assert_eq!(calls()[called..], ["light.turn_on", "light.turn_off"]);
+    Ok(())
+}
+
+#[test]
+fn sse_parser() {
+    let mut parser = sse::SseParser::default();
+    assert!(parser.feed(b"data: pi").is_empty());
+    assert_eq!(parser.feed(b"ng\r\n\r\n: comment\n\n"), ["ping"]);
+    assert_eq!(
+        parser.feed(b"event: message\ndata: {\"a\":\ndata:1}\n\ndata: {}\n\n"),
+        ["{\"a\":\n1}", "{}"]
+    );
+}
+
+#[tokio::test]
+async fn core_config_update() -> anyhow::Result<()> {
+    use structs::{CoreConfigUpdate, UnitSystemName};
+
+    let server = ws_mock::MockWebSocket::respond(|_| serde_json::Value::Null).await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    ws.update_core_config(CoreConfigUpdate {
+        location_name: Some("Cabin".to_owned()),
+        latitude: Some(61.5),
+        unit_system: Some(UnitSystemName::UsCustomary),
+        time_zone: Some("Europe/Helsinki".to_owned()),
+        ..Default::default()
+    })
+    .await?;
+    // unset fields are left out and keep their values
+    assert_eq!(
+        server.last("config/core/update"),
+        serde_json::json!({
+            "type": "config/core/update",
+            "location_name": "Cabin",
+            "latitude": 61.5,
+            "unit_system": "us_customary",
+            "time_zone": "Europe/Helsinki",
+        })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn zone_crud() -> anyhow::Result<()> {
+    use crate::zones::ZoneInput;
+
+    let server = ws_mock::MockWebSocket::start(|mut connection| async move {
+        while let Some(message) = connection.recv().await {
+            let zone = |id: &str| {
+                let mut zone = message.clone();
+                zone["id"] = id.into();
+                zone
+            };
+            match message["type"].as_str().unwrap() {
+                "zone/list" => {
+                    let zones = serde_json::json!([
+                        {"id": "work", "name": "Work", "latitude": 52.5, "longitude": 13.4, "radius": 250.0},
+                    ]);
+                    connection.reply(&message, zones).await
+                }
+                "zone/create" => connection.reply(&message, zone("gym")).await,
+                "zone/update" => {
+                    let zone = zone(message["zone_id"].as_str().unwrap());
+                    connection.reply(&message, zone).await
+                }
+                "zone/delete" if message["zone_id"] == "home" => {
+                    connection
+                        .fail(&message, "not_found", "Unable to find zone_id home")
+                        .await
+                }
+                _ => connection.reply(&message, serde_json::Value::Null).await,
+            }
+        }
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let zones = ws.zones().await?;
+    assert_eq!(
+        (zones[0].id.as_str(), zones[0].radius),
+        ("work", Some(250.0))
+    );
+    assert!(!zones[0].passive);
+
+    let gym = ZoneInput {
+        name: "Gym".to_owned(),
+        latitude: 52.52,
+        longitude: 13.41,
+        passive: Some(true),
+        ..Default::default()
+    };
+    let created = ws.create_zone(gym.clone()).await?;
+    assert_eq!(
+        server.last("zone/create"),
+        serde_json::json!({"type": "zone/create", "name": "Gym", "latitude": 52.52, "longitude": 13.41, "passive": true})
+    );
+    assert_eq!((created.id.as_str(), created.passive), ("gym", true));
+
+    let updated = ws
+        .update_zone(
+            "gym",
+            ZoneInput {
+                radius: Some(50.0),
+                ..gym
+            },
+        )
+        .await?;
+    assert_eq!(server.last("zone/update")["zone_id"], "gym");
+    assert_eq!(updated.radius, Some(50.0));
+
+    ws.delete_zone("gym").await?;
+    assert_eq!(
+        server.last("zone/delete"),
+        serde_json::json!({"type": "zone/delete", "zone_id": "gym"})
+    );
+    let error = ws.delete_zone("home").await.unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<ws::CommandError>().unwrap().code,
+        "not_found"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn area_assignment() -> anyhow::Result<()> {
+    let server = ws_mock::MockWebSocket::respond(|message| match message["type"].as_str().unwrap() {
+        "config/area_registry/create" => {
+            serde_json::json!({"area_id": "garden", "name": message["name"]})
+        }
+        "config/entity_registry/update" => serde_json::json!({"entity_entry": {
+            "entity_id": message["entity_id"], "platform": "hue", "area_id": message["area_id"],
+        }}),
+        "config/device_registry/update" => {
+            serde_json::json!({"id": message["device_id"], "area_id": message["area_id"]})
+        }
+        "config/entity_registry/list" => {
+            serde_json::json!([{"entity_id": "light.porch", "platform": "hue", "device_id": "d1"}])
+        }
+        "config/device_registry/list" => serde_json::json!([{"id": "d1"}]),
+        "get_states" => serde_json::json!([{"entity_id": "light.porch", "state": "on"}]),
+        _ => serde_json::json!([]),
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let area = ws.create_area("Garden").await?;
+    assert_eq!(
+        (area.area_id.as_str(), area.name.as_str()),
+        ("garden", "Garden")
+    );
+    assert!(ws.states_in_area("garden").await?.is_empty());
+
+    let entity = ws.assign_entity_area("light.porch", Some("garden")).await?;
+    assert_eq!(entity.area_id.as_deref(), Some("garden"));
+    assert_eq!(
+        server.last("config/entity_registry/update"),
+        serde_json::json!({"type": "config/entity_registry/update", "entity_id": "light.porch", "area_id": "garden"})
+    );
+    // removing sends a null area
+    ws.assign_entity_area("light.porch", None).await?;
+    assert_eq!(
+        server.last("config/entity_registry/update")["area_id"],
+        serde_json::Value::Null
+    );
+
+    let device = ws.assign_device_area("d1", Some("garden")).await?;
+    assert_eq!(device.area_id.as_deref(), Some("garden"));
+    assert_eq!(
+        server.last("config/device_registry/update"),
+        serde_json::json!({"type": "config/device_registry/update", "device_id": "d1", "area_id": "garden"})
+    );
+    // assignments drop the cached areas
+    ws.states_in_area("garden").await?;
+    assert_eq!(server.sent("config/entity_registry/list").len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn entity_registry_updates() -> anyhow::Result<()> {
+    use registry::EntityRegistryUpdate;
+
+    // answers with the entry as it would look after the update
+    let server = ws_mock::MockWebSocket::respond(|message| {
+        let mut entry = serde_json::json!({"entity_id": message["entity_id"], "platform": "zha"});
+        if let Some(new_entity_id) = message.get("new_entity_id") {
+            entry["entity_id"] = new_entity_id.clone();
+        }
+        for field in ["name", "icon", "disabled_by", "hidden_by"] {
+            if let Some(value) = message.get(field) {
+                entry[field] = value.clone();
+            }
+        }
+        serde_json::json!({"entity_entry": entry})
+    })
+    .await;
+    let (url, token) = server.credentials();
+    let sent = || {
+        let mut command = server.last("config/entity_registry/update");
+        command.as_object_mut().unwrap().remove("type");
+        command
+    };
+
+    let ws = hass().websocket(url, token).await?;
+    let entry = ws
+        .rename_entity_id("sensor.0x00158d_temperature", "sensor.attic_temperature")
+        .await?;
+    assert_eq!(entry.entity_id.0, "sensor.attic_temperature");
+    assert_eq!(
+        sent(),
+        serde_json::json!({"entity_id": "sensor.0x00158d_temperature", "new_entity_id": "sensor.attic_temperature"})
+    );
+
+    let entry = ws
+        .set_entity_name("sensor.attic_temperature", Some("Attic"))
+        .await?;
+    assert_eq!(entry.name.as_deref(), Some("Attic"));
+    // None restores the name of the integration, sent as null rather than left out
+    ws.set_entity_name("sensor.attic_temperature", None).await?;
+    assert_eq!(
+        sent(),
+        serde_json::json!({"entity_id": "sensor.attic_temperature", "name": null})
+    );
+    ws.set_entity_icon("sensor.attic_temperature", Some("mdi:home-roof"))
+        .await?;
+    assert_eq!(sent()["icon"], "mdi:home-roof");
+
+    assert!(
+        ws.set_entity_disabled("sensor.attic_temperature", true)
+            .await?
+            .is_disabled()
+    );
+    assert_eq!(sent()["disabled_by"], "user");
+    assert!(
+        !ws.set_entity_disabled("sensor.attic_temperature", false)
+            .await?
+            .is_disabled()
+    );
+    assert_eq!(sent()["disabled_by"], serde_json::Value::Null);
+    assert!(
+        ws.set_entity_hidden("sensor.attic_temperature", true)
+            .await?
+            .is_hidden()
+    );
+    assert_eq!(sent()["hidden_by"], "user");
+
+    // unset fields are left out
+    ws.update_entity(
+        "sensor.attic_temperature",
+        EntityRegistryUpdate {
+            name: Some(Some("Attic".to_owned())),
+            icon: Some(None),
+            ..Default::default()
+        },
+    )
+    .await?;
+    assert_eq!(
+        sent(),
+        serde_json::json!({"entity_id": "sensor.attic_temperature", "name": "Attic", "icon": null})
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn device_automation_lists() -> anyhow::Result<()> {
+    use crate::device_automation::DeviceAutomationKind;
+
+    let server = ws_mock::MockWebSocket::respond(|message| match message["type"].as_str().unwrap() {
+        "device_automation/trigger/list" => serde_json::json!([{
+            "device_id": message["device_id"], "domain": "zha", "platform": "device",
+            "type": "remote_button_short_press", "subtype": "turn_on", "metadata": {},
+        }]),
+        "device_automation/condition/list" => serde_json::json!([{
+            "device_id": message["device_id"], "domain": "light", "entity_id": "light.desk",
+            "condition": "device", "type": "is_on",
+        }]),
+        "device_automation/action/list" => serde_json::json!([{
+            "device_id": message["device_id"], "domain": "light", "entity_id": "light.desk",
+            "type": "brightness_increase",
+        }]),
+        "device_automation/trigger/capabilities" => serde_json::json!({
+            "extra_fields": [{"name": "for", "optional": true, "type": "positive_time_period_dict"}],
+        }),
+        _ => serde_json::Value::Null,
+    })
+    .await;
+    let (url, token) = server.credentials();
+
+    let ws = hass().websocket(url, token).await?;
+    let triggers = ws.device_triggers("d1").await?;
+    assert_eq!(
+        server.last("device_automation/trigger/list"),
+        serde_json::json!({"type": "device_automation/trigger/list", "device_id": "d1"})
+    );
+    assert_eq!(triggers[0].kind, "remote_button_short_press");
+    assert_eq!(triggers[0].platform.as_deref(), Some("device"));
+
+    let conditions = ws.device_conditions("d1").await?;
+    assert_eq!(
+        (
+            conditions[0].kind.as_str(),
+            conditions[0].condition.as_deref()
+        ),
+        ("is_on", Some("device"))
+    );
+    let actions = ws.device_actions("d1").await?;
+    assert_eq!(actions[0].entity_id.as_deref(), Some("light.desk"));
+
+    // the listed automations are sent back as they are
+    let capabilities = ws
+        .device_automation_capabilities(DeviceAutomationKind::Trigger, &triggers[0])
+        .await?;
+    assert_eq!(capabilities.extra_fields[0]["name"], "for");
+    assert_eq!(
+        server.last("device_automation/trigger/capabilities")["trigger"],
+        serde_json::json!({
+            "device_id": "d1", "domain": "zha", "platform": "device",
+            "type": "remote_button_short_press", "subtype": "turn_on", "metadata": {},
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,1302 @@
+//! REST endpoints against a [`MockServer`], one test per endpoint
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use super::mock::MockServer;
+use crate::services::ServiceCallError;
+use crate::{hass, structs};
+
+#[tokio::test]
+async fn config() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.json(
+        "GET /api/config",
+        200,
+        json!({"version": "2025.6.0", "location_name": "Home", "unit_system": {"temperature": "°C"}, "components": ["hue"]}),
+    );
+    let (url, token) = server.credentials();
+
+    let config = hass().config(url, token).await?;
+    assert_eq!(config.version, "2025.6.0");
+    assert_eq!(config.unit_system.temperature, "°C");
+    assert!(config.has_component("hue"));
+    assert_eq!(
+        server
+            .last()
+            .headers
+            .get("authorization")
+            .map(String::as_str),
+        Some("Bearer token")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn client() -> anyhow::Result<()> {
+    use crate::client::Client;
+
+    let server = MockServer::start().await;
+    server
+        .json("GET /api/config", 200, json!({"version": "2025.6.0"}))
+        .json(
+            "POST /api/services/light/turn_on",
+            200,
+            json!([{"entity_id": "light.kitchen", "state": "on"}]),
+        );
+    let (url, _) = server.credentials();
+
+    let client = Client::new(format!("{}/", url.unwrap()), "s3cr3t")?;
+    assert!(!format!("{client:?}").contains("s3cr3t"));
+    assert_eq!(client.config().await?.version, "2025.6.0");
+    assert_eq!(
+        server
+            .last()
+            .headers
+            .get("authorization")
+            .map(String::as_str),
+        Some("Bearer s3cr3t")
+    );
+
+    client
+        .request()
+        .service(
+            "light",
+            "turn_on",
+            json!({"entity_id": "light.kitchen"}),
+            false,
+        )
+        .await?;
+    assert_eq!(server.last().path, "/api/services/light/turn_on");
+    Ok(())
+}
+
+#[tokio::test]
+async fn unauthorized() {
+    let server = MockServer::start().await;
+    server.text("GET /api/config", 401, "401: Unauthorized");
+    let (url, token) = server.credentials();
+
+    let error = hass().config(url, token).await.unwrap_err();
+    assert!(error.to_string().contains("401"));
+}
+
+#[tokio::test]
+async fn wait_until_ready() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.text("GET /api/", 502, "Bad Gateway");
+    let (url, token) = server.credentials();
+
+    let error = hass()
+        .wait_until_ready(url.clone(), token.clone(), Duration::from_millis(100))
+        .await
+        .unwrap_err();
+    assert!(format!("{error:#}").contains("502"), "{error:#}");
+
+    let (ready_url, ready_token) = (url.clone(), token.clone());
+    let ready = tokio::spawn(async move {
+        hass()
+            .wait_until_ready(ready_url, ready_token, Duration::from_secs(10))
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    server
+        .json("GET /api/", 200, json!({"message": "API running."}))
+        .json("GET /api/core/state", 200, json!({"state": "STARTING"}));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!ready.is_finished());
+    server.json("GET /api/core/state", 200, json!({"state": "RUNNING"}));
+    assert_eq!(ready.await??.state, "RUNNING");
+
+    server.text("GET /api/", 401, "401: Unauthorized");
+    let error = hass()
+        .wait_until_ready(url, token, Duration::from_secs(10))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("401"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn instance_info() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server
+        .json(
+            "GET /api/config",
+            200,
+            json!({"version": "2025.6.0", "components": ["light", "light.hue", "hue"]}),
+        )
+        .json(
+            "GET /api/states",
+            200,
+            json!([
+                {"entity_id": "light.kitchen", "state": "on"},
+                {"entity_id": "light.hallway", "state": "off"},
+                {"entity_id": "sun.sun", "state": "above_horizon"},
+            ]),
+        )
+        .json("GET /api/core/state", 200, json!({"state": "RUNNING"}));
+    let (url, token) = server.credentials();
+
+    let info = hass().instance_info(url.clone(), token.clone()).await?;
+    assert_eq!(
+        info.core_state.map(|state| state.state).as_deref(),
+        Some("RUNNING")
+    );
+    assert_eq!(info.components, ["hue", "light"]);
+    assert_eq!(info.entity_count, 3);
+    assert_eq!(info.entities_per_domain["light"], 2);
+
+    server.text("GET /api/core/state", 404, "404: Not Found");
+    let info = hass().instance_info(url.clone(), token.clone()).await?;
+    assert!(info.core_state.is_none());
+
+    server.text("GET /api/core/state", 500, "500 Internal Server Error");
+    let error = hass().instance_info(url, token).await.unwrap_err();
+    assert!(error.to_string().contains("500"), "{error:#}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn events() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.json(
+        "GET /api/events",
+        200,
+        json!([{"event": "state_changed", "listener_count": 5}]),
+    );
+    let (url, token) = server.credentials();
+
+    let events = hass().events(url, token).await?;
+    assert_eq!(events[0].event, "state_changed");
+    assert_eq!(events[0].listener_count, 5);
+    Ok(())
+}
+
+#[tokio::test]
+async fn services() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.json(
+        "GET /api/services",
+        200,
+        json!([
+            {"domain": "light", "services": {"turn_on": {}, "turn_off": {}}},
+            {"domain": "weather", "services": {"get_forecasts": {
+                "name": "Get forecasts",
+                "fields": {
+                    "type": {"required": true, "selector": {"select": {"options": ["daily", "hourly"]}}},
+                    "advanced_fields": {"collapsed": true, "fields": {"limit": {"selector": {"number": {}}}}},
+                },
+                "target": {"entity": [{"domain": ["weather"]}]},
+                "response": {"optional": false},
+            }}},
+        ]),
+    );
+    let (url, token) = server.credentials();
+
+    let services = hass().services(url, token).await?;
+    assert_eq!(services[0].domain, "light");
+    let turn_on = &services[0].services["turn_on"];
+    assert!(turn_on.target.is_none() && turn_on.response.is_none());
+
+    let forecasts = &services[1].services["get_forecasts"];
+    assert_eq!(forecasts.name, "Get forecasts");
+    assert_eq!(forecasts.required_fields(), ["type"]);
+    let fields: Vec<&str> = forecasts
+        .all_fields()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(fields, ["limit", "type"]);
+    assert_eq!(
+        forecasts.target.as_ref().unwrap().entity[0].domain,
+        ["weather"]
+    );
+    assert!(!forecasts.response.unwrap().optional);
+    Ok(())
+}
+
+#[tokio::test]
+async fn history() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.json(
+        "GET /api/history/period",
+        200,
+        json!([
+            [{"entity_id": "light.desk", "state": "on", "last_changed": "2025-01-01T00:00:00+00:00"}],
+            [{"entity_id": "light.couch", "state": "off", "last_changed": "2025-01-01T00:00:00+00:00"}],
+        ]),
+    );
+    let (url, token) = server.credentials();
+
+    let history = hass()
+        .history(url, token, Some("light.desk"), true, true, false)
+        .await?;
+    assert_eq!(history.len(), 2);
+    assert_eq!(
+        server.last().path,
+        "/api/history/period?filter_entity_id=light.desk&minimal_response&no_attributes"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn logbook() -> anyhow::Result<()> {
+    use chrono::{FixedOffset, TimeZone, Utc};
+
+    let server = MockServer::start().await;
+    server.json(
+        "GET /api/logbook",
+        200,
+        json!([{"name": "Desk", "message": "turned on", "entity_id": "light.desk", "when": "2025-01-01T00:00:00+00:00"}]),
+    );
+    let (url, token) = server.credentials();
+
+    let logbook = hass()
+        .logbook(url.clone(), token.clone(), Some("light.desk"), None, None)
+        .await?;
+    assert_eq!(logbook[0].message.as_deref(), Some("turned on"));
+    assert_eq!(server.last().path, "/api/logbook?entity=light.desk");
+
+    server.json("GET /api/logbook/2025-01-01T00:00:00+00:00", 200, json!([]));
+    hass()
+        .logbook(
+            url,
+            token,
+            Some("light.desk"),
+            Some(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap()),
+            Some(
+                FixedOffset::east_opt(3600)
+                    .unwrap()
+                    .with_ymd_and_hms(2025, 1, 2, 1, 0, 0)
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+        )
+        .await?;
+    assert_eq!(
+        server.last().path,
+        "/api/logbook/2025-01-01T00:00:00+00:00?entity=light.desk&end_time=2025-01-02T00%3A00%3A00%2B00%3A00"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn states() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server
+        .json(
+            "GET /api/states",
+            200,
+            json!([{"entity_id": "light.desk", "state": "on"}, {"entity_id": "sun.sun", "state": "above_horizon"}]),
+        )
+        .json(
+            "GET /api/states/light.desk",
+            200,
+            json!({"entity_id": "light.desk", "state": "on", "attributes": {"friendly_name": "Desk"}}),
+        );
+    let (url, token) = server.credentials();
+
+    assert_eq!(
+        hass().states(url.clone(), token.clone(), None).await?.len(),
+        2
+    );
+    let desk = hass().states(url, token, Some("light.desk")).await?;
+    assert_eq!(
+        desk[0]
+            .attributes
+            .as_ref()
+            .and_then(|a| a.friendly_name.as_deref()),
+        Some("Desk")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn post_state() -> anyhow::Result<()> {
+    let state = json!({
+        "entity_id": "sensor.outside",
+        "state": "21.5",
+        "attributes": {"friendly_name": "Outside", "unit_of_measurement": "°C", "device_class": "temperature"},
+        "last_changed": "2025-07-01T12:00:00+00:00",
+        "last_reported": "2025-07-01T12:00:00+00:00",
+        "last_updated": "2025-07-01T12:00:00+00:00",
+        "context": {"id": "01J1", "parent_id": null, "user_id": null},
+    });
+    let server = MockServer::start().await;
+    server
+        .json("GET /api/states/sensor.outside", 200, state.clone())
+        .json("POST /api/states/sensor.outside", 200, state.clone());
+    let (url, token) = server.credentials();
+
+    let current = hass()
+        .states(url.clone(), token.clone(), Some("sensor.outside"))
+        .await?
+        .remove(0);
+    assert_eq!(serde_json::to_value(&current)?, state);
+
+    let mut request = current.into_request();
+    request.state = "22.0".to_owned();
+    hass()
+        .request()
+        .state(url, token, "sensor.outside", request)
+        .await?;
+    assert_eq!(
+        server.last().json(),
+        json!({
+            "state": "22.0",
+            "attributes": {"friendly_name": "Outside", "unit_of_measurement": "°C", "device_class": "temperature"},
+        })
+    );
+
+    let attributes = structs::Attributes {
+        friendly_name: Some("Outside".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        serde_json::to_value(attributes)?,
+        json!({"friendly_name": "Outside"})
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn event_stream() -> anyhow::Result<()> {
+    use futures_util::StreamExt;
+
+    let event = json!({
+        "event_type": "state_changed",
+        "data": {"entity_id": "light.desk", "new_state": {"entity_id": "light.desk", "state": "on"}},
+        "origin": "LOCAL",
+    });
+    let server = MockServer::start().await;
+    server.text(
+        "GET /api/stream",
+        200,
+        &format!("data: ping\n\ndata: {event}\n\n"),
+    );
+    let (url, token) = server.credentials();
+
+    let mut events = hass()
+        .event_stream(url, token, Some(&["state_changed", "call_service"]))
+        .await?;
+    let change = events.next().await.unwrap()?.state_changed().unwrap();
+    assert_eq!(change.new_state.unwrap().state, "on");
+    // the mock closes the connection after every response
+    assert_eq!(events.next().await.unwrap()?.event_type, "state_changed");
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[1].path,
+        "/api/stream?restrict=state_changed%2Ccall_service"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn fire_event() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.json(
+        "POST /api/events/tag_scanned",
+        200,
+        json!({"message": "Event tag_scanned fired."}),
+    );
+    let (url, token) = server.credentials();
+
+    let response = hass()
+        .request()
+        .events(url, token, "tag_scanned", json!({"tag_id": "abc"}))
+        .await?;
+    assert_eq!(response.message, "Event tag_scanned fired.");
+    assert_eq!(server.last().json(), json!({"tag_id": "abc"}));
+    Ok(())
+}
+
+#[tokio::test]
+async fn call_service() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server
+        .json(
+            "POST /api/services/weather/get_forecasts",
+            200,
+            json!({"changed_states": [], "service_response": {"weather.home": {"forecast": []}}}),
+        )
+        .text("POST /api/services/light/turn_on", 400, "400: Bad Request");
+    let (url, token) = server.credentials();
+
+    let response = hass()
+        .request()
+        .service(
+            url.clone(),
+            token.clone(),
+            "weather",
+            "get_forecasts",
+            json!({"type": "daily"}),
+            true,
+        )
+        .await?;
+    assert!(response["service_response"]["weather.home"].is_object());
+    assert_eq!(
+        server.last().path,
+        "/api/services/weather/get_forecasts?return_response"
+    );
+
+    let error = hass()
+        .request()
+        .service(
+            url,
+            token,
+            "light",
+            "turn_on",
+            json!({"brightness": "max"}),
+            false,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<ServiceCallError>(),
+        Some(ServiceCallError::InvalidFormat { field: None, .. })
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn typed_service_responses() -> anyhow::Result<()> {
+    use crate::responses::{Forecasts, KnownResponse, TodoItems, TodoStatus};
+
+    let server = MockServer::start().await;
+    server
+        .json(
+            "POST /api/services/weather/get_forecasts",
+            200,
+            json!({"changed_states": [], "service_response": {"weather.home": {"forecast": [
+                {"datetime": "2025-06-01T00:00:00+00:00", "condition": "sunny", "temperature": 24.5, "templow": 12, "uv_index": 6}
+            ]}}}),
+        )
+        .json(
+            "POST /api/services/todo/get_items",
+            200,
+            json!({"changed_states": [], "service_response": {"todo.shopping": {"items": [
+                {"summary": "milk", "uid": "1", "status": "needs_action"},
+                {"summary": "bread", "uid": "2", "status": "completed"}
+            ]}}}),
+        )
+        .json(
+            "POST /api/services/calendar/get_events",
+            200,
+            json!({"changed_states": []}),
+        );
+    let (url, token) = server.credentials();
+
+    let forecasts: Forecasts = hass()
+        .request()
+        .service_typed(
+            url.clone(),
+            token.clone(),
+            json!({"entity_id": "weather.home", "type": "daily"}),
+        )
+        .await?;
+    let forecast = &forecasts["weather.home"].forecast[0];
+    assert_eq!(forecast.condition.as_deref(), Some("sunny"));
+    assert_eq!(forecast.templow, Some(12.0));
+    assert_eq!(forecast.other["uv_index"], 6);
+    assert_eq!(
+        server.last().path,
+        "/api/services/weather/get_forecasts?return_response"
+    );
+
+    let items: TodoItems = hass()
+        .request()
+        .service_typed(
+            url.clone(),
+            token.clone(),
+            json!({"entity_id": "todo.shopping"}),
+        )
+        .await?;
+    let open: Vec<_> = items["todo.shopping"]
+        .items
+        .iter()
+        .filter(|item| item.status == TodoStatus::NeedsAction)
+        .map(|item| item.summary.as_str())
+        .collect();
+    assert_eq!(open, ["milk"]);
+
+    // the same CalendarEvent as calendar_events, with plain strings as times
+    server.once_json(
+        "POST /api/services/calendar/get_events",
+        200,
+        &[],
+        Some(json!({"changed_states": [], "service_response": {"calendar.holidays": {"events": [
+            {"summary": "Summer break", "start": "2025-07-01", "end": "2025-07-15"},
+            {"summary": "Dentist", "start": "2025-07-03T10:00:00+02:00", "end": "2025-07-03T10:30:00+02:00"}
+        ]}}})),
+    );
+    let events: crate::responses::CalendarEvents = hass()
+        .request()
+        .service_typed(url.clone(), token.clone(), json!({}))
+        .await?;
+    let events = &events["calendar.holidays"].events;
+    assert!(events[0].is_all_day());
+    assert_eq!(events[0].end.date().to_string(), "2025-07-15");
+    assert!(!events[1].is_all_day());
+    assert_eq!(events[1].start.date().to_string(), "2025-07-03");
+
+    let error = hass()
+        .request()
+        .service_typed::<crate::responses::CalendarEvents>(url, token, json!({}))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "calendar.get_events returned no response"
+    );
+
+    let conversation = json!({
+        "response": {"response_type": "action_done", "language": "en", "speech": {"plain": {"speech": "Turned on the light", "extra_data": null}}, "data": {}},
+        "conversation_id": "01J"
+    });
+    let Some(KnownResponse::Conversation(result)) =
+        KnownResponse::parse("conversation", "process", conversation)?
+    else {
+        panic!("conversation.process is known");
+    };
+    assert_eq!(result.speech(), Some("Turned on the light"));
+    assert!(KnownResponse::parse("light", "turn_on", json!({}))?.is_none());
+    assert!(KnownResponse::parse("todo", "get_items", json!([])).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn scenes() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server
+        .json(
+            "GET /api/states",
+            200,
+            json!([
+                {"entity_id": "scene.movie_night", "state": "2025-06-01T20:15:00.000000+00:00", "attributes": {
+                    "entity_id": ["light.living_room", "light.kitchen"], "id": "1717", "friendly_name": "Movie night"
+                }},
+                {"entity_id": "scene.morning", "state": "unknown", "attributes": {"entity_id": ["light.bedroom"]}},
+                {"entity_id": "light.kitchen", "state": "on"},
+            ]),
+        )
+        .json(
+            "GET /api/config/scene/config/1717",
+            200,
+            json!({"id": "1717", "name": "Movie night", "entities": {
+                "light.living_room": {"state": "on", "brightness": 40},
+                "light.kitchen": {"state": "off"},
+            }}),
+        );
+    let (url, token) = server.credentials();
+
+    let scenes = hass().scenes(url.clone(), token.clone()).await?;
+    assert_eq!(scenes.len(), 2);
+    assert_eq!(scenes[0].entity_id, "scene.morning");
+    assert_eq!(scenes[0].last_activated, None);
+    let movie = &scenes[1];
+    assert_eq!(movie.id.as_deref(), Some("1717"));
+    assert!(movie.contains("light.kitchen"));
+    assert_eq!(
+        movie.last_activated.map(|at| at.to_rfc3339()).as_deref(),
+        Some("2025-06-01T20:15:00+00:00")
+    );
+
+    let scene = hass().scene_config(url, token, "1717").await?;
+    let subset = scene.subset(&["light.living_room"])?;
+    assert_eq!(subset.entities.len(), 1);
+    assert_eq!(
+        subset.entities["light.living_room"].attributes["brightness"],
+        40
+    );
+    assert!(scene.subset(&["light.garage"]).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn customize_yaml() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server
+        .json(
+            "GET /api/config/customize/config/sensor.outside",
+            200,
+            json!({"local": {"friendly_name": "Outside"}, "global": {"icon": "mdi:thermometer"}}),
+        )
+        .json(
+            "POST /api/config/customize/config/sensor.outside",
+            200,
+            json!({"result": "ok"}),
+        );
+    let (url, token) = server.credentials();
+
+    let customize = hass()
+        .customize_yaml(url.clone(), token.clone(), "sensor.outside")
+        .await?;
+    assert_eq!(customize.local["friendly_name"], "Outside");
+    assert_eq!(customize.global["icon"], "mdi:thermometer");
+
+    let mut attributes = customize.local;
+    attributes.insert("icon".to_owned(), json!("mdi:weather-sunny"));
+    hass()
+        .request()
+        .save_customize_yaml(url, token, "sensor.outside", &attributes)
+        .await?;
+    assert_eq!(
+        server.last().json(),
+        json!({"friendly_name": "Outside", "icon": "mdi:weather-sunny"})
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn history_query() -> anyhow::Result<()> {
+    use crate::history::HistoryQuery;
+    use chrono::TimeZone;
+
+    let server = MockServer::start().await;
+    server.json(
+        "GET /api/history/period/2025-06-01T00:00:00+00:00",
+        200,
+        json!([
+            [
+                {"entity_id": "sensor.outside", "state": "18.5", "last_changed": "2025-06-01T00:00:00+00:00"},
+                {"state": "19.0", "last_changed": "2025-06-01T01:00:00+00:00"},
+            ],
+            [{"entity_id": "sensor.inside", "state": "21.0", "last_changed": "2025-06-01T00:00:00+00:00"}],
+        ]),
+    );
+    let (url, token) = server.credentials();
+
+    let start = chrono::Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap();
+    let query = HistoryQuery::new()
+        .start(start)
+        .end(start + chrono::TimeDelta::hours(6))
+        .entities(["sensor.outside", "sensor.inside"])
+        .minimal_response();
+    let history = hass().history_query(url, token, &query).await?;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].len(), 2);
+    assert_eq!(history[1][0].entity_id.as_deref(), Some("sensor.inside"));
+    assert_eq!(
+        server.last().path,
+        "/api/history/period/2025-06-01T00:00:00+00:00?end_time=2025-06-01T06%3A00%3A00%2B00%3A00&filter_entity_id=sensor.outside%2Csensor.inside&minimal_response"
+    );
+
+    // attributes outside the whitelist are dropped, the history stays grouped
+    server.json(
+        "GET /api/history/period",
+        200,
+        json!([[{"entity_id": "media_player.tv", "state": "playing", "last_changed": "2025-06-01T00:00:00+00:00",
+                 "attributes": {"volume_level": 0.4, "entity_picture": "/api/media_player_proxy/x"}}]]),
+    );
+    let query = HistoryQuery::new()
+        .entity("media_player.tv")
+        .attributes(["volume_level"]);
+    let history = hass()
+        .history_query(Some(server.url.clone()), Some("token".to_owned()), &query)
+        .await?;
+    assert_eq!(
+        history[0][0].attributes.as_ref().unwrap().other_fields,
+        json!({"volume_level": 0.4})
+    );
+
+    assert_eq!(HistoryQuery::new().path()?, "/api/history/period");
+    assert!(
+        HistoryQuery::new()
+            .start(start)
+            .end(start - chrono::TimeDelta::hours(1))
+            .path()
+            .is_err()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn calendars() -> anyhow::Result<()> {
+    use crate::structs::CalendarTime;
+
+    let server = MockServer::start().await;
+    server
+        .json(
+            "GET /api/calendars",
+            200,
+            json!([{"entity_id": "calendar.holidays", "name": "Holidays"}]),
+        )
+        .json(
+            "GET /api/calendars/calendar.holidays",
+            200,
+            json!([
+                {"summary": "Summer break", "start": {"date": "2025-07-01"}, "end": {"date": "2025-07-15"}},
+                {
+                    "summary": "Dentist",
+                    "start": {"dateTime": "2025-07-03T10:00:00+02:00"},
+                    "end": {"dateTime": "2025-07-03T10:30:00+02:00"},
+                    "location": "Main street 1",
+                    "uid": "abc",
+                },
+            ]),
+        );
+    let (url, token) = server.credentials();
+
+    let calendars = hass().calendars(url.clone(), token.clone()).await?;
+    assert_eq!(calendars[0].entity_id, "calendar.holidays");
+    assert_eq!(calendars[0].name, "Holidays");
+
+    let events = hass()
+        .calendar_events(
+            url,
+            token,
+            "calendar.holidays",
+            "2025-07-01T00:00:00Z",
+            "2025-08-01T00:00:00Z",
+        )
+        .await?;
+    assert_eq!(
+        server.last().path,
+        "/api/calendars/calendar.holidays?start=2025-07-01T00%3A00%3A00%2B00%3A00&end=2025-08-01T00%3A00%3A00%2B00%3A00"
+    );
+    assert!(events[0].is_all_day());
+    assert_eq!(events[0].end.date().to_string(), "2025-07-15");
+    assert!(!events[1].is_all_day());
+    assert!(
+        matches!(events[1].start, CalendarTime::DateTime(start) if start.to_rfc3339() == "2025-07-03T10:00:00+02:00")
+    );
+    assert_eq!(events[1].location.as_deref(), Some("Main street 1"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn camera_proxy() -> anyhow::Result<()> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use chrono::TimeZone;
+
+    let server = MockServer::start().await;
+    server.bytes(
+        "GET /api/camera_proxy/camera.door",
+        200,
+        "image/jpeg",
+        b"jpeg data",
+    );
+    let (url, token) = server.credentials();
+    let path = "/api/camera_proxy/camera.door?time=1700000000";
+
+    let image = hass()
+        .camera_proxy(url.clone(), token.clone(), "camera.door", 1_700_000_000u64)
+        .await?;
+    assert_eq!(image.as_ref(), b"jpeg data");
+    assert_eq!(server.last().path, path);
+
+    let offset = chrono::FixedOffset::east_opt(3600).unwrap();
+    let times: [crate::timestamp::Timestamp; 3] = [
+        "2023-11-14T23:13:20+01:00".into(),
+        offset.timestamp_opt(1_700_000_000, 0).unwrap().into(),
+        (UNIX_EPOCH + Duration::from_secs(1_700_000_000)).into(),
+    ];
+    for time in times {
+        hass()
+            .camera_proxy(url.clone(), token.clone(), "camera.door", time)
+            .await?;
+        assert_eq!(server.last().path, path);
+    }
+    hass()
+        .camera_proxy(url.clone(), token.clone(), "camera.door", SystemTime::now())
+        .await?;
+
+    // invalid timestamps fail before sending anything
+    let requests = server.requests().len();
+    let error = hass()
+        .camera_proxy(url, token, "camera.door", "yesterday")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("invalid timestamp"), "{error:#}");
+    assert_eq!(server.requests().len(), requests);
+    Ok(())
+}
+
+#[tokio::test]
+async fn camera_snapshot_to() -> anyhow::Result<()> {
+    let image = [b"\xff\xd8\xff".as_slice(), &[0x42; 64 * 1024]].concat();
+    let server = MockServer::start().await;
+    server
+        .bytes(
+            "GET /api/camera_proxy/camera.door",
+            200,
+            "image/jpeg",
+            &image,
+        )
+        .text("GET /api/camera_proxy/camera.garage", 404, "404: Not Found");
+    let (url, token) = server.credentials();
+    let path = std::env::temp_dir().join(format!(
+        "homeassistant-rs-snapshot-{}.jpg",
+        std::process::id()
+    ));
+
+    let info = hass()
+        .camera_snapshot_to(url.clone(), token.clone(), "camera.door", &path)
+        .await?;
+    assert!(
+        server
+            .last()
+            .path
+            .starts_with("/api/camera_proxy/camera.door?time=")
+    );
+    assert_eq!(info.content_type.as_deref(), Some("image/jpeg"));
+    assert_eq!(info.size, image.len() as u64);
+    assert_eq!(tokio::fs::read(&path).await?, image);
+
+    // a failed request leaves the file alone
+    let error = hass()
+        .camera_snapshot_to(url, token, "camera.garage", &path)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("404"), "{error:#}");
+    assert_eq!(tokio::fs::read(&path).await?.len(), image.len());
+    tokio::fs::remove_file(&path).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn downloads() -> anyhow::Result<()> {
+    use futures_util::TryStreamExt;
+
+    use crate::camera::SnapshotOptions;
+
+    let server = MockServer::start().await;
+    server
+        .text("GET /api/camera_proxy/camera.door", 200, "jpeg data")
+        .text("GET /api/backup/download/abc123", 200, "tar data")
+        .text("GET /api/error_log", 500, "500: Internal Server Error");
+    let (url, token) = server.credentials();
+
+    let download = hass()
+        .camera_download(
+            url.clone(),
+            token.clone(),
+            "camera.door",
+            SnapshotOptions::new().time(1_700_000_000u64).width(640),
+        )
+        .await?;
+    assert_eq!(
+        server.last().path,
+        "/api/camera_proxy/camera.door?time=1700000000&width=640"
+    );
+    assert_eq!(download.content_type(), Some("text/plain"));
+    assert_eq!(download.content_length(), Some(9));
+    let chunks: Vec<bytes::Bytes> = download.into_stream().try_collect().await?;
+    assert_eq!(chunks.concat(), b"jpeg data");
+
+    let mut archive = Vec::new();
+    let written = hass()
+        .backup_download(url.clone(), token.clone(), "abc123", "backup.local")
+        .await?
+        .write_to(&mut archive)
+        .await?;
+    assert_eq!(
+        server.last().path,
+        "/api/backup/download/abc123?agent_id=backup.local"
+    );
+    assert_eq!((written, archive.as_slice()), (8, b"tar data".as_slice()));
+
+    let error = hass().error_log_download(url, token).await.unwrap_err();
+    assert!(error.to_string().contains("500"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn camera_stream() -> anyhow::Result<()> {
+    let body = [
+        b"--frame\r\nContent-Type: image/jpeg\r\nContent-Length: 3\r\n\r\nabc\r\n".as_slice(),
+        // a part which never ends
+        &[b'x'; 200],
+    ]
+    .concat();
+    let server = MockServer::start().await;
+    server.bytes(
+        "GET /api/camera_proxy_stream/camera.door",
+        200,
+        "multipart/x-mixed-replace; boundary=frame",
+        &body,
+    );
+    let (url, token) = server.credentials();
+
+    let mut stream = hass()
+        .camera_stream(url, token, "camera.door")
+        .await?
+        .max_frame_size(64);
+    let frame = stream.next_frame().await?.unwrap();
+    assert_eq!(&frame.data[..], b"abc");
+    let error = stream.next_frame().await.unwrap_err();
+    assert!(error.to_string().contains("max_frame_size"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn template() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.text("POST /api/template", 200, "on");
+    let (url, token) = server.credentials();
+
+    let rendered = hass()
+        .request()
+        .template(
+            url,
+            token,
+            structs::TemplateRequest {
+                template: "{{ states('light.desk') }}".to_owned(),
+            },
+        )
+        .await?;
+    assert_eq!(rendered, "on");
+    assert_eq!(
+        server.last().json(),
+        json!({"template": "{{ states('light.desk') }}"})
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_check() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.json(
+        "POST /api/config/core/check_config",
+        200,
+        json!({"errors": null, "result": "valid", "warnings": null}),
+    );
+    let (url, token) = server.credentials();
+
+    let check = hass().request().config_check(url, token).await?;
+    assert_eq!(check.result, "valid");
+    assert!(check.errors.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn intent() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+    server.json(
+        "POST /api/intent/handle",
+        200,
+        json!({"speech": {"plain": {"speech": "Turned on the light"}}}),
+    );
+    let (url, token) = server.credentials();
+
+    let response = hass()
+        .request()
+        .intent(
+            url,
+            token,
+            json!({"name": "HassTurnOn", "data": {"name": "desk"}}),
+        )
+        .await?;
+    assert!(response.contains("Turned on the light"));
+    assert_eq!(server.last().json()["name"], "HassTurnOn");
+    Ok(())
+}
+
+#[tokio::test]
+async fn domains() -> anyhow::Result<()> {
+    use crate::client::Client;
+    use crate::domains::HvacMode;
+
+    let server = MockServer::start().await;
+    server
+        .json(
+            "POST /api/services/light/turn_on",
+            200,
+            json!([{"entity_id": "light.desk", "state": "on"}]),
+        )
+        .json("POST /api/services/switch/toggle", 200, json!([]))
+        .json("POST /api/services/climate/set_temperature", 200, json!([]))
+        .json("POST /api/services/media_player/volume_set", 200, json!([]));
+    let (url, token) = server.credentials();
+    let client = Client::new(url.unwrap(), token.unwrap())?;
+
+    let changed = client
+        .light()
+        .turn_on("light.desk")
+        .brightness(128)
+        .rgb((0, 0, 255))
+        .transition(Duration::from_millis(1500))
+        .await?;
+    assert_eq!(changed[0]["state"], "on");
+    assert_eq!(
+        server.last().json(),
+        json!({"entity_id": "light.desk", "brightness": 128, "rgb_color": [0, 0, 255], "transition": 1.5})
+    );
+
+    client.switch().toggle("switch.fan").await?;
+    assert_eq!(server.last().json(), json!({"entity_id": "switch.fan"}));
+
+    client
+        .climate()
+        .set_temperature("climate.living_room", 21.5)
+        .hvac_mode(HvacMode::HeatCool)
+        .await?;
+    assert_eq!(
+        server.last().json(),
+        json!({"entity_id": "climate.living_room", "temperature": 21.5, "hvac_mode": "heat_cool"})
+    );
+
+    client
+        .media_player()
+        .volume_set("media_player.kitchen", 1.7)
+        .await?;
+    assert_eq!(
+        server.last().json(),
+        json!({"entity_id": "media_player.kitchen", "volume_level": 1.0})
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_log() -> anyhow::Result<()> {
+    use crate::error_log::LogLevel;
+
+    let server = MockServer::start().await;
+    server.text(
+        "GET /api/error_log",
+        200,
+        "2025-07-01 12:00:00.123 WARNING (MainThread) [homeassistant.components.http] Login attempt failed\n\
+         2025-07-01 12:00:05.456 ERROR (SyncWorker_3) [homeassistant.components.zha.core] Error doing job: Task exception\n\
+         Traceback (most recent call last):\n  File \"zha.py\", line 1, in <module>\n\
+         ValueError: bad value\n\
+         2025-07-01 12:01:00 INFO (Recorder) [homeassistant.components.recorder] Purged 3 states\n",
+    );
+    let (url, token) = server.credentials();
+
+    assert!(
+        hass()
+            .error_log(url.clone(), token.clone())
+            .await?
+            .starts_with("2025-07-01 12:00:00.123 WARNING")
+    );
+    assert_eq!(server.last().path, "/api/error_log");
+
+    let entries = hass().error_log_entries(url, token).await?;
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].level, LogLevel::Warning);
+    assert_eq!(entries[0].logger, "homeassistant.components.http");
+    assert_eq!(entries[0].message, "Login attempt failed");
+    assert_eq!(
+        entries[1].timestamp,
+        "2025-07-01T12:00:05.456".parse::<chrono::NaiveDateTime>()?
+    );
+    assert!(entries[1].message.ends_with("\nValueError: bad value"));
+    assert_eq!(entries[2].level, LogLevel::Info);
+    let errors: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.level >= LogLevel::Warning)
+        .map(|entry| entry.logger.as_str())
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            "homeassistant.components.http",
+            "homeassistant.components.zha.core"
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn download_error_log() -> anyhow::Result<()> {
+    let log = "2025-07-01 12:00:00.123 WARNING (MainThread) [homeassistant.components.http] Login attempt failed\n"
+        .repeat(1000);
+    let server = MockServer::start().await;
+    server.text("GET /api/error_log", 200, &log);
+    let (url, token) = server.credentials();
+
+    let mut file = tokio::io::BufWriter::new(Vec::new());
+    let written = hass()
+        .download_error_log(url.clone(), token.clone(), &mut file)
+        .await?;
+    assert_eq!(server.last().path, "/api/error_log");
+    assert_eq!(written, log.len() as u64);
+    assert_eq!(file.into_inner(), log.as_bytes());
+
+    #[cfg(feature = "gzip")]
+    {
+        use tokio::io::AsyncReadExt;
+
+        let mut compressed = Vec::new();
+        let written = hass()
+            .download_error_log_gzip(url.clone(), token.clone(), &mut compressed)
+            .await?;
+        assert_eq!(written, log.len() as u64);
+        assert!(compressed.len() < log.len() / 10);
+        let mut decompressed = String::new();
+        async_compression::tokio::bufread::GzipDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .await?;
+        assert_eq!(decompressed, log);
+    }
+
+    server.text("GET /api/error_log", 401, "401: Unauthorized");
+    let error = hass()
+        .download_error_log(url, token, Vec::new())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("401"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn correlate() -> anyhow::Result<()> {
+    use chrono::{TimeDelta, TimeZone, Utc};
+
+    use crate::correlation::CorrelationQuery;
+
+    let start = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+    let query = CorrelationQuery::new(
+        "sensor.outside",
+        "sensor.inside",
+        start,
+        start + TimeDelta::hours(1),
+    )
+    .step(TimeDelta::minutes(15));
+    let path = query.history_query().path()?;
+    let route = path.split_once('?').unwrap().0;
+
+    let server = MockServer::start().await;
+    server.json(
+        &format!("GET {route}"),
+        200,
+        json!([
+            [
+                {"entity_id": "sensor.inside", "state": "20", "last_changed": "2025-01-01T00:00:00+00:00"},
+                {"entity_id": "sensor.inside", "state": "21", "last_changed": "2025-01-01T00:20:00+00:00"},
+                {"entity_id": "sensor.inside", "state": "23", "last_changed": "2025-01-01T00:40:00+00:00"},
+            ],
+            [
+                {"entity_id": "sensor.outside", "state": "5", "last_changed": "2025-01-01T00:00:00+00:00"},
+                {"entity_id": "sensor.outside", "state": "unavailable", "last_changed": "2025-01-01T00:10:00+00:00"},
+                {"entity_id": "sensor.outside", "state": "7", "last_changed": "2025-01-01T00:20:00+00:00"},
+                {"entity_id": "sensor.outside", "state": "11", "last_changed": "2025-01-01T00:40:00+00:00"},
+            ],
+        ]),
+    );
+    let (url, token) = server.credentials();
+
+    let correlation = hass().correlate(url, token, &query).await?.unwrap();
+    assert!(correlation.coefficient > 0.99);
+    assert_eq!(correlation.samples, 5);
+    assert_eq!(server.last().path, path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn retries() -> anyhow::Result<()> {
+    use reqwest::Method;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    use crate::retry::{self, RetryPolicy};
+
+    let policy = RetryPolicy {
+        initial_delay: Duration::from_millis(10),
+        max_delay: Duration::from_secs(1),
+        ..Default::default()
+    };
+    assert_eq!(policy.backoff(1), Duration::from_millis(10));
+    assert_eq!(policy.backoff(3), Duration::from_millis(40));
+    assert_eq!(policy.backoff(20), Duration::from_secs(1));
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+    assert_eq!(retry::retry_after(&headers), Some(Duration::from_secs(120)));
+    headers.insert(
+        RETRY_AFTER,
+        HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+    );
+    assert_eq!(retry::retry_after(&headers), Some(Duration::ZERO));
+
+    let server = MockServer::start().await;
+    server
+        .once("GET /api/config", 503, &[("Retry-After", "0")])
+        .once("GET /api/config", 502, &[])
+        .json("GET /api/config", 200, json!({"version": "2025.6.0"}))
+        .once(
+            "POST /api/services/light/turn_on",
+            429,
+            &[("Retry-After", "0")],
+        )
+        .once("POST /api/services/light/turn_on", 502, &[])
+        .once("GET /api/states", 503, &[("Retry-After", "3600")]);
+    let url = server.url.clone();
+
+    let response = retry::run(Some(&policy), &Method::GET, || {
+        crate::request(&url, "token", "/api/config")
+    })
+    .await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(server.requests().len(), 3);
+
+    // service calls are retried after 429, but may have run before the proxy gave up
+    let response = retry::run(Some(&policy), &Method::POST, || {
+        crate::post(&url, "token", "/api/services/light/turn_on", json!({}))
+    })
+    .await?;
+    assert_eq!(response.status(), 502);
+    assert_eq!(server.requests().len(), 5);
+
+    // longer than the policy is willing to wait
+    let response = retry::run(Some(&policy), &Method::GET, || {
+        crate::request(&url, "token", "/api/states")
+    })
+    .await?;
+    assert_eq!(response.status(), 503);
+    assert_eq!(server.requests().len(), 6);
+
+    // connection errors are retried for every method, until the attempts run out
+    drop(server);
+    let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let closed_url = format!("http://{}", closed.local_addr()?);
+    drop(closed);
+    let mut attempts = 0;
+    let result = retry::run(Some(&policy), &Method::POST, || {
+        attempts += 1;
+        crate::post(
+            &closed_url,
+            "token",
+            "/api/services/light/turn_on",
+            json!({}),
+        )
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(attempts, policy.max_attempts);
+    Ok(())
+}
+
+#[tokio::test]
+async fn retry_policy_setting() -> anyhow::Result<()> {
+    use crate::retry::RetryPolicy;
+    use crate::settings;
+
+    /// disables retries again even if an assertion fails
+    struct Disable;
+    impl Drop for Disable {
+        fn drop(&mut self) {
+            settings::set_retry_policy(None);
+        }
+    }
+
+    let server = MockServer::start().await;
+    server
+        .once("POST /api/services/light/turn_on", 429, &[])
+        .json("POST /api/services/light/turn_on", 200, json!([]));
+    let (url, token) = server.credentials();
+
+    settings::set_retry_policy(Some(RetryPolicy {
+        initial_delay: Duration::from_millis(1),
+        ..Default::default()
+    }));
+    let _disable = Disable;
+    hass()
+        .request()
+        .service(url, token, "light", "turn_on", json!({}), false)
+        .await?;
+    assert_eq!(server.requests().len(), 2);
+    Ok(())
+}
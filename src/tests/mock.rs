@@ -0,0 +1,249 @@
+//! A minimal Homeassistant REST server for tests which do not need a live instance
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// a request received by a [`MockServer`]
+#[derive(Debug, Clone)]
+pub(crate) struct Recorded {
+    pub(crate) method: String,
+    /// path including the query string
+    pub(crate) path: String,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: String,
+}
+
+impl Recorded {
+    pub(crate) fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// answers requests by `METHOD /path` (without the query string), unknown routes get a 404
+pub(crate) struct MockServer {
+    pub(crate) url: String,
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+    /// answered once each before `routes`, see [`MockServer::once`]
+    queued: Arc<Mutex<HashMap<String, VecDeque<Route>>>>,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl MockServer {
+    pub(crate) async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes: Arc<Mutex<HashMap<String, Route>>> = Arc::default();
+        let queued: Arc<Mutex<HashMap<String, VecDeque<Route>>>> = Arc::default();
+        let requests: Arc<Mutex<Vec<Recorded>>> = Arc::default();
+
+        let (served_routes, served_queue, recorded) =
+            (routes.clone(), queued.clone(), requests.clone());
+        let handle = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let Some(request) = read_request(&mut stream).await else {
+                    continue;
+                };
+                let key = format!(
+                    "{} {}",
+                    request.method,
+                    request.path.split('?').next().unwrap_or_default()
+                );
+                let route = served_queue
+                    .lock()
+                    .unwrap()
+                    .get_mut(&key)
+                    .and_then(VecDeque::pop_front)
+                    .or_else(|| served_routes.lock().unwrap().get(&key).cloned());
+                recorded.lock().unwrap().push(request);
+
+                let route = route.unwrap_or(Route {
+                    status: 404,
+                    content_type: "text/plain",
+                    headers: Vec::new(),
+                    body: b"404: Not Found".to_vec(),
+                });
+                let headers: String = route
+                    .headers
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {value}\r\n"))
+                    .collect();
+                let head = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nContent-Length: {}\r\n{headers}Connection: close\r\n\r\n",
+                    route.status,
+                    route.content_type,
+                    route.body.len(),
+                );
+                let _ = stream
+                    .write_all(&[head.as_bytes(), &route.body].concat())
+                    .await;
+            }
+        });
+
+        Self {
+            url,
+            routes,
+            queued,
+            requests,
+            handle,
+        }
+    }
+
+    /// answers `route` (e.g. `GET /api/config`) with `status` and a JSON body
+    pub(crate) fn json(&self, route: &str, status: u16, body: serde_json::Value) -> &Self {
+        self.bytes(
+            route,
+            status,
+            "application/json",
+            body.to_string().as_bytes(),
+        )
+    }
+
+    /// answers `route` with `status` and a plain text body
+    pub(crate) fn text(&self, route: &str, status: u16, body: &str) -> &Self {
+        self.bytes(route, status, "text/plain", body.as_bytes())
+    }
+
+    /// answers `route` with `status` and a body of any content type, e.g. an image
+    pub(crate) fn bytes(
+        &self,
+        route: &str,
+        status: u16,
+        content_type: &'static str,
+        body: &[u8],
+    ) -> &Self {
+        self.routes.lock().unwrap().insert(
+            route.to_owned(),
+            Route {
+                status,
+                content_type,
+                headers: Vec::new(),
+                body: body.to_vec(),
+            },
+        );
+        self
+    }
+
+    /// adds a header to the responses of `route`, which has to be set before
+    pub(crate) fn header(&self, route: &str, name: &str, value: &str) -> &Self {
+        self.routes
+            .lock()
+            .unwrap()
+            .get_mut(route)
+            .expect("route not set")
+            .headers
+            .push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// answers the next request of `route` with `status`, `headers` and an empty body, before the
+    /// responses set with [`json`](Self::json) or [`text`](Self::text)
+    pub(crate) fn once(&self, route: &str, status: u16, headers: &[(&str, &str)]) -> &Self {
+        self.once_json(route, status, headers, None)
+    }
+
+    /// like [`once`](Self::once), with a JSON body unless `body` is [`None`]
+    pub(crate) fn once_json(
+        &self,
+        route: &str,
+        status: u16,
+        headers: &[(&str, &str)],
+        body: Option<serde_json::Value>,
+    ) -> &Self {
+        let (content_type, body) = match body {
+            Some(body) => ("application/json", body.to_string().into_bytes()),
+            None => ("text/plain", Vec::new()),
+        };
+        self.queued
+            .lock()
+            .unwrap()
+            .entry(route.to_owned())
+            .or_default()
+            .push_back(Route {
+                status,
+                content_type,
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                body,
+            });
+        self
+    }
+
+    /// credentials for the `ha_url` and `ha_token` parameters
+    pub(crate) fn credentials(&self) -> (Option<String>, Option<String>) {
+        (Some(self.url.clone()), Some("token".to_owned()))
+    }
+
+    /// all requests received so far, oldest first
+    pub(crate) fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// the last request received
+    pub(crate) fn last(&self) -> Recorded {
+        self.requests().pop().expect("no request received")
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<Recorded> {
+    let mut data = Vec::new();
+    let mut buffer = [0; 4096];
+    let header_end = loop {
+        if let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let read = stream.read(&mut buffer).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buffer[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_owned();
+    let path = request_line.next()?.to_owned();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_owned()))
+        .collect();
+
+    let length: usize = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    while data.len() < header_end + length {
+        let read = stream.read(&mut buffer).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..read]);
+    }
+    let body = String::from_utf8_lossy(&data[header_end..]).into_owned();
+
+    Some(Recorded {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
@@ -0,0 +1,171 @@
+//! A minimal Homeassistant WebSocket server for tests which do not need a live instance
+
+use std::sync::{Arc, Mutex};
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::net::TcpStream;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+
+/// accepts WebSocket connections and authenticates them, then serves each with the function passed
+/// to [`MockWebSocket::start`]
+pub(crate) struct MockWebSocket {
+    pub(crate) url: String,
+    commands: Arc<Mutex<Vec<Value>>>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl MockWebSocket {
+    /// serves every connection with `serve` in its own task, connections are numbered from 0
+    pub(crate) async fn start<F, Fut>(mut serve: F) -> Self
+    where
+        F: FnMut(MockConnection) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let commands: Arc<Mutex<Vec<Value>>> = Arc::default();
+
+        let recorded = commands.clone();
+        let handle = tokio::spawn(async move {
+            let mut index = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                let Ok(mut socket) = tokio_tungstenite::accept_async(stream).await else {
+                    continue;
+                };
+                let send = |value: Value| Message::text(value.to_string());
+                if socket
+                    .send(send(json!({"type": "auth_required"})))
+                    .await
+                    .is_err()
+                    || socket.next().await.is_none()
+                    || socket
+                        .send(send(json!({"type": "auth_ok", "ha_version": "2025.6.0"})))
+                        .await
+                        .is_err()
+                {
+                    continue;
+                }
+                tokio::spawn(serve(MockConnection {
+                    index,
+                    closed: false,
+                    socket,
+                    commands: recorded.clone(),
+                }));
+                index += 1;
+            }
+        });
+
+        Self {
+            url,
+            commands,
+            handle,
+        }
+    }
+
+    /// answers every command successfully with the result of `result`
+    pub(crate) async fn respond(result: impl Fn(&Value) -> Value + Send + Sync + 'static) -> Self {
+        let result = Arc::new(result);
+        Self::start(move |mut connection| {
+            let result = result.clone();
+            async move {
+                while let Some(message) = connection.recv().await {
+                    connection.reply(&message, result(&message)).await;
+                }
+            }
+        })
+        .await
+    }
+
+    /// credentials for the `ha_url` and `ha_token` parameters
+    pub(crate) fn credentials(&self) -> (Option<String>, Option<String>) {
+        (Some(self.url.clone()), Some("token".to_owned()))
+    }
+
+    /// all commands received so far on any connection, oldest first
+    pub(crate) fn commands(&self) -> Vec<Value> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// the commands received so far of type `command_type`
+    pub(crate) fn sent(&self, command_type: &str) -> Vec<Value> {
+        self.commands()
+            .into_iter()
+            .filter(|command| command["type"] == command_type)
+            .collect()
+    }
+
+    /// the last command of type `command_type` received, without its id
+    pub(crate) fn last(&self, command_type: &str) -> Value {
+        let mut command = self.sent(command_type).pop().expect("no command received");
+        command.as_object_mut().unwrap().remove("id");
+        command
+    }
+}
+
+impl Drop for MockWebSocket {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// an authenticated connection of a [`MockWebSocket`]
+pub(crate) struct MockConnection {
+    /// the number of the connection, counting from 0
+    pub(crate) index: usize,
+    /// whether the client sent a close frame, rather than dropping the connection
+    pub(crate) closed: bool,
+    socket: WebSocketStream<TcpStream>,
+    commands: Arc<Mutex<Vec<Value>>>,
+}
+
+impl MockConnection {
+    /// the next command, [`None`] once the client closed the connection
+    pub(crate) async fn recv(&mut self) -> Option<Value> {
+        loop {
+            match self.socket.next().await? {
+                Ok(Message::Text(text)) => {
+                    let message: Value = serde_json::from_str(&text).unwrap();
+                    self.commands.lock().unwrap().push(message.clone());
+                    return Some(message);
+                }
+                Ok(Message::Close(_)) => {
+                    self.closed = true;
+                    return None;
+                }
+                Err(_) => return None,
+                Ok(_) => {}
+            }
+        }
+    }
+
+    pub(crate) async fn send(&mut self, value: Value) {
+        let _ = self.socket.send(Message::text(value.to_string())).await;
+    }
+
+    /// answers `command` successfully with `result`
+    pub(crate) async fn reply(&mut self, command: &Value, result: Value) {
+        self.send(
+            json!({"id": command["id"], "type": "result", "success": true, "result": result}),
+        )
+        .await;
+    }
+
+    /// fails `command` with an error like Homeassistant's
+    pub(crate) async fn fail(&mut self, command: &Value, code: &str, message: &str) {
+        self.send(json!({
+            "id": command["id"],
+            "type": "result",
+            "success": false,
+            "error": {"code": code, "message": message},
+        }))
+        .await;
+    }
+
+    /// sends `event` to the subscription `id`
+    pub(crate) async fn event(&mut self, id: &Value, event: Value) {
+        self.send(json!({"id": id, "type": "event", "event": event}))
+            .await;
+    }
+}
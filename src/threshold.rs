@@ -0,0 +1,164 @@
+//! Hysteresis switching for thermostat-like control loops
+//!
+//! A [`Threshold`] turns an output on once a numeric input crosses one limit and off once it
+//! crosses the other one, optionally keeping each state for a minimum time to protect e.g.
+//! compressors and boilers.
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use std::time::Duration;
+//! use homeassistant_rs::threshold::Threshold;
+//!
+//! // run the dehumidifier between 55% and 65% humidity
+//! let events = ws.subscribe_events(Some("state_changed")).await?;
+//! Threshold::on_above(65.0, 55.0)
+//!     .min_dwell(Duration::from_secs(300))
+//!     .drive(&ws, events, "sensor.cellar_humidity", "switch.dehumidifier")
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use futures_util::stream::BoxStream;
+use futures_util::{Stream, StreamExt, future};
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::structs::Event;
+use crate::ws::HomeAssistantWs;
+
+/// output state emitted by a [`Threshold`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Switch {
+    On,
+    Off,
+}
+
+impl Switch {
+    /// `turn_on` or `turn_off`
+    pub fn service(self) -> &'static str {
+        match self {
+            Switch::On => "turn_on",
+            Switch::Off => "turn_off",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Threshold {
+    on: f64,
+    off: f64,
+    /// on while the input is low, e.g. a heater
+    below: bool,
+    min_dwell: Duration,
+    state: Option<(Switch, Instant)>,
+}
+
+impl Threshold {
+    /// switches on once the input rises above `on`, and off once it falls below `off`
+    pub fn on_above(on: f64, off: f64) -> Self {
+        Self {
+            on,
+            off,
+            below: false,
+            min_dwell: Duration::ZERO,
+            state: None,
+        }
+    }
+
+    /// switches on once the input falls below `on`, and off once it rises above `off`
+    pub fn on_below(on: f64, off: f64) -> Self {
+        Self {
+            below: true,
+            ..Self::on_above(on, off)
+        }
+    }
+
+    /// minimum time the output stays on or off before it is switched again
+    pub fn min_dwell(mut self, min_dwell: Duration) -> Self {
+        self.min_dwell = min_dwell;
+        self
+    }
+
+    /// the current output, [`None`] until the input crossed a limit for the first time
+    pub fn state(&self) -> Option<Switch> {
+        self.state.map(|(state, _)| state)
+    }
+
+    /// feeds a sample, returns the new output if it changed
+    pub fn update(&mut self, value: f64) -> Option<Switch> {
+        self.update_at(value, Instant::now())
+    }
+
+    /// like [`update`](Self::update), with the time the sample was taken
+    pub fn update_at(&mut self, value: f64, at: Instant) -> Option<Switch> {
+        let (turn_on, turn_off) = if self.below {
+            (value < self.on, value > self.off)
+        } else {
+            (value > self.on, value < self.off)
+        };
+        let next = match (self.state, turn_on, turn_off) {
+            (Some((_, since)), _, _) if at.duration_since(since) < self.min_dwell => return None,
+            (Some((Switch::On, _)), _, _) | (None, _, _) if turn_off => Switch::Off,
+            (Some((Switch::Off, _)), _, _) | (None, _, _) if turn_on => Switch::On,
+            _ => return None,
+        };
+        self.state = Some((next, at));
+        Some(next)
+    }
+
+    /// consumes `state_changed` events and emits the output changes caused by numeric states of
+    /// `entity_id`, other events are ignored
+    pub fn switches<S>(
+        mut self,
+        events: S,
+        entity_id: &str,
+    ) -> BoxStream<'static, anyhow::Result<Switch>>
+    where
+        S: Stream<Item = anyhow::Result<Event>> + Send + 'static,
+    {
+        let entity_id = entity_id.to_owned();
+        events
+            .filter_map(move |item| {
+                let item = match item {
+                    Ok(event) => event
+                        .state_changed()
+                        .filter(|data| data.entity_id == entity_id)
+                        .and_then(|data| data.new_state?.state.parse().ok())
+                        .and_then(|value| self.update(value))
+                        .map(Ok),
+                    Err(e) => Some(Err(e)),
+                };
+                future::ready(item)
+            })
+            .boxed()
+    }
+
+    /// switches `target` with `homeassistant.turn_on` and `homeassistant.turn_off` following
+    /// the numeric states of `entity_id`, until `events` ends or a service call fails
+    pub async fn drive<S>(
+        self,
+        ws: &HomeAssistantWs,
+        events: S,
+        entity_id: &str,
+        target: &str,
+    ) -> anyhow::Result<()>
+    where
+        S: Stream<Item = anyhow::Result<Event>> + Send + 'static,
+    {
+        let mut switches = self.switches(events, entity_id);
+        while let Some(switch) = switches.next().await {
+            ws.call_service(
+                "homeassistant",
+                switch?.service(),
+                json!({}),
+                Some(json!({ "entity_id": target })),
+                false,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! [`Timestamp`], accepted by all functions taking a point in time
+//!
+//! ```
+//! use homeassistant_rs::timestamp::Timestamp;
+//!
+//! let from_unix = Timestamp::from(1_735_689_600u64);
+//! let from_str = Timestamp::from("2025-01-01T00:00:00Z");
+//! let from_chrono = Timestamp::from(chrono::Utc::now());
+//! let from_system = Timestamp::from(std::time::SystemTime::now());
+//!
+//! assert_eq!(from_unix, from_str);
+//! ```
+//!
+//! Enable the `time` feature for conversions from `time::OffsetDateTime`.
+
+use std::time::SystemTime;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inner {
+    Valid(DateTime<Utc>),
+    /// kept until the timestamp is used, so conversions from strings can stay infallible
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timestamp(Inner);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Self(Inner::Valid(Utc::now()))
+    }
+
+    /// parses an RFC 3339 timestamp, e.g. `2025-01-01T00:00:00+01:00`
+    pub fn parse(str: &str) -> anyhow::Result<Self> {
+        Ok(Self(Inner::Valid(
+            DateTime::parse_from_rfc3339(str)
+                .map_err(|e| anyhow::Error::msg(format!("invalid timestamp {str:?}: {e}")))?
+                .with_timezone(&Utc),
+        )))
+    }
+
+    pub fn to_datetime(&self) -> anyhow::Result<DateTime<Utc>> {
+        match &self.0 {
+            Inner::Valid(time) => Ok(*time),
+            Inner::Invalid(str) => Self::parse(str)?.to_datetime(),
+        }
+    }
+
+    pub fn unix_seconds(&self) -> anyhow::Result<i64> {
+        Ok(self.to_datetime()?.timestamp())
+    }
+
+    /// formats the timestamp as accepted by Homeassistant, e.g. `2025-01-01T00:00:00+00:00`
+    pub fn to_rfc3339(&self) -> anyhow::Result<String> {
+        Ok(self.to_datetime()?.to_rfc3339())
+    }
+}
+
+impl<Tz: TimeZone> From<DateTime<Tz>> for Timestamp {
+    fn from(value: DateTime<Tz>) -> Self {
+        Self(Inner::Valid(value.with_timezone(&Utc)))
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(value: SystemTime) -> Self {
+        Self(Inner::Valid(value.into()))
+    }
+}
+
+/// unix time in seconds
+impl From<u64> for Timestamp {
+    fn from(value: u64) -> Self {
+        match i64::try_from(value) {
+            Ok(value) => value.into(),
+            Err(_) => Self(Inner::Invalid(value.to_string())),
+        }
+    }
+}
+
+/// unix time in seconds
+impl From<i64> for Timestamp {
+    fn from(value: i64) -> Self {
+        match Utc.timestamp_opt(value, 0).single() {
+            Some(time) => Self(Inner::Valid(time)),
+            None => Self(Inner::Invalid(value.to_string())),
+        }
+    }
+}
+
+/// RFC 3339 string, invalid strings result in an error once the timestamp is used
+impl From<&str> for Timestamp {
+    fn from(value: &str) -> Self {
+        Self::parse(value).unwrap_or_else(|_| Self(Inner::Invalid(value.to_owned())))
+    }
+}
+
+impl From<String> for Timestamp {
+    fn from(value: String) -> Self {
+        value.as_str().into()
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for Timestamp {
+    fn from(value: time::OffsetDateTime) -> Self {
+        let nanos = value.unix_timestamp_nanos();
+        match i64::try_from(nanos) {
+            Ok(nanos) => Self(Inner::Valid(Utc.timestamp_nanos(nanos))),
+            Err(_) => Self(Inner::Invalid(value.to_string())),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Helpers for building and joining URLs, including entity pictures and brand icons
+//!
+//! Paths below `/api/` require authentication. Browsers and media players can not send the bearer
+//! token, so those paths have to be signed first, see
+//! [`HomeAssistantWs::sign_path`](crate::ws::HomeAssistantWs::sign_path) and
+//! [`HomeAssistantWs::entity_picture_url`](crate::ws::HomeAssistantWs::entity_picture_url).
+
+use std::net::SocketAddr;
+
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use reqwest::Url;
+
+use crate::structs;
+
+/// characters which must be escaped within a single path segment
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+const BRANDS_URL: &str = "https://brands.home-assistant.io";
+
+/// builds a base url from an address, IPv6 addresses are enclosed in brackets
+/// ```
+/// use std::net::{Ipv6Addr, SocketAddr};
+/// use homeassistant_rs::urls::base_url;
+///
+/// let addr = SocketAddr::from(("fd00::1".parse::<Ipv6Addr>().unwrap(), 8123));
+/// assert_eq!(base_url(addr, false), "http://[fd00::1]:8123");
+/// assert_eq!(base_url(([192, 168, 1, 2], 443), true), "https://192.168.1.2:443");
+/// ```
+pub fn base_url(addr: impl Into<SocketAddr>, tls: bool) -> String {
+    format!("{}://{}", if tls { "https" } else { "http" }, addr.into())
+}
+
+/// joins `path` (which may contain a query) onto `base_url`, absolute URLs are returned unchanged
+///
+/// a path prefix of `base_url` is kept, e.g. for instances served below a subpath by a reverse proxy
+pub fn join(base_url: &str, path: &str) -> anyhow::Result<Url> {
+    let mut base = Url::parse(base_url)?;
+    if !base.path().ends_with('/') {
+        let prefix = format!("{}/", base.path());
+        base.set_path(&prefix);
+    }
+    Ok(base.join(path.trim_start_matches('/'))?)
+}
+
+/// percent-encodes a single path segment, e.g. an entity id
+pub fn encode_segment(segment: &str) -> String {
+    utf8_percent_encode(segment, SEGMENT).to_string()
+}
+
+/// percent-encodes a query parameter value
+pub fn encode_query(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+/// query string builder, renders to an empty string or `?key=value&flag`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Query {
+    pairs: Vec<(&'static str, Option<String>)>,
+}
+
+impl Query {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds `key=value`
+    pub(crate) fn param(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.pairs.push((key, Some(value.to_string())));
+        self
+    }
+
+    /// adds `key=value` if `value` is set
+    pub(crate) fn opt(self, key: &'static str, value: Option<impl ToString>) -> Self {
+        match value {
+            Some(value) => self.param(key, value),
+            None => self,
+        }
+    }
+
+    /// adds `key` without a value if `enabled`
+    pub(crate) fn flag(mut self, key: &'static str, enabled: bool) -> Self {
+        if enabled {
+            self.pairs.push((key, None));
+        }
+        self
+    }
+}
+
+impl std::fmt::Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (key, value)) in self.pairs.iter().enumerate() {
+            f.write_str(if i == 0 { "?" } else { "&" })?;
+            f.write_str(key)?;
+            if let Some(value) = value {
+                write!(f, "={}", encode_query(value))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// returns the `entity_picture` attribute of a state, if set
+pub fn entity_picture(state: &structs::StatesResponse) -> Option<&str> {
+    state
+        .attributes
+        .as_ref()?
+        .other_fields
+        .get("entity_picture")?
+        .as_str()
+}
+
+/// whether `path` requires authentication and does not carry a signature or access token yet
+pub fn needs_signing(path: &str) -> bool {
+    path.starts_with("/api/") && !path.contains("authSig=") && !path.contains("token=")
+}
+
+/// returns the URL of an integration's icon, as shown in the Homeassistant UI
+pub fn brand_icon_url(domain: &str, dark: bool) -> String {
+    format!(
+        "{BRANDS_URL}/_/{domain}/{}icon.png",
+        if dark { "dark_" } else { "" }
+    )
+}
+
+/// returns the URL of an integration's logo
+pub fn brand_logo_url(domain: &str, dark: bool) -> String {
+    format!(
+        "{BRANDS_URL}/_/{domain}/{}logo.png",
+        if dark { "dark_" } else { "" }
+    )
+}
@@ -0,0 +1,110 @@
+//! Typed state streams of single entities
+//!
+//! [`watch_typed`](HomeAssistantWs::watch_typed) yields the state of an entity together with its
+//! attributes parsed into a struct, e.g. [`LightAttributes`]:
+//! ```no_run
+//! # use homeassistant_rs::prelude::*;
+//! # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+//! use homeassistant_rs::watch::LightAttributes;
+//!
+//! let mut desk = ws.watch_typed::<LightAttributes>("light.desk").await?;
+//! while let Some(change) = desk.next().await {
+//!     let (state, attributes) = change?;
+//!     println!("{state} at brightness {:?}", attributes.brightness);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Any `Deserialize` type works, attributes which do not match it are returned as an error for
+//! that change only, the stream continues.
+
+use futures_util::stream::BoxStream;
+use futures_util::{Stream, StreamExt, future};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::structs::Event;
+use crate::ws::HomeAssistantWs;
+
+/// attributes of `light` entities, unset while the light is off
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LightAttributes {
+    pub friendly_name: Option<String>,
+    /// 0 to 255
+    pub brightness: Option<u8>,
+    /// e.g. `color_temp`, `hs` or `brightness`
+    pub color_mode: Option<String>,
+    #[serde(default)]
+    pub supported_color_modes: Vec<String>,
+    pub color_temp_kelvin: Option<u32>,
+    pub min_color_temp_kelvin: Option<u32>,
+    pub max_color_temp_kelvin: Option<u32>,
+    pub hs_color: Option<(f64, f64)>,
+    pub rgb_color: Option<(u8, u8, u8)>,
+    pub effect: Option<String>,
+    pub effect_list: Option<Vec<String>>,
+    /// fields not covered above, e.g. added by newer releases
+    #[serde(flatten)]
+    pub other: Value,
+}
+
+/// the state and parsed attributes of `entity_id` from the `state_changed` events in `events`
+///
+/// changes removing the entity are skipped, attributes not matching `A` are yielded as an error
+pub fn typed_states<A, S>(
+    events: S,
+    entity_id: &str,
+) -> BoxStream<'static, anyhow::Result<(String, A)>>
+where
+    A: DeserializeOwned + Send + 'static,
+    S: Stream<Item = anyhow::Result<Event>> + Send + 'static,
+{
+    let entity_id = entity_id.to_owned();
+    events
+        .filter_map(move |event| {
+            future::ready(match event {
+                Ok(event) => typed_state(&event, &entity_id),
+                Err(e) => Some(Err(e)),
+            })
+        })
+        .boxed()
+}
+
+fn typed_state<A: DeserializeOwned>(
+    event: &Event,
+    entity_id: &str,
+) -> Option<anyhow::Result<(String, A)>> {
+    if event.event_type != "state_changed" || event.data["entity_id"] != entity_id {
+        return None;
+    }
+    let new_state = event.data.get("new_state").filter(|s| !s.is_null())?;
+    let state = new_state["state"].as_str().unwrap_or_default().to_owned();
+    let attributes = new_state
+        .get("attributes")
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    Some(
+        serde_json::from_value(attributes)
+            .map(|attributes| (state, attributes))
+            .map_err(|e| anyhow::Error::msg(format!("unexpected attributes of {entity_id}: {e}"))),
+    )
+}
+
+impl HomeAssistantWs {
+    /// subscribes to the state changes of `entity_id` and yields them with the attributes parsed
+    /// as `A`, see [`watch`](crate::watch)
+    ///
+    /// only changes after subscribing are yielded
+    pub async fn watch_typed<A>(
+        &self,
+        entity_id: &str,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<(String, A)>>>
+    where
+        A: DeserializeOwned + Send + 'static,
+    {
+        let events = self.subscribe_events(Some("state_changed")).await?;
+        Ok(typed_states(events, entity_id))
+    }
+}
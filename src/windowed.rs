@@ -0,0 +1,557 @@
+//! Long-range queries split into time windows
+//!
+//! History, logbook and statistics queries over months are split into windows which are fetched
+//! one after another, windows failing with a connection error or a server error are retried.
+//! [`WindowedQuery::fetch_range`] reports the progress after every window:
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use chrono::{Duration, Utc};
+//! use homeassistant_rs::windowed::{HistoryRange, WindowOptions, WindowedQuery};
+//!
+//! let end = Utc::now();
+//! let history = HistoryRange::new(["sensor.outside_temperature"])
+//!     .no_attributes()
+//!     .fetch_range(end - Duration::days(90), end, &WindowOptions::new(), |progress| {
+//!         println!("{}/{} windows", progress.window + 1, progress.windows)
+//!     })
+//!     .await
+//!     .unwrap();
+//! # });
+//! ```
+//!
+//! Other sources implement [`WindowedQuery::fetch_window`] and get the same chunking and retries.
+
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, BoxStream};
+use futures_util::{FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::history::HistoryQuery;
+use crate::structs::{HistoryResponse, LogBook};
+use crate::ws::HomeAssistantWs;
+use crate::{credentials, decode, request, urls};
+
+/// how a time range is split and how failed windows are retried
+#[derive(Debug, Clone)]
+pub struct WindowOptions {
+    window: Option<TimeDelta>,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl Default for WindowOptions {
+    fn default() -> Self {
+        Self {
+            window: None,
+            retries: 2,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl WindowOptions {
+    /// the [`default_window`](WindowedQuery::default_window) of the query, 2 retries after 1 second
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the length of a window, overriding the default of the query
+    pub fn window(mut self, window: TimeDelta) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    /// how often a window failing with a [retryable](WindowedQuery::is_retryable) error is fetched
+    /// again before giving up
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// the delay before the first retry, doubled for every further retry
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+}
+
+/// progress of [`WindowedQuery::fetch_range`], reported after every window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowProgress {
+    /// index of the fetched window
+    pub window: usize,
+    /// number of windows in the range
+    pub windows: usize,
+    /// items fetched so far
+    pub items: usize,
+    /// end of the fetched window
+    pub until: DateTime<Utc>,
+}
+
+/// the items of one window, yielded by [`WindowedQuery::stream_range`]
+#[derive(Debug, Clone)]
+pub struct Window<T> {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// index of the window
+    pub index: usize,
+    /// number of windows in the range
+    pub count: usize,
+    pub items: Vec<T>,
+}
+
+/// splits `start..end` into consecutive windows of at most `size`
+pub fn split(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    size: TimeDelta,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut windows = Vec::new();
+    if size <= TimeDelta::zero() {
+        return windows;
+    }
+    let mut from = start;
+    while from < end {
+        let to = (from + size).min(end);
+        windows.push((from, to));
+        from = to;
+    }
+    windows
+}
+
+/// a query over a time range which can be fetched in windows
+pub trait WindowedQuery: Send + Sync {
+    type Item: Send + 'static;
+
+    /// fetches the items from `start` up to `end`
+    fn fetch_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxFuture<'_, anyhow::Result<Vec<Self::Item>>>;
+
+    /// fetches a window after the first, like [`fetch_window`](Self::fetch_window) by default
+    ///
+    /// for sources which repeat the last item before `start` at the start of every window, so it
+    /// is not returned twice
+    fn fetch_following_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxFuture<'_, anyhow::Result<Vec<Self::Item>>> {
+        self.fetch_window(start, end)
+    }
+
+    /// the length of a window unless set in the [`WindowOptions`], one day by default
+    fn default_window(&self) -> TimeDelta {
+        TimeDelta::days(1)
+    }
+
+    /// whether a window failing with `error` is fetched again, by default after connection errors
+    /// and server errors, but not e.g. after a `400 Bad Request`
+    fn is_retryable(&self, error: &anyhow::Error) -> bool {
+        is_transient(error)
+    }
+
+    /// fetches `start..end` window by window, oldest first
+    ///
+    /// the stream ends after the first window which still fails after all retries
+    fn stream_range<'a>(
+        &'a self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        options: &WindowOptions,
+    ) -> BoxStream<'a, anyhow::Result<Window<Self::Item>>>
+    where
+        Self: Sized,
+    {
+        let options = options.clone();
+        let windows = split(
+            start,
+            end,
+            options.window.unwrap_or_else(|| self.default_window()),
+        );
+        let count = windows.len();
+        stream::iter(windows.into_iter().enumerate())
+            .then(move |(index, (start, end))| {
+                let options = options.clone();
+                async move {
+                    let mut delay = options.retry_delay;
+                    let mut attempt = 0;
+                    loop {
+                        let fetched = if index == 0 {
+                            self.fetch_window(start, end).await
+                        } else {
+                            self.fetch_following_window(start, end).await
+                        };
+                        match fetched {
+                            Ok(items) => {
+                                break Ok(Window {
+                                    start,
+                                    end,
+                                    index,
+                                    count,
+                                    items,
+                                });
+                            }
+                            Err(e) if attempt >= options.retries || !self.is_retryable(&e) => {
+                                break Err(e.context(format!(
+                                    "window {start} - {end} failed after {} attempts",
+                                    attempt + 1
+                                )));
+                            }
+                            Err(_) => {
+                                tokio::time::sleep(delay).await;
+                                delay *= 2;
+                                attempt += 1;
+                            }
+                        }
+                    }
+                }
+            })
+            .scan(false, |failed, window| {
+                let item = (!*failed).then_some(window);
+                *failed = matches!(item, Some(Err(_)));
+                futures_util::future::ready(item)
+            })
+            .boxed()
+    }
+
+    /// fetches `start..end` window by window and returns all items, `progress` is called after
+    /// every window
+    fn fetch_range<'a>(
+        &'a self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        options: &WindowOptions,
+        mut progress: impl FnMut(WindowProgress) + Send + 'a,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Self::Item>>>
+    where
+        Self: Sized,
+    {
+        let mut windows = self.stream_range(start, end, options);
+        async move {
+            let mut items = Vec::new();
+            while let Some(window) = windows.next().await {
+                let window = window?;
+                items.extend(window.items);
+                progress(WindowProgress {
+                    window: window.index,
+                    windows: window.count,
+                    items: items.len(),
+                    until: window.end,
+                });
+            }
+            Ok(items)
+        }
+        .boxed()
+    }
+}
+
+/// whether `error` is a connection error or a server error, see [`WindowedQuery::is_retryable`]
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    if let Some(status) = error.downcast_ref::<reqwest::StatusCode>() {
+        return status.is_server_error();
+    }
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.status().is_some_and(|s| s.is_server_error()))
+}
+
+/// `/api/history/period/<start>`, every window starts with the state of each entity at its start,
+/// which is left out after the first window
+#[derive(Debug, Clone, Default)]
+pub struct HistoryRange {
+    ha_url: Option<String>,
+    ha_token: Option<String>,
+    entity_ids: Vec<String>,
+    minimal_response: bool,
+    no_attributes: bool,
+    significant_changes_only: bool,
+}
+
+impl HistoryRange {
+    pub fn new<S: Into<String>>(entity_ids: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            entity_ids: entity_ids.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// url and token, read from the environment if not set
+    pub fn credentials(mut self, ha_url: Option<String>, ha_token: Option<String>) -> Self {
+        self.ha_url = ha_url;
+        self.ha_token = ha_token;
+        self
+    }
+
+    pub fn minimal_response(mut self) -> Self {
+        self.minimal_response = true;
+        self
+    }
+
+    pub fn no_attributes(mut self) -> Self {
+        self.no_attributes = true;
+        self
+    }
+
+    pub fn significant_changes_only(mut self) -> Self {
+        self.significant_changes_only = true;
+        self
+    }
+
+    /// the path of the window `start..end`, fails if it ends before it starts
+    pub fn path(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> anyhow::Result<String> {
+        let mut query = HistoryQuery::new()
+            .start(start)
+            .end(end)
+            .entities(&self.entity_ids);
+        if self.minimal_response {
+            query = query.minimal_response();
+        }
+        if self.no_attributes {
+            query = query.no_attributes();
+        }
+        if self.significant_changes_only {
+            query = query.significant_changes_only();
+        }
+        query.path()
+    }
+}
+
+impl HistoryRange {
+    /// the rows of `start..end`, grouped by entity
+    async fn fetch_entities(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<Vec<HistoryResponse>>> {
+        let (url, token) = credentials(self.ha_url.clone(), self.ha_token.clone())?;
+        let client = request(&url, &token, &self.path(start, end)?).await?;
+        if !client.status().is_success() {
+            return Err(anyhow::Error::msg(client.status()));
+        }
+        decode::json(client).await
+    }
+}
+
+impl WindowedQuery for HistoryRange {
+    type Item = HistoryResponse;
+
+    fn fetch_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxFuture<'_, anyhow::Result<Vec<HistoryResponse>>> {
+        async move { Ok(self.fetch_entities(start, end).await?.concat()) }.boxed()
+    }
+
+    fn fetch_following_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxFuture<'_, anyhow::Result<Vec<HistoryResponse>>> {
+        async move {
+            let entities = self.fetch_entities(start, end).await?;
+            Ok(entities
+                .into_iter()
+                .flat_map(|rows| without_start_state(rows, start))
+                .collect())
+        }
+        .boxed()
+    }
+}
+
+/// the rows of an entity without the first one if it is the state at `start`, which the previous
+/// window ended with
+fn without_start_state(rows: Vec<HistoryResponse>, start: DateTime<Utc>) -> Vec<HistoryResponse> {
+    let mut rows = rows.into_iter();
+    let Some(first) = rows.next() else {
+        return Vec::new();
+    };
+    let changed_later =
+        DateTime::parse_from_rfc3339(&first.last_changed).is_ok_and(|changed| changed > start);
+    if changed_later {
+        return std::iter::once(first).chain(rows).collect();
+    }
+    let mut rows: Vec<HistoryResponse> = rows.collect();
+    // with `minimal_response` only the first row names the entity
+    if let Some(second) = rows.first_mut() {
+        second.entity_id = second.entity_id.take().or(first.entity_id);
+    }
+    rows
+}
+
+/// `/api/logbook/<start>`
+#[derive(Debug, Clone, Default)]
+pub struct LogbookRange {
+    ha_url: Option<String>,
+    ha_token: Option<String>,
+    entity_id: Option<String>,
+}
+
+impl LogbookRange {
+    /// entries of all entities, or only of `entity_id`
+    pub fn new(entity_id: Option<&str>) -> Self {
+        Self {
+            entity_id: entity_id.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+
+    /// url and token, read from the environment if not set
+    pub fn credentials(mut self, ha_url: Option<String>, ha_token: Option<String>) -> Self {
+        self.ha_url = ha_url;
+        self.ha_token = ha_token;
+        self
+    }
+
+    /// the path of the window `start..end`
+    pub fn path(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+        let query = urls::Query::new()
+            .param("end_time", end.to_rfc3339())
+            .opt("entity", self.entity_id.as_deref());
+        format!(
+            "/api/logbook/{}{query}",
+            urls::encode_segment(&start.to_rfc3339())
+        )
+    }
+}
+
+impl WindowedQuery for LogbookRange {
+    type Item = LogBook;
+
+    fn fetch_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxFuture<'_, anyhow::Result<Vec<LogBook>>> {
+        async move {
+            let (url, token) = credentials(self.ha_url.clone(), self.ha_token.clone())?;
+            let client = request(&url, &token, &self.path(start, end)).await?;
+            if !client.status().is_success() {
+                return Err(anyhow::Error::msg(client.status()));
+            }
+            decode::json::<Vec<LogBook>>(client).await
+        }
+        .boxed()
+    }
+}
+
+/// aggregation period of long-term statistics
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatisticsPeriod {
+    #[serde(rename = "5minute")]
+    FiveMinutes,
+    #[default]
+    #[serde(rename = "hour")]
+    Hour,
+    #[serde(rename = "day")]
+    Day,
+    #[serde(rename = "week")]
+    Week,
+    #[serde(rename = "month")]
+    Month,
+}
+
+/// a row of long-term statistics, timestamps are in milliseconds since the epoch
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StatisticsRow {
+    /// e.g. `sensor.energy_consumption`, filled in from the response
+    #[serde(default)]
+    pub statistic_id: String,
+    pub start: f64,
+    pub end: f64,
+    pub mean: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub sum: Option<f64>,
+    pub state: Option<f64>,
+    pub change: Option<f64>,
+    pub last_reset: Option<f64>,
+}
+
+impl StatisticsRow {
+    /// `start` as a timestamp
+    pub fn start_time(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp_millis(self.start as i64)
+    }
+}
+
+/// `recorder/statistics_during_period` (WebSocket only), windows of 30 days by default
+#[derive(Debug, Clone)]
+pub struct StatisticsRange {
+    ws: HomeAssistantWs,
+    statistic_ids: Vec<String>,
+    period: StatisticsPeriod,
+}
+
+impl StatisticsRange {
+    pub fn new<S: Into<String>>(
+        ws: &HomeAssistantWs,
+        statistic_ids: impl IntoIterator<Item = S>,
+        period: StatisticsPeriod,
+    ) -> Self {
+        Self {
+            ws: ws.clone(),
+            statistic_ids: statistic_ids.into_iter().map(Into::into).collect(),
+            period,
+        }
+    }
+}
+
+/// flattens the `{statistic_id: [row, ...]}` result of `recorder/statistics_during_period`
+pub fn statistics_rows(
+    result: std::collections::HashMap<String, Vec<StatisticsRow>>,
+) -> Vec<StatisticsRow> {
+    let mut rows: Vec<StatisticsRow> = result
+        .into_iter()
+        .flat_map(|(statistic_id, rows)| {
+            rows.into_iter().map(move |row| StatisticsRow {
+                statistic_id: statistic_id.clone(),
+                ..row
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        a.start
+            .total_cmp(&b.start)
+            .then_with(|| a.statistic_id.cmp(&b.statistic_id))
+    });
+    rows
+}
+
+impl WindowedQuery for StatisticsRange {
+    type Item = StatisticsRow;
+
+    fn fetch_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxFuture<'_, anyhow::Result<Vec<StatisticsRow>>> {
+        async move {
+            let result = self
+                .ws
+                .command_as(json!({
+                    "type": "recorder/statistics_during_period",
+                    "start_time": start.to_rfc3339(),
+                    "end_time": end.to_rfc3339(),
+                    "statistic_ids": self.statistic_ids,
+                    "period": self.period,
+                }))
+                .await?;
+            Ok(statistics_rows(result))
+        }
+        .boxed()
+    }
+
+    fn default_window(&self) -> TimeDelta {
+        TimeDelta::days(30)
+    }
+}
@@ -0,0 +1,1175 @@
+//! WebSocket API (`/api/websocket`)
+//!
+//! A [`HomeAssistantWs`] is a single authenticated connection. Commands and subscriptions are
+//! multiplexed over it by their `id`, a background task dispatches incoming messages. Clones share
+//! the connection, so one connection can serve a whole application. Identical
+//! [`subscribe_events`](HomeAssistantWs::subscribe_events) subscriptions share a single
+//! subscription on Homeassistant, every event is received once and handed to all of them.
+//!
+//! ```no_run
+//! # use tokio::runtime::Runtime;
+//! # let rt = Runtime::new().unwrap();
+//! # rt.block_on(async {
+//! use homeassistant_rs::prelude::*;
+//!
+//! let ws = hass().websocket(None, None).await.unwrap();
+//! let mut events = ws.subscribe_events(Some("state_changed")).await.unwrap();
+//!
+//! while let Some(event) = events.next().await {
+//!     println!("{:?}", event.unwrap());
+//! }
+//! # });
+//! ```
+//!
+//! Incoming frames are decoded by a pluggable [`Codec`], see [`codec`](crate::codec).
+//!
+//! By default the connection ends when Homeassistant restarts, see
+//! [`HomeAssistantWs::set_reconnect`] to reconnect and resubscribe automatically. A reconnecting
+//! connection also checks the core state when a REST request to its url is answered with
+//! `502 Bad Gateway`, as a reverse proxy does while Homeassistant is stopped, and pauses commands
+//! and REST requests until Homeassistant is running again.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, Stream, StreamExt};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Value, json};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::{self, Message, client::IntoClientRequest};
+
+#[cfg(feature = "audit")]
+use crate::audit;
+use crate::codec::Codec;
+use crate::secret::SecretString;
+use crate::{failover, settings, stats, structs, urls};
+
+type Socket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+struct Inner {
+    next_id: AtomicU64,
+    outgoing: Mutex<mpsc::UnboundedSender<Message>>,
+    pending: Mutex<HashMap<u64, oneshot::Sender<Value>>>,
+    subscribers: Mutex<HashMap<u64, Subscriber>>,
+    url: String,
+    token: SecretString,
+    codec: Arc<dyn Codec>,
+    ha_version: Mutex<String>,
+    connected: watch::Sender<bool>,
+    reconnect: Mutex<Option<ReconnectOptions>>,
+    /// when the connection was lost, while reconnecting
+    disconnected_at: Mutex<Option<DateTime<Utc>>>,
+    /// see [`HomeAssistantWs::cached_registries`]
+    registries: Mutex<Option<CachedRegistries>>,
+    /// bumped with the registries lock held whenever the cache is invalidated, fetches started
+    /// before are not cached
+    registry_generation: AtomicU64,
+    /// sequence number of the last event or reconnect, shared by all subscriptions
+    event_seq: AtomicU64,
+    limits: Limits,
+    /// why the connection was closed, if not by Homeassistant
+    closed_reason: Mutex<Option<String>>,
+}
+
+/// how long [`HomeAssistantWs::shutdown`] waits for Homeassistant
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// registries and when they were fetched
+pub(crate) type CachedRegistries = (tokio::time::Instant, Arc<crate::registry::Registries>);
+
+lazy_static::lazy_static! {
+    /// connections with reconnecting enabled, REST requests to their url wait while they reconnect
+    static ref RECONNECTING: Mutex<Vec<Weak<Inner>>> = Mutex::new(Vec::new());
+}
+
+/// a subscription on Homeassistant, by its current id
+struct Subscriber {
+    /// the id of the subscribing command, a fresh one is assigned when resubscribing
+    id: Arc<AtomicU64>,
+    /// the [`Subscription`]s receiving its events, by [`Registration::listener`]
+    listeners: HashMap<u64, mpsc::UnboundedSender<Delivery>>,
+    /// subscribing command, sent again after reconnecting
+    payload: Value,
+    /// can be joined by identical subscribing commands, see [`HomeAssistantWs::subscribe_shared`]
+    shared: bool,
+}
+
+enum Delivery {
+    Event {
+        seq: u64,
+        event: Value,
+    },
+    /// takes up a sequence number, so missed events show up as a gap
+    Reconnected(MissedWindow),
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Inner {
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.event_seq.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn send(&self, payload: &Value) -> anyhow::Result<()> {
+        self.send_message(Message::text(payload.to_string()))
+    }
+
+    fn send_message(&self, message: Message) -> anyhow::Result<()> {
+        lock(&self.outgoing)
+            .send(message)
+            .map_err(|_| self.closed())
+    }
+
+    /// the error of commands on a closed connection, includes the reason if known
+    fn closed(&self) -> anyhow::Error {
+        match &*lock(&self.closed_reason) {
+            Some(reason) => anyhow::Error::msg(format!("websocket connection closed: {reason}")),
+            None => anyhow::Error::msg("websocket connection closed"),
+        }
+    }
+
+    fn dispatch(&self, message: Value) {
+        let Some(id) = message["id"].as_u64() else {
+            return;
+        };
+
+        if message["type"] == "event" {
+            let mut subscribers = lock(&self.subscribers);
+            if let Some(subscriber) = subscribers.get_mut(&id) {
+                // numbered with the lock held, so numbers follow the order of delivery
+                let seq = self.next_seq();
+                subscriber.listeners.retain(|_, tx| {
+                    tx.send(Delivery::Event {
+                        seq,
+                        event: message["event"].clone(),
+                    })
+                    .is_ok()
+                });
+                if subscriber.listeners.is_empty() {
+                    subscribers.remove(&id);
+                }
+            }
+        } else if let Some(pending) = lock(&self.pending).remove(&id) {
+            let _ = pending.send(message);
+        }
+    }
+}
+
+/// how a connection is re-established after Homeassistant restarted or the connection dropped,
+/// see [`HomeAssistantWs::set_reconnect`]
+#[derive(Debug, Clone)]
+pub struct ReconnectOptions {
+    /// delay before the first attempt, doubled after every failed attempt
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// how long to wait for Homeassistant to finish starting (core state `RUNNING`)
+    pub startup_timeout: Duration,
+    /// how long commands wait for the connection to come back before failing
+    pub command_timeout: Duration,
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            startup_timeout: Duration::from_secs(300),
+            command_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// limits of a connection, protecting small devices from huge responses, e.g. the system log
+/// after an integration flooded it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// largest accepted message in bytes, a larger message closes the connection and fails all
+    /// pending commands with an error naming its size
+    pub max_message_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_message_size: 64 << 20,
+        }
+    }
+}
+
+impl Limits {
+    fn config(&self) -> WebSocketConfig {
+        WebSocketConfig::default()
+            .max_message_size(Some(self.max_message_size))
+            .max_frame_size(Some(self.max_message_size))
+    }
+}
+
+/// the time during which events may have been missed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissedWindow {
+    /// when the connection was lost
+    pub from: DateTime<Utc>,
+    /// when the subscriptions were re-established
+    pub to: DateTime<Utc>,
+}
+
+/// an item of [`Subscription::with_reconnects`]
+#[derive(Debug, Clone)]
+pub enum SubscriptionEvent<T> {
+    Event(T),
+    /// the connection was re-established, events within `missed_window` were not received and
+    /// any cached state should be refreshed
+    Reconnected {
+        missed_window: MissedWindow,
+    },
+}
+
+/// error response of a command, can be obtained with [`anyhow::Error::downcast_ref`]
+#[derive(Debug, Clone, Default)]
+pub struct CommandError {
+    /// e.g. `not_found`, `invalid_format` or `home_assistant_error`
+    pub code: String,
+    pub message: String,
+    /// the whole `error` object, including translation keys and placeholders if present
+    pub details: Value,
+}
+
+impl CommandError {
+    fn from_value(error: &Value) -> Self {
+        Self {
+            code: error["code"].as_str().unwrap_or("unknown_error").to_owned(),
+            message: error["message"].as_str().unwrap_or_default().to_owned(),
+            details: error.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// an authenticated connection to the WebSocket API, cheap to clone
+#[derive(Clone)]
+pub struct HomeAssistantWs {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for HomeAssistantWs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HomeAssistantWs")
+            .field("url", &self.inner.url)
+            .field("token", &self.inner.token)
+            .field("ha_version", &*lock(&self.inner.ha_version))
+            .field("connected", &*self.inner.connected.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn websocket_url(url: &str) -> anyhow::Result<Url> {
+    let mut url = urls::join(url, "/api/websocket")?;
+    let scheme = match url.scheme() {
+        "https" => "wss",
+        "http" => "ws",
+        scheme => return Err(anyhow::Error::msg(format!("unsupported scheme {scheme}"))),
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| anyhow::Error::msg("invalid websocket url"))?;
+    Ok(url)
+}
+
+fn decode(codec: &dyn Codec, message: &Message) -> Option<anyhow::Result<Value>> {
+    match message {
+        Message::Text(text) => Some(codec.decode_text(text)),
+        Message::Binary(bytes) => Some(codec.decode_binary(bytes)),
+        _ => None,
+    }
+}
+
+/// connects and authenticates, returns the url connected to, the Homeassistant version and the
+/// socket
+async fn handshake(
+    url: &str,
+    token: &str,
+    codec: &dyn Codec,
+    limits: &Limits,
+) -> anyhow::Result<(String, String, Socket)> {
+    let headers = settings::get().request_headers().await?;
+    let connector = settings::tls_connector()?;
+    let mut connected = None;
+    let mut error = None;
+    for candidate in failover::candidates(url) {
+        let mut request = websocket_url(&candidate)?.as_str().into_client_request()?;
+        request.headers_mut().extend(headers.clone());
+        match tokio_tungstenite::connect_async_tls_with_config(
+            request,
+            Some(limits.config()),
+            false,
+            connector.clone(),
+        )
+        .await
+        {
+            Ok((socket, _)) => {
+                failover::mark(&candidate, true);
+                connected = Some((candidate, socket));
+                break;
+            }
+            Err(tungstenite::Error::Io(e)) => {
+                failover::mark(&candidate, false);
+                error = Some(e.into());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let Some((url, mut socket)) = connected else {
+        return Err(error.unwrap_or(anyhow::Error::msg("no url to connect to")));
+    };
+
+    loop {
+        let message = socket
+            .next()
+            .await
+            .ok_or(anyhow::Error::msg("websocket closed during authentication"))??;
+        let Some(message) = decode(codec, &message).transpose()? else {
+            continue;
+        };
+
+        match message["type"].as_str() {
+            Some("auth_required") => {
+                socket
+                    .send(Message::text(
+                        json!({"type": "auth", "access_token": token}).to_string(),
+                    ))
+                    .await?
+            }
+            Some("auth_ok") => {
+                let version = message["ha_version"].as_str().unwrap_or("").to_owned();
+                return Ok((url, version, socket));
+            }
+            Some("auth_invalid") => {
+                return Err(anyhow::Error::msg(format!(
+                    "authentication failed: {}",
+                    message["message"].as_str().unwrap_or("auth_invalid")
+                )));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// spawns the writer and reader of `socket`, the reader reconnects if enabled
+fn run(inner: &Arc<Inner>, socket: Socket) {
+    let (mut sink, mut stream) = socket.split();
+    let (outgoing, mut rx) = mpsc::unbounded_channel::<Message>();
+    *lock(&inner.outgoing) = outgoing;
+    *lock(&inner.closed_reason) = None;
+
+    // the writer ends once the sender has been dropped, either by dropping every handle or by
+    // reconnecting
+    tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+        let _ = sink.close().await;
+    });
+
+    let weak: Weak<Inner> = Arc::downgrade(inner);
+    let codec = inner.codec.clone();
+    tokio::spawn(async move {
+        while let Some(message) = stream.next().await {
+            let Some(inner) = weak.upgrade() else { break };
+            let message = match message {
+                Ok(message) => message,
+                Err(tungstenite::Error::Capacity(e)) => {
+                    *lock(&inner.closed_reason) =
+                        Some(format!("{e}, see Limits::max_message_size"));
+                    break;
+                }
+                Err(_) => break,
+            };
+            stats::add(&stats::COUNTERS.bytes_received, message.len() as u64);
+            let Some(Ok(value)) = decode(&*codec, &message) else {
+                continue;
+            };
+            // coalesced messages arrive as an array
+            match value {
+                Value::Array(values) => {
+                    stats::add(&stats::COUNTERS.messages_received, values.len() as u64);
+                    values.into_iter().for_each(|v| inner.dispatch(v))
+                }
+                value => {
+                    stats::add(&stats::COUNTERS.messages_received, 1);
+                    inner.dispatch(value)
+                }
+            }
+        }
+
+        // connection closed: fail all pending commands, end all subscriptions unless reconnecting
+        let Some(inner) = weak.upgrade() else { return };
+        inner.connected.send_replace(false);
+        lock(&inner.disconnected_at).get_or_insert_with(Utc::now);
+        // ends the writer, e.g. after an oversized message the socket is still open
+        *lock(&inner.outgoing) = mpsc::unbounded_channel().0;
+        lock(&inner.pending).clear();
+        let options = lock(&inner.reconnect).clone();
+        match options {
+            Some(options) => {
+                drop(inner);
+                reconnect(weak, options).await;
+            }
+            None => lock(&inner.subscribers).clear(),
+        }
+    });
+}
+
+async fn reconnect(weak: Weak<Inner>, options: ReconnectOptions) {
+    let mut delay = options.initial_delay;
+    loop {
+        tokio::time::sleep(delay).await;
+        let Some(inner) = weak.upgrade() else { return };
+        if lock(&inner.reconnect).is_none() {
+            lock(&inner.subscribers).clear();
+            return;
+        }
+
+        if let Ok((_, version, socket)) = handshake(
+            &inner.url,
+            inner.token.expose_secret(),
+            &*inner.codec,
+            &inner.limits,
+        )
+        .await
+        {
+            *lock(&inner.ha_version) = version;
+            stats::add(&stats::COUNTERS.reconnects, 1);
+            run(&inner, socket);
+            if resume(HomeAssistantWs { inner }, &options).await.is_err()
+                && let Some(inner) = weak.upgrade()
+            {
+                // close the new connection, its reader starts over
+                *lock(&inner.outgoing) = mpsc::unbounded_channel().0;
+            }
+            return;
+        }
+        delay = (delay * 2).min(options.max_delay);
+    }
+}
+
+/// notifies the subscribers, re-establishes all subscriptions and waits for Homeassistant to
+/// finish starting
+async fn resume(ws: HomeAssistantWs, options: &ReconnectOptions) -> anyhow::Result<()> {
+    let to = Utc::now();
+    let missed_window = MissedWindow {
+        from: lock(&ws.inner.disconnected_at).take().unwrap_or(to),
+        to,
+    };
+
+    let mut subscriptions: Vec<u64> = lock(&ws.inner.subscribers).keys().copied().collect();
+    subscriptions.sort_unstable();
+    // the reconnect takes up a sequence number, see [`Subscription::sequenced`]
+    ws.inner.next_seq();
+    for old_id in subscriptions {
+        // moved to a fresh id before resubscribing, events may arrive right after the result
+        let (id, payload) = {
+            let mut subscribers = lock(&ws.inner.subscribers);
+            let Some(subscriber) = subscribers.remove(&old_id) else {
+                continue;
+            };
+            let id = ws.inner.next_id();
+            subscriber.id.store(id, Ordering::Relaxed);
+            // the marker precedes all events of the new connection
+            for tx in subscriber.listeners.values() {
+                let _ = tx.send(Delivery::Reconnected(missed_window));
+            }
+            let payload = subscriber.payload.clone();
+            subscribers.insert(id, subscriber);
+            (id, payload)
+        };
+        match ws.request(id, payload).await {
+            Ok(_) => {}
+            // the connection dropped again
+            Err(e) if lock(&ws.inner.outgoing).is_closed() => return Err(e),
+            Err(_) => {
+                lock(&ws.inner.subscribers).remove(&id);
+            }
+        }
+    }
+    ws.request(
+        ws.inner.next_id(),
+        json!({"type": "supported_features", "features": {"coalesce_messages": 1}}),
+    )
+    .await?;
+
+    // commands stay paused until Homeassistant finished starting
+    wait_running(&ws, options.startup_timeout).await?;
+    ws.inner.connected.send_replace(true);
+    Ok(())
+}
+
+/// whether the core state in a `get_config` result is `RUNNING`, older versions don't report it
+fn is_running(config: &Value) -> bool {
+    config["state"]
+        .as_str()
+        .is_none_or(|state| state == "RUNNING")
+}
+
+/// polls the core state until Homeassistant is running or `timeout` elapsed
+async fn wait_running(ws: &HomeAssistantWs, timeout: Duration) -> anyhow::Result<()> {
+    let started = tokio::time::Instant::now();
+    loop {
+        let config = ws
+            .request(ws.inner.next_id(), json!({"type": "get_config"}))
+            .await?;
+        if is_running(&config) || started.elapsed() >= timeout {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// checks the core state of the reconnecting connections to `url` after a REST request was
+/// answered with `502 Bad Gateway`, pauses those which are not running until they are
+pub(crate) async fn check_restart(url: &str) {
+    let url = url.trim_end_matches('/');
+    let connections: Vec<Arc<Inner>> = lock(&RECONNECTING)
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|inner| inner.url == url && *inner.connected.borrow())
+        .collect();
+    for inner in connections {
+        let Some(options) = lock(&inner.reconnect).clone() else {
+            continue;
+        };
+        let ws = HomeAssistantWs { inner };
+        let config = tokio::time::timeout(
+            Duration::from_secs(5),
+            ws.request(ws.inner.next_id(), json!({"type": "get_config"})),
+        )
+        .await;
+        if matches!(&config, Ok(Ok(config)) if is_running(config)) {
+            continue;
+        }
+
+        ws.inner.connected.send_replace(false);
+        lock(&ws.inner.disconnected_at).get_or_insert_with(Utc::now);
+        tokio::spawn(async move {
+            // if the connection drops meanwhile, reconnecting takes over
+            if wait_running(&ws, options.startup_timeout).await.is_ok() {
+                lock(&ws.inner.disconnected_at).take();
+                ws.inner.connected.send_replace(true);
+            }
+        });
+    }
+}
+
+/// waits while a connection to `url` reconnects, e.g. during a restart of Homeassistant
+pub(crate) async fn wait_for_restart(url: &str) {
+    let url = url.trim_end_matches('/');
+    let restarting = lock(&RECONNECTING)
+        .iter()
+        .filter_map(Weak::upgrade)
+        .find(|inner| inner.url == url && !*inner.connected.borrow());
+    if let Some(inner) = restarting {
+        let _ = HomeAssistantWs { inner }.wait_connected().await;
+    }
+}
+
+impl HomeAssistantWs {
+    pub(crate) async fn connect(
+        url: &str,
+        token: &str,
+        codec: Arc<dyn Codec>,
+        limits: Limits,
+    ) -> anyhow::Result<Self> {
+        let (url, ha_version, socket) = handshake(url, token, &*codec, &limits).await?;
+
+        let inner = Arc::new(Inner {
+            next_id: AtomicU64::new(1),
+            outgoing: Mutex::new(mpsc::unbounded_channel().0),
+            pending: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(HashMap::new()),
+            url: url.trim_end_matches('/').to_owned(),
+            token: token.into(),
+            codec,
+            ha_version: Mutex::new(ha_version),
+            connected: watch::Sender::new(true),
+            reconnect: Mutex::new(None),
+            disconnected_at: Mutex::new(None),
+            registries: Mutex::new(None),
+            registry_generation: AtomicU64::new(0),
+            event_seq: AtomicU64::new(0),
+            limits,
+            closed_reason: Mutex::new(None),
+        });
+        run(&inner, socket);
+
+        let ws = Self { inner };
+        ws.command(json!({"type": "supported_features", "features": {"coalesce_messages": 1}}))
+            .await?;
+        Ok(ws)
+    }
+
+    /// version of the connected Homeassistant instance, as reported during authentication
+    pub fn ha_version(&self) -> String {
+        lock(&self.inner.ha_version).clone()
+    }
+
+    /// the cached registries and when they were fetched
+    pub(crate) fn registry_cache(&self) -> MutexGuard<'_, Option<CachedRegistries>> {
+        lock(&self.inner.registries)
+    }
+
+    /// see [`Inner::registry_generation`]
+    pub(crate) fn registry_generation(&self) -> u64 {
+        self.inner.registry_generation.load(Ordering::Relaxed)
+    }
+
+    /// drops the cached registries, including those still being fetched
+    pub(crate) fn clear_registry_cache(&self) {
+        let mut cache = self.registry_cache();
+        self.inner
+            .registry_generation
+            .fetch_add(1, Ordering::Relaxed);
+        cache.take();
+    }
+
+    /// base url of the connected Homeassistant instance
+    pub fn url(&self) -> &str {
+        &self.inner.url
+    }
+
+    /// enables (or with [`None`] disables) reconnecting when the connection drops, e.g. because
+    /// Homeassistant restarts
+    ///
+    /// while disconnected, commands wait for the connection to come back and events of
+    /// subscriptions are buffered. After reconnecting all subscriptions are re-established and
+    /// [`Subscription::with_reconnects`] yields a [`SubscriptionEvent::Reconnected`] marker.
+    /// Without reconnecting, all subscriptions end when the connection drops.
+    pub fn set_reconnect(&self, options: Option<ReconnectOptions>) {
+        let enabled = options.is_some();
+        *lock(&self.inner.reconnect) = options;
+
+        let mut reconnecting = lock(&RECONNECTING);
+        reconnecting.retain(|inner| {
+            inner
+                .upgrade()
+                .is_some_and(|inner| !Arc::ptr_eq(&inner, &self.inner))
+        });
+        if enabled {
+            reconnecting.push(Arc::downgrade(&self.inner));
+        }
+    }
+
+    /// whether the connection is currently established
+    pub fn is_connected(&self) -> bool {
+        *self.inner.connected.borrow()
+    }
+
+    /// disconnected for good, i.e. without reconnecting
+    pub(crate) fn is_closed(&self) -> bool {
+        !self.is_connected() && lock(&self.inner.reconnect).is_none()
+    }
+
+    /// closes the connection gracefully: disables reconnecting, unsubscribes all subscriptions
+    /// (which ends their streams) and closes the WebSocket once Homeassistant answered
+    ///
+    /// commands sent before are answered first. Waits at most [`SHUTDOWN_TIMEOUT`] for
+    /// Homeassistant, the connection is closed either way. Dropping the last handle and
+    /// subscription closes the connection as well, but without unsubscribing first
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        self.set_reconnect(None);
+
+        let ids: Vec<u64> = lock(&self.inner.subscribers).keys().copied().collect();
+        let unsubscribed = futures_util::future::join_all(ids.into_iter().map(|id| {
+            self.request(
+                self.inner.next_id(),
+                json!({"type": "unsubscribe_events", "subscription": id}),
+            )
+        }));
+        let unsubscribed = tokio::time::timeout(SHUTDOWN_TIMEOUT, unsubscribed).await;
+        lock(&self.inner.subscribers).clear();
+
+        // the reader marks the connection as closed once Homeassistant confirmed the close frame
+        let mut connected = self.inner.connected.subscribe();
+        if self.inner.send_message(Message::Close(None)).is_ok() {
+            let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, connected.wait_for(|c| !*c)).await;
+        }
+        *lock(&self.inner.outgoing) = mpsc::unbounded_channel().0;
+        self.inner.connected.send_replace(false);
+        lock(&self.inner.pending).clear();
+
+        match unsubscribed {
+            Ok(results) => results.into_iter().try_for_each(|result| result.map(drop)),
+            Err(_) => Err(anyhow::Error::msg("timed out unsubscribing")),
+        }
+    }
+
+    /// waits until the connection is established, if reconnecting is enabled
+    pub(crate) async fn wait_connected(&self) -> anyhow::Result<()> {
+        let Some(options) = lock(&self.inner.reconnect).clone() else {
+            return Ok(());
+        };
+        let mut connected = self.inner.connected.subscribe();
+        match tokio::time::timeout(options.command_timeout, connected.wait_for(|c| *c)).await {
+            Ok(Ok(_)) => Ok(()),
+            _ => Err(self.inner.closed()),
+        }
+    }
+
+    async fn request(&self, id: u64, mut payload: Value) -> anyhow::Result<Value> {
+        payload["id"] = id.into();
+        stats::add(&stats::COUNTERS.commands_sent, 1);
+
+        let (tx, rx) = oneshot::channel();
+        lock(&self.inner.pending).insert(id, tx);
+        if let Err(e) = self.inner.send(&payload) {
+            lock(&self.inner.pending).remove(&id);
+            return Err(e);
+        }
+
+        let response = rx.await.map_err(|_| self.inner.closed())?;
+
+        if response["success"] == false {
+            Err(CommandError::from_value(&response["error"]).into())
+        } else {
+            Ok(response.get("result").cloned().unwrap_or(Value::Null))
+        }
+    }
+
+    /// sends a command and returns its `result`
+    pub(crate) async fn command(&self, payload: Value) -> anyhow::Result<Value> {
+        self.wait_connected().await?;
+        #[cfg(feature = "audit")]
+        if let Some((kind, target)) = audit::is_enabled()
+            .then(|| audit::classify_command(&payload))
+            .flatten()
+        {
+            let serialized = payload.to_string();
+            let result = self.request(self.inner.next_id(), payload).await;
+            audit::record(
+                kind,
+                target,
+                audit::Transport::WebSocket,
+                serialized.as_bytes(),
+                result.is_ok(),
+            );
+            return result;
+        }
+        self.request(self.inner.next_id(), payload).await
+    }
+
+    /// sends a command and deserializes its `result` into `T`
+    pub(crate) async fn command_as<T: DeserializeOwned>(
+        &self,
+        payload: Value,
+    ) -> anyhow::Result<T> {
+        Ok(serde_json::from_value(self.command(payload).await?)?)
+    }
+
+    /// sends the command `command_type` with the fields of `payload` (an object or `null`) and
+    /// returns its `result`, for commands without a dedicated method
+    ///
+    /// the `id` is assigned by the connection, errors are returned as [`CommandError`]
+    /// ```no_run
+    /// # use homeassistant_rs::prelude::*;
+    /// # async fn example(ws: HomeAssistantWs) -> anyhow::Result<()> {
+    /// let entries = ws
+    ///     .send_command("config_entries/get", json!({"domain": "hue"}))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_command(&self, command_type: &str, payload: Value) -> anyhow::Result<Value> {
+        let mut payload = match payload {
+            Value::Object(payload) => payload,
+            Value::Null => serde_json::Map::new(),
+            payload => {
+                return Err(anyhow::Error::msg(format!(
+                    "payload of {command_type} has to be an object, got {payload}"
+                )));
+            }
+        };
+        if payload.contains_key("id") {
+            return Err(anyhow::Error::msg(
+                "the id of a command is assigned automatically",
+            ));
+        }
+        payload.insert("type".to_owned(), command_type.into());
+        self.command(Value::Object(payload)).await
+    }
+
+    /// like [`send_command`](Self::send_command), deserializes the `result` into `T`
+    pub async fn send_command_as<T: DeserializeOwned>(
+        &self,
+        command_type: &str,
+        payload: Value,
+    ) -> anyhow::Result<T> {
+        Ok(serde_json::from_value(
+            self.send_command(command_type, payload).await?,
+        )?)
+    }
+
+    /// sends a subscribing command, the returned [`Subscription`] receives its events
+    pub(crate) async fn subscribe<T: DeserializeOwned>(
+        &self,
+        payload: Value,
+    ) -> anyhow::Result<Subscription<T>> {
+        self.wait_connected().await?;
+        let id = self.inner.next_id();
+        let shared_id = Arc::new(AtomicU64::new(id));
+        let (tx, rx) = mpsc::unbounded_channel();
+        // register before sending, events may arrive right after the result
+        lock(&self.inner.subscribers).insert(
+            id,
+            Subscriber {
+                id: shared_id.clone(),
+                listeners: HashMap::from([(id, tx)]),
+                payload: payload.clone(),
+                shared: false,
+            },
+        );
+
+        if let Err(e) = self.request(id, payload).await {
+            lock(&self.inner.subscribers).remove(&id);
+            return Err(e);
+        }
+        Ok(self.subscription(shared_id, id, rx))
+    }
+
+    /// like [`subscribe`](Self::subscribe), but joins an identical subscription of this
+    /// connection instead of subscribing again
+    ///
+    /// only for subscriptions without an initial event, a joining subscription only receives the
+    /// events from then on
+    pub(crate) async fn subscribe_shared<T: DeserializeOwned>(
+        &self,
+        payload: Value,
+    ) -> anyhow::Result<Subscription<T>> {
+        self.wait_connected().await?;
+        let joined = lock(&self.inner.subscribers)
+            .iter_mut()
+            .find(|(_, subscriber)| subscriber.shared && subscriber.payload == payload)
+            .map(|(_, subscriber)| {
+                let listener = self.inner.next_id();
+                let (tx, rx) = mpsc::unbounded_channel();
+                subscriber.listeners.insert(listener, tx);
+                (subscriber.id.clone(), listener, rx)
+            });
+        if let Some((id, listener, rx)) = joined {
+            return Ok(self.subscription(id, listener, rx));
+        }
+
+        // shared once confirmed, concurrent first subscribers each subscribe on their own
+        let subscription = self.subscribe(payload).await?;
+        if let Some(subscriber) = lock(&self.inner.subscribers).get_mut(&subscription.id()) {
+            subscriber.shared = true;
+        }
+        Ok(subscription)
+    }
+
+    fn subscription<T>(
+        &self,
+        id: Arc<AtomicU64>,
+        listener: u64,
+        rx: mpsc::UnboundedReceiver<Delivery>,
+    ) -> Subscription<T> {
+        stats::add(&stats::COUNTERS.active_subscriptions, 1);
+        Subscription {
+            registration: Registration {
+                id,
+                listener,
+                inner: self.inner.clone(),
+            },
+            rx,
+            _type: PhantomData,
+        }
+    }
+
+    /// sends a binary frame, e.g. audio for the assist pipeline
+    #[cfg_attr(not(feature = "satellite"), allow(dead_code))]
+    pub(crate) fn send_binary(&self, data: Vec<u8>) -> anyhow::Result<()> {
+        self.inner.send_message(Message::binary(data))
+    }
+
+    /// sends `ping` and waits for the `pong`
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        self.command(json!({"type": "ping"})).await?;
+        Ok(())
+    }
+
+    /// `get_config`, returns [`ConfigResponse`](structs::ConfigResponse)
+    pub async fn config(&self) -> anyhow::Result<structs::ConfigResponse> {
+        self.command_as(json!({"type": "get_config"})).await
+    }
+
+    /// `config/core/update`, changes the core config (location, unit system, time zone, urls, ...)
+    pub async fn update_core_config(
+        &self,
+        update: structs::CoreConfigUpdate,
+    ) -> anyhow::Result<()> {
+        let mut payload = serde_json::to_value(update)?;
+        payload["type"] = "config/core/update".into();
+        self.command(payload).await?;
+        Ok(())
+    }
+
+    /// `get_states`, returns a Vec containing [`StatesResponse`](structs::StatesResponse)
+    pub async fn states(&self) -> anyhow::Result<Vec<structs::StatesResponse>> {
+        self.command_as(json!({"type": "get_states"})).await
+    }
+
+    /// `auth/sign_path`, returns `path` with a signature valid for `expires`
+    pub async fn sign_path(&self, path: &str, expires: Duration) -> anyhow::Result<String> {
+        let result = self
+            .command(json!({"type": "auth/sign_path", "path": path, "expires": expires.as_secs()}))
+            .await?;
+        result["path"]
+            .as_str()
+            .map(str::to_owned)
+            .ok_or(anyhow::Error::msg("sign_path returned no path"))
+    }
+
+    /// returns the absolute URL of a state's `entity_picture`, signing it when required
+    pub async fn entity_picture_url(
+        &self,
+        state: &structs::StatesResponse,
+        expires: Duration,
+    ) -> anyhow::Result<Option<String>> {
+        let Some(picture) = urls::entity_picture(state) else {
+            return Ok(None);
+        };
+        let path = if urls::needs_signing(picture) {
+            self.sign_path(picture, expires).await?
+        } else {
+            picture.to_owned()
+        };
+        Ok(Some(urls::join(self.url(), &path)?.to_string()))
+    }
+
+    /// `subscribe_events`, subscribes to all events or only to `event_type`
+    ///
+    /// subscriptions to the same `event_type` share one subscription on Homeassistant
+    pub async fn subscribe_events(
+        &self,
+        event_type: Option<&str>,
+    ) -> anyhow::Result<Subscription<structs::Event>> {
+        let mut payload = json!({"type": "subscribe_events"});
+        if let Some(event_type) = event_type {
+            payload["event_type"] = event_type.into();
+        }
+        self.subscribe_shared(payload).await
+    }
+}
+
+/// stream of events belonging to a subscription, unsubscribes when dropped
+///
+/// events are yielded in the order Homeassistant sent them, which also means in order per entity.
+/// Every event is numbered, see [`sequenced`](Self::sequenced)
+pub struct Subscription<T> {
+    registration: Registration,
+    rx: mpsc::UnboundedReceiver<Delivery>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Subscription<T> {
+    /// id of the subscribing command, the same for subscriptions sharing it
+    ///
+    /// changes when the subscription is re-established after reconnecting
+    pub fn id(&self) -> u64 {
+        self.registration.id.load(Ordering::Relaxed)
+    }
+
+    /// yields the raw JSON of every event next to the typed value
+    pub fn with_raw(self) -> Subscription<Raw<T>> {
+        Subscription {
+            registration: self.registration,
+            rx: self.rx,
+            _type: PhantomData,
+        }
+    }
+
+    /// additionally yields a [`SubscriptionEvent::Reconnected`] marker after the connection was
+    /// re-established, see [`HomeAssistantWs::set_reconnect`]
+    pub fn with_reconnects(self) -> WithReconnects<T> {
+        WithReconnects(self)
+    }
+
+    /// yields every event with its sequence number
+    ///
+    /// events are numbered consecutively per connection, starting at 1, across all of its
+    /// subscriptions. A reconnect takes up a number as well, so events missed while reconnecting
+    /// show up as a gap. Merging the subscriptions of a connection by number, downstream consumers
+    /// can thereby detect lost and reordered events; a single subscription only sees increasing
+    /// numbers
+    pub fn sequenced(self) -> Sequenced<T> {
+        Sequenced(self)
+    }
+}
+
+impl<T: DeserializeOwned> Stream for Subscription<T> {
+    type Item = anyhow::Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            return match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(Delivery::Event { event, .. })) => {
+                    Poll::Ready(Some(Ok(serde_json::from_value(event)?)))
+                }
+                Poll::Ready(Some(Delivery::Reconnected(_))) => continue,
+                Poll::Ready(None) => Poll::Ready(None),
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
+
+/// a [`Subscription`] which also reports reconnects
+pub struct WithReconnects<T>(Subscription<T>);
+
+impl<T> WithReconnects<T> {
+    pub fn id(&self) -> u64 {
+        self.0.id()
+    }
+
+    /// the connection the subscription belongs to
+    pub(crate) fn connection(&self) -> HomeAssistantWs {
+        HomeAssistantWs {
+            inner: self.0.registration.inner.clone(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Stream for WithReconnects<T> {
+    type Item = anyhow::Result<SubscriptionEvent<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.rx.poll_recv(cx).map(|delivery| {
+            delivery.map(|delivery| match delivery {
+                Delivery::Event { event, .. } => {
+                    Ok(SubscriptionEvent::Event(serde_json::from_value(event)?))
+                }
+                Delivery::Reconnected(missed_window) => {
+                    Ok(SubscriptionEvent::Reconnected { missed_window })
+                }
+            })
+        })
+    }
+}
+
+/// an event and its sequence number within the connection
+#[derive(Debug, Clone)]
+pub struct SequencedEvent<T> {
+    pub seq: u64,
+    pub event: T,
+}
+
+/// a [`Subscription`] yielding [`SequencedEvent`]s
+pub struct Sequenced<T>(Subscription<T>);
+
+impl<T> Sequenced<T> {
+    pub fn id(&self) -> u64 {
+        self.0.id()
+    }
+}
+
+impl<T: DeserializeOwned> Stream for Sequenced<T> {
+    type Item = anyhow::Result<SequencedEvent<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            return match self.0.rx.poll_recv(cx) {
+                Poll::Ready(Some(Delivery::Event { seq, event })) => {
+                    Poll::Ready(Some(Ok(SequencedEvent {
+                        seq,
+                        event: serde_json::from_value(event)?,
+                    })))
+                }
+                Poll::Ready(Some(Delivery::Reconnected(_))) => continue,
+                Poll::Ready(None) => Poll::Ready(None),
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
+
+/// unsubscribes when the last subscription sharing `id` is dropped
+struct Registration {
+    /// shared with the [`Subscriber`], see [`Subscription::id`]
+    id: Arc<AtomicU64>,
+    /// key of the sender within [`Subscriber::listeners`]
+    listener: u64,
+    inner: Arc<Inner>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        stats::COUNTERS
+            .active_subscriptions
+            .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+        let id = {
+            let mut subscribers = lock(&self.inner.subscribers);
+            // resubscribing changes the id with the lock held
+            let id = self.id.load(Ordering::Relaxed);
+            if let Some(subscriber) = subscribers.get_mut(&id) {
+                subscriber.listeners.remove(&self.listener);
+                if !subscriber.listeners.is_empty() {
+                    return;
+                }
+                subscribers.remove(&id);
+            }
+            id
+        };
+        let _ = self.inner.send(&json!({
+            "id": self.inner.next_id(),
+            "type": "unsubscribe_events",
+            "subscription": id,
+        }));
+    }
+}
+
+/// a typed value together with the JSON it was deserialized from, gives access to fields the
+/// typed struct does not know about
+#[derive(Debug, Clone)]
+pub struct Raw<T> {
+    pub value: T,
+    pub raw: Value,
+}
+
+impl<T> std::ops::Deref for Raw<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Raw<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Value::deserialize(deserializer)?;
+        let value = T::deserialize(&raw).map_err(serde::de::Error::custom)?;
+        Ok(Self { value, raw })
+    }
+}
@@ -0,0 +1,64 @@
+//! Zone management (WebSocket only)
+//!
+//! Only zones created through the UI/storage can be managed, zones defined in YAML are read-only.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::ws::HomeAssistantWs;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ZoneInput {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// radius in meters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// passive zones are only used for automations and do not affect the state of persons
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passive: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Zone {
+    pub id: String,
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub radius: Option<f64>,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub passive: bool,
+}
+
+impl HomeAssistantWs {
+    /// `zone/list`, returns a Vec containing [`Zone`]
+    pub async fn zones(&self) -> anyhow::Result<Vec<Zone>> {
+        self.command_as(json!({"type": "zone/list"})).await
+    }
+
+    /// `zone/create`, returns the created [`Zone`]
+    pub async fn create_zone(&self, zone: ZoneInput) -> anyhow::Result<Zone> {
+        let mut payload = serde_json::to_value(zone)?;
+        payload["type"] = "zone/create".into();
+        self.command_as(payload).await
+    }
+
+    /// `zone/update`, returns the updated [`Zone`]
+    pub async fn update_zone(&self, zone_id: &str, zone: ZoneInput) -> anyhow::Result<Zone> {
+        let mut payload = serde_json::to_value(zone)?;
+        payload["type"] = "zone/update".into();
+        payload["zone_id"] = zone_id.into();
+        self.command_as(payload).await
+    }
+
+    /// `zone/delete`
+    pub async fn delete_zone(&self, zone_id: &str) -> anyhow::Result<()> {
+        self.command(json!({"type": "zone/delete", "zone_id": zone_id}))
+            .await?;
+        Ok(())
+    }
+}